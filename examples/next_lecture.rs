@@ -0,0 +1,27 @@
+//! Prints the next upcoming event of every module.
+
+use fh_kiel_ical_splitter::{Config, Result, Scraper};
+
+fn main() -> Result<()> {
+    let mut scraper = Scraper::new(Config::default())?;
+    let now = chrono::Utc::now();
+
+    for module in scraper.collect_events()?.values() {
+        let next_event = module
+            .events
+            .iter()
+            .filter(|event| event.start > now)
+            .min_by_key(|event| event.start);
+
+        if let Some(event) = next_event {
+            println!(
+                "{}: {} in {}",
+                module.display_name,
+                event.start.format("%d.%m.%Y %H:%M"),
+                event.location.as_deref().unwrap_or("unbekanntem Raum")
+            );
+        }
+    }
+
+    Ok(())
+}
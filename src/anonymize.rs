@@ -0,0 +1,182 @@
+//! Removal of lecturer names from the summaries and descriptions of the published events
+
+use crate::calendar::{PROPERTY_NAME_DESCRIPTION, PROPERTY_NAME_SUMMARY};
+use crate::prelude::*;
+use crate::props;
+use ical::parser::ical::component::IcalEvent;
+use regex::Regex;
+
+/// Regexes matching lecturer names removed by `--strip-names` unless overridden: a trailing
+/// ` - Müller` or ` - Müller-Lüdenscheidt`, and names following a title like `Prof. Dr. Müller`.
+pub const DEFAULT_NAME_PATTERNS: &[&str] = &[
+    r"\s+-\s+\p{Lu}\p{Ll}+(?:-\p{Lu}\p{Ll}+)?\s*$",
+    r"(?:\b(?:Prof|Dr)\.\s*)+(?:\p{Lu}\p{Ll}+(?:-\p{Lu}\p{Ll}+)?)?",
+];
+
+/// Properties names are removed from.
+const STRIPPED_PROPERTIES: [&str; 2] = [PROPERTY_NAME_SUMMARY, PROPERTY_NAME_DESCRIPTION];
+
+/// Removes personal names from events.
+#[derive(Debug)]
+pub struct NameStripper {
+    patterns: Vec<Regex>,
+}
+
+impl NameStripper {
+    /// Creates a stripper removing every match of `patterns`.
+    pub fn new(patterns: &[String]) -> Result<Self> {
+        let patterns = patterns
+            .iter()
+            .map(|pattern| {
+                Regex::new(pattern)
+                    .map_err(|err| Error::InvalidNamePattern(pattern.clone(), err.to_string()))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Self { patterns })
+    }
+
+    /// Removes the names from the summary and description of `event`. Returns `true` if anything
+    /// was removed.
+    pub fn strip_event(&self, event: &mut IcalEvent) -> bool {
+        let mut stripped = false;
+
+        for name in STRIPPED_PROPERTIES {
+            let Some(value) = props::get_value(event, name).and_then(|value| self.strip(value))
+            else {
+                continue;
+            };
+
+            stripped |= props::set_value_preserving_params(event, name, value);
+        }
+
+        stripped
+    }
+
+    /// Returns `text` without the names, or `None` if there were none. Text which would end up
+    /// empty is kept as it is, since it most likely wasn't a name after all.
+    pub fn strip(&self, text: &str) -> Option<String> {
+        if !self.patterns.iter().any(|pattern| pattern.is_match(text)) {
+            return None;
+        }
+
+        let mut stripped = text.to_owned();
+        for pattern in &self.patterns {
+            stripped = pattern.replace_all(&stripped, "").into_owned();
+        }
+
+        // Removing a name from the middle leaves double spaces behind
+        let stripped = stripped.split_whitespace().collect::<Vec<_>>().join(" ");
+
+        (!stripped.is_empty()).then_some(stripped)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn default_stripper() -> NameStripper {
+        let patterns: Vec<String> = DEFAULT_NAME_PATTERNS
+            .iter()
+            .map(|pattern| (*pattern).to_owned())
+            .collect();
+
+        NameStripper::new(&patterns).unwrap()
+    }
+
+    #[test]
+    fn trailing_names_are_removed() {
+        let name_stripper = default_stripper();
+
+        for (summary, stripped) in [
+            ("Mathematik 1 - Müller", "Mathematik 1"),
+            ("Mathematik 1 - Müller-Lüdenscheidt", "Mathematik 1"),
+            (
+                "Grundlagen der Elektrotechnik - Özdemir",
+                "Grundlagen der Elektrotechnik",
+            ),
+            ("Übung Physik - Weiß ", "Übung Physik"),
+        ] {
+            assert_eq!(
+                name_stripper.strip(summary).as_deref(),
+                Some(stripped),
+                "the name should be removed from '{summary}'"
+            );
+        }
+    }
+
+    #[test]
+    fn names_after_titles_are_removed() {
+        let name_stripper = default_stripper();
+
+        for (summary, stripped) in [
+            ("Mathematik 1 Prof. Müller", "Mathematik 1"),
+            ("Mathematik 1 Prof. Dr. Müller-Lüdenscheidt", "Mathematik 1"),
+            ("Sprechstunde Dr. Ärmel Raum C-12", "Sprechstunde Raum C-12"),
+            ("Seminar bei Prof.Dr. Schäfer", "Seminar bei"),
+        ] {
+            assert_eq!(
+                name_stripper.strip(summary).as_deref(),
+                Some(stripped),
+                "the name should be removed from '{summary}'"
+            );
+        }
+    }
+
+    #[test]
+    fn text_without_names_is_kept() {
+        let name_stripper = default_stripper();
+
+        for summary in [
+            "Mathematik 1",
+            "Labor - A",
+            "Projekt - IT-Sicherheit 2",
+            // Nothing would be left of a summary which is only a name
+            "Prof. Dr. Müller",
+        ] {
+            assert_eq!(
+                name_stripper.strip(summary),
+                None,
+                "'{summary}' should be kept as it is"
+            );
+        }
+    }
+
+    #[test]
+    fn summary_and_description_of_events_are_stripped() {
+        let mut event = IcalEvent::new();
+        props::upsert_property(
+            &mut event,
+            PROPERTY_NAME_SUMMARY,
+            "Mathematik 1 - Müller".to_owned(),
+        );
+        props::upsert_property(
+            &mut event,
+            PROPERTY_NAME_DESCRIPTION,
+            "Fragen an Prof. Dr. Schäfer".to_owned(),
+        );
+
+        assert!(
+            default_stripper().strip_event(&mut event),
+            "the event should be reported as stripped"
+        );
+        assert_eq!(
+            [PROPERTY_NAME_SUMMARY, PROPERTY_NAME_DESCRIPTION]
+                .map(|name| props::get_value(&event, name)),
+            [Some("Mathematik 1"), Some("Fragen an")],
+            "the names should be removed from both properties"
+        );
+    }
+
+    #[test]
+    fn invalid_patterns_are_rejected() {
+        assert!(
+            matches!(
+                NameStripper::new(&["(".to_owned()]),
+                Err(Error::InvalidNamePattern(..))
+            ),
+            "an invalid regex should be reported"
+        );
+    }
+}
@@ -0,0 +1,282 @@
+//! Public API for using the aggregated events from other programs
+
+use crate::cache::{Cache, ClearSelection, CACHE_FOLDER};
+use crate::calendar::{
+    self, CalendarEntry, PROPERTY_NAME_DTEND, PROPERTY_NAME_DTSTART, PROPERTY_NAME_LOCATION,
+    PROPERTY_NAME_PRODUCT_ID, PROPERTY_NAME_SOURCE, PROPERTY_NAME_SUMMARY,
+};
+use crate::clock::{SystemClock, ThreadSleeper};
+use crate::config::Config;
+use crate::exit_code::ExitCode;
+use crate::manifest::{self, MANIFEST_FILE};
+use crate::names::{self, GroupBy};
+use crate::pipeline::Pipeline;
+use crate::prelude::*;
+use crate::{datetime, props, scrape};
+use chrono::{DateTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use ical::parser::ical::component::IcalEvent;
+use regex::RegexBuilder;
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+
+/// Time zone of date-times without a `TZID` or UTC marker, the one the FH Kiel is in.
+const DEFAULT_TIME_ZONE: Tz = chrono_tz::Europe::Berlin;
+
+/// Canonical name of a module, the same for every spelling of the name. Depending on
+/// `--group-by` it is followed by the semester and group directory.
+pub type ModuleKey = String;
+
+/// A single event of a module.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Event {
+    pub summary: String,
+    pub start: DateTime<Tz>,
+    /// `None` if the event has no valid `DTEND`.
+    pub end: Option<DateTime<Tz>>,
+    pub location: Option<String>,
+    /// Path of the source calendar relative to its base URL, `None` with `--no-provenance`.
+    pub source: Option<String>,
+}
+
+/// All events of a module, collected from every source calendar.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ModuleCalendar {
+    /// The preferred spelling of the module name.
+    pub display_name: String,
+    pub department: String,
+    pub semester: String,
+    pub group: String,
+    /// Events in the order they were found, events without a valid start are left out.
+    pub events: Vec<Event>,
+}
+
+/// Scrapes the calendars and aggregates their events per module.
+///
+/// ```no_run
+/// use fh_kiel_ical_splitter::{Config, Scraper};
+///
+/// let mut scraper = Scraper::new(Config::default())?;
+/// for (key, module) in scraper.collect_events()? {
+///     println!("{key}: {} events", module.events.len());
+/// }
+/// # Ok::<(), fh_kiel_ical_splitter::Error>(())
+/// ```
+pub struct Scraper {
+    pipeline: Pipeline,
+}
+
+impl Scraper {
+    /// Creates a scraper using the real HTTP client and cache folder.
+    pub fn new(config: Config) -> Result<Self> {
+        Ok(Self {
+            pipeline: Pipeline::new(config, Box::new(SystemClock), Box::new(ThreadSleeper))?,
+        })
+    }
+
+    /// Scrapes, downloads and aggregates the calendars without writing any output.
+    pub fn collect_events(&mut self) -> Result<BTreeMap<ModuleKey, ModuleCalendar>> {
+        let links = self.pipeline.scrape()?;
+        let downloads = self.pipeline.download(links);
+        let modules = self.pipeline.aggregate(downloads)?;
+
+        modules
+            .into_iter()
+            .map(|(key, mut entry)| {
+                self.pipeline.restore_events(&key, &mut entry)?;
+                Ok((key, ModuleCalendar::from_entry(entry)))
+            })
+            .collect()
+    }
+
+    /// Runs everything the command line tool does and returns the exit code of the process.
+    pub fn run(self) -> Result<ExitCode> {
+        self.pipeline.run()
+    }
+}
+
+impl ModuleCalendar {
+    /// Returns the next `count` events which haven't ended at `now`, ordered by their start.
+    #[must_use]
+    pub fn upcoming_events(&self, now: DateTime<Utc>, count: usize) -> Vec<&Event> {
+        let mut events: Vec<&Event> = self
+            .events
+            .iter()
+            .filter(|event| event.end.unwrap_or(event.start) > now)
+            .collect();
+        events.sort_by_key(|event| event.start);
+        events.truncate(count);
+
+        events
+    }
+
+    fn from_entry(entry: CalendarEntry) -> Self {
+        let events = entry.events.iter().filter_map(Event::from_ical).collect();
+
+        Self {
+            display_name: entry.display_name,
+            department: entry.department,
+            semester: entry.semester,
+            group: entry.group_dir,
+            events,
+        }
+    }
+}
+
+impl Event {
+    fn from_ical(event: &IcalEvent) -> Option<Self> {
+        let optional_string = |name: &str| {
+            props::get_value(event, name)
+                .map(str::trim)
+                .map(str::to_owned)
+        };
+
+        Some(Self {
+            summary: optional_string(PROPERTY_NAME_SUMMARY).unwrap_or_default(),
+            start: parse_zoned_date_time(event, PROPERTY_NAME_DTSTART)?,
+            end: parse_zoned_date_time(event, PROPERTY_NAME_DTEND),
+            location: optional_string(PROPERTY_NAME_LOCATION).filter(|value| !value.is_empty()),
+            source: optional_string(PROPERTY_NAME_SOURCE),
+        })
+    }
+}
+
+/// Loads the module calendars written by a previous run from the output folder `folder`.
+///
+/// The manifest is used to find them. The group of the modules is the label shown on the index, calendars
+/// split by month and tombstones are left out.
+pub fn load_output(folder: &Path) -> Result<BTreeMap<ModuleKey, ModuleCalendar>> {
+    let paths = manifest::read_manifest_paths(&folder.join(MANIFEST_FILE))?;
+    let calendar_paths: BTreeSet<&str> = paths
+        .iter()
+        .map(String::as_str)
+        .filter(|path| scrape::is_ics_url(path))
+        .collect();
+
+    let mut modules = BTreeMap::new();
+    for path in &calendar_paths {
+        // The calendars of single months are next to the calendar of their module
+        let is_month_file = Path::new(path)
+            .parent()
+            .and_then(Path::to_str)
+            .is_some_and(|parent| calendar_paths.contains(format!("{parent}.ics").as_str()));
+        if is_month_file {
+            continue;
+        }
+
+        let content = std::fs::read_to_string(folder.join(path))?;
+        let Some(Ok(ical_calendar)) = ical::IcalParser::new(content.as_bytes()).next() else {
+            warn!("Skipping '{path}', it is no valid calendar");
+            continue;
+        };
+
+        let Some([department, semester, group, display_name]) = ical_calendar
+            .properties
+            .iter()
+            .find(|property| property.name == PROPERTY_NAME_PRODUCT_ID)
+            .and_then(|property| property.value.as_deref())
+            .and_then(calendar::parse_module_product_id)
+        else {
+            continue;
+        };
+
+        // Modules with the same name are only split into several calendars by --group-by
+        let mut key = names::canonical_key(display_name);
+        if modules.contains_key(&key) {
+            key = GroupBy::ModuleGroup.key_for(display_name, semester, group);
+        }
+
+        modules.insert(
+            key,
+            ModuleCalendar {
+                display_name: display_name.to_owned(),
+                department: department.to_owned(),
+                semester: semester.to_owned(),
+                group: group.to_owned(),
+                events: ical_calendar
+                    .events
+                    .iter()
+                    .filter_map(Event::from_ical)
+                    .collect(),
+            },
+        );
+    }
+
+    Ok(modules)
+}
+
+/// Parses a date-time property, respecting its `TZID` parameter and the UTC marker.
+fn parse_zoned_date_time(event: &IcalEvent, name: &str) -> Option<DateTime<Tz>> {
+    let value = props::get_value(event, name)?;
+    let date_time = datetime::parse_date_time(value)?;
+
+    if value.trim().ends_with('Z') {
+        return Some(Tz::UTC.from_utc_datetime(&date_time));
+    }
+
+    let time_zone = props::get_param(event, name, "TZID")
+        .and_then(|tzid| tzid.parse::<Tz>().ok())
+        .unwrap_or(DEFAULT_TIME_ZONE);
+
+    time_zone.from_local_datetime(&date_time).earliest()
+}
+
+/// Removes entries from the download cache, either all of them or the ones whose URL matches
+/// `url_pattern`. Returns the URLs of the removed entries.
+pub fn clear_cache(url_pattern: Option<&str>, all: bool) -> Result<Vec<String>> {
+    let selection = match (url_pattern, all) {
+        (_, true) => ClearSelection::All,
+        (Some(url_pattern), false) => ClearSelection::UrlPattern(
+            RegexBuilder::new(url_pattern)
+                .case_insensitive(true)
+                .build()?,
+        ),
+        (None, false) => return Err(Error::NothingToClear),
+    };
+
+    let cache = Cache::new(Path::new(CACHE_FOLDER))?;
+
+    cache.clear(&selection)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::CacheMetadata;
+    use crate::testing;
+
+    #[test]
+    fn clear_cache_refuses_without_a_selection() {
+        assert!(
+            matches!(clear_cache(None, false), Err(Error::NothingToClear)),
+            "nothing should be removed without --url-pattern or --all"
+        );
+    }
+
+    #[test]
+    fn clear_cache_matches_the_url_pattern_ignoring_case() {
+        testing::in_working_dir("clear-cache", |_| {
+            let cache = Cache::new(Path::new(CACHE_FOLDER)).unwrap();
+            for url in [
+                "https://fh-kalender.de/Informatik/plan.ics",
+                "https://fh-kalender.de/Bau/plan.ics",
+            ] {
+                cache.store(url, url, &CacheMetadata::for_url(url)).unwrap();
+            }
+
+            let removed = clear_cache(Some("informatik"), false).unwrap();
+
+            assert_eq!(
+                removed,
+                ["https://fh-kalender.de/Informatik/plan.ics"],
+                "the pattern should match regardless of case"
+            );
+            assert_eq!(
+                clear_cache(None, true).unwrap(),
+                ["https://fh-kalender.de/Bau/plan.ics"],
+                "--all should remove the rest"
+            );
+        });
+    }
+}
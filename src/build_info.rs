@@ -0,0 +1,84 @@
+//! Version information embedded in every output, to tell which build and run produced it
+
+use crate::manifest::sha256_hex;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+/// Version of the program.
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Short hash of the git commit the program was built from, `unknown` outside of a checkout.
+pub const GIT_SHA: &str = env!("GIT_SHORT_SHA");
+
+/// Identifies the program and its build, e.g. `fh_kiel_ical_splitter v0.1.0 (git 1a2b3c4)`.
+pub const GENERATOR: &str = concat!(
+    env!("CARGO_PKG_NAME"),
+    " v",
+    env!("CARGO_PKG_VERSION"),
+    " (git ",
+    env!("GIT_SHORT_SHA"),
+    ")"
+);
+
+/// Describes the build and the run which produced the output.
+#[derive(Debug, Clone, Serialize)]
+pub struct BuildInfo {
+    pub generator: &'static str,
+    pub version: &'static str,
+    pub git_sha: &'static str,
+    pub run_timestamp: DateTime<Utc>,
+    /// Hash of the command line arguments of the run.
+    pub config_hash: String,
+}
+
+impl BuildInfo {
+    /// Collects the build information for a run started at `run_timestamp`.
+    pub fn new(arguments: &[String], run_timestamp: DateTime<Utc>) -> Self {
+        Self {
+            generator: GENERATOR,
+            version: VERSION,
+            git_sha: GIT_SHA,
+            run_timestamp,
+            config_hash: sha256_hex(arguments.join("\0").as_bytes()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use regex::Regex;
+
+    #[test]
+    fn generator_names_the_program_version_and_commit() {
+        let format =
+            Regex::new(r"^fh_kiel_ical_splitter v\d+\.\d+\.\d+ \(git ([0-9a-f]+|unknown)\)$")
+                .unwrap();
+
+        assert!(
+            format.is_match(GENERATOR),
+            "the generator should have the documented format: {GENERATOR}"
+        );
+        assert!(
+            GENERATOR.contains(VERSION) && GENERATOR.contains(GIT_SHA),
+            "the generator should contain the version and the git hash: {GENERATOR}"
+        );
+    }
+
+    #[test]
+    fn config_hash_depends_only_on_the_arguments() {
+        let arguments = ["--strip-names".to_owned(), "--no-cache".to_owned()];
+        let first = BuildInfo::new(&arguments, Utc::now());
+        let second = BuildInfo::new(&arguments, Utc::now());
+        let other = BuildInfo::new(&arguments[..1], Utc::now());
+
+        assert_eq!(
+            first.config_hash, second.config_hash,
+            "the same arguments should have the same hash"
+        );
+        assert_ne!(
+            first.config_hash, other.config_hash,
+            "different arguments should have a different hash"
+        );
+    }
+}
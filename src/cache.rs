@@ -0,0 +1,599 @@
+//! On-disk cache of downloaded websites
+
+use crate::fetch::FetchResponse;
+use crate::manifest::sha256_hex;
+use crate::paths;
+use crate::prelude::*;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::time::Duration;
+
+/// Folder the cache lives in unless overridden.
+pub const CACHE_FOLDER: &str = ".cache";
+
+/// Extension of the sidecar files holding the metadata of a cache entry.
+const METADATA_EXTENSION: &str = "meta";
+
+/// Extension of the files an entry is written to before it is renamed into place.
+const TEMPORARY_EXTENSION: &str = "tmp";
+
+/// Number of locks the entries are spread over. Entries sharing a lock only wait for each other.
+const KEY_LOCK_STRIPES: usize = 16;
+
+/// Metadata stored next to every cached file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CacheMetadata {
+    /// The URL the content was downloaded from.
+    pub url: String,
+    /// `ETag` header of the response, used for conditional requests.
+    #[serde(default)]
+    pub etag: Option<String>,
+    /// `Last-Modified` header of the response, used for conditional requests.
+    #[serde(default)]
+    pub last_modified: Option<String>,
+    /// `Content-Length` header of the response, compared by the HEAD probe.
+    #[serde(default)]
+    pub content_length: Option<String>,
+}
+
+impl CacheMetadata {
+    /// Metadata which only knows the URL.
+    pub fn for_url(url: &str) -> Self {
+        Self {
+            url: url.to_owned(),
+            etag: None,
+            last_modified: None,
+            content_length: None,
+        }
+    }
+
+    /// Collects the metadata worth keeping from a response.
+    pub fn from_response(response: &FetchResponse) -> Self {
+        let header = |name: &str| response.header(name).map(str::to_owned);
+
+        Self {
+            url: response.url.clone(),
+            etag: header("etag"),
+            last_modified: header("last-modified"),
+            content_length: header("content-length"),
+        }
+    }
+
+    /// Whether the headers of a HEAD response show that the cached content is still current. At
+    /// least one of `ETag` and `Last-Modified` has to be known on both sides, every header known
+    /// on both sides has to match.
+    pub fn matches_head(&self, head: &FetchResponse) -> bool {
+        if !head.status().is_success() {
+            return false;
+        }
+
+        let compare = |cached: Option<&String>, name: &str| {
+            cached
+                .zip(head.header(name))
+                .map(|(cached, current)| cached == current)
+        };
+        let etag = compare(self.etag.as_ref(), "etag");
+        let last_modified = compare(self.last_modified.as_ref(), "last-modified");
+        let content_length = compare(self.content_length.as_ref(), "content-length");
+
+        (etag.is_some() || last_modified.is_some())
+            && [etag, last_modified, content_length]
+                .into_iter()
+                .all(|matches| matches.unwrap_or(true))
+    }
+}
+
+/// Which entries `cache clear` removes.
+#[derive(Debug)]
+pub enum ClearSelection {
+    All,
+    UrlPattern(Regex),
+}
+
+/// File written to check whether the cache folder is writable.
+const WRITE_PROBE_FILE: &str = ".write-probe";
+
+/// Stores the body of every successful download in a folder, keyed by URL.
+///
+/// Safe to share between threads: an entry is only read or written while holding its lock, so
+/// the content and metadata read always belong together, and entries are written to a temporary
+/// file first, so a reader never sees a partially written entry.
+#[derive(Debug)]
+pub struct Cache {
+    folder: PathBuf,
+    /// A disabled cache never has any content and forgets everything stored in it.
+    enabled: bool,
+    key_locks: [Mutex<()>; KEY_LOCK_STRIPES],
+}
+
+impl Cache {
+    /// Opens the cache in `folder`, creating the folder if it doesn't exist yet.
+    pub fn new(folder: &Path) -> Result<Self> {
+        std::fs::create_dir_all(folder)?;
+
+        Ok(Self {
+            folder: folder.to_owned(),
+            enabled: true,
+            key_locks: std::array::from_fn(|_| Mutex::new(())),
+        })
+    }
+
+    /// Opens the cache in `folder`, falling back to a disabled cache if the folder can't be
+    /// written, e.g. in a read-only checkout.
+    pub fn open_or_disable(folder: &Path) -> Self {
+        match Self::new(folder).and_then(|cache| cache.check_writable().map(|()| cache)) {
+            Ok(cache) => cache,
+            Err(err) => {
+                warn!(
+                    "Cache folder '{}' is not writable, running without cache: {err}",
+                    folder.display()
+                );
+                Self::disabled()
+            }
+        }
+    }
+
+    /// Returns a cache which downloads always bypass.
+    pub fn disabled() -> Self {
+        Self {
+            folder: PathBuf::new(),
+            enabled: false,
+            key_locks: std::array::from_fn(|_| Mutex::new(())),
+        }
+    }
+
+    fn check_writable(&self) -> Result<()> {
+        let probe_file = self.folder.join(WRITE_PROBE_FILE);
+        std::fs::write(&probe_file, b"")?;
+        std::fs::remove_file(&probe_file)?;
+
+        Ok(())
+    }
+
+    /// Cache files are named after the hash of the URL, since URLs contain characters like `:` and
+    /// `?` which aren't valid in file names on every platform.
+    fn path_for(&self, url: &str) -> PathBuf {
+        self.folder.join(sha256_hex(url.as_bytes()))
+    }
+
+    /// Locks the entry of `cache_file`. The lock only guards files, so a poisoned one is fine to
+    /// use. The stripe is always in range, so this never returns `None`.
+    fn lock_entry(&self, cache_file: &Path) -> MutexGuard<'_, ()> {
+        // The file names are hashes already, so their first byte spreads the entries evenly
+        let stripe = cache_file
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| name.get(..2))
+            .and_then(|prefix| usize::from_str_radix(prefix, 16).ok())
+            .unwrap_or_default()
+            % KEY_LOCK_STRIPES;
+
+        // The remainder always names a stripe, so falling back to the first one never happens
+        let [first, ..] = &self.key_locks;
+        self.key_locks
+            .get(stripe)
+            .unwrap_or(first)
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn metadata_path_for(cache_file: &Path) -> PathBuf {
+        let mut file_name = cache_file.as_os_str().to_owned();
+        file_name.push(".");
+        file_name.push(METADATA_EXTENSION);
+
+        PathBuf::from(file_name)
+    }
+
+    /// Returns the cached content for `url`, unless there is none or it is older than `max_age`.
+    pub fn load(&self, url: &str, max_age: Option<Duration>) -> Result<Option<String>> {
+        if !self.enabled {
+            return Ok(None);
+        }
+
+        let cache_file = self.path_for(url);
+        let _guard = self.lock_entry(&cache_file);
+
+        if !cache_file.exists() || !is_cache_file_fresh(&cache_file, max_age)? {
+            return Ok(None);
+        }
+
+        Ok(Some(std::fs::read_to_string(cache_file)?))
+    }
+
+    /// Returns the cached content for `url` together with its metadata regardless of its age.
+    pub fn load_with_metadata(&self, url: &str) -> Result<Option<(String, CacheMetadata)>> {
+        if !self.enabled {
+            return Ok(None);
+        }
+
+        let cache_file = self.path_for(url);
+        let _guard = self.lock_entry(&cache_file);
+        if !cache_file.exists() {
+            return Ok(None);
+        }
+
+        let content = std::fs::read_to_string(&cache_file)?;
+
+        Ok(Some((content, Self::read_metadata(url, &cache_file))))
+    }
+
+    /// Returns the metadata stored for `url`.
+    pub fn load_metadata(&self, url: &str) -> CacheMetadata {
+        if !self.enabled {
+            return CacheMetadata::for_url(url);
+        }
+
+        let cache_file = self.path_for(url);
+        let _guard = self.lock_entry(&cache_file);
+
+        Self::read_metadata(url, &cache_file)
+    }
+
+    /// Reads the metadata of the entry `cache_file` of `url`, whose lock must be held.
+    fn read_metadata(url: &str, cache_file: &Path) -> CacheMetadata {
+        // Entries written before the metadata existed only know their URL
+        std::fs::read_to_string(Self::metadata_path_for(cache_file))
+            .ok()
+            .and_then(|metadata| serde_json::from_str(&metadata).ok())
+            .unwrap_or_else(|| CacheMetadata::for_url(url))
+    }
+
+    /// Stores `content` as the cached content for `url`.
+    pub fn store(&self, url: &str, content: &str, metadata: &CacheMetadata) -> Result<()> {
+        if !self.enabled {
+            return Ok(());
+        }
+
+        let cache_file = self.path_for(url);
+        let _guard = self.lock_entry(&cache_file);
+        paths::write_atomically(&cache_file, content)?;
+        paths::write_atomically(
+            &Self::metadata_path_for(&cache_file),
+            &serde_json::to_string(metadata)?,
+        )?;
+
+        Ok(())
+    }
+
+    /// Removes the selected entries and returns a description of every removed entry.
+    pub fn clear(&self, selection: &ClearSelection) -> Result<Vec<String>> {
+        let mut removed = Vec::new();
+
+        for dir_entry in std::fs::read_dir(&self.folder)? {
+            let cache_file = dir_entry?.path();
+            if !cache_file.is_file()
+                || cache_file.ends_with(WRITE_PROBE_FILE)
+                || cache_file.extension().is_some_and(|extension| {
+                    extension == METADATA_EXTENSION || extension == TEMPORARY_EXTENSION
+                })
+            {
+                continue;
+            }
+
+            let metadata_file = Self::metadata_path_for(&cache_file);
+            let url = std::fs::read_to_string(&metadata_file)
+                .ok()
+                .and_then(|content| serde_json::from_str::<CacheMetadata>(&content).ok())
+                .map(|metadata| metadata.url);
+
+            let selected = match selection {
+                ClearSelection::All => true,
+                ClearSelection::UrlPattern(pattern) => {
+                    url.as_deref().is_some_and(|url| pattern.is_match(url))
+                }
+            };
+            if !selected {
+                continue;
+            }
+
+            std::fs::remove_file(&cache_file)?;
+            if metadata_file.exists() {
+                std::fs::remove_file(&metadata_file)?;
+            }
+
+            removed.push(url.unwrap_or_else(|| cache_file.display().to_string()));
+        }
+
+        removed.sort();
+
+        Ok(removed)
+    }
+}
+
+fn is_cache_file_fresh(cache_file: &Path, max_age: Option<Duration>) -> Result<bool> {
+    let Some(max_age) = max_age else {
+        return Ok(true);
+    };
+
+    let modified = std::fs::metadata(cache_file)?.modified()?;
+
+    // A modification time in the future is treated as fresh
+    Ok(modified.elapsed().map_or(true, |age| age <= max_age))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+    use std::sync::Arc;
+    use std::thread;
+
+    fn cache_in(name: &str) -> (Cache, PathBuf) {
+        let folder = testing::temp_dir(&format!("cache-{name}"));
+
+        (Cache::new(&folder).unwrap(), folder)
+    }
+
+    fn metadata(url: &str, etag: &str) -> CacheMetadata {
+        CacheMetadata {
+            etag: Some(etag.to_owned()),
+            ..CacheMetadata::for_url(url)
+        }
+    }
+
+    #[test]
+    fn concurrent_stores_of_different_urls_are_all_kept() {
+        let (cache, folder) = cache_in("different-urls");
+        let cache = Arc::new(cache);
+
+        let threads: Vec<_> = (0..8)
+            .map(|thread| {
+                let cache = Arc::clone(&cache);
+                thread::spawn(move || {
+                    for page in 0..16 {
+                        let url = format!("https://example.org/{thread}/{page}");
+                        cache.store(&url, &url, &metadata(&url, &url)).unwrap();
+                    }
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+
+        for thread in 0..8 {
+            for page in 0..16 {
+                let url = format!("https://example.org/{thread}/{page}");
+                let (content, metadata) = cache.load_with_metadata(&url).unwrap().unwrap();
+                assert_eq!(content, url, "content of {url} should be kept");
+                assert_eq!(
+                    metadata.etag.as_deref(),
+                    Some(url.as_str()),
+                    "metadata of {url} should be kept"
+                );
+            }
+        }
+
+        std::fs::remove_dir_all(folder).unwrap();
+    }
+
+    #[test]
+    fn concurrent_readers_see_content_and_metadata_of_the_same_store() {
+        let (cache, folder) = cache_in("same-url");
+        let cache = Arc::new(cache);
+        let url = "https://example.org/plan.html";
+        cache.store(url, "0", &metadata(url, "0")).unwrap();
+
+        let writers: Vec<_> = (0..4)
+            .map(|writer| {
+                let cache = Arc::clone(&cache);
+                thread::spawn(move || {
+                    for round in 0..50 {
+                        let version = (writer * 50 + round).to_string();
+                        cache
+                            .store(url, &version, &metadata(url, &version))
+                            .unwrap();
+                    }
+                })
+            })
+            .collect();
+        let readers: Vec<_> = (0..4)
+            .map(|_| {
+                let cache = Arc::clone(&cache);
+                thread::spawn(move || {
+                    for _ in 0..50 {
+                        let (content, metadata) = cache.load_with_metadata(url).unwrap().unwrap();
+                        assert_eq!(
+                            metadata.etag.as_deref(),
+                            Some(content.as_str()),
+                            "content and metadata should belong to the same store"
+                        );
+                    }
+                })
+            })
+            .collect();
+        for thread in writers.into_iter().chain(readers) {
+            thread.join().unwrap();
+        }
+
+        std::fs::remove_dir_all(folder).unwrap();
+    }
+
+    #[test]
+    fn clear_ignores_temporary_files() {
+        let (cache, folder) = cache_in("temporary-files");
+        let url = "https://example.org/plan.html";
+        cache
+            .store(url, "plan", &CacheMetadata::for_url(url))
+            .unwrap();
+        std::fs::write(folder.join("unfinished.tmp"), "").unwrap();
+
+        let removed = cache.clear(&ClearSelection::All).unwrap();
+
+        assert_eq!(removed.len(), 1, "only the entry should be removed");
+        assert!(
+            folder.join("unfinished.tmp").exists(),
+            "temporary files should be left alone"
+        );
+
+        std::fs::remove_dir_all(folder).unwrap();
+    }
+
+    #[test]
+    fn clear_by_url_pattern_removes_only_matching_entries() {
+        let (cache, folder) = cache_in("url-pattern");
+        let urls = [
+            "https://fh-kalender.de/files/informatik/plan.ics",
+            "https://fh-kalender.de/files/informatik/labor.ics",
+            "https://fh-kalender.de/files/maschinenwesen/plan.ics",
+        ];
+        for url in urls {
+            cache.store(url, url, &CacheMetadata::for_url(url)).unwrap();
+        }
+
+        let removed = cache
+            .clear(&ClearSelection::UrlPattern(
+                Regex::new(r"/informatik/\w+\.ics$").unwrap(),
+            ))
+            .unwrap();
+
+        assert_eq!(
+            removed,
+            [
+                "https://fh-kalender.de/files/informatik/labor.ics",
+                "https://fh-kalender.de/files/informatik/plan.ics"
+            ],
+            "matching entries should be reported by their URL"
+        );
+        for url in urls {
+            assert_eq!(
+                cache.load(url, None).unwrap().is_some(),
+                !url.contains("/informatik/"),
+                "only matching entries should be removed, checking {url}"
+            );
+        }
+        assert_eq!(
+            std::fs::read_dir(&folder).unwrap().count(),
+            2,
+            "the metadata of removed entries should be removed as well"
+        );
+
+        std::fs::remove_dir_all(folder).unwrap();
+    }
+
+    #[test]
+    fn clear_by_url_pattern_keeps_entries_without_metadata() {
+        let (cache, folder) = cache_in("without-metadata");
+        let url = "https://fh-kalender.de/files/plan.ics";
+        cache
+            .store(url, "plan", &CacheMetadata::for_url(url))
+            .unwrap();
+        std::fs::remove_file(Cache::metadata_path_for(&cache.path_for(url))).unwrap();
+
+        let pattern = ClearSelection::UrlPattern(Regex::new(r"\w+\.ics$").unwrap());
+        assert_eq!(
+            cache.clear(&pattern).unwrap(),
+            Vec::<String>::new(),
+            "an entry of unknown URL should never match a pattern"
+        );
+        assert_eq!(
+            cache.clear(&ClearSelection::All).unwrap().len(),
+            1,
+            "clearing everything should remove it"
+        );
+
+        std::fs::remove_dir_all(folder).unwrap();
+    }
+
+    #[test]
+    fn unwritable_folder_disables_the_cache() {
+        let folder = testing::temp_dir("cache-unwritable");
+        // A file in the way makes the folder impossible to create, even for root
+        std::fs::write(folder.join("file"), "").unwrap();
+        let cache = Cache::open_or_disable(&folder.join("file").join("cache"));
+        let url = "https://example.org/plan.html";
+
+        cache
+            .store(url, "plan", &CacheMetadata::for_url(url))
+            .unwrap();
+
+        assert!(!cache.enabled, "the cache should be disabled");
+        assert_eq!(
+            cache.load(url, None).unwrap(),
+            None,
+            "nothing should be stored"
+        );
+
+        std::fs::remove_dir_all(folder).unwrap();
+    }
+
+    #[test]
+    fn cache_file_names_are_valid_on_every_platform() {
+        let (cache, folder) = cache_in("file-names");
+        let url = "https://fh-kalender.de/files/plan.ics?week=1&lang=de";
+
+        let file_name = cache.path_for(url).file_name().unwrap().to_owned();
+
+        assert!(
+            file_name
+                .to_str()
+                .unwrap()
+                .chars()
+                .all(|c| c.is_ascii_hexdigit()),
+            "'{}' should only contain hex digits",
+            file_name.display()
+        );
+        cache
+            .store(url, "plan", &CacheMetadata::for_url(url))
+            .unwrap();
+        assert_eq!(
+            cache.load(url, None).unwrap().as_deref(),
+            Some("plan"),
+            "the entry should be stored under that name"
+        );
+
+        std::fs::remove_dir_all(folder).unwrap();
+    }
+
+    #[test]
+    fn head_responses_match_only_with_a_validator_known_on_both_sides() {
+        let metadata = CacheMetadata {
+            etag: Some("\"v1\"".to_owned()),
+            last_modified: None,
+            content_length: Some("120".to_owned()),
+            ..CacheMetadata::for_url("https://fh-kalender.test/a.ics")
+        };
+        let head = |status_code: u16, headers: &[(&str, &str)]| FetchResponse {
+            headers: headers
+                .iter()
+                .map(|(name, value)| ((*name).to_owned(), (*value).to_owned()))
+                .collect(),
+            ..testing::response("https://fh-kalender.test/a.ics", status_code, "")
+        };
+
+        let cases = [
+            (
+                head(200, &[("etag", "\"v1\""), ("content-length", "120")]),
+                true,
+            ),
+            (head(200, &[("etag", "\"v1\"")]), true),
+            (
+                head(200, &[("etag", "\"v2\""), ("content-length", "120")]),
+                false,
+            ),
+            (
+                head(200, &[("etag", "\"v1\""), ("content-length", "121")]),
+                false,
+            ),
+            (
+                head(200, &[("last-modified", "Wed, 12 Mar 2025 10:00:00 GMT")]),
+                false,
+            ),
+            (head(200, &[("content-length", "120")]), false),
+            (head(405, &[("etag", "\"v1\"")]), false),
+        ];
+
+        for (response, expected) in cases {
+            assert_eq!(
+                metadata.matches_head(&response),
+                expected,
+                "{:?} with status {}",
+                response.headers,
+                response.status_code
+            );
+        }
+    }
+}
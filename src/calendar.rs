@@ -0,0 +1,632 @@
+//! Aggregated module calendars
+
+use crate::manifest::sha256_hex;
+use crate::props;
+use chrono::{DateTime, NaiveDate, Utc};
+use ical::parser::ical::component::IcalEvent;
+use ical::property::Property;
+use std::collections::{BTreeMap, BTreeSet};
+
+pub const PROPERTY_NAME_SUMMARY: &str = "SUMMARY";
+pub const PROPERTY_NAME_DTSTART: &str = "DTSTART";
+pub const PROPERTY_NAME_DTEND: &str = "DTEND";
+pub const PROPERTY_NAME_LOCATION: &str = "LOCATION";
+pub const PROPERTY_NAME_STATUS: &str = "STATUS";
+pub const PROPERTY_NAME_SOURCE: &str = "X-FH-SOURCE";
+
+pub const STATUS_CANCELLED: &str = "CANCELLED";
+
+/// Summary prefixes which mark an event as cancelled unless overridden on the command line.
+pub const CANCELLED_SUMMARY_PREFIXES: &[&str] = &["Entfällt:", "Abgesagt:"];
+pub const PROPERTY_NAME_UID: &str = "UID";
+pub const PROPERTY_NAME_SEQUENCE: &str = "SEQUENCE";
+pub const PROPERTY_NAME_DTSTAMP: &str = "DTSTAMP";
+pub const PROPERTY_NAME_CALENDAR_DESCRIPTION: &str = "X-WR-CALDESC";
+pub const PROPERTY_NAME_CALENDAR_NAME: &str = "X-WR-CALNAME";
+pub const PROPERTY_NAME_LAST_CHANGED: &str = "X-LAST-CHANGED";
+pub const PROPERTY_NAME_GENERATOR: &str = "X-GENERATOR";
+pub const PROPERTY_NAME_ALSO_LISTED_IN: &str = "X-FH-ALSO-LISTED-IN";
+pub const PROPERTY_NAME_DESCRIPTION: &str = "DESCRIPTION";
+pub const PROPERTY_NAME_UNDATED: &str = "X-FH-UNDATED";
+pub const PROPERTY_NAME_IGNORED: &str = "X-FH-IGNORED";
+pub const PROPERTY_NAME_TRANSPARENCY: &str = "TRANSP";
+pub const PROPERTY_NAME_PRODUCT_ID: &str = "PRODID";
+pub const TRANSPARENCY_OPAQUE: &str = "OPAQUE";
+pub const TRANSPARENCY_TRANSPARENT: &str = "TRANSPARENT";
+
+/// Summary prefix of events which are kept on a placeholder date because they had no DTSTART.
+pub const UNDATED_SUMMARY_PREFIX: &str = "[DATUM UNBEKANNT]";
+
+/// Summary prefix of events matching the ignore list which are kept with `--annotate-ignored`,
+/// unless overridden on the command line.
+pub const IGNORED_SUMMARY_PREFIX: &str = "[AUSGEBLENDET]";
+
+/// Start of the PRODID of the module calendars, followed by where the module comes from.
+const MODULE_PRODUCT_ID_PREFIX: &str = "-//Veranstaltungsplan der FH Kiel//";
+
+/// All events of a single module, collected from every source calendar.
+#[derive(Debug)]
+pub struct CalendarEntry {
+    pub events: Vec<IcalEvent>,
+    pub display_name: String,
+    pub department: String,
+    /// Semester the module belongs to, e.g. `WiSe_2425`.
+    pub semester: String,
+    pub group_dir: String,
+    pub base_url: String,
+    /// Latest `Last-Modified` of the source calendars contributing to the module.
+    pub source_last_modified: Option<DateTime<Utc>>,
+    /// Number of events each group directory the module is listed in contributed.
+    pub group_dir_events: BTreeMap<String, u32>,
+    /// Texts of the links to the source calendars, in the order they were found.
+    pub link_texts: Vec<String>,
+    /// Whether `--strip-names` removed a lecturer name from `display_name`.
+    pub name_stripped: bool,
+}
+
+impl CalendarEntry {
+    /// Moves the module to the group directory most of its events come from, e.g. when a retake
+    /// course is listed in several semesters. Ties keep the directory the module was found in
+    /// first.
+    pub fn settle_group_dir(&mut self) {
+        let current = self
+            .group_dir_events
+            .get(&self.group_dir)
+            .copied()
+            .unwrap_or_default();
+
+        if let Some((group_dir, _)) = self
+            .group_dir_events
+            .iter()
+            .filter(|(_, events)| **events > current)
+            .max_by_key(|(_, events)| **events)
+        {
+            self.group_dir = group_dir.clone();
+        }
+    }
+
+    /// Records the text of the link to a source calendar unless it is already known.
+    pub fn add_link_text(&mut self, link_text: Option<&str>) {
+        if let Some(link_text) = link_text {
+            if !self.link_texts.iter().any(|known| known == link_text) {
+                self.link_texts.push(link_text.to_owned());
+            }
+        }
+    }
+
+    /// Returns the group directories the module is listed in apart from its own.
+    pub fn other_group_dirs(&self) -> Vec<&str> {
+        self.group_dir_events
+            .keys()
+            .filter(|group_dir| **group_dir != self.group_dir)
+            .map(String::as_str)
+            .collect()
+    }
+}
+
+/// Module calendars keyed by the canonical module name.
+pub type Modules = BTreeMap<String, CalendarEntry>;
+
+pub fn is_event_already_present(new_event: &IcalEvent, events: &Vec<IcalEvent>) -> bool {
+    let new_event_start = props::get_value(new_event, PROPERTY_NAME_DTSTART);
+    let new_event_end = props::get_value(new_event, PROPERTY_NAME_DTEND);
+
+    for event in events {
+        let event_start = props::get_value(event, PROPERTY_NAME_DTSTART);
+        let event_end = props::get_value(event, PROPERTY_NAME_DTEND);
+
+        // Cancelled events only count as duplicates of other cancelled events
+        if new_event_start == event_start
+            && new_event_end == event_end
+            && is_cancelled(new_event) == is_cancelled(event)
+        {
+            return true;
+        }
+    }
+
+    false
+}
+
+/// Returns the UID of `event`, adding a stable one derived from the module and start time if the
+/// event has none. Events of a module starting at the same time are numbered in the order they
+/// are passed, `generated` collects the UIDs generated for the module so far.
+pub fn ensure_uid(
+    event: &mut IcalEvent,
+    module_key: &str,
+    generated: &mut BTreeSet<String>,
+) -> String {
+    if let Some(uid) = props::get_value(event, PROPERTY_NAME_UID) {
+        return uid.to_owned();
+    }
+
+    let start = props::get_value(event, PROPERTY_NAME_DTSTART).unwrap_or_default();
+    let hash = sha256_hex(format!("{module_key}\n{start}").as_bytes());
+    let mut uid = format!("{hash}@fh_kiel_ical_splitter");
+    let mut number = 1;
+    while !generated.insert(uid.clone()) {
+        number += 1;
+        uid = format!("{hash}-{number}@fh_kiel_ical_splitter");
+    }
+
+    props::upsert_property(event, PROPERTY_NAME_UID, uid.clone());
+
+    uid
+}
+
+/// Hashes the content of `event`, ignoring the order of its properties as well as the properties
+/// which change without the event changing (SEQUENCE and DTSTAMP).
+pub fn event_content_hash(event: &IcalEvent) -> String {
+    let mut lines: Vec<String> = event
+        .properties
+        .iter()
+        .filter(|p| p.name != PROPERTY_NAME_SEQUENCE && p.name != PROPERTY_NAME_DTSTAMP)
+        .map(property_line)
+        .collect();
+    lines.sort_unstable();
+
+    sha256_hex(lines.join("\n").as_bytes())
+}
+
+/// Formats every property of `event` the way it appears in an iCalendar file.
+pub fn property_lines(event: &IcalEvent) -> Vec<String> {
+    event.properties.iter().map(property_line).collect()
+}
+
+/// Formats a property in iCalendar notation, without line folding.
+pub fn property_line(property: &Property) -> String {
+    let params = property
+        .params
+        .iter()
+        .flatten()
+        .map(|(name, values)| format!(";{name}={}", values.join(",")))
+        .collect::<Vec<_>>()
+        .concat();
+
+    format!(
+        "{}{params}:{}",
+        property.name,
+        property.value.as_deref().unwrap_or_default()
+    )
+}
+
+/// Properties emitted first, in this order. All other properties follow sorted by name.
+const CANONICAL_PROPERTY_ORDER: [&str; 7] = [
+    PROPERTY_NAME_UID,
+    PROPERTY_NAME_DTSTAMP,
+    PROPERTY_NAME_DTSTART,
+    PROPERTY_NAME_DTEND,
+    PROPERTY_NAME_SUMMARY,
+    PROPERTY_NAME_LOCATION,
+    PROPERTY_NAME_DESCRIPTION,
+];
+
+/// Brings the properties of `event` into a canonical order, so the output doesn't depend on the
+/// order of the upstream exports. Values and parameters are left untouched, properties with the
+/// same name keep their relative order.
+pub fn sort_properties(event: &mut IcalEvent) {
+    event.properties.sort_by(|a, b| {
+        property_rank(&a.name)
+            .cmp(&property_rank(&b.name))
+            .then_with(|| a.name.cmp(&b.name))
+    });
+}
+
+fn property_rank(name: &str) -> usize {
+    CANONICAL_PROPERTY_ORDER
+        .iter()
+        .position(|canonical| *canonical == name)
+        .unwrap_or(CANONICAL_PROPERTY_ORDER.len())
+}
+
+/// Sets the SEQUENCE of `event`, replacing any existing value.
+pub fn set_sequence(event: &mut IcalEvent, sequence: u32) {
+    props::upsert_property(event, PROPERTY_NAME_SEQUENCE, sequence.to_string());
+}
+
+/// Returns `true` if the event has `STATUS:CANCELLED`.
+pub fn is_cancelled(event: &IcalEvent) -> bool {
+    event.properties.iter().any(|p| {
+        p.name == PROPERTY_NAME_STATUS
+            && p.value
+                .as_deref()
+                .is_some_and(|value| value.trim().eq_ignore_ascii_case(STATUS_CANCELLED))
+    })
+}
+
+/// Sets `STATUS:CANCELLED` on the event, replacing any other status.
+pub fn mark_cancelled(event: &mut IcalEvent) {
+    props::upsert_property(event, PROPERTY_NAME_STATUS, STATUS_CANCELLED.to_owned());
+}
+
+/// Strips the first matching cancellation prefix from `summary`, ignoring case. Returns `None` if
+/// no prefix matches.
+pub fn strip_cancelled_prefix(summary: &str, prefixes: &[String]) -> Option<String> {
+    let folded_summary = summary.to_lowercase();
+
+    prefixes
+        .iter()
+        .find(|prefix| folded_summary.starts_with(&prefix.to_lowercase()))
+        .map(|prefix| {
+            summary
+                .chars()
+                .skip(prefix.chars().count())
+                .collect::<String>()
+                .trim()
+                .to_owned()
+        })
+}
+
+/// Records the path of the source calendar an event was parsed from.
+pub fn set_source(event: &mut IcalEvent, source_path: &str) {
+    props::upsert_property(event, PROPERTY_NAME_SOURCE, source_path.to_owned());
+}
+
+/// Marks whether the event blocks busy time, unless the upstream already decided.
+pub fn set_default_transparency(event: &mut IcalEvent, is_transparent: bool) {
+    if props::get_value(event, PROPERTY_NAME_TRANSPARENCY).is_some() {
+        return;
+    }
+
+    let transparency = if is_transparent {
+        TRANSPARENCY_TRANSPARENT
+    } else {
+        TRANSPARENCY_OPAQUE
+    };
+    props::upsert_property(event, PROPERTY_NAME_TRANSPARENCY, transparency.to_owned());
+}
+
+/// Places an event without a DTSTART on `date` as an all-day event and marks it as undated.
+pub fn set_placeholder_date(event: &mut IcalEvent, date: NaiveDate) {
+    let date_param = || Some(vec![("VALUE".to_owned(), vec!["DATE".to_owned()])]);
+
+    props::remove_property(event, PROPERTY_NAME_DTSTART);
+    props::remove_property(event, PROPERTY_NAME_DTEND);
+    event.properties.push(Property {
+        name: PROPERTY_NAME_DTSTART.to_owned(),
+        params: date_param(),
+        value: Some(date.format("%Y%m%d").to_string()),
+    });
+    event.properties.push(Property {
+        name: PROPERTY_NAME_DTEND.to_owned(),
+        params: date_param(),
+        value: Some(date.succ_opt().unwrap_or(date).format("%Y%m%d").to_string()),
+    });
+    props::upsert_property(event, PROPERTY_NAME_UNDATED, "TRUE".to_owned());
+}
+
+/// Marks an event matching the ignore `pattern` which is kept for auditing. It never blocks busy
+/// time, regardless of what the upstream decided.
+pub fn mark_ignored(event: &mut IcalEvent, pattern: &str) {
+    props::upsert_property(event, PROPERTY_NAME_IGNORED, pattern.to_owned());
+    props::upsert_property(
+        event,
+        PROPERTY_NAME_TRANSPARENCY,
+        TRANSPARENCY_TRANSPARENT.to_owned(),
+    );
+}
+
+/// Returns `true` if the event was kept by `mark_ignored` although it matches the ignore list.
+pub fn is_ignored(event: &IcalEvent) -> bool {
+    props::get_value(event, PROPERTY_NAME_IGNORED).is_some()
+}
+
+/// Returns `true` if the event was placed on a placeholder date by `set_placeholder_date`.
+pub fn is_undated(event: &IcalEvent) -> bool {
+    props::get_value(event, PROPERTY_NAME_UNDATED).is_some()
+}
+
+/// Start of the first calendar in a file.
+const BEGIN_CALENDAR: &str = "BEGIN:VCALENDAR";
+
+/// Returns the PRODID of a module calendar, which records where the module comes from.
+pub fn module_product_id(
+    department: &str,
+    semester: &str,
+    group_label: &str,
+    module: &str,
+) -> String {
+    format!("{MODULE_PRODUCT_ID_PREFIX}{department}//{semester}//{group_label}//{module}//")
+}
+
+/// Splits a PRODID written by `module_product_id` into department, semester, group label and
+/// module name. Returns `None` for the PRODID of other calendars like tombstones.
+pub fn parse_module_product_id(product_id: &str) -> Option<[&str; 4]> {
+    let fields = product_id
+        .strip_prefix(MODULE_PRODUCT_ID_PREFIX)?
+        .strip_suffix("//")?;
+    let mut fields = fields.splitn(4, "//");
+
+    Some([
+        fields.next()?,
+        fields.next()?,
+        fields.next()?,
+        fields.next()?,
+    ])
+}
+
+/// Returns `true` if `content` contains a calendar anywhere, like `sanitize_source` looks for it.
+pub fn contains_calendar(content: &str) -> bool {
+    content.to_ascii_uppercase().contains(BEGIN_CALENDAR)
+}
+
+/// Cleans up a downloaded calendar file before parsing, since the parser rejects the whole file
+/// on a leading BOM or anything in front of the first `BEGIN:VCALENDAR`. Lone `\n` line endings
+/// are turned into `\r\n`.
+///
+/// Returns the cleaned content and whatever was stripped from its start.
+pub fn sanitize_source(content: &str) -> (String, &str) {
+    let without_bom = content.strip_prefix('\u{feff}').unwrap_or(content);
+
+    // Files without any calendar are left for the parser to complain about
+    let start = without_bom
+        .to_ascii_uppercase()
+        .find(BEGIN_CALENDAR)
+        .unwrap_or(0);
+    let calendar = without_bom.get(start..).unwrap_or(without_bom);
+
+    let mut normalized = String::with_capacity(calendar.len());
+    let mut previous = None;
+    for character in calendar.chars() {
+        if character == '\n' && previous != Some('\r') {
+            normalized.push('\r');
+        }
+        normalized.push(character);
+        previous = Some(character);
+    }
+
+    let stripped = content
+        .get(..content.len().saturating_sub(calendar.len()))
+        .unwrap_or_default();
+
+    (normalized, stripped)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(start: &str, location: &str) -> IcalEvent {
+        let mut event = IcalEvent::new();
+        props::upsert_property(&mut event, PROPERTY_NAME_DTSTART, start.to_owned());
+        props::upsert_property(&mut event, PROPERTY_NAME_LOCATION, location.to_owned());
+
+        event
+    }
+
+    /// Generates the UIDs of events given as `(start, location)`, all in the same module.
+    fn uids(events: &[(&str, &str)]) -> Vec<String> {
+        let mut generated = BTreeSet::new();
+        events
+            .iter()
+            .map(|(start, location)| {
+                ensure_uid(&mut event(start, location), "mathe", &mut generated)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn generated_uids_do_not_depend_on_the_room() {
+        assert_eq!(
+            uids(&[("20241014T081500", "C-12.01")]),
+            uids(&[("20241014T081500", "C-13.02")]),
+            "a room change should keep the UID, so the SEQUENCE can be bumped"
+        );
+        assert_ne!(
+            uids(&[("20241014T081500", "C-12.01")]),
+            uids(&[("20241021T081500", "C-12.01")]),
+            "events at different times should have different UIDs"
+        );
+    }
+
+    #[test]
+    fn events_at_the_same_time_get_distinct_stable_uids() {
+        let events = [
+            ("20241014T081500", "C-12.01"),
+            ("20241014T081500", "C-13.02"),
+            ("20241014T081500", "C-14.03"),
+        ];
+
+        let first = uids(&events);
+
+        assert_eq!(
+            first.iter().collect::<BTreeSet<_>>().len(),
+            3,
+            "parallel events should not share a UID"
+        );
+        assert_eq!(first, uids(&events), "the UIDs should be stable");
+    }
+
+    #[test]
+    fn upstream_uids_are_kept() {
+        let mut event = event("20241014T081500", "C-12.01");
+        props::upsert_property(&mut event, PROPERTY_NAME_UID, "upstream".to_owned());
+
+        assert_eq!(
+            ensure_uid(&mut event, "mathe", &mut BTreeSet::new()),
+            "upstream",
+            "the UID of the source should be used"
+        );
+    }
+
+    /// Event with the properties given as `(name, value)`, with a parameter on the start.
+    fn event_with(properties: &[(&str, &str)]) -> IcalEvent {
+        let mut event = IcalEvent::new();
+        event.properties = properties
+            .iter()
+            .map(|(name, value)| Property {
+                name: (*name).to_owned(),
+                params: (*name == PROPERTY_NAME_DTSTART)
+                    .then(|| vec![("TZID".to_owned(), vec!["Europe/Berlin".to_owned()])]),
+                value: Some((*value).to_owned()),
+            })
+            .collect();
+
+        event
+    }
+
+    #[test]
+    fn properties_are_sorted_into_the_canonical_order() {
+        let mut event = event_with(&[
+            ("X-FH-SOURCE", "a.ics"),
+            ("CATEGORIES", "Vorlesung"),
+            (PROPERTY_NAME_DESCRIPTION, "Raumänderung"),
+            (PROPERTY_NAME_LOCATION, "C-12.01"),
+            ("CATEGORIES", "Pflicht"),
+            (PROPERTY_NAME_SUMMARY, "Mathe"),
+            (PROPERTY_NAME_DTEND, "20241014T094500"),
+            (PROPERTY_NAME_DTSTART, "20241014T081500"),
+            (PROPERTY_NAME_DTSTAMP, "20241001T000000Z"),
+            (PROPERTY_NAME_UID, "mathe-1"),
+        ]);
+        let mut original = property_lines(&event);
+
+        sort_properties(&mut event);
+
+        let sorted = property_lines(&event);
+        assert_eq!(
+            sorted,
+            [
+                "UID:mathe-1",
+                "DTSTAMP:20241001T000000Z",
+                "DTSTART;TZID=Europe/Berlin:20241014T081500",
+                "DTEND:20241014T094500",
+                "SUMMARY:Mathe",
+                "LOCATION:C-12.01",
+                "DESCRIPTION:Raumänderung",
+                "CATEGORIES:Vorlesung",
+                "CATEGORIES:Pflicht",
+                "X-FH-SOURCE:a.ics",
+            ],
+            "properties with the same name should keep their relative order"
+        );
+        original.sort();
+        let mut sorted = sorted;
+        sorted.sort();
+        assert_eq!(
+            original, sorted,
+            "no property should be lost, changed or duplicated"
+        );
+    }
+
+    #[test]
+    fn sorting_does_not_depend_on_the_upstream_order() {
+        let properties = [
+            (PROPERTY_NAME_SUMMARY, "Mathe"),
+            (PROPERTY_NAME_UID, "mathe-1"),
+            ("TRANSP", "OPAQUE"),
+            (PROPERTY_NAME_DTSTART, "20241014T081500"),
+            ("CLASS", "PUBLIC"),
+        ];
+        let mut forward = event_with(&properties);
+        let reversed: Vec<_> = properties.iter().rev().copied().collect();
+        let mut backward = event_with(&reversed);
+
+        sort_properties(&mut forward);
+        sort_properties(&mut backward);
+
+        assert_eq!(
+            property_lines(&forward),
+            property_lines(&backward),
+            "the order should be the same for any upstream order"
+        );
+    }
+
+    /// Parses the events of `content` after sanitizing it, failing the test on a parser error.
+    fn parse_sanitized(content: &str) -> (Vec<IcalEvent>, String) {
+        let (content, stripped) = sanitize_source(content);
+        let events = ical::IcalParser::new(content.as_bytes())
+            .flat_map(|calendar| calendar.unwrap().events)
+            .collect();
+
+        (events, stripped.to_owned())
+    }
+
+    const CALENDAR: &str = "BEGIN:VCALENDAR\r\nVERSION:2.0\r\nBEGIN:VEVENT\r\nUID:1\r\nSUMMARY:Mathe\r\nDTSTART:20241014T081500\r\nEND:VEVENT\r\nEND:VCALENDAR\r\n";
+
+    #[test]
+    fn leading_bom_is_stripped() {
+        let (events, stripped) = parse_sanitized(&format!("\u{feff}{CALENDAR}"));
+
+        assert_eq!(events.len(), 1, "the event should be parsed");
+        assert_eq!(stripped, "\u{feff}", "the BOM should be reported");
+    }
+
+    #[test]
+    fn blank_lines_and_junk_before_the_calendar_are_stripped() {
+        let (events, stripped) = parse_sanitized(&format!("\r\n\nExport vom 12.03.\r\n{CALENDAR}"));
+
+        assert_eq!(events.len(), 1, "the event should be parsed");
+        assert_eq!(
+            stripped, "\r\n\nExport vom 12.03.\r\n",
+            "everything before the calendar should be reported"
+        );
+    }
+
+    #[test]
+    fn lone_line_feeds_are_normalized() {
+        let line_feeds_only = CALENDAR.replace("\r\n", "\n");
+
+        let (content, stripped) = sanitize_source(&line_feeds_only);
+
+        assert_eq!(content, CALENDAR, "every line should end with CRLF");
+        assert_eq!(stripped, "", "nothing should be stripped");
+        assert_eq!(
+            parse_sanitized(&line_feeds_only).0.len(),
+            1,
+            "the event should be parsed"
+        );
+    }
+
+    /// A module found first in `group_dir`, with the number of events each group contributed.
+    fn listed_in(group_dir: &str, group_dir_events: &[(&str, u32)]) -> CalendarEntry {
+        CalendarEntry {
+            events: Vec::new(),
+            display_name: "Mathe".to_owned(),
+            department: "iue".to_owned(),
+            semester: "WiSe_2425".to_owned(),
+            group_dir: group_dir.to_owned(),
+            base_url: String::new(),
+            source_last_modified: None,
+            group_dir_events: group_dir_events
+                .iter()
+                .map(|(group_dir, events)| ((*group_dir).to_owned(), *events))
+                .collect(),
+            link_texts: Vec::new(),
+            name_stripped: false,
+        }
+    }
+
+    #[test]
+    fn group_dir_is_the_one_with_the_most_events() {
+        let mut entry = listed_in("semester_1", &[("semester_1", 1), ("semester_3", 3)]);
+
+        entry.settle_group_dir();
+
+        assert_eq!(
+            entry.group_dir, "semester_3",
+            "the majority group should win"
+        );
+        assert_eq!(
+            entry.other_group_dirs(),
+            ["semester_1"],
+            "the other group should be listed"
+        );
+    }
+
+    #[test]
+    fn ties_keep_the_group_dir_found_first() {
+        let mut entry = listed_in("semester_3", &[("semester_1", 2), ("semester_3", 2)]);
+
+        entry.settle_group_dir();
+
+        assert_eq!(
+            entry.group_dir, "semester_3",
+            "a tie shouldn't move the module"
+        );
+        assert_eq!(
+            entry.other_group_dirs(),
+            ["semester_1"],
+            "the other group should be listed"
+        );
+    }
+}
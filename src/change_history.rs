@@ -0,0 +1,244 @@
+//! Page listing the event changes of the last runs grouped by module, linked from the index as
+//! "Was ist neu?"
+
+use crate::datetime;
+use crate::feed::{self, EventChange, FeedEntry};
+use crate::index::{self, Language};
+use crate::prelude::*;
+use crate::scrape::escape_html;
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use std::fmt::Write as _;
+
+/// Path of the page in the output folder.
+pub const CHANGES_HTML_FILE: &str = "changes.html";
+
+/// Number of runs kept on the page unless overridden.
+pub const DEFAULT_CHANGE_HISTORY_RUNS: usize = 10;
+
+/// The changed events of a module in a single run.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ModuleChanges {
+    pub module: String,
+    /// Path of the module calendar in the output folder.
+    pub path: String,
+    pub changes: Vec<EventChange>,
+}
+
+impl From<&FeedEntry> for ModuleChanges {
+    fn from(entry: &FeedEntry) -> Self {
+        Self {
+            module: entry.module.clone(),
+            path: entry.path.clone(),
+            changes: entry.changes.clone(),
+        }
+    }
+}
+
+/// The changes of a single run, a run without changes has no modules.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChangeRun {
+    pub at: DateTime<Utc>,
+    pub modules: Vec<ModuleChanges>,
+}
+
+/// Adds `run` to the runs of previous runs, newest first, keeping the last `max_runs`. A run
+/// without changes is only added if there is no other one on the same day in `display_zone`, so
+/// frequent runs don't push the interesting ones off the page.
+pub fn update_history(
+    previous: &[ChangeRun],
+    run: ChangeRun,
+    display_zone: Tz,
+    max_runs: usize,
+) -> Vec<ChangeRun> {
+    let day = |run: &ChangeRun| run.at.with_timezone(&display_zone).date_naive();
+    let already_recorded = run.modules.is_empty()
+        && previous
+            .iter()
+            .any(|previous| previous.modules.is_empty() && day(previous) == day(&run));
+
+    let mut history: Vec<ChangeRun> = (!already_recorded)
+        .then_some(run)
+        .into_iter()
+        .chain(previous.iter().cloned())
+        .collect();
+
+    // Newest first
+    history.sort_by_key(|run| std::cmp::Reverse(run.at));
+    history.truncate(max_runs);
+
+    history
+}
+
+/// Renders the page, linking every module to its calendar below `link_prefix` and showing all
+/// times in `display_zone`.
+pub fn render_html(history: &[ChangeRun], link_prefix: &str, display_zone: Tz) -> Result<String> {
+    let mut body = String::new();
+
+    writeln!(
+        &mut body,
+        "<p><a href=\"{link_prefix}{}\">Zurück zur Übersicht</a></p>",
+        Language::German.file_name()
+    )?;
+
+    if history.is_empty() {
+        writeln!(&mut body, "<p>Noch keine Änderungen aufgezeichnet</p>")?;
+    }
+
+    for run in history {
+        writeln!(
+            &mut body,
+            "<h2>Lauf vom {}</h2>",
+            datetime::format_in_zone(run.at, display_zone, "%d.%m.%Y %H:%M")
+        )?;
+
+        if run.modules.is_empty() {
+            writeln!(&mut body, "<p>keine Änderungen</p>")?;
+            continue;
+        }
+
+        let mut modules: Vec<&ModuleChanges> = run.modules.iter().collect();
+        modules.sort_by(|a, b| a.module.cmp(&b.module));
+
+        for module in modules {
+            writeln!(
+                &mut body,
+                "<h3><a href=\"{link_prefix}{}\">{}</a></h3>\n<ul>",
+                escape_html(&module.path),
+                escape_html(&module.module)
+            )?;
+            for change in &module.changes {
+                writeln!(
+                    &mut body,
+                    "<li>{}</li>",
+                    escape_html(&feed::describe_change(change, display_zone))
+                )?;
+            }
+            writeln!(&mut body, "</ul>")?;
+        }
+    }
+
+    index::render_page(
+        Language::German,
+        "Änderungen der Vorlesungspläne der FH-Kiel",
+        "Was ist neu? – Vorlesungspläne der FH-Kiel",
+        "Was ist neu?",
+        &body,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::feed::EventChangeKind;
+    use chrono_tz::Europe::Berlin;
+
+    fn run(at: &str, modules: &[&str]) -> ChangeRun {
+        ChangeRun {
+            at: at.parse().unwrap(),
+            modules: modules
+                .iter()
+                .map(|module| ModuleChanges {
+                    module: (*module).to_owned(),
+                    path: format!("{module}.ics"),
+                    changes: vec![EventChange {
+                        kind: EventChangeKind::Added,
+                        start: "20241014T081500".to_owned(),
+                        previous_start: None,
+                    }],
+                })
+                .collect(),
+        }
+    }
+
+    fn times(history: &[ChangeRun]) -> Vec<String> {
+        history.iter().map(|run| run.at.to_rfc3339()).collect()
+    }
+
+    #[test]
+    fn history_keeps_the_newest_runs() {
+        let previous = [
+            run("2024-10-13T08:00:00Z", &["Physik"]),
+            run("2024-10-12T08:00:00Z", &["Chemie"]),
+            run("2024-10-11T08:00:00Z", &["Mathematik 1"]),
+        ];
+
+        let history = update_history(
+            &previous,
+            run("2024-10-14T08:00:00Z", &["Labor"]),
+            Berlin,
+            3,
+        );
+
+        assert_eq!(
+            times(&history),
+            [
+                "2024-10-14T08:00:00+00:00",
+                "2024-10-13T08:00:00+00:00",
+                "2024-10-12T08:00:00+00:00"
+            ],
+            "oldest run is dropped, newest first"
+        );
+    }
+
+    #[test]
+    fn run_without_changes_is_recorded_once_per_day() {
+        let previous = [run("2024-10-14T06:00:00Z", &[])];
+
+        let same_day = update_history(&previous, run("2024-10-14T18:00:00Z", &[]), Berlin, 10);
+        assert_eq!(
+            times(&same_day),
+            ["2024-10-14T06:00:00+00:00"],
+            "second empty run is dropped"
+        );
+
+        let with_changes = update_history(
+            &previous,
+            run("2024-10-14T18:00:00Z", &["Physik"]),
+            Berlin,
+            10,
+        );
+        assert_eq!(
+            with_changes.len(),
+            2,
+            "runs with changes are always recorded"
+        );
+
+        let next_day = update_history(&previous, run("2024-10-15T06:00:00Z", &[]), Berlin, 10);
+        assert_eq!(next_day.len(), 2, "empty run of the next day is recorded");
+    }
+
+    #[test]
+    fn day_of_an_empty_run_is_taken_in_the_display_zone() {
+        // 23:30 UTC is already the next day in Kiel
+        let previous = [run("2024-10-14T20:00:00Z", &[])];
+
+        let history = update_history(&previous, run("2024-10-14T23:30:00Z", &[]), Berlin, 10);
+
+        assert_eq!(
+            history.len(),
+            2,
+            "runs on different local days are both recorded"
+        );
+    }
+
+    #[test]
+    fn page_escapes_module_names() {
+        let html =
+            render_html(&[run("2024-10-14T08:00:00Z", &["C&A <Labor>"])], "", Berlin).unwrap();
+
+        assert!(
+            html.contains("<a href=\"C&amp;A &lt;Labor&gt;.ics\">C&amp;A &lt;Labor&gt;</a>"),
+            "module is escaped: {html}"
+        );
+        assert!(
+            html.contains("<h1>Was ist neu?</h1>"),
+            "page has a heading: {html}"
+        );
+        assert!(
+            html.contains("Lauf vom 14.10.2024 10:00"),
+            "run is shown in the display zone: {html}"
+        );
+    }
+}
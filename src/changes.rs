@@ -0,0 +1,194 @@
+//! Feed of module changes, one all-day event per module and day its calendar changed
+
+use crate::calendar::{
+    PROPERTY_NAME_DESCRIPTION, PROPERTY_NAME_DTEND, PROPERTY_NAME_DTSTAMP, PROPERTY_NAME_DTSTART,
+    PROPERTY_NAME_SUMMARY, PROPERTY_NAME_UID,
+};
+use crate::manifest::sha256_hex;
+use chrono::{Duration, NaiveDate};
+use ical::generator::{Emitter, IcalCalendarBuilder};
+use ical::parser::ical::component::IcalEvent;
+use ical::property::Property;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+/// Path of the change feed in the output folder.
+pub const CHANGES_FILE: &str = "meta/changes.ics";
+
+/// Number of days changes stay in the feed.
+const CHANGES_MAX_AGE_DAYS: i64 = 90;
+
+const PROPERTY_NAME_URL: &str = "URL";
+
+/// A day on which the calendar of a module changed.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChangeRecord {
+    /// Canonical name of the module.
+    pub key: String,
+    pub module: String,
+    /// Path of the module calendar in the output folder.
+    pub path: String,
+    pub date: NaiveDate,
+}
+
+/// Adds the changes of this run to the ones of previous runs, dropping changes older than the
+/// maximum age. A module changing twice on the same day is only recorded once.
+pub fn update_changes(
+    previous: &[ChangeRecord],
+    current: Vec<ChangeRecord>,
+    today: NaiveDate,
+) -> Vec<ChangeRecord> {
+    let oldest = today - Duration::days(CHANGES_MAX_AGE_DAYS);
+
+    let recorded: HashSet<(String, NaiveDate)> = current
+        .iter()
+        .map(|change| (change.key.clone(), change.date))
+        .collect();
+
+    let mut changes: Vec<ChangeRecord> = previous
+        .iter()
+        .filter(|previous| !recorded.contains(&(previous.key.clone(), previous.date)))
+        .cloned()
+        .chain(current)
+        .filter(|change| change.date >= oldest)
+        .collect();
+
+    // Newest first
+    changes.sort_by(|a, b| b.date.cmp(&a.date).then_with(|| a.key.cmp(&b.key)));
+
+    changes
+}
+
+/// Renders the change feed, linking every change to the module calendar below `link_prefix`.
+pub fn render_changes(changes: &[ChangeRecord], link_prefix: &str) -> String {
+    let mut calendar = IcalCalendarBuilder::version("2.0")
+        .gregorian()
+        .prodid("-//Veranstaltungsplan der FH Kiel//Änderungen//".to_owned())
+        .build();
+
+    for change in changes {
+        let date = change.date.format("%Y%m%d").to_string();
+        let next_date = change
+            .date
+            .succ_opt()
+            .unwrap_or(change.date)
+            .format("%Y%m%d");
+        let date_param = || Some(vec![("VALUE".to_owned(), vec!["DATE".to_owned()])]);
+        let link = format!("{link_prefix}{}", change.path);
+
+        let mut event = IcalEvent::new();
+        let mut push = |name: &str, params, value: String| {
+            event.properties.push(Property {
+                name: name.to_owned(),
+                params,
+                value: Some(value),
+            });
+        };
+
+        // Stable across runs, so calendar apps update instead of duplicating the event
+        push(
+            PROPERTY_NAME_UID,
+            None,
+            format!(
+                "{}-{date}@fh_kiel_ical_splitter",
+                sha256_hex(change.key.as_bytes())
+            ),
+        );
+        push(PROPERTY_NAME_DTSTAMP, None, format!("{date}T000000Z"));
+        push(PROPERTY_NAME_DTSTART, date_param(), date);
+        push(PROPERTY_NAME_DTEND, date_param(), next_date.to_string());
+        push(
+            PROPERTY_NAME_SUMMARY,
+            None,
+            format!("Geändert: {}", change.module),
+        );
+        push(
+            PROPERTY_NAME_DESCRIPTION,
+            None,
+            format!("Neuer Stand des Kalenders: {link}"),
+        );
+        push(PROPERTY_NAME_URL, None, link);
+
+        calendar.events.push(event);
+    }
+
+    calendar.generate()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn change(key: &str, date: NaiveDate) -> ChangeRecord {
+        ChangeRecord {
+            key: key.to_owned(),
+            module: key.to_owned(),
+            path: format!("{key}.ics"),
+            date,
+        }
+    }
+
+    fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    #[test]
+    fn changes_accumulate_over_several_runs() {
+        let first = date(2024, 3, 1);
+        let second = date(2024, 3, 2);
+        let third = date(2024, 3, 5);
+
+        let changes = update_changes(&[], vec![change("mathe", first)], first);
+        let changes = update_changes(&changes, vec![change("physik", second)], second);
+        let changes = update_changes(
+            &changes,
+            vec![change("mathe", third), change("physik", third)],
+            third,
+        );
+
+        assert_eq!(
+            changes,
+            vec![
+                change("mathe", third),
+                change("physik", third),
+                change("physik", second),
+                change("mathe", first),
+            ],
+            "changes should accumulate newest first"
+        );
+    }
+
+    #[test]
+    fn repeated_change_on_the_same_day_is_recorded_once() {
+        let today = date(2024, 3, 1);
+
+        let changes = update_changes(&[], vec![change("mathe", today)], today);
+        let changes = update_changes(&changes, vec![change("mathe", today)], today);
+
+        assert_eq!(
+            changes,
+            vec![change("mathe", today)],
+            "a module changing twice a day should only be recorded once"
+        );
+    }
+
+    #[test]
+    fn changes_expire_after_90_days() {
+        let first = date(2024, 1, 1);
+        let changes = update_changes(&[], vec![change("mathe", first)], first);
+
+        let last_day = first + Duration::days(CHANGES_MAX_AGE_DAYS);
+        let changes = update_changes(&changes, Vec::new(), last_day);
+        assert_eq!(
+            changes,
+            vec![change("mathe", first)],
+            "a change should be kept for 90 days"
+        );
+
+        let changes = update_changes(&changes, Vec::new(), last_day + Duration::days(1));
+        assert!(
+            changes.is_empty(),
+            "a change should be dropped after 90 days"
+        );
+    }
+}
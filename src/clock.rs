@@ -0,0 +1,116 @@
+//! Time sources and the global run deadline
+
+use chrono::{DateTime, Utc};
+use std::time::{Duration, Instant};
+
+/// Source of the current time, abstracted so the deadline handling can be driven by a fake clock.
+pub trait Clock: Send + Sync {
+    fn now(&self) -> Instant;
+
+    /// Returns the current date and time, for decisions depending on the calendar date.
+    fn utc_now(&self) -> DateTime<Utc>;
+}
+
+/// Clock backed by the operating system's monotonic clock.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn utc_now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+/// Point in time after which no new downloads should be started.
+pub struct Deadline {
+    clock: Box<dyn Clock>,
+    expires_at: Option<Instant>,
+}
+
+impl Deadline {
+    /// Creates a deadline which expires `max_runtime` after now, or never if `max_runtime` is `None`.
+    pub fn new(clock: Box<dyn Clock>, max_runtime: Option<Duration>) -> Self {
+        let expires_at = max_runtime.map(|max_runtime| clock.now() + max_runtime);
+
+        Self { clock, expires_at }
+    }
+
+    /// Returns the current time of the clock the deadline is measured with.
+    pub fn now(&self) -> Instant {
+        self.clock.now()
+    }
+
+    /// Returns the current date and time of the clock the deadline is measured with.
+    pub fn utc_now(&self) -> DateTime<Utc> {
+        self.clock.utc_now()
+    }
+
+    /// Returns `true` once the deadline has passed.
+    pub fn is_expired(&self) -> bool {
+        self.expires_at
+            .is_some_and(|expires_at| self.clock.now() >= expires_at)
+    }
+
+    /// Returns `true` if the deadline will have passed after waiting for `duration`.
+    pub fn expires_within(&self, duration: Duration) -> bool {
+        self.expires_at
+            .is_some_and(|expires_at| self.clock.now() + duration >= expires_at)
+    }
+}
+
+/// Waits for a given duration, abstracted so delays can be skipped when driven by a fake clock.
+pub trait Sleeper: Send + Sync {
+    fn sleep(&self, duration: Duration);
+}
+
+/// Sleeper which blocks the current thread.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ThreadSleeper;
+
+impl Sleeper for ThreadSleeper {
+    fn sleep(&self, duration: Duration) {
+        std::thread::sleep(duration);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::FakeClock;
+
+    #[test]
+    fn deadline_expires_after_max_runtime() {
+        let clock = FakeClock::new();
+        let deadline = Deadline::new(Box::new(clock.clone()), Some(Duration::from_secs(60)));
+
+        assert!(!deadline.is_expired(), "deadline should not expire right away");
+        assert!(
+            deadline.expires_within(Duration::from_secs(60)),
+            "waiting the whole runtime should reach the deadline"
+        );
+
+        clock.advance(Duration::from_secs(59));
+        assert!(!deadline.is_expired(), "deadline should not expire early");
+
+        clock.advance(Duration::from_secs(1));
+        assert!(deadline.is_expired(), "deadline should expire after 60 seconds");
+    }
+
+    #[test]
+    fn deadline_without_max_runtime_never_expires() {
+        let clock = FakeClock::new();
+        let deadline = Deadline::new(Box::new(clock.clone()), None);
+
+        clock.advance(Duration::from_secs(365 * 24 * 60 * 60));
+
+        assert!(!deadline.is_expired(), "deadline should never expire");
+        assert!(
+            !deadline.expires_within(Duration::MAX / 2),
+            "deadline should never be reached"
+        );
+    }
+}
@@ -0,0 +1,226 @@
+//! Adjustments of the generated calendars for clients with known quirks
+
+use crate::calendar::{PROPERTY_NAME_DTEND, PROPERTY_NAME_DTSTART};
+use crate::{datetime, props};
+use chrono::Duration;
+use ical::parser::ical::component::{IcalCalendar, IcalEvent};
+use ical::property::Property;
+
+/// Maximum length of a content line in octets, excluding the line break.
+const MAX_LINE_OCTETS: usize = 75;
+
+const PROPERTY_NAME_METHOD: &str = "METHOD";
+const METHOD_PUBLISH: &str = "PUBLISH";
+
+/// Clients whose quirks the output can be adjusted to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compat {
+    /// Outlook desktop garbles umlauts in badly folded lines, rejects events ending at their
+    /// start and wants to know the calendar is published rather than an invitation.
+    Outlook,
+}
+
+impl Compat {
+    /// Parses the client names accepted on the command line.
+    #[must_use]
+    pub fn parse(client: &str) -> Option<Self> {
+        match client {
+            "outlook" => Some(Self::Outlook),
+            _ => None,
+        }
+    }
+}
+
+/// Adds `METHOD:PUBLISH` to the calendar unless it has a method.
+pub fn ensure_publish_method(calendar: &mut IcalCalendar) {
+    if calendar
+        .properties
+        .iter()
+        .any(|property| property.name == PROPERTY_NAME_METHOD)
+    {
+        return;
+    }
+
+    calendar.properties.push(Property {
+        name: PROPERTY_NAME_METHOD.to_owned(),
+        params: None,
+        value: Some(METHOD_PUBLISH.to_owned()),
+    });
+}
+
+/// Removes control characters other than tabs from all property values, and makes events without
+/// a duration last one minute.
+pub fn adjust_event(event: &mut IcalEvent) {
+    for property in &mut event.properties {
+        if let Some(value) = &mut property.value {
+            value.retain(|c| c == '\t' || !c.is_control());
+        }
+    }
+
+    // All-day events always span at least a day
+    let start = props::get_value(event, PROPERTY_NAME_DTSTART);
+    let end = props::get_value(event, PROPERTY_NAME_DTEND);
+    let Some((start, end)) = start.zip(end) else {
+        return;
+    };
+    if start != end || datetime::is_date_only(start) {
+        return;
+    }
+
+    let is_utc = start.ends_with('Z');
+    if let Some(bumped_end) =
+        datetime::parse_date_time(start).map(|start| start + Duration::minutes(1))
+    {
+        let mut bumped_end = bumped_end.format("%Y%m%dT%H%M%S").to_string();
+        if is_utc {
+            bumped_end.push('Z');
+        }
+        props::set_value_preserving_params(event, PROPERTY_NAME_DTEND, bumped_end);
+    }
+}
+
+/// Folds the content lines again so no line is longer than 75 octets and no UTF-8 sequence is
+/// split across lines.
+pub fn refold(content: &str) -> String {
+    let unfolded = content.replace("\r\n ", "").replace("\r\n\t", "");
+    let mut folded = String::with_capacity(content.len());
+
+    for line in unfolded.split_inclusive("\r\n") {
+        let (line, line_break) = line
+            .strip_suffix("\r\n")
+            .map_or((line, ""), |line| (line, "\r\n"));
+
+        let mut octets = 0;
+        for character in line.chars() {
+            // Continuation lines start with a space, which counts towards their length
+            if octets + character.len_utf8() > MAX_LINE_OCTETS {
+                folded.push_str("\r\n ");
+                octets = 1;
+            }
+            folded.push(character);
+            octets += character.len_utf8();
+        }
+        folded.push_str(line_break);
+    }
+
+    folded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ical::generator::Emitter;
+
+    fn property(name: &str, value: &str) -> Property {
+        Property {
+            name: name.to_owned(),
+            params: None,
+            value: Some(value.to_owned()),
+        }
+    }
+
+    fn event(properties: &[(&str, &str)]) -> IcalEvent {
+        let mut event = IcalEvent::new();
+        event.properties = properties
+            .iter()
+            .map(|(name, value)| property(name, value))
+            .collect();
+
+        event
+    }
+
+    #[test]
+    fn problematic_events_are_adjusted() {
+        let mut local = event(&[
+            ("SUMMARY", "Mathe\u{7}\tÜbung\u{0}"),
+            ("DTSTART", "20241014T081500"),
+            ("DTEND", "20241014T081500"),
+        ]);
+        let mut utc = event(&[
+            ("DTSTART", "20241014T235930Z"),
+            ("DTEND", "20241014T235930Z"),
+        ]);
+
+        adjust_event(&mut local);
+        adjust_event(&mut utc);
+
+        assert_eq!(
+            local.generate(),
+            "BEGIN:VEVENT\r\nSUMMARY:Mathe\tÜbung\r\nDTSTART:20241014T081500\r\nDTEND:20241014T081600\r\nEND:VEVENT\r\n",
+            "control characters should be removed and the event should last a minute"
+        );
+        assert_eq!(
+            utc.generate(),
+            "BEGIN:VEVENT\r\nDTSTART:20241014T235930Z\r\nDTEND:20241015T000030Z\r\nEND:VEVENT\r\n",
+            "the end of a UTC event should stay in UTC"
+        );
+    }
+
+    #[test]
+    fn events_with_a_duration_are_kept() {
+        let properties = [
+            ("SUMMARY", "Mathe"),
+            ("DTSTART", "20241014T081500"),
+            ("DTEND", "20241014T094500"),
+        ];
+        let all_day = [("DTSTART", "20241014"), ("DTEND", "20241014")];
+
+        for properties in [&properties[..], &all_day[..]] {
+            let mut adjusted = event(properties);
+            adjust_event(&mut adjusted);
+
+            assert_eq!(
+                adjusted.generate(),
+                event(properties).generate(),
+                "{properties:?} shouldn't be changed"
+            );
+        }
+    }
+
+    #[test]
+    fn the_publish_method_is_added_once() {
+        let mut calendar = IcalCalendar::new();
+        calendar.properties.push(property("VERSION", "2.0"));
+
+        ensure_publish_method(&mut calendar);
+        ensure_publish_method(&mut calendar);
+
+        assert_eq!(
+            calendar.generate(),
+            "BEGIN:VCALENDAR\r\nVERSION:2.0\r\nMETHOD:PUBLISH\r\nEND:VCALENDAR\r\n",
+            "the method should be added once"
+        );
+
+        let mut request = IcalCalendar::new();
+        request.properties.push(property("METHOD", "REQUEST"));
+        ensure_publish_method(&mut request);
+        assert_eq!(
+            request.generate(),
+            "BEGIN:VCALENDAR\r\nMETHOD:REQUEST\r\nEND:VCALENDAR\r\n",
+            "an existing method should be kept"
+        );
+    }
+
+    #[test]
+    fn lines_are_folded_between_characters() {
+        let umlauts = "Ä".repeat(40);
+        let content = format!("BEGIN:VEVENT\r\nSUMMARY:{umlauts}\r\nEND:VEVENT\r\n");
+
+        let folded = refold(&content);
+
+        assert_eq!(
+            folded,
+            format!(
+                "BEGIN:VEVENT\r\nSUMMARY:{}\r\n {}\r\nEND:VEVENT\r\n",
+                "Ä".repeat(33),
+                "Ä".repeat(7)
+            ),
+            "the line should be folded before the character exceeding 75 octets"
+        );
+        assert_eq!(
+            refold(&folded),
+            folded,
+            "folding again should change nothing"
+        );
+    }
+}
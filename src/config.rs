@@ -0,0 +1,1098 @@
+//! Command line configuration
+
+use crate::anonymize::DEFAULT_NAME_PATTERNS;
+use crate::calendar::{CANCELLED_SUMMARY_PREFIXES, IGNORED_SUMMARY_PREFIX};
+use crate::change_history::DEFAULT_CHANGE_HISTORY_RUNS;
+use crate::compat::Compat;
+use crate::config_file::ConfigFile;
+use crate::contact::Contact;
+use crate::datetime::DEFAULT_DISPLAY_TIME_ZONE;
+use crate::event_filter::EventFilterRule;
+use crate::fetch::{DEFAULT_ERROR_PAGE_MARKERS, DEFAULT_MAX_RESPONSE_SIZE};
+use crate::ignore::{MatchMode, IGNORED_EVENT_NAMES, TRANSPARENT_EVENT_NAMES};
+use crate::lookup::DEFAULT_NEXT_COUNT;
+use crate::minify::DEFAULT_MINIFY_DENYLIST;
+use crate::names::GroupBy;
+use crate::politeness::{
+    DEFAULT_BURST_DELAY, DEFAULT_DUTY_CYCLE, DEFAULT_MAX_DELAY, DEFAULT_MIN_DELAY,
+};
+use crate::prelude::*;
+use crate::revision::DEFAULT_REVISION_PATTERN;
+use crate::scrape::DEFAULT_DEPARTMENTS_JSON_ID;
+use crate::template::{OutputTemplate, SummaryTemplate};
+use crate::tombstone::DEFAULT_TOMBSTONE_DAYS;
+use chrono::NaiveDate;
+use chrono_tz::Tz;
+use regex::Regex;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// Site which is scraped when no base URL is given.
+pub const DEFAULT_BASE_URL: &str = "https://fh-kalender.de/";
+
+/// Maximum length of calendar file names unless overridden.
+pub const DEFAULT_MAX_SLUG_LENGTH: usize = 100;
+
+/// Maximum length of the full path of a calendar file unless overridden, matching `MAX_PATH` on
+/// Windows.
+pub const DEFAULT_MAX_PATH_LENGTH: usize = 260;
+
+/// Number of retries shared by all downloads of a run unless overridden.
+pub const DEFAULT_RETRY_BUDGET: usize = 50;
+
+/// Growth in percent of the total output size compared to the previous run which is warned about
+/// unless overridden.
+pub const DEFAULT_MAX_SIZE_GROWTH: usize = 50;
+
+/// Largest gap between two events which are merged with `--merge-adjacent` unless overridden.
+pub const DEFAULT_MERGE_MAX_GAP: Duration = Duration::from_secs(15 * 60);
+
+/// Where the lecture week is added to the summary of an event.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WeekLabelPosition {
+    /// `VW 3: Mathematik 1`
+    Prefix,
+    /// `Mathematik 1 (VW 3)`
+    Suffix,
+}
+
+/// What the program should do.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    /// Scrape the websites and generate the calendars.
+    Generate,
+    /// Remove entries from the download cache.
+    CacheClear {
+        /// Remove the entries whose URL matches this regex.
+        url_pattern: Option<String>,
+        /// Remove every entry.
+        all: bool,
+    },
+    /// Check the published calendars against the local output.
+    Verify {
+        /// URL the output folder is published under.
+        public_base_url: Option<String>,
+        /// Check every calendar instead of a sample.
+        full: bool,
+    },
+    /// Show the upcoming events of a module from the local output.
+    Next {
+        /// Part of the name of the module.
+        module: String,
+        /// Number of events to show.
+        count: usize,
+    },
+}
+
+/// Settings for a single run, parsed from the command line.
+#[derive(Debug)]
+#[expect(
+    clippy::struct_excessive_bools,
+    reason = "every switch on the command line is a flag of its own"
+)]
+pub struct Config {
+    /// What to do, defaults to generating the calendars.
+    pub command: Command,
+
+    /// The arguments the configuration was parsed from, excluding the program name.
+    pub arguments: Vec<String>,
+
+    /// Sites to scrape, in order of preference. Always end with a slash.
+    pub base_urls: Vec<String>,
+
+    /// User agent sent with every request instead of the default one.
+    pub user_agent: Option<String>,
+
+    /// Contact information appended to the default user agent, e.g. `+mailto:me@example.com`.
+    pub contact: Option<String>,
+
+    /// Stop starting new downloads once the run has taken longer than this.
+    pub max_runtime: Option<Duration>,
+
+    /// Number of retries shared by all downloads, once used up failed downloads are not retried.
+    pub retry_budget: usize,
+
+    /// How long to wait for another run to finish, fail right away if `None`.
+    pub wait_for_lock: Option<Duration>,
+
+    /// Write a machine-readable report of the run to this path.
+    pub report: Option<PathBuf>,
+
+    /// Write the response metadata of every fetched calendar file as CSV to this path.
+    pub fetch_log: Option<PathBuf>,
+
+    /// Patterns of event names which should not end up in any calendar.
+    pub ignored_event_names: Vec<(MatchMode, String)>,
+
+    /// Patterns of module names whose calendars should not be published at all.
+    pub ignored_modules: Vec<(MatchMode, String)>,
+
+    /// Rules dropping events by module, summary, location, weekday or start time, see
+    /// `--drop-events`.
+    pub event_filters: Vec<EventFilterRule>,
+
+    /// Patterns of informational event names which get `TRANSP:TRANSPARENT`, all other events
+    /// get `TRANSP:OPAQUE` unless the upstream already set a value.
+    pub transparent_event_names: Vec<(MatchMode, String)>,
+
+    /// Append a `?v=<hash>` query parameter to the calendar links in the index.
+    pub cache_busting: bool,
+
+    /// Don't fetch or respect robots.txt, meant for testing against local fixtures.
+    pub ignore_robots: bool,
+
+    /// Fail the run on problems which are otherwise only reported.
+    pub strict: bool,
+
+    /// Fail the run on anomalies in the source events, like duplicate properties or odd dates.
+    pub strict_input: bool,
+
+    /// Parse every written calendar again with the `icalendar` crate and fail the run if it reads
+    /// the events differently. Requires the `cross_validate` feature.
+    pub cross_validate: bool,
+
+    /// Growth in percent of the total size of the calendars compared to the previous run which is
+    /// treated as a problem.
+    pub max_size_growth: usize,
+
+    /// Share of the wall time our requests may occupy, in percent. The delay after a request
+    /// grows with its response time.
+    pub duty_cycle: u32,
+
+    /// Shortest delay between two requests.
+    pub min_delay: Duration,
+
+    /// Longest delay between two requests.
+    pub max_delay: Duration,
+
+    /// Shortest delays after requests to URLs starting with the prefix, replacing `min_delay`.
+    /// The longest matching prefix wins.
+    pub prefix_delays: Vec<(String, Duration)>,
+
+    /// Number of network requests at the start of a run which use `burst_delay` instead, to
+    /// prime a cold cache quickly.
+    pub initial_burst: usize,
+
+    /// Delay between the requests of the initial burst.
+    pub burst_delay: Duration,
+
+    /// Show the underlying errors of failures which are otherwise summarized.
+    pub verbose: bool,
+
+    /// Print the exit codes and their meaning instead of running a command.
+    pub print_exit_codes: bool,
+
+    /// Time zone of the dates and times in the index, the text export and the feeds, independent
+    /// of the one of the machine running the splitter.
+    pub display_timezone: Tz,
+
+    /// Links on department pages matching this regex are crawled for more calendars. Defaults
+    /// to the internal links below the department page.
+    pub semester_page_pattern: Option<Regex>,
+
+    /// Calendar files which are always downloaded, even if no page links to them. Either
+    /// absolute URLs or paths starting with `/files/` below the first base URL.
+    pub pinned_sources: Vec<String>,
+
+    /// Regexes matched against the URLs of the calendar files, matching files are not downloaded.
+    pub excluded_sources: Vec<String>,
+
+    /// Regexes matched against successful responses, a match marks the response as an error page
+    /// which is retried and never cached.
+    pub error_page_markers: Vec<String>,
+
+    /// Largest response body accepted in bytes, larger responses fail without being cached.
+    pub max_response_size: u64,
+
+    /// Id of the script element on the main page holding the department list as JSON.
+    pub departments_json_id: String,
+
+    /// Summary prefixes marking an event as cancelled.
+    pub cancelled_summary_prefixes: Vec<String>,
+
+    /// Remove lecturer names from the summaries and descriptions of the events.
+    pub strip_names: bool,
+
+    /// Regexes matching the names removed with `strip_names`.
+    pub name_patterns: Vec<String>,
+
+    /// Regex matching the revision marker in the file names of calendars, only the newest
+    /// revision of a file is downloaded. `None` downloads every revision.
+    pub revision_pattern: Option<String>,
+
+    /// Drop cancelled events instead of emitting them with `STATUS:CANCELLED`.
+    pub drop_cancelled: bool,
+
+    /// Keep events without a DTSTART as all-day events on `undated_placeholder_date`.
+    pub keep_undated: bool,
+
+    /// Keep events matching the ignore list as transparent events with `ignored_summary_prefix`
+    /// in front of their summary, to check what the ignore list catches.
+    pub annotate_ignored: bool,
+
+    /// Summary prefix of the events kept with `annotate_ignored`.
+    pub ignored_summary_prefix: String,
+
+    /// Date events without a DTSTART are placed on when keeping them.
+    pub undated_placeholder_date: NaiveDate,
+
+    /// Merge back-to-back events with the same summary and location into a single event.
+    pub merge_adjacent: bool,
+
+    /// How finely events with the same name are split into calendars.
+    pub group_by: GroupBy,
+
+    /// Keep the events in per-module spill files instead of memory until they are written.
+    pub low_memory: bool,
+
+    /// Largest gap between two events which are still merged.
+    pub merge_max_gap: Duration,
+
+    /// Remove redundant properties from the generated calendars.
+    pub minify_ics: bool,
+
+    /// Property names removed when minifying, a trailing `*` matches any suffix.
+    pub minify_denylist: Vec<String>,
+
+    /// Add the lecture week, counted from the start of the semester, to the summary of every event.
+    pub academic_week: Option<WeekLabelPosition>,
+
+    /// First day of the lectures, keyed by the semester as it appears in the URLs, e.g.
+    /// `WiSe_2425`.
+    pub semester_starts: BTreeMap<String, NaiveDate>,
+
+    /// Contact added as organizer to the events without one, keyed by the department as it
+    /// appears in the URLs.
+    pub department_contacts: BTreeMap<String, Contact>,
+
+    /// Collect events without a name in a separate module instead of skipping them.
+    pub keep_unnamed: bool,
+
+    /// Maximum length of the file name of a module calendar, without extension.
+    pub max_slug_length: usize,
+
+    /// Maximum length of the full path of a calendar file, longer file names are truncated.
+    pub max_path_length: usize,
+
+    /// Send all requests through this proxy, credentials may be embedded in the URL.
+    pub proxy: Option<String>,
+
+    /// Credentials for the proxy as `<user>:<password>`.
+    pub proxy_user: Option<String>,
+
+    /// Additional root certificate in PEM format, e.g. of an intercepting proxy.
+    pub proxy_ca_cert: Option<PathBuf>,
+
+    /// Ignore the proxies configured in the environment, e.g. `HTTPS_PROXY`.
+    pub no_proxy: bool,
+
+    /// Lowercase hex SHA-256 fingerprints of the server certificates which are accepted. Every
+    /// certificate is accepted if empty.
+    pub pinned_cert_sha256: Vec<String>,
+
+    /// Save every response as a fixture in this folder.
+    pub record: Option<PathBuf>,
+
+    /// Serve all responses from the fixtures in this folder instead of the network.
+    pub replay: Option<PathBuf>,
+
+    /// Read the calendar files from this folder, laid out like the `/files/...` paths of the site,
+    /// instead of scraping the site.
+    pub source_dir: Option<PathBuf>,
+
+    /// Download calendar files without the query of their link, e.g. `?v=3`.
+    pub strip_ics_query: bool,
+
+    /// Neither read from nor write to the download cache.
+    pub no_cache: bool,
+
+    /// Check cached calendar files with a HEAD request first and skip the download if they are
+    /// unchanged.
+    pub head_probe: bool,
+
+    /// Store the events parsed from every calendar file, so unchanged files aren't parsed again.
+    pub parse_cache: bool,
+
+    /// How long cached listing pages are used without revalidation, `None` means forever.
+    pub html_cache_max_age: Option<Duration>,
+
+    /// How long cached calendar files are used without revalidation, `None` means forever.
+    pub ics_cache_max_age: Option<Duration>,
+
+    /// Don't record the source calendar of every event as `X-FH-SOURCE`.
+    pub no_provenance: bool,
+
+    /// Layout of the generated calendars below the output folder.
+    pub output_template: OutputTemplate,
+
+    /// Days a calendar which disappeared is replaced by a tombstone pointing to its new path.
+    /// Tombstones are disabled with 0.
+    pub tombstone_days: u32,
+
+    /// Number of runs whose changes are listed on the changes page.
+    pub change_history_runs: usize,
+
+    /// Modules with more events than this additionally get one calendar per month.
+    pub split_large_modules: Option<usize>,
+
+    /// Adjust the calendars to the quirks of this client.
+    pub compat: Option<Compat>,
+
+    /// Text appended to the summary of every event, e.g. `({department}, {group})`.
+    pub summary_suffix: Option<SummaryTemplate>,
+
+    /// Also write a plain-text weekly overview of every module into this folder.
+    pub text_export: Option<PathBuf>,
+
+    /// Write a self-contained HTML page with the events of every module to this path.
+    pub single_file_output: Option<PathBuf>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            command: Command::Generate,
+            arguments: Vec::new(),
+            base_urls: vec![DEFAULT_BASE_URL.to_owned()],
+            user_agent: None,
+            contact: None,
+            max_runtime: None,
+            retry_budget: DEFAULT_RETRY_BUDGET,
+            wait_for_lock: None,
+            report: None,
+            fetch_log: None,
+            ignored_event_names: IGNORED_EVENT_NAMES
+                .iter()
+                .map(|(mode, pattern)| (*mode, (*pattern).to_owned()))
+                .collect(),
+            ignored_modules: Vec::new(),
+            transparent_event_names: TRANSPARENT_EVENT_NAMES
+                .iter()
+                .map(|(mode, pattern)| (*mode, (*pattern).to_owned()))
+                .collect(),
+            cache_busting: false,
+            ignore_robots: false,
+            strict: false,
+            strict_input: false,
+            cross_validate: false,
+            strip_names: false,
+            name_patterns: DEFAULT_NAME_PATTERNS
+                .iter()
+                .map(|pattern| (*pattern).to_owned())
+                .collect(),
+            revision_pattern: Some(DEFAULT_REVISION_PATTERN.to_owned()),
+            max_size_growth: DEFAULT_MAX_SIZE_GROWTH,
+            duty_cycle: DEFAULT_DUTY_CYCLE,
+            min_delay: DEFAULT_MIN_DELAY,
+            max_delay: DEFAULT_MAX_DELAY,
+            prefix_delays: Vec::new(),
+            initial_burst: 0,
+            burst_delay: DEFAULT_BURST_DELAY,
+            verbose: false,
+            print_exit_codes: false,
+            display_timezone: DEFAULT_DISPLAY_TIME_ZONE,
+            semester_page_pattern: None,
+            pinned_sources: Vec::new(),
+            excluded_sources: Vec::new(),
+            error_page_markers: DEFAULT_ERROR_PAGE_MARKERS
+                .iter()
+                .map(|marker| (*marker).to_owned())
+                .collect(),
+            max_response_size: DEFAULT_MAX_RESPONSE_SIZE,
+            departments_json_id: DEFAULT_DEPARTMENTS_JSON_ID.to_owned(),
+            cancelled_summary_prefixes: CANCELLED_SUMMARY_PREFIXES
+                .iter()
+                .map(|prefix| (*prefix).to_owned())
+                .collect(),
+            drop_cancelled: false,
+            keep_undated: false,
+            annotate_ignored: false,
+            ignored_summary_prefix: IGNORED_SUMMARY_PREFIX.to_owned(),
+            undated_placeholder_date: NaiveDate::from_ymd_opt(1970, 1, 1).unwrap(),
+            merge_adjacent: false,
+            low_memory: false,
+            group_by: GroupBy::default(),
+            merge_max_gap: DEFAULT_MERGE_MAX_GAP,
+            minify_ics: false,
+            academic_week: None,
+            semester_starts: BTreeMap::new(),
+            department_contacts: BTreeMap::new(),
+            minify_denylist: DEFAULT_MINIFY_DENYLIST
+                .iter()
+                .map(|name| (*name).to_owned())
+                .collect(),
+            keep_unnamed: false,
+            max_slug_length: DEFAULT_MAX_SLUG_LENGTH,
+            max_path_length: DEFAULT_MAX_PATH_LENGTH,
+            proxy: None,
+            proxy_user: None,
+            proxy_ca_cert: None,
+            no_proxy: false,
+            pinned_cert_sha256: Vec::new(),
+            record: None,
+            replay: None,
+            source_dir: None,
+            no_cache: false,
+            strip_ics_query: false,
+            event_filters: Vec::new(),
+            parse_cache: false,
+            head_probe: false,
+            html_cache_max_age: Some(Duration::ZERO),
+            ics_cache_max_age: None,
+            no_provenance: false,
+            output_template: OutputTemplate::default(),
+            summary_suffix: None,
+            compat: None,
+            split_large_modules: None,
+            tombstone_days: DEFAULT_TOMBSTONE_DAYS,
+            change_history_runs: DEFAULT_CHANGE_HISTORY_RUNS,
+            text_export: None,
+            single_file_output: None,
+        }
+    }
+}
+
+impl Config {
+    /// Parses the configuration from the arguments the process was started with.
+    pub fn from_args() -> Result<Self> {
+        Self::parse(std::env::args().skip(1))
+    }
+
+    /// Parses the configuration from the given arguments, excluding the program name.
+    #[expect(
+        clippy::too_many_lines,
+        reason = "a single match with one arm per command line flag"
+    )]
+    pub fn parse<I>(args: I) -> Result<Self>
+    where
+        I: IntoIterator<Item = String>,
+    {
+        let mut config = Self {
+            arguments: args.into_iter().collect(),
+            ..Self::default()
+        };
+        let mut args = config.arguments.clone().into_iter().peekable();
+        let mut has_custom_base_url = false;
+
+        // Subcommands come first
+        if args.peek().map(String::as_str) == Some("cache") {
+            args.next();
+            match args.next() {
+                Some(subcommand) if subcommand == "clear" => {
+                    config.command = Command::CacheClear {
+                        url_pattern: None,
+                        all: false,
+                    };
+                }
+                Some(subcommand) => return Err(Error::InvalidArgument(subcommand)),
+                None => return Err(Error::MissingArgumentValue("cache".to_owned())),
+            }
+        } else if args.peek().map(String::as_str) == Some("verify") {
+            args.next();
+            config.command = Command::Verify {
+                public_base_url: None,
+                full: false,
+            };
+        } else if args.peek().map(String::as_str) == Some("next") {
+            args.next();
+            config.command = Command::Next {
+                module: next_value(&mut args, "next")?,
+                count: DEFAULT_NEXT_COUNT,
+            };
+        }
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                // Flags after the file override its settings
+                "--config" => {
+                    let path = PathBuf::from(next_value(&mut args, &arg)?);
+                    ConfigFile::load(&path)?.apply(&mut config);
+                }
+                "--base-url" => {
+                    let mut base_url = next_value(&mut args, &arg)?;
+                    if !base_url.ends_with('/') {
+                        base_url.push('/');
+                    }
+
+                    // The first custom base URL replaces the default one
+                    if !has_custom_base_url {
+                        config.base_urls.clear();
+                        has_custom_base_url = true;
+                    }
+                    if !config.base_urls.contains(&base_url) {
+                        config.base_urls.push(base_url);
+                    }
+                }
+                "--user-agent" => {
+                    config.user_agent = Some(next_value(&mut args, &arg)?);
+                }
+                "--contact" => {
+                    config.contact = Some(next_value(&mut args, &arg)?);
+                }
+                "--max-runtime" => {
+                    let value = next_value(&mut args, &arg)?;
+                    config.max_runtime = Some(parse_duration(&value)?);
+                }
+                "--max-size-growth" => {
+                    let value = next_value(&mut args, &arg)?;
+                    config.max_size_growth = value
+                        .parse()
+                        .map_err(|_| Error::InvalidArgumentValue(arg.clone(), value))?;
+                }
+                "--duty-cycle" => {
+                    let value = next_value(&mut args, &arg)?;
+                    config.duty_cycle = value
+                        .parse()
+                        .ok()
+                        .filter(|duty_cycle| (1..=100).contains(duty_cycle))
+                        .ok_or_else(|| Error::InvalidArgumentValue(arg.clone(), value))?;
+                }
+                "--min-delay" => {
+                    config.min_delay = parse_duration(&next_value(&mut args, &arg)?)?;
+                }
+                "--max-delay" => {
+                    config.max_delay = parse_duration(&next_value(&mut args, &arg)?)?;
+                }
+                "--prefix-delay" => {
+                    let value = next_value(&mut args, &arg)?;
+                    let Some((prefix, delay)) = value.split_once('=') else {
+                        return Err(Error::InvalidArgumentValue(arg, value));
+                    };
+                    config
+                        .prefix_delays
+                        .push((prefix.to_owned(), parse_duration(delay)?));
+                }
+                "--initial-burst" => {
+                    let value = next_value(&mut args, &arg)?;
+                    config.initial_burst = value
+                        .parse()
+                        .map_err(|_| Error::InvalidArgumentValue(arg.clone(), value))?;
+                }
+                "--burst-delay" => {
+                    config.burst_delay = parse_duration(&next_value(&mut args, &arg)?)?;
+                }
+                "--display-timezone" => {
+                    let value = next_value(&mut args, &arg)?;
+                    config.display_timezone = value
+                        .parse()
+                        .map_err(|_| Error::InvalidArgumentValue(arg.clone(), value))?;
+                }
+                "--max-response-size" => {
+                    let value = next_value(&mut args, &arg)?;
+                    config.max_response_size = parse_size(&value)
+                        .ok_or_else(|| Error::InvalidArgumentValue(arg.clone(), value))?;
+                }
+                "--retry-budget" => {
+                    let value = next_value(&mut args, &arg)?;
+                    config.retry_budget = value
+                        .parse()
+                        .map_err(|_| Error::InvalidArgumentValue(arg.clone(), value))?;
+                }
+                "--wait-for-lock" => {
+                    let value = next_value(&mut args, &arg)?;
+                    config.wait_for_lock = Some(parse_duration(&value)?);
+                }
+                "--html-cache-max-age" => {
+                    let value = next_value(&mut args, &arg)?;
+                    config.html_cache_max_age = parse_max_age(&value)?;
+                }
+                "--ics-cache-max-age" => {
+                    let value = next_value(&mut args, &arg)?;
+                    config.ics_cache_max_age = parse_max_age(&value)?;
+                }
+                "--report" => {
+                    config.report = Some(PathBuf::from(next_value(&mut args, &arg)?));
+                }
+                "--fetch-log" => {
+                    config.fetch_log = Some(PathBuf::from(next_value(&mut args, &arg)?));
+                }
+                "--proxy" => {
+                    config.proxy = Some(next_value(&mut args, &arg)?);
+                }
+                "--proxy-user" => {
+                    config.proxy_user = Some(next_value(&mut args, &arg)?);
+                }
+                "--proxy-ca-cert" => {
+                    config.proxy_ca_cert = Some(PathBuf::from(next_value(&mut args, &arg)?));
+                }
+                "--no-proxy" => config.no_proxy = true,
+                "--pinned-cert-sha256" => {
+                    let value = next_value(&mut args, &arg)?;
+                    let fingerprint = parse_fingerprint(&value)
+                        .ok_or_else(|| Error::InvalidArgumentValue(arg.clone(), value))?;
+                    config.pinned_cert_sha256.push(fingerprint);
+                }
+                "--record" => {
+                    config.record = Some(PathBuf::from(next_value(&mut args, &arg)?));
+                }
+                "--replay" => {
+                    config.replay = Some(PathBuf::from(next_value(&mut args, &arg)?));
+                }
+                "--source-dir" => {
+                    config.source_dir = Some(PathBuf::from(next_value(&mut args, &arg)?));
+                }
+                "--text-export" => {
+                    config.text_export = Some(PathBuf::from(next_value(&mut args, &arg)?));
+                }
+                "--single-file-output" => {
+                    config.single_file_output = Some(PathBuf::from(next_value(&mut args, &arg)?));
+                }
+                "--ignore" => {
+                    let value = next_value(&mut args, &arg)?;
+                    config.ignored_event_names.push(parse_name_pattern(&value)?);
+                }
+                "--ignore-module" => {
+                    let value = next_value(&mut args, &arg)?;
+                    config.ignored_modules.push(parse_name_pattern(&value)?);
+                }
+                "--transparent" => {
+                    let value = next_value(&mut args, &arg)?;
+                    config
+                        .transparent_event_names
+                        .push(parse_name_pattern(&value)?);
+                }
+                "--cache-busting" => config.cache_busting = true,
+                "--ignore-robots" => config.ignore_robots = true,
+                "--no-cache" => config.no_cache = true,
+                "--strip-ics-query" => config.strip_ics_query = true,
+                "--parse-cache" => config.parse_cache = true,
+                "--head-probe" => config.head_probe = true,
+                "--strict" => config.strict = true,
+                "--strict-input" => config.strict_input = true,
+                "--cross-validate" => config.cross_validate = true,
+                "--verbose" => config.verbose = true,
+                "--print-exit-codes" => config.print_exit_codes = true,
+                "--url-pattern" => match &mut config.command {
+                    Command::CacheClear { url_pattern, .. } => {
+                        *url_pattern = Some(next_value(&mut args, &arg)?);
+                    }
+                    Command::Generate | Command::Verify { .. } | Command::Next { .. } => {
+                        return Err(Error::InvalidArgument(arg))
+                    }
+                },
+                "--all" => match &mut config.command {
+                    Command::CacheClear { all, .. } => *all = true,
+                    Command::Generate | Command::Verify { .. } | Command::Next { .. } => {
+                        return Err(Error::InvalidArgument(arg))
+                    }
+                },
+                "--public-base-url" => match &mut config.command {
+                    Command::Verify {
+                        public_base_url, ..
+                    } => {
+                        let mut value = next_value(&mut args, &arg)?;
+                        if !value.ends_with('/') {
+                            value.push('/');
+                        }
+                        *public_base_url = Some(value);
+                    }
+                    Command::Generate | Command::CacheClear { .. } | Command::Next { .. } => {
+                        return Err(Error::InvalidArgument(arg))
+                    }
+                },
+                "--full" => match &mut config.command {
+                    Command::Verify { full, .. } => *full = true,
+                    Command::Generate | Command::CacheClear { .. } | Command::Next { .. } => {
+                        return Err(Error::InvalidArgument(arg))
+                    }
+                },
+                "--count" => match &mut config.command {
+                    Command::Next { count, .. } => {
+                        let value = next_value(&mut args, &arg)?;
+                        *count = value
+                            .parse()
+                            .map_err(|_| Error::InvalidArgumentValue(arg.clone(), value))?;
+                    }
+                    Command::Generate | Command::CacheClear { .. } | Command::Verify { .. } => {
+                        return Err(Error::InvalidArgument(arg))
+                    }
+                },
+                "--strip-names" => config.strip_names = true,
+                "--name-pattern" => {
+                    config.name_patterns.push(next_value(&mut args, &arg)?);
+                }
+                "--revision-pattern" => {
+                    config.revision_pattern = Some(next_value(&mut args, &arg)?);
+                }
+                "--all-revisions" => config.revision_pattern = None,
+                "--drop-cancelled" => config.drop_cancelled = true,
+                "--keep-unnamed" => config.keep_unnamed = true,
+                "--keep-undated" => config.keep_undated = true,
+                "--annotate-ignored" => config.annotate_ignored = true,
+                "--ignored-prefix" => {
+                    config.ignored_summary_prefix = next_value(&mut args, &arg)?;
+                }
+                "--merge-adjacent" => config.merge_adjacent = true,
+                "--low-memory" => config.low_memory = true,
+                "--group-by" => {
+                    let value = next_value(&mut args, &arg)?;
+                    config.group_by = match value.as_str() {
+                        "module" => GroupBy::Module,
+                        "module-group" => GroupBy::ModuleGroup,
+                        "module-semester" => GroupBy::ModuleSemester,
+                        _ => return Err(Error::InvalidArgumentValue(arg, value)),
+                    };
+                }
+                "--merge-max-gap" => {
+                    let value = next_value(&mut args, &arg)?;
+                    config.merge_max_gap = parse_duration(&value)?;
+                }
+                "--undated-placeholder-date" => {
+                    let value = next_value(&mut args, &arg)?;
+                    config.undated_placeholder_date = NaiveDate::parse_from_str(&value, "%Y-%m-%d")
+                        .map_err(|_| Error::InvalidArgumentValue(arg.clone(), value))?;
+                }
+                "--academic-week" => {
+                    let value = next_value(&mut args, &arg)?;
+                    config.academic_week = Some(match value.as_str() {
+                        "prefix" => WeekLabelPosition::Prefix,
+                        "suffix" => WeekLabelPosition::Suffix,
+                        _ => return Err(Error::InvalidArgumentValue(arg, value)),
+                    });
+                }
+                "--semester-start" => {
+                    let value = next_value(&mut args, &arg)?;
+                    let (semester, date) = value
+                        .split_once('=')
+                        .and_then(|(semester, date)| {
+                            NaiveDate::parse_from_str(date, "%Y-%m-%d")
+                                .ok()
+                                .map(|date| (semester.to_owned(), date))
+                        })
+                        .ok_or_else(|| Error::InvalidArgumentValue(arg.clone(), value.clone()))?;
+                    config.semester_starts.insert(semester, date);
+                }
+                "--department-contact" => {
+                    let value = next_value(&mut args, &arg)?;
+                    let (department, contact) = value
+                        .split_once('=')
+                        .and_then(|(department, mailbox)| {
+                            Contact::parse(mailbox).map(|contact| (department.to_owned(), contact))
+                        })
+                        .ok_or_else(|| Error::InvalidArgumentValue(arg.clone(), value.clone()))?;
+                    config.department_contacts.insert(department, contact);
+                }
+                "--drop-events" => {
+                    let value = next_value(&mut args, &arg)?;
+                    config.event_filters.push(EventFilterRule::parse(&value)?);
+                }
+                "--minify-ics" => config.minify_ics = true,
+                "--minify-remove" => {
+                    config.minify_denylist.push(next_value(&mut args, &arg)?);
+                }
+                "--no-provenance" => config.no_provenance = true,
+                "--max-slug-length" => {
+                    let value = next_value(&mut args, &arg)?;
+                    config.max_slug_length = value
+                        .parse()
+                        .map_err(|_| Error::InvalidArgumentValue(arg.clone(), value))?;
+                }
+                "--max-path-length" => {
+                    let value = next_value(&mut args, &arg)?;
+                    config.max_path_length = value
+                        .parse()
+                        .map_err(|_| Error::InvalidArgumentValue(arg.clone(), value))?;
+                }
+                "--cancelled-prefix" => {
+                    config
+                        .cancelled_summary_prefixes
+                        .push(next_value(&mut args, &arg)?);
+                }
+                "--output-template" => {
+                    config.output_template = OutputTemplate::parse(&next_value(&mut args, &arg)?)?;
+                }
+                "--split-large-modules" => {
+                    let value = next_value(&mut args, &arg)?;
+                    config.split_large_modules = Some(
+                        value
+                            .parse()
+                            .map_err(|_| Error::InvalidArgumentValue(arg.clone(), value))?,
+                    );
+                }
+                "--tombstone-days" => {
+                    let value = next_value(&mut args, &arg)?;
+                    config.tombstone_days = value
+                        .parse()
+                        .map_err(|_| Error::InvalidArgumentValue(arg.clone(), value))?;
+                }
+                "--change-history-runs" => {
+                    let value = next_value(&mut args, &arg)?;
+                    config.change_history_runs = value
+                        .parse()
+                        .map_err(|_| Error::InvalidArgumentValue(arg.clone(), value))?;
+                }
+                "--compat" => {
+                    let value = next_value(&mut args, &arg)?;
+                    config.compat = Some(
+                        Compat::parse(&value)
+                            .ok_or_else(|| Error::InvalidArgumentValue(arg.clone(), value))?,
+                    );
+                }
+                "--summary-suffix" => {
+                    config.summary_suffix =
+                        Some(SummaryTemplate::parse(&next_value(&mut args, &arg)?)?);
+                }
+                "--departments-json-id" => {
+                    config.departments_json_id = next_value(&mut args, &arg)?;
+                }
+                "--pin-source" => {
+                    config.pinned_sources.push(next_value(&mut args, &arg)?);
+                }
+                "--exclude-source" => {
+                    config.excluded_sources.push(next_value(&mut args, &arg)?);
+                }
+                "--error-page-marker" => {
+                    config.error_page_markers.push(next_value(&mut args, &arg)?);
+                }
+                "--no-default-error-page-markers" => {
+                    config
+                        .error_page_markers
+                        .retain(|marker| !DEFAULT_ERROR_PAGE_MARKERS.contains(&marker.as_str()));
+                }
+                "--semester-page-pattern" => {
+                    // Compiled right away, so a typo fails before anything is downloaded
+                    config.semester_page_pattern = Some(Regex::new(&next_value(&mut args, &arg)?)?);
+                }
+                "--no-default-ignores" => {
+                    config.ignored_event_names.retain(|(mode, pattern)| {
+                        !IGNORED_EVENT_NAMES
+                            .iter()
+                            .any(|(default_mode, default_pattern)| {
+                                default_mode == mode && pattern == default_pattern
+                            })
+                    });
+                }
+                "--no-default-transparent" => {
+                    config.transparent_event_names.retain(|(mode, pattern)| {
+                        !TRANSPARENT_EVENT_NAMES
+                            .iter()
+                            .any(|(default_mode, default_pattern)| {
+                                default_mode == mode && pattern == default_pattern
+                            })
+                    });
+                }
+                _ => return Err(Error::InvalidArgument(arg)),
+            }
+        }
+
+        if config.record.is_some() && config.replay.is_some() {
+            return Err(Error::InvalidArgument(
+                "--record can't be combined with --replay".to_owned(),
+            ));
+        }
+
+        if config.source_dir.is_some() && config.replay.is_some() {
+            return Err(Error::InvalidArgument(
+                "--source-dir can't be combined with --replay".to_owned(),
+            ));
+        }
+
+        if config.cross_validate && !cfg!(feature = "cross_validate") {
+            return Err(Error::InvalidArgument(
+                "--cross-validate requires the cross_validate feature".to_owned(),
+            ));
+        }
+
+        if config.proxy_user.is_some() && config.proxy.is_none() {
+            return Err(Error::InvalidArgument(
+                "--proxy-user requires --proxy".to_owned(),
+            ));
+        }
+
+        Ok(config)
+    }
+}
+
+/// Returns the value following a command line flag.
+fn next_value<I>(args: &mut I, flag: &str) -> Result<String>
+where
+    I: Iterator<Item = String>,
+{
+    args.next()
+        .ok_or_else(|| Error::MissingArgumentValue(flag.to_owned()))
+}
+
+/// Parses a cache maximum age, which is either a duration or `forever`.
+fn parse_max_age(value: &str) -> Result<Option<Duration>> {
+    if value == "forever" {
+        return Ok(None);
+    }
+
+    parse_duration(value).map(Some)
+}
+
+/// Parses durations like `500ms`, `90`, `90s`, `30m` or `2h`. A number without a unit is read as
+/// seconds.
+pub fn parse_duration(value: &str) -> Result<Duration> {
+    if let Some(millis) = value.strip_suffix("ms") {
+        return millis
+            .parse()
+            .map(Duration::from_millis)
+            .map_err(|_| Error::InvalidDuration(value.to_owned()));
+    }
+
+    let (number, multiplier) = match value.char_indices().last() {
+        Some((index, 's')) => (value.get(..index), 1),
+        Some((index, 'm')) => (value.get(..index), 60),
+        Some((index, 'h')) => (value.get(..index), 60 * 60),
+        Some(_) | None => (Some(value), 1),
+    };
+
+    number
+        .and_then(|number| number.parse::<u64>().ok())
+        .and_then(|number| number.checked_mul(multiplier))
+        .map(Duration::from_secs)
+        .ok_or_else(|| Error::InvalidDuration(value.to_owned()))
+}
+
+/// Parses sizes in bytes like `500`, `512K`, `10M` or `1G`, with binary multiples.
+fn parse_size(value: &str) -> Option<u64> {
+    let (number, multiplier) = match value.char_indices().last() {
+        Some((index, 'K' | 'k')) => (value.get(..index)?, 1024),
+        Some((index, 'M' | 'm')) => (value.get(..index)?, 1024 * 1024),
+        Some((index, 'G' | 'g')) => (value.get(..index)?, 1024 * 1024 * 1024),
+        Some(_) | None => (value, 1),
+    };
+
+    number.parse::<u64>().ok()?.checked_mul(multiplier)
+}
+
+/// Parses a SHA-256 fingerprint in hex, optionally separated by colons like
+/// `AB:CD:...`, into lowercase hex without separators.
+fn parse_fingerprint(value: &str) -> Option<String> {
+    let fingerprint = value.replace(':', "").to_lowercase();
+
+    (fingerprint.len() == 64 && fingerprint.chars().all(|c| c.is_ascii_hexdigit()))
+        .then_some(fingerprint)
+}
+
+/// Parses name patterns of the form `<mode>:<pattern>`, e.g. `prefix:Jobmesse`. Without a known
+/// mode the whole value is used as a substring pattern.
+fn parse_name_pattern(value: &str) -> Result<(MatchMode, String)> {
+    if value.is_empty() {
+        return Err(Error::InvalidArgument(value.to_owned()));
+    }
+
+    Ok(value
+        .split_once(':')
+        .and_then(|(mode, pattern)| MatchMode::parse(mode).map(|mode| (mode, pattern.to_owned())))
+        .unwrap_or_else(|| (MatchMode::Substring, value.to_owned())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Config> {
+        Config::parse(args.iter().map(|arg| (*arg).to_owned()))
+    }
+
+    #[test]
+    fn semester_page_pattern_is_compiled_when_parsing() {
+        let config = parse(&["--semester-page-pattern", "^/[a-z-]+/(wi|so)se-[0-9]+$"]).unwrap();
+
+        assert!(
+            config
+                .semester_page_pattern
+                .is_some_and(|regex| regex.is_match("/informatik-elektrotechnik/wise-2425")),
+            "the pattern should be usable right away"
+        );
+        assert!(
+            matches!(
+                parse(&["--semester-page-pattern", "(wise"]),
+                Err(Error::RegexPattern(_))
+            ),
+            "an invalid pattern should be rejected at startup"
+        );
+    }
+
+    #[test]
+    fn flags_after_the_config_file_override_it() {
+        let folder = crate::testing::temp_dir("flags_after_the_config_file_override_it");
+        let path = folder.join("config.json");
+        std::fs::write(
+            &path,
+            r#"{"semester_starts": {"WiSe_2425": "2024-10-07", "SoSe_25": "2025-03-24"}}"#,
+        )
+        .unwrap();
+        let path = path.to_str().unwrap();
+
+        let config = parse(&["--config", path, "--semester-start", "SoSe_25=2025-03-17"]).unwrap();
+
+        assert_eq!(
+            config.semester_starts.get("WiSe_2425").copied(),
+            NaiveDate::from_ymd_opt(2024, 10, 7),
+            "the start from the file should be used"
+        );
+        assert_eq!(
+            config.semester_starts.get("SoSe_25").copied(),
+            NaiveDate::from_ymd_opt(2025, 3, 17),
+            "the later flag should replace the start from the file"
+        );
+        assert!(
+            matches!(
+                parse(&["--config", &folder.join("missing.json").to_string_lossy()]),
+                Err(Error::InvalidConfigFile { .. })
+            ),
+            "a missing config file should be rejected"
+        );
+    }
+
+    #[test]
+    fn proxy_settings_are_parsed() {
+        let config = parse(&[
+            "--proxy",
+            "http://proxy.fh-kiel.test:3128",
+            "--proxy-user",
+            "student:geheim",
+            "--proxy-ca-cert",
+            "proxy.pem",
+            "--no-proxy",
+        ])
+        .unwrap();
+
+        assert_eq!(
+            (
+                config.proxy.as_deref(),
+                config.proxy_user.as_deref(),
+                config.proxy_ca_cert.as_deref(),
+                config.no_proxy
+            ),
+            (
+                Some("http://proxy.fh-kiel.test:3128"),
+                Some("student:geheim"),
+                Some(std::path::Path::new("proxy.pem")),
+                true
+            ),
+            "every proxy setting should be taken over"
+        );
+    }
+
+    #[test]
+    fn proxy_user_requires_a_proxy() {
+        assert!(
+            matches!(
+                parse(&["--proxy-user", "student:geheim"]),
+                Err(Error::InvalidArgument(message)) if message.contains("--proxy")
+            ),
+            "credentials without a proxy should be rejected"
+        );
+    }
+
+    #[test]
+    fn initial_burst_is_parsed_with_a_delay_in_milliseconds() {
+        let config = parse(&["--initial-burst", "50", "--burst-delay", "250ms"]).unwrap();
+
+        assert_eq!(
+            (config.initial_burst, config.burst_delay),
+            (50, std::time::Duration::from_millis(250)),
+            "the burst should be taken over"
+        );
+        assert_eq!(
+            parse(&[]).unwrap().burst_delay,
+            DEFAULT_BURST_DELAY,
+            "the burst delay should default to 500ms"
+        );
+    }
+}
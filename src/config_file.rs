@@ -0,0 +1,272 @@
+//! Settings read from the JSON file given with `--config`, for the lists and maps which are
+//! unwieldy as command line flags
+
+use crate::config::{self, Config};
+use crate::contact::Contact;
+use crate::event_filter::EventFilterRule;
+use crate::prelude::*;
+use chrono::NaiveDate;
+use serde::{Deserialize, Deserializer};
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::time::Duration;
+
+/// Contents of a config file. Every key is optional, unknown keys are rejected so a typo doesn't
+/// go unnoticed.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ConfigFile {
+    /// First day of the lectures per semester, like `{"WiSe_2425": "2024-10-07"}`.
+    semester_starts: BTreeMap<String, NaiveDate>,
+
+    /// Contact of the secretariat per department, like
+    /// `{"iue": {"name": "Sekretariat Informatik", "email": "info@fh-kiel.de"}}`.
+    department_contacts: BTreeMap<String, Contact>,
+
+    /// Rules dropping events, like `[{"module": "Mathematik*", "location": "^Online$"}]`.
+    drop_events: Vec<EventFilterRule>,
+
+    /// Calendar files which are always downloaded, absolute URLs or `/files/...` paths.
+    pinned_sources: Vec<String>,
+
+    /// Regexes of the URLs of calendar files which are never downloaded.
+    excluded_sources: Vec<String>,
+
+    /// Shortest delays after requests to some URLs, like
+    /// `[{"prefix": "https://mirror.fh-kiel.de/", "min_delay": "5s"}]`.
+    prefix_delays: Vec<PrefixDelay>,
+}
+
+/// Shortest delay after requests to URLs starting with `prefix`, see `--prefix-delay`.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PrefixDelay {
+    prefix: String,
+    /// Written like the value of `--min-delay`, e.g. `5s` or `500ms`.
+    #[serde(deserialize_with = "deserialize_duration")]
+    min_delay: Duration,
+}
+
+fn deserialize_duration<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> std::result::Result<Duration, D::Error> {
+    let value = String::deserialize(deserializer)?;
+    config::parse_duration(&value).map_err(serde::de::Error::custom)
+}
+
+impl ConfigFile {
+    /// Reads and parses the config file at `path`.
+    pub fn load(path: &Path) -> Result<Self> {
+        let invalid = |reason: String| Error::InvalidConfigFile {
+            path: path.to_owned(),
+            reason,
+        };
+
+        let content = std::fs::read_to_string(path).map_err(|err| invalid(err.to_string()))?;
+        Self::parse(&content).map_err(|err| invalid(err.to_string()))
+    }
+
+    fn parse(content: &str) -> serde_json::Result<Self> {
+        serde_json::from_str(content)
+    }
+
+    /// Adds the settings to `config`, entries of maps replace those set before.
+    pub fn apply(self, config: &mut Config) {
+        config.semester_starts.extend(self.semester_starts);
+        config.department_contacts.extend(self.department_contacts);
+        config.event_filters.extend(self.drop_events);
+        config.pinned_sources.extend(self.pinned_sources);
+        config.excluded_sources.extend(self.excluded_sources);
+        config.prefix_delays.extend(
+            self.prefix_delays
+                .into_iter()
+                .map(|prefix_delay| (prefix_delay.prefix, prefix_delay.min_delay)),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn semester_starts_are_read() {
+        let mut config = Config::default();
+
+        ConfigFile::parse(
+            r#"{"semester_starts": {"WiSe_2425": "2024-10-07", "SoSe_25": "2025-03-24"}}"#,
+        )
+        .unwrap()
+        .apply(&mut config);
+
+        assert_eq!(
+            config.semester_starts.get("WiSe_2425").copied(),
+            NaiveDate::from_ymd_opt(2024, 10, 7),
+            "the start of the winter semester should be read"
+        );
+        assert_eq!(
+            config.semester_starts.get("SoSe_25").copied(),
+            NaiveDate::from_ymd_opt(2025, 3, 24),
+            "the start of the summer semester should be read"
+        );
+    }
+
+    #[test]
+    fn department_contacts_replace_those_of_the_flags() {
+        let mut config = Config::parse([
+            "--department-contact".to_owned(),
+            "iue=Sekretariat <alt@fh-kiel.de>".to_owned(),
+        ])
+        .unwrap();
+
+        ConfigFile::parse(
+            r#"{"department_contacts": {
+                "iue": {"name": "Sekretariat Informatik, Elektrotechnik", "email": "iue@fh-kiel.de"},
+                "mbau": {"name": "Sekretariat Maschinenwesen", "email": "mbau@fh-kiel.de"}
+            }}"#,
+        )
+        .unwrap()
+        .apply(&mut config);
+
+        assert_eq!(
+            config.department_contacts.get("iue"),
+            Contact::new("Sekretariat Informatik, Elektrotechnik", "iue@fh-kiel.de").as_ref(),
+            "the contact of the file should replace the one of the flag"
+        );
+        assert_eq!(
+            config.department_contacts.get("mbau"),
+            Contact::new("Sekretariat Maschinenwesen", "mbau@fh-kiel.de").as_ref(),
+            "the contacts of the other departments should be read"
+        );
+    }
+
+    #[test]
+    fn drop_event_rules_are_added_to_those_of_the_flags() {
+        let mut config =
+            Config::parse(["--drop-events".to_owned(), "weekday=sa".to_owned()]).unwrap();
+
+        ConfigFile::parse(
+            r#"{"drop_events": [{"module": "Mathe*", "location": "^Online$"}, {"time": "00:00-08:00"}]}"#,
+        )
+        .unwrap()
+        .apply(&mut config);
+
+        let rules: Vec<String> = config
+            .event_filters
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(
+            rules,
+            [
+                "weekday=sa",
+                "module=Mathe*;location=^Online$",
+                "time=00:00-08:00"
+            ],
+            "the rules of the file should follow those of the flags"
+        );
+    }
+
+    #[test]
+    fn pinned_sources_are_read() {
+        let mut config = Config::default();
+
+        ConfigFile::parse(
+            r#"{"pinned_sources": ["/files/iue/WiSe_2425/semester_1/a.ics", "https://fh-kalender.de/files/mbau/WiSe_2425/semester_1/b.ics"]}"#,
+        )
+        .unwrap()
+        .apply(&mut config);
+
+        assert_eq!(
+            config.pinned_sources,
+            [
+                "/files/iue/WiSe_2425/semester_1/a.ics",
+                "https://fh-kalender.de/files/mbau/WiSe_2425/semester_1/b.ics"
+            ],
+            "paths and absolute URLs should be read"
+        );
+    }
+
+    #[test]
+    fn excluded_sources_are_read() {
+        let mut config = Config::default();
+
+        ConfigFile::parse(r#"{"excluded_sources": ["/semester_1/broken\\.ics$"]}"#)
+            .unwrap()
+            .apply(&mut config);
+
+        assert_eq!(
+            config.excluded_sources,
+            [r"/semester_1/broken\.ics$"],
+            "the patterns should be read"
+        );
+    }
+
+    #[test]
+    fn prefix_delays_are_read() {
+        let mut config = Config::default();
+
+        ConfigFile::parse(
+            r#"{"prefix_delays": [
+                {"prefix": "https://fh-kalender.de/files/", "min_delay": "2s"},
+                {"prefix": "https://fh-kalender.de/files/mbau/", "min_delay": "500ms"}
+            ]}"#,
+        )
+        .unwrap()
+        .apply(&mut config);
+
+        assert_eq!(
+            config.prefix_delays,
+            [
+                (
+                    "https://fh-kalender.de/files/".to_owned(),
+                    Duration::from_secs(2)
+                ),
+                (
+                    "https://fh-kalender.de/files/mbau/".to_owned(),
+                    Duration::from_millis(500)
+                )
+            ],
+            "the prefixes and their delays should be read"
+        );
+    }
+
+    #[test]
+    fn invalid_files_are_rejected() {
+        assert!(
+            ConfigFile::parse(r#"{"semester_starts": {"WiSe_2425": "7.10.2024"}}"#).is_err(),
+            "dates must be written as YYYY-MM-DD"
+        );
+        assert!(
+            ConfigFile::parse(r#"{"semester_start": {}}"#).is_err(),
+            "unknown keys should be rejected"
+        );
+        assert!(
+            ConfigFile::parse(r#"{"drop_events": [{"room": "Online"}]}"#).is_err(),
+            "unknown fields of a rule should be rejected"
+        );
+        assert!(
+            ConfigFile::parse(
+                r#"{"department_contacts": {"iue": {"name": "Sekretariat", "email": "fh-kiel.de"}}}"#
+            )
+            .is_err(),
+            "invalid email addresses should be rejected"
+        );
+        assert!(
+            ConfigFile::parse(r#"{"department_contacts": {"iue": {"name": "Sekretariat"}}}"#)
+                .is_err(),
+            "a contact needs an email address"
+        );
+        assert!(
+            ConfigFile::parse(
+                r#"{"prefix_delays": [{"prefix": "https://fh-kalender.de/", "min_delay": "soon"}]}"#
+            )
+            .is_err(),
+            "invalid delays should be rejected"
+        );
+        assert!(
+            ConfigFile::parse("{}").is_ok(),
+            "every key should be optional"
+        );
+    }
+}
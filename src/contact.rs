@@ -0,0 +1,201 @@
+//! Contact of the department secretariat, added to the events as their organizer
+
+use crate::props;
+use ical::parser::ical::component::IcalEvent;
+use ical::property::Property;
+use serde::Deserialize;
+
+const PROPERTY_NAME_ORGANIZER: &str = "ORGANIZER";
+const PARAMETER_NAME_COMMON_NAME: &str = "CN";
+
+/// Whom to ask about the events of a department.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "ContactFields")]
+pub struct Contact {
+    pub name: String,
+    pub email: String,
+}
+
+/// A contact as written in the config file, like `{"name": "Sekretariat", "email": "..."}`.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ContactFields {
+    name: String,
+    email: String,
+}
+
+impl TryFrom<ContactFields> for Contact {
+    type Error = String;
+
+    fn try_from(fields: ContactFields) -> Result<Self, Self::Error> {
+        Self::new(&fields.name, &fields.email)
+            .ok_or_else(|| format!("'{} <{}>' isn't a valid contact", fields.name, fields.email))
+    }
+}
+
+impl Contact {
+    /// Parses a contact written as mailbox, like `Sekretariat Informatik <info@fh-kiel.de>`.
+    #[must_use]
+    pub fn parse(mailbox: &str) -> Option<Self> {
+        let (name, email) = mailbox.trim().strip_suffix('>')?.rsplit_once('<')?;
+        Self::new(name, email)
+    }
+
+    /// Returns the contact unless the name is empty or the email address is invalid.
+    #[must_use]
+    pub fn new(name: &str, email: &str) -> Option<Self> {
+        let name = name.trim();
+        let email = email.trim();
+
+        let valid_email = email
+            .split_once('@')
+            .is_some_and(|(local, domain)| !local.is_empty() && !domain.is_empty())
+            && !email
+                .chars()
+                .any(|c| c.is_whitespace() || c.is_control() || matches!(c, '<' | '>' | '"'));
+        if name.is_empty() || !valid_email {
+            return None;
+        }
+
+        Some(Self {
+            name: name.to_owned(),
+            email: email.to_owned(),
+        })
+    }
+
+    /// Builds the `ORGANIZER;CN=...:mailto:...` property of the contact.
+    #[must_use]
+    pub fn organizer_property(&self) -> Property {
+        Property {
+            name: PROPERTY_NAME_ORGANIZER.to_owned(),
+            params: Some(vec![(
+                PARAMETER_NAME_COMMON_NAME.to_owned(),
+                vec![quote_param_value(&self.name)],
+            )]),
+            value: Some(format!("mailto:{}", self.email)),
+        }
+    }
+}
+
+/// Adds the contact as organizer of `event` unless the event has one. Returns whether it was
+/// added.
+pub fn add_organizer(event: &mut IcalEvent, contact: &Contact) -> bool {
+    if props::get_value(event, PROPERTY_NAME_ORGANIZER).is_some() {
+        return false;
+    }
+
+    event.properties.push(contact.organizer_property());
+    true
+}
+
+/// Encodes `value` as parameter value. Double quotes and line breaks can't appear in parameter
+/// values at all and are encoded as described in RFC 6868, values containing `:`, `;` or `,` are
+/// quoted as required by RFC 5545.
+fn quote_param_value(value: &str) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '^' => encoded.push_str("^^"),
+            '"' => encoded.push_str("^'"),
+            '\n' => encoded.push_str("^n"),
+            c if c.is_control() => {}
+            c => encoded.push(c),
+        }
+    }
+
+    if encoded.contains([':', ';', ',']) {
+        format!("\"{encoded}\"")
+    } else {
+        encoded
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn organizer(name: &str) -> (String, String) {
+        let property = Contact::new(name, "info@fh-kiel.de")
+            .unwrap()
+            .organizer_property();
+        let params = property
+            .params
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(name, values)| format!("{name}={}", values.join(",")))
+            .collect::<Vec<_>>()
+            .join(";");
+
+        (params, property.value.unwrap_or_default())
+    }
+
+    #[test]
+    fn mailboxes_are_parsed() {
+        assert_eq!(
+            Contact::parse(" Sekretariat Informatik <info@fh-kiel.de> "),
+            Contact::new("Sekretariat Informatik", "info@fh-kiel.de"),
+            "the name and the address should be split"
+        );
+        assert_eq!(
+            Contact::parse("info@fh-kiel.de"),
+            None,
+            "a contact needs a name"
+        );
+        assert_eq!(
+            Contact::parse("Sekretariat <info fh-kiel.de>"),
+            None,
+            "invalid addresses should be rejected"
+        );
+    }
+
+    #[test]
+    fn the_organizer_has_the_name_and_the_address() {
+        assert_eq!(
+            organizer("Sekretariat Informatik"),
+            (
+                "CN=Sekretariat Informatik".to_owned(),
+                "mailto:info@fh-kiel.de".to_owned()
+            ),
+            "the name should be the common name and the address a mailto URI"
+        );
+    }
+
+    #[test]
+    fn names_with_separators_are_quoted() {
+        assert_eq!(
+            organizer("Sekretariat Informatik, Elektrotechnik").0,
+            "CN=\"Sekretariat Informatik, Elektrotechnik\"",
+            "a comma would split the parameter value"
+        );
+        assert_eq!(
+            organizer("Sekretariat: Raum C-12").0,
+            "CN=\"Sekretariat: Raum C-12\"",
+            "a colon would end the parameters"
+        );
+        assert_eq!(
+            organizer("Das \"Sekretariat\" ^").0,
+            "CN=Das ^'Sekretariat^' ^^",
+            "double quotes and carets should be encoded as in RFC 6868"
+        );
+    }
+
+    #[test]
+    fn existing_organizers_are_kept() {
+        let contact = Contact::new("Sekretariat", "info@fh-kiel.de").unwrap();
+        let mut event = IcalEvent::new();
+
+        assert!(
+            add_organizer(&mut event, &contact),
+            "the organizer should be added"
+        );
+        assert!(
+            !add_organizer(&mut event, &contact),
+            "the organizer shouldn't be added twice"
+        );
+        assert_eq!(
+            event.properties.len(),
+            1,
+            "the event should have a single organizer"
+        );
+    }
+}
@@ -0,0 +1,228 @@
+//! Cross-validation of the written calendars with the independent parser of the `icalendar` crate
+//!
+//! Reading the output back with the `ical` crate which generated it would hide bugs both share,
+//! so `--cross-validate` compares the events with what a second parser makes of the file.
+
+use crate::calendar::{
+    PROPERTY_NAME_DTEND, PROPERTY_NAME_DTSTART, PROPERTY_NAME_LOCATION, PROPERTY_NAME_SEQUENCE,
+    PROPERTY_NAME_STATUS, PROPERTY_NAME_SUMMARY, PROPERTY_NAME_UID,
+};
+use crate::props;
+use ical::parser::ical::component::IcalEvent;
+use serde::Serialize;
+
+/// Properties whose values have to survive the round trip.
+const COMPARED_PROPERTIES: [&str; 7] = [
+    PROPERTY_NAME_UID,
+    PROPERTY_NAME_DTSTART,
+    PROPERTY_NAME_DTEND,
+    PROPERTY_NAME_SUMMARY,
+    PROPERTY_NAME_LOCATION,
+    PROPERTY_NAME_STATUS,
+    PROPERTY_NAME_SEQUENCE,
+];
+
+/// A property the second parser reads differently.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Discrepancy {
+    pub property: String,
+    pub detail: String,
+}
+
+/// Properties of a single event as read by the second parser: uppercase name and raw value.
+type ParsedEvent = Vec<(String, String)>;
+
+/// Parses the written calendar `content` again and compares it with the `expected` events, in
+/// the order they were written.
+pub fn compare(expected: &[IcalEvent], content: &str) -> Vec<Discrepancy> {
+    let parsed = match parse_events(content) {
+        Ok(parsed) => parsed,
+        Err(err) => {
+            return vec![Discrepancy {
+                property: "VCALENDAR".to_owned(),
+                detail: format!("failed to parse: {err}"),
+            }]
+        }
+    };
+
+    if parsed.len() != expected.len() {
+        return vec![Discrepancy {
+            property: "VEVENT".to_owned(),
+            detail: format!("{} events instead of {}", parsed.len(), expected.len()),
+        }];
+    }
+
+    let mut discrepancies = Vec::new();
+    for (event, parsed_event) in expected.iter().zip(&parsed) {
+        let uid = props::get_value(event, PROPERTY_NAME_UID).unwrap_or_default();
+
+        for name in COMPARED_PROPERTIES {
+            let expected_value = props::get_value(event, name).map(normalize);
+            let parsed_value = parsed_event
+                .iter()
+                .find(|(parsed_name, _)| parsed_name == name)
+                .map(|(_, value)| normalize(value));
+
+            if expected_value != parsed_value {
+                discrepancies.push(Discrepancy {
+                    property: name.to_owned(),
+                    detail: format!(
+                        "event '{uid}' has '{}' but is read back as '{}'",
+                        expected_value.as_deref().unwrap_or("(missing)"),
+                        parsed_value.as_deref().unwrap_or("(missing)")
+                    ),
+                });
+            }
+        }
+    }
+
+    discrepancies
+}
+
+/// Evens out the known representational differences between the two parsers:
+///
+/// - `ical` keeps the backslash escapes of text values, while `icalendar` may resolve some of
+///   them. Both sides are unescaped, so it doesn't matter which one does.
+/// - `ical` keeps trailing whitespace of a value, `icalendar` drops it together with the line
+///   ending.
+fn normalize(value: &str) -> String {
+    let mut normalized = String::with_capacity(value.len());
+    let mut characters = value.trim_end().chars();
+
+    while let Some(character) = characters.next() {
+        if character != '\\' {
+            normalized.push(character);
+            continue;
+        }
+
+        match characters.next() {
+            Some('n' | 'N') => normalized.push('\n'),
+            Some(escaped) => normalized.push(escaped),
+            None => normalized.push('\\'),
+        }
+    }
+
+    normalized
+}
+
+#[cfg(feature = "cross_validate")]
+fn parse_events(content: &str) -> Result<Vec<ParsedEvent>, String> {
+    // Line folding is not part of the comparison, `ical` unfolds as well
+    let unfolded = icalendar::parser::unfold(content);
+    let calendar = icalendar::parser::read_calendar(&unfolded)?;
+
+    Ok(calendar
+        .components
+        .iter()
+        .flat_map(|component| component.components.iter())
+        .filter(|component| component.name.as_str().eq_ignore_ascii_case("VEVENT"))
+        .map(|event| {
+            event
+                .properties
+                .iter()
+                .map(|property| {
+                    (
+                        property.name.as_str().to_ascii_uppercase(),
+                        property.val.as_str().to_owned(),
+                    )
+                })
+                .collect()
+        })
+        .collect())
+}
+
+#[cfg(not(feature = "cross_validate"))]
+fn parse_events(_content: &str) -> Result<Vec<ParsedEvent>, String> {
+    Err("built without the cross_validate feature".to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    fn events(content: &str) -> Vec<IcalEvent> {
+        ical::IcalParser::new(content.as_bytes())
+            .flat_map(|calendar| calendar.unwrap().events)
+            .collect()
+    }
+
+    #[test]
+    fn normalize_evens_out_escapes_and_trailing_whitespace() {
+        assert_eq!(
+            normalize(r"Raum C\, 12\;13  "),
+            "Raum C, 12;13",
+            "escapes are resolved"
+        );
+        assert_eq!(normalize(r"a\nb\Nc"), "a\nb\nc", "newlines are resolved");
+        assert_eq!(normalize(r"a\"), "a\\", "a trailing backslash is kept");
+    }
+
+    #[cfg(feature = "cross_validate")]
+    #[test]
+    fn round_trip_has_no_discrepancies() {
+        let content = testing::calendar(&[
+            ("Mathematik 1", "20241014T081500"),
+            ("Physik\\, Labor", "20241015T100000"),
+        ]);
+
+        assert_eq!(
+            compare(&events(&content), &content),
+            [],
+            "both parsers agree"
+        );
+    }
+
+    #[cfg(feature = "cross_validate")]
+    #[test]
+    fn differing_value_is_reported() {
+        let expected = events(&testing::calendar(&[("Mathematik 1", "20241014T081500")]));
+        let content = testing::calendar(&[("Mathematik 1", "20241014T081500")])
+            .replace("LOCATION:C-12.01", "LOCATION:C-13.01");
+
+        assert_eq!(
+            compare(&expected, &content),
+            [Discrepancy {
+                property: PROPERTY_NAME_LOCATION.to_owned(),
+                detail: "event 'Mathematik 1-20241014T081500' has 'C-12.01' but is read back as \
+                         'C-13.01'"
+                    .to_owned(),
+            }],
+            "changed location is reported"
+        );
+    }
+
+    #[cfg(feature = "cross_validate")]
+    #[test]
+    fn missing_event_is_reported() {
+        let expected = events(&testing::calendar(&[
+            ("Mathematik 1", "20241014T081500"),
+            ("Physik", "20241015T100000"),
+        ]));
+        let content = testing::calendar(&[("Mathematik 1", "20241014T081500")]);
+
+        assert_eq!(
+            compare(&expected, &content),
+            [Discrepancy {
+                property: "VEVENT".to_owned(),
+                detail: "1 events instead of 2".to_owned(),
+            }],
+            "event count is compared first"
+        );
+    }
+
+    #[cfg(not(feature = "cross_validate"))]
+    #[test]
+    fn comparison_fails_without_the_feature() {
+        let content = testing::calendar(&[("Mathematik 1", "20241014T081500")]);
+
+        assert_eq!(
+            compare(&events(&content), &content),
+            [Discrepancy {
+                property: "VCALENDAR".to_owned(),
+                detail: "failed to parse: built without the cross_validate feature".to_owned(),
+            }],
+            "nothing is compared without the second parser"
+        );
+    }
+}
@@ -0,0 +1,169 @@
+//! Parsing of iCalendar date and date-time values
+
+use chrono::{DateTime, NaiveDate, NaiveDateTime, TimeZone, Utc, Weekday};
+use chrono_tz::Tz;
+
+/// Time zone human-readable dates are shown in unless overridden, the one the FH Kiel is in.
+pub const DEFAULT_DISPLAY_TIME_ZONE: Tz = chrono_tz::Europe::Berlin;
+
+/// Parses a DATE (`20250407`) or DATE-TIME (`20250407T091500`, optionally with a trailing `Z`)
+/// value. Dates are returned as midnight of that day.
+pub fn parse_date_time(value: &str) -> Option<NaiveDateTime> {
+    let value = value.trim();
+    let value = value.strip_suffix('Z').unwrap_or(value);
+
+    if value.contains('T') {
+        NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%S").ok()
+    } else {
+        NaiveDate::parse_from_str(value, "%Y%m%d")
+            .ok()
+            .and_then(|date| date.and_hms_opt(0, 0, 0))
+    }
+}
+
+/// Parses a DATE or DATE-TIME value like `parse_date_time` and converts it to the wall time in
+/// `display_zone`. UTC values and values with a known `tzid` are converted, while dates and
+/// floating times are returned as they are.
+pub fn parse_display_time(
+    value: &str,
+    tzid: Option<&str>,
+    display_zone: Tz,
+) -> Option<NaiveDateTime> {
+    let date_time = parse_date_time(value)?;
+    if is_date_only(value) {
+        return Some(date_time);
+    }
+
+    let source_zone = if value.trim().ends_with('Z') {
+        Some(Tz::UTC)
+    } else {
+        tzid.and_then(|tzid| tzid.parse::<Tz>().ok())
+    };
+
+    // Times skipped by a DST change are shown as they are
+    Some(
+        source_zone
+            .and_then(|zone| zone.from_local_datetime(&date_time).earliest())
+            .map_or(date_time, |zoned| {
+                zoned.with_timezone(&display_zone).naive_local()
+            }),
+    )
+}
+
+/// Formats an instant as the wall time in `display_zone`.
+pub fn format_in_zone(date_time: DateTime<Utc>, display_zone: Tz, format: &str) -> String {
+    date_time
+        .with_timezone(&display_zone)
+        .format(format)
+        .to_string()
+}
+
+/// Returns `true` if `value` is a DATE without a time, i.e. an all-day value.
+pub fn is_date_only(value: &str) -> bool {
+    !value.contains('T')
+}
+
+/// Parses an HTTP date like `Wed, 12 Mar 2025 10:00:00 GMT`, as used by `Last-Modified`.
+pub fn parse_http_date(value: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc2822(value.trim())
+        .ok()
+        .map(|date_time| date_time.with_timezone(&Utc))
+}
+
+/// Returns the lecture week `date` falls into, the week containing `semester_start` being week 1.
+/// Weeks start on Monday. Dates before the start of the semester have no lecture week.
+pub fn academic_week(date: NaiveDate, semester_start: NaiveDate) -> Option<u32> {
+    if date < semester_start {
+        return None;
+    }
+
+    let weeks = (date.week(Weekday::Mon).first_day()
+        - semester_start.week(Weekday::Mon).first_day())
+    .num_weeks();
+
+    u32::try_from(weeks)
+        .ok()
+        .map(|weeks| weeks.saturating_add(1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    #[test]
+    fn academic_weeks_start_on_monday() {
+        // The semester starts on a Wednesday, its first week still began on Monday
+        let semester_start = date(2024, 10, 9);
+
+        assert_eq!(
+            academic_week(date(2024, 10, 8), semester_start),
+            None,
+            "days before the start should have no week"
+        );
+        assert_eq!(
+            academic_week(date(2024, 10, 9), semester_start),
+            Some(1),
+            "the first day should be in week 1"
+        );
+        assert_eq!(
+            academic_week(date(2024, 10, 13), semester_start),
+            Some(1),
+            "Sunday should still be in week 1"
+        );
+        assert_eq!(
+            academic_week(date(2024, 10, 14), semester_start),
+            Some(2),
+            "the next Monday should start week 2"
+        );
+        assert_eq!(
+            academic_week(date(2025, 1, 6), semester_start),
+            Some(14),
+            "weeks should be counted across the turn of the year"
+        );
+    }
+
+    #[test]
+    fn the_same_instant_is_shown_in_each_display_zone() {
+        let instant = Utc.with_ymd_and_hms(2025, 1, 15, 12, 0, 0).unwrap();
+
+        assert_eq!(
+            [
+                chrono_tz::Europe::Berlin,
+                chrono_tz::UTC,
+                chrono_tz::Asia::Tokyo
+            ]
+            .map(|zone| format_in_zone(instant, zone, "%d.%m.%Y %H:%M")),
+            ["15.01.2025 13:00", "15.01.2025 12:00", "15.01.2025 21:00"],
+            "the instant should be shown as the wall time of each zone"
+        );
+    }
+
+    #[test]
+    fn event_times_are_converted_to_the_display_zone() {
+        let time = |value: &str, tzid: Option<&str>, zone: Tz| {
+            parse_display_time(value, tzid, zone).map(|time| time.format("%H:%M").to_string())
+        };
+
+        for (value, tzid, berlin, utc) in [
+            ("20250407T071500Z", None, "09:15", "07:15"),
+            ("20250407T091500", Some("Europe/Berlin"), "09:15", "07:15"),
+            // Floating times and unknown zones are shown as they are
+            ("20250407T091500", None, "09:15", "09:15"),
+            ("20250407T091500", Some("Mitteleuropa"), "09:15", "09:15"),
+            ("20250407", None, "00:00", "00:00"),
+        ] {
+            assert_eq!(
+                [
+                    time(value, tzid, chrono_tz::Europe::Berlin),
+                    time(value, tzid, chrono_tz::UTC)
+                ],
+                [Some(berlin.to_owned()), Some(utc.to_owned())],
+                "showing '{value}' with TZID {tzid:?}"
+            );
+        }
+    }
+}
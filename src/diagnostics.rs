@@ -0,0 +1,398 @@
+//! Collected diagnostics of a run
+
+use crate::cross_validate::Discrepancy;
+use crate::prelude::*;
+use crate::stats::{BaseStats, DepartmentStats, RunStats, SourceStats};
+use crate::validate::EventAnomaly;
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use tracing::field::{Field, Visit};
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::layer::{Context, Layer};
+
+/// A module whose calendar could not be written.
+#[derive(Debug, Clone, Serialize)]
+pub struct WriteFailure {
+    pub module: String,
+    pub error: String,
+}
+
+/// A module dropped because its name matched the module ignore list.
+#[derive(Debug, Clone, Serialize)]
+pub struct IgnoredModule {
+    pub module: String,
+    /// The pattern of the ignore list the name matched.
+    pub pattern: String,
+    /// Number of events the module had after aggregation.
+    pub events: u32,
+}
+
+/// A module calendar which the `icalendar` crate reads differently.
+#[derive(Debug, Clone, Serialize)]
+pub struct CrossValidationFailure {
+    pub module: String,
+    #[serde(flatten)]
+    pub discrepancy: Discrepancy,
+}
+
+/// A file besides the calendars, like the index, the statistics or the feed, which could not be
+/// written.
+#[derive(Debug, Clone, Serialize)]
+pub struct IndexWriteFailure {
+    pub file: String,
+    pub error: String,
+}
+
+/// Where the content of a fetched calendar file came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FetchOrigin {
+    /// Downloaded from the server.
+    Network,
+    /// The server answered a conditional request with 304 Not Modified.
+    NotModified,
+    /// A HEAD request showed the cached content is still current.
+    HeadProbe,
+    /// The cached content was fresh enough to be used without asking the server.
+    Cache,
+}
+
+impl FetchOrigin {
+    /// Whether the content came from the cache rather than a download.
+    pub const fn is_cache_hit(self) -> bool {
+        match self {
+            Self::Network => false,
+            Self::NotModified | Self::HeadProbe | Self::Cache => true,
+        }
+    }
+
+    pub const fn description(self) -> &'static str {
+        match self {
+            Self::Network => "network",
+            Self::NotModified => "not_modified",
+            Self::HeadProbe => "head_probe",
+            Self::Cache => "cache",
+        }
+    }
+}
+
+/// The response metadata of a fetched calendar file, telling which version of the file was seen.
+#[derive(Debug, Clone, Serialize)]
+pub struct FetchRecord {
+    pub url: String,
+    /// Status code of the response, `None` if no request was sent.
+    pub status: Option<u16>,
+    pub origin: FetchOrigin,
+    pub content_length: Option<String>,
+    pub last_modified: Option<String>,
+    pub etag: Option<String>,
+    /// Content encoding the body was transferred with.
+    pub content_encoding: Option<String>,
+    /// Size of the body as transferred in bytes, `None` if no request was sent.
+    pub encoded_size: Option<usize>,
+    /// Size of the decoded body in bytes, `None` if no request was sent.
+    pub decoded_size: Option<usize>,
+    /// Delay waited after the request in milliseconds, `None` if there was no wait.
+    pub delay_ms: Option<u128>,
+}
+
+/// An event without a DTSTART, kept with all its properties for upstream bug reports.
+#[derive(Debug, Clone, Serialize)]
+pub struct UndatedEvent {
+    /// URL of the source calendar.
+    pub source: String,
+    /// Every property of the event in iCalendar notation.
+    pub properties: Vec<String>,
+}
+
+/// Why a link to a calendar file was not downloaded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SkipReason {
+    /// The link points to the calendar of a lecturer.
+    Lecturer,
+    /// The path doesn't have the expected `/files/<department>/<semester>/<group>/` layout.
+    PatternMismatch,
+    /// A component of the path could lead outside of the output folder, like `..`.
+    UnsafePath,
+    /// The link points to another host.
+    OffHost,
+    /// The link was already found before.
+    Duplicate,
+    /// robots.txt disallows the link.
+    RobotsDisallowed,
+    /// A pinned source which could not be downloaded.
+    PinnedUnavailable,
+    /// The URL matches a pattern given with `--exclude-source`.
+    Excluded,
+    /// The maximum runtime was exceeded before the download.
+    DeadlineExceeded,
+    /// The download failed, even after retrying.
+    DownloadFailed,
+    /// A newer revision of the file was found, see `--revision-pattern`.
+    Superseded,
+}
+
+impl SkipReason {
+    pub const fn description(self) -> &'static str {
+        match self {
+            Self::Lecturer => "lecturer calendar",
+            Self::PatternMismatch => "pattern mismatch",
+            Self::UnsafePath => "unsafe path",
+            Self::OffHost => "off-host",
+            Self::Duplicate => "duplicate",
+            Self::RobotsDisallowed => "disallowed by robots.txt",
+            Self::PinnedUnavailable => "pinned source unavailable",
+            Self::Excluded => "excluded",
+            Self::DeadlineExceeded => "deadline exceeded",
+            Self::DownloadFailed => "download failed",
+            Self::Superseded => "superseded by a newer revision",
+        }
+    }
+}
+
+/// A link to a calendar file which was not downloaded.
+#[derive(Debug, Clone, Serialize)]
+pub struct SkippedLink {
+    /// Base URL of the site the link was found on.
+    pub base_url: String,
+    pub url: String,
+    pub reason: SkipReason,
+}
+
+/// Name of the field which marks a warning as one which tends to repeat, like the same server
+/// error for many URLs. Its value is the message the repetitions are summarized under.
+pub const REPEATED_FIELD: &str = "repeated";
+
+/// Repeated warnings counted by the layer installed in `main`.
+static GLOBAL_REPEATED_WARNINGS: Lazy<RepeatedWarnings> = Lazy::new(RepeatedWarnings::default);
+
+/// Layer which lets only the first warning with each [`REPEATED_FIELD`] value through and counts
+/// the rest, clones share the same counts.
+#[derive(Debug, Clone, Default)]
+pub struct RepeatedWarnings {
+    counts: Arc<Mutex<BTreeMap<String, usize>>>,
+}
+
+impl RepeatedWarnings {
+    /// The instance counting the warnings of the whole process.
+    #[must_use]
+    pub fn global() -> &'static Self {
+        &GLOBAL_REPEATED_WARNINGS
+    }
+
+    /// Logs how often each warning was repeated since the last call and returns how often each
+    /// one occurred.
+    pub fn summarize(&self) -> BTreeMap<String, usize> {
+        let counts =
+            std::mem::take(&mut *self.counts.lock().unwrap_or_else(PoisonError::into_inner));
+
+        for (message, count) in counts.iter().filter(|(_, count)| **count > 1) {
+            warn!("{message}: previous message repeated {} times", count - 1);
+        }
+
+        counts
+    }
+}
+
+impl<S: Subscriber> Layer<S> for RepeatedWarnings {
+    fn event_enabled(&self, event: &Event<'_>, _ctx: Context<'_, S>) -> bool {
+        if *event.metadata().level() != Level::WARN {
+            return true;
+        }
+
+        let mut visitor = RepeatedVisitor::default();
+        event.record(&mut visitor);
+        let Some(message) = visitor.message else {
+            return true;
+        };
+
+        let mut counts = self.counts.lock().unwrap_or_else(PoisonError::into_inner);
+        let count = counts.entry(message).or_default();
+        *count += 1;
+        let first = *count == 1;
+        drop(counts);
+
+        first
+    }
+}
+
+/// Picks the value of [`REPEATED_FIELD`] out of an event.
+#[derive(Debug, Default)]
+struct RepeatedVisitor {
+    message: Option<String>,
+}
+
+impl Visit for RepeatedVisitor {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == REPEATED_FIELD {
+            self.message = Some(value.to_owned());
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        if field.name() == REPEATED_FIELD {
+            self.message = Some(format!("{value:?}"));
+        }
+    }
+}
+
+/// Everything noteworthy that happened during a run, filled in by the pipeline stages.
+#[derive(Debug, Default)]
+pub struct Diagnostics {
+    /// URLs which were not downloaded because the deadline was exceeded.
+    pub skipped_urls: Vec<String>,
+    /// URLs of calendar files which failed to download, apart from pinned sources.
+    pub failed_urls: Vec<String>,
+    /// Links to calendar files which were filtered out while scraping.
+    pub skipped_links: Vec<SkippedLink>,
+    /// Event statistics keyed by the URL of the source calendar.
+    pub source_stats: BTreeMap<String, SourceStats>,
+    /// Statistics for every scraped base URL, in scraping order.
+    pub base_stats: Vec<(String, BaseStats)>,
+    /// Statistics for every department, keyed by the department parsed from the source path.
+    pub department_stats: BTreeMap<String, DepartmentStats>,
+    /// Modules for which the base URLs disagreed on the location.
+    pub conflicting_modules: BTreeSet<String>,
+    /// Modules dropped because their name matched the module ignore list.
+    pub ignored_modules: Vec<IgnoredModule>,
+    /// Modules whose calendar could not be written.
+    pub write_failures: Vec<WriteFailure>,
+    /// Files listing the calendars which could not be written.
+    pub index_write_failures: Vec<IndexWriteFailure>,
+    /// Events without a DTSTART.
+    pub undated_events: Vec<UndatedEvent>,
+    /// Anomalies found while validating the parsed events.
+    pub event_anomalies: Vec<EventAnomaly>,
+    /// Discrepancies found by `--cross-validate`.
+    pub cross_validation_failures: Vec<CrossValidationFailure>,
+    /// Counters of the whole run.
+    pub run_stats: RunStats,
+    /// Total size of the calendars of the previous and this run, if it grew by more than allowed.
+    pub size_growth: Option<(usize, usize)>,
+    /// Metadata of every fetched calendar file, collected while downloading.
+    fetch_records: Mutex<Vec<FetchRecord>>,
+}
+
+impl Diagnostics {
+    /// Metadata of every fetched calendar file so far. The workers add to it while downloading.
+    pub fn fetch_records(&self) -> MutexGuard<'_, Vec<FetchRecord>> {
+        self.fetch_records
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Whether calendar files are missing from the run because the deadline was exceeded or their
+    /// download failed. Their calendars might well be back next time.
+    pub fn is_partial(&self) -> bool {
+        !self.skipped_urls.is_empty() || !self.failed_urls.is_empty()
+    }
+
+    /// Records a link which is not downloaded.
+    pub fn skip_link(&mut self, base_url: &str, url: String, reason: SkipReason) {
+        debug!("Skipping '{url}': {}", reason.description());
+        *self.run_stats.urls_skipped.entry(reason).or_default() += 1;
+
+        self.skipped_links.push(SkippedLink {
+            base_url: base_url.to_owned(),
+            url,
+            reason,
+        });
+    }
+
+    /// Number of links from `base_url` which were skipped for `reason`.
+    pub fn count_skipped_links(&self, base_url: &str, reason: SkipReason) -> usize {
+        self.skipped_links
+            .iter()
+            .filter(|link| link.base_url == base_url && link.reason == reason)
+            .count()
+    }
+
+    /// Returns the statistics of `base_url`, creating them if necessary.
+    pub fn base_stats_mut(&mut self, base_url: &str) -> &mut BaseStats {
+        if !self.base_stats.iter().any(|(url, _)| url == base_url) {
+            self.base_stats
+                .push((base_url.to_owned(), BaseStats::default()));
+        }
+
+        self.base_stats
+            .iter_mut()
+            .find(|(url, _)| url == base_url)
+            .map(|(_, base_stats)| base_stats)
+            .unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::CapturedLogs;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    #[test]
+    fn repeated_warnings_are_logged_once_and_summarized() {
+        let logs = CapturedLogs::default();
+        let layer = RepeatedWarnings::default();
+        let subscriber = logs.subscriber().with(layer.clone());
+
+        let counts = tracing::subscriber::with_default(subscriber, || {
+            for url in ["a.ics", "b.ics", "c.ics"] {
+                warn!(repeated = "Requests failed", "Request for '{url}' failed");
+            }
+            warn!(repeated = %format_args!("Skipping {}", "events"), "Skipping event in 'a.ics'");
+            warn!("Unrelated warning");
+            warn!("Unrelated warning");
+
+            layer.summarize()
+        });
+
+        let lines = logs.lines();
+        let logged = |text: &str| lines.iter().filter(|line| line.contains(text)).count();
+        assert_eq!(
+            logged("Request for 'a.ics' failed"),
+            1,
+            "first repetition is logged"
+        );
+        assert_eq!(
+            logged("Request for 'b.ics' failed"),
+            0,
+            "repetitions are suppressed"
+        );
+        assert_eq!(
+            logged("Request for 'c.ics' failed"),
+            0,
+            "repetitions are suppressed"
+        );
+        assert_eq!(
+            logged("Skipping event in 'a.ics'"),
+            1,
+            "other messages are logged"
+        );
+        assert_eq!(
+            logged("Unrelated warning"),
+            2,
+            "unmarked warnings are never suppressed"
+        );
+        assert_eq!(
+            logged("Requests failed: previous message repeated 2 times"),
+            1,
+            "repetitions are summarized"
+        );
+        assert_eq!(
+            logged("Skipping events: previous"),
+            0,
+            "single warnings need no summary"
+        );
+        assert_eq!(
+            counts,
+            BTreeMap::from([
+                ("Requests failed".to_owned(), 3),
+                ("Skipping events".to_owned(), 1)
+            ]),
+            "summary counts every occurrence"
+        );
+        assert!(layer.summarize().is_empty(), "summary resets the counts");
+    }
+}
@@ -1,14 +1,34 @@
 //! Main Crate Error
 
 use reqwest::StatusCode;
+use std::path::PathBuf;
 
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
     #[error("Request failed: {0}")]
     RequestFailed(StatusCode),
 
-    #[error("Empty response")]
-    EmptyResponse,
+    #[error("Empty response after {0} attempts")]
+    EmptyResponse(usize),
+
+    #[error("'{url}' returned an error page matching '{marker}'")]
+    ErrorPage { url: String, marker: String },
+
+    #[error("'{url}' is larger than the limit of {limit} bytes")]
+    ResponseTooLarge { url: String, limit: u64 },
+
+    #[error("Unsupported content encoding '{0}'")]
+    UnsupportedContentEncoding(String),
+
+    #[error("'{url}' has the unsafe path component '{component}', {reason}")]
+    UnsafePathComponent {
+        url: String,
+        component: String,
+        reason: &'static str,
+    },
+
+    #[error("Output path '{}' is outside of the output folder", .0.display())]
+    PathOutsideOutput(PathBuf),
 
     #[error(transparent)]
     IO(#[from] std::io::Error),
@@ -19,12 +39,139 @@ pub enum Error {
     #[error(transparent)]
     Reqwest(#[from] reqwest::Error),
 
+    #[error(transparent)]
+    Tls(#[from] rustls::Error),
+
+    #[error(transparent)]
+    ThreadPool(#[from] rayon::ThreadPoolBuildError),
+
     #[error(transparent)]
     TracingDispatcherSetGlobalDefault(#[from] tracing::dispatcher::SetGlobalDefaultError),
 
     #[error(transparent)]
     RegexPattern(#[from] regex::Error),
 
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+
+    #[error(transparent)]
+    Fmt(#[from] std::fmt::Error),
+
+    #[error(transparent)]
+    Xml(#[from] quick_xml::Error),
+
     #[error("Invalid URL: {0}")]
     InvalidUrl(String),
+
+    #[error("Invalid argument: {0}")]
+    InvalidArgument(String),
+
+    #[error("Missing value for argument: {0}")]
+    MissingArgumentValue(String),
+
+    #[error("Invalid value for argument {0}: {1}")]
+    InvalidArgumentValue(String, String),
+
+    #[error("Invalid duration: {0}")]
+    InvalidDuration(String),
+
+    #[error("Invalid output template '{0}': {1}")]
+    InvalidOutputTemplate(String, String),
+
+    #[error("Invalid summary suffix '{0}': {1}")]
+    InvalidSummaryTemplate(String, String),
+
+    #[error("Invalid event filter: {0}")]
+    InvalidEventFilter(String),
+
+    #[error("Invalid source exclusion pattern '{0}': {1}")]
+    InvalidSourceExclusion(String, String),
+
+    #[error("Invalid error page marker '{0}': {1}")]
+    InvalidErrorPageMarker(String, String),
+
+    #[error("Invalid name pattern '{0}': {1}")]
+    InvalidNamePattern(String, String),
+
+    #[error("Invalid revision pattern '{0}': {1}")]
+    InvalidRevisionPattern(String, String),
+
+    #[error("Invalid proxy URL '{0}'")]
+    InvalidProxy(String),
+
+    #[error("Invalid proxy credentials, expected <user>:<password>")]
+    InvalidProxyUser,
+
+    #[error("Failed to load the CA certificate '{}': {reason}", .path.display())]
+    InvalidCaCertificate { path: PathBuf, reason: String },
+
+    #[error("Invalid config file '{}': {reason}", .path.display())]
+    InvalidConfigFile { path: PathBuf, reason: String },
+
+    #[error(
+        "The certificate of {host} has the SHA-256 fingerprint {fingerprint}, which is not pinned"
+    )]
+    CertificateMismatch { host: String, fingerprint: String },
+
+    #[error("Invalid user agent '{0}', it must not contain control characters")]
+    InvalidUserAgent(String),
+
+    #[error("Failed to format the manifest")]
+    ManifestFormat,
+
+    #[error("Failed to write '{}': {source}", .path.display())]
+    OutputWrite {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    #[error("Failed to write {0} calendars")]
+    OutputWriteFailures(usize),
+
+    #[error("Failed to write {0} index files")]
+    IndexWriteFailures(usize),
+
+    #[error("Found {0} anomalies in the source calendars")]
+    InputAnomalies(usize),
+
+    #[error("Found {0} discrepancies when parsing the calendars with the icalendar crate")]
+    CrossValidationFailures(usize),
+
+    #[error("Total size of the calendars grew from {previous} to {current} bytes")]
+    OutputSizeGrowth { previous: usize, current: usize },
+
+    #[error("Refusing to clear the cache without --url-pattern or --all")]
+    NothingToClear,
+
+    #[error("Refusing to follow redirect from '{from}' to '{to}' on a different host")]
+    RedirectOffHost { from: String, to: String },
+
+    #[error("Another run is already in progress, the lock is held by {0}")]
+    AlreadyRunning(String),
+
+    #[error("No recorded response for '{0}'")]
+    UnrecordedUrl(String),
+
+    #[error("Could not reach {host} – check your network connection")]
+    Unreachable {
+        host: String,
+        #[source]
+        source: Box<Self>,
+    },
+
+    #[error("Deadline exceeded before '{0}' could be downloaded")]
+    DeadlineExceeded(String),
+}
+
+impl Error {
+    /// Whether this is a network problem which might be gone when retrying, like a DNS or TLS
+    /// failure or a timeout.
+    #[must_use]
+    pub fn is_transient(&self) -> bool {
+        if let Self::Reqwest(err) = self {
+            err.is_connect() || err.is_timeout()
+        } else {
+            false
+        }
+    }
 }
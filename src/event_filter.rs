@@ -0,0 +1,400 @@
+//! Rules dropping events by module, summary, location, weekday or time
+
+use crate::calendar::{PROPERTY_NAME_DTSTART, PROPERTY_NAME_LOCATION, PROPERTY_NAME_SUMMARY};
+use crate::prelude::*;
+use crate::{datetime, props};
+use chrono::{Datelike, NaiveTime, Weekday};
+use ical::parser::ical::component::IcalEvent;
+use regex::{Regex, RegexBuilder};
+use serde::Deserialize;
+
+/// Conditions of a rule as written in the config file or with `--drop-events`. An event is
+/// dropped if it matches all conditions of the rule.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EventFilterRule {
+    /// Glob matched against the whole module name, like `Mathematik*`.
+    pub module: Option<String>,
+    /// Regex matched against the summary, ignoring case.
+    pub summary: Option<String>,
+    /// Regex matched against the location, ignoring case.
+    pub location: Option<String>,
+    /// Weekday of the start, like `sat`, `Saturday` or `Sa`.
+    pub weekday: Option<String>,
+    /// Start times like `00:00-08:00`, excluding the end.
+    pub time: Option<String>,
+}
+
+impl EventFilterRule {
+    /// Parses rules of the form `<field>=<value>;<field>=<value>`, e.g.
+    /// `module=Mathematik*;location=^Online$`.
+    pub fn parse(rule: &str) -> Result<Self> {
+        let invalid = || Error::InvalidEventFilter(rule.to_owned());
+
+        let mut parsed = Self::default();
+        for condition in rule
+            .split(';')
+            .filter(|condition| !condition.trim().is_empty())
+        {
+            let (field, value) = condition.split_once('=').ok_or_else(invalid)?;
+            let value = Some(value.to_owned());
+
+            match field.trim() {
+                "module" => parsed.module = value,
+                "summary" => parsed.summary = value,
+                "location" => parsed.location = value,
+                "weekday" => parsed.weekday = value,
+                "time" => parsed.time = value,
+                _ => return Err(invalid()),
+            }
+        }
+
+        Ok(parsed)
+    }
+}
+
+impl std::fmt::Display for EventFilterRule {
+    /// Writes the rule in the form of `--drop-events`.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let conditions = [
+            ("module", &self.module),
+            ("summary", &self.summary),
+            ("location", &self.location),
+            ("weekday", &self.weekday),
+            ("time", &self.time),
+        ]
+        .into_iter()
+        .filter_map(|(field, value)| value.as_ref().map(|value| format!("{field}={value}")))
+        .collect::<Vec<_>>();
+
+        write!(f, "{}", conditions.join(";"))
+    }
+}
+
+/// A compiled rule dropping every event which matches all of its conditions.
+#[derive(Debug)]
+struct FilterRule {
+    /// The rule as given, for the logs.
+    rule: String,
+    module: Option<Regex>,
+    summary: Option<Regex>,
+    location: Option<Regex>,
+    weekday: Option<Weekday>,
+    /// Start times from the first up to, but excluding, the second.
+    time: Option<(NaiveTime, NaiveTime)>,
+    /// Number of events dropped by this rule.
+    matched: usize,
+}
+
+impl FilterRule {
+    fn compile(rule: &EventFilterRule) -> Result<Self> {
+        let invalid = || Error::InvalidEventFilter(rule.to_string());
+
+        // A rule without conditions would drop every event
+        if *rule == EventFilterRule::default() {
+            return Err(invalid());
+        }
+
+        Ok(Self {
+            rule: rule.to_string(),
+            module: rule
+                .module
+                .as_deref()
+                .map(|glob| glob_regex(glob.trim()))
+                .transpose()?,
+            summary: rule
+                .summary
+                .as_deref()
+                .map(case_insensitive_regex)
+                .transpose()?,
+            location: rule
+                .location
+                .as_deref()
+                .map(case_insensitive_regex)
+                .transpose()?,
+            weekday: rule
+                .weekday
+                .as_deref()
+                .map(|weekday| parse_weekday(weekday).ok_or_else(invalid))
+                .transpose()?,
+            time: rule
+                .time
+                .as_deref()
+                .map(|time| {
+                    time.split_once('-')
+                        .and_then(|(from, to)| Some((parse_time(from)?, parse_time(to)?)))
+                        .ok_or_else(invalid)
+                })
+                .transpose()?,
+            matched: 0,
+        })
+    }
+
+    fn matches(&self, module: &str, event: &IcalEvent) -> bool {
+        let value_matches = |regex: Option<&Regex>, name: &str| {
+            regex.map_or(true, |regex| {
+                props::get_value(event, name).is_some_and(|value| regex.is_match(value))
+            })
+        };
+
+        // All-day events have no start time, so they never match a time range
+        let start = props::get_value(event, PROPERTY_NAME_DTSTART)
+            .and_then(|value| datetime::parse_date_time(value).map(|start| (value, start)));
+        let weekday_matches = self.weekday.map_or(true, |weekday| {
+            start.is_some_and(|(_, start)| start.weekday() == weekday)
+        });
+        let time_matches = self.time.map_or(true, |(from, to)| {
+            start.is_some_and(|(value, start)| {
+                !datetime::is_date_only(value) && from <= start.time() && start.time() < to
+            })
+        });
+
+        self.module
+            .as_ref()
+            .map_or(true, |module_regex| module_regex.is_match(module))
+            && value_matches(self.summary.as_ref(), PROPERTY_NAME_SUMMARY)
+            && value_matches(self.location.as_ref(), PROPERTY_NAME_LOCATION)
+            && weekday_matches
+            && time_matches
+    }
+}
+
+/// Compiled list of event filter rules. An event is dropped if any rule matches it.
+#[derive(Debug)]
+pub struct EventFilter {
+    rules: Vec<FilterRule>,
+}
+
+impl EventFilter {
+    /// Compiles the given rules, failing on the first invalid one.
+    pub fn new(rules: &[EventFilterRule]) -> Result<Self> {
+        Ok(Self {
+            rules: rules
+                .iter()
+                .map(FilterRule::compile)
+                .collect::<Result<Vec<_>>>()?,
+        })
+    }
+
+    /// Returns the first rule matching the event of `module` and counts the match.
+    pub fn find_match(&mut self, module: &str, event: &IcalEvent) -> Option<&str> {
+        self.rules
+            .iter_mut()
+            .find(|rule| rule.matches(module, event))
+            .map(|rule| {
+                rule.matched += 1;
+                rule.rule.as_str()
+            })
+    }
+
+    /// Logs how many events every rule dropped, warning about rules which never matched.
+    pub fn log_matches(&self) {
+        for rule in &self.rules {
+            if rule.matched == 0 {
+                warn!("Event filter '{}' didn't match any event", rule.rule);
+            } else {
+                info!(
+                    "Event filter '{}' dropped {} events",
+                    rule.rule, rule.matched
+                );
+            }
+        }
+    }
+}
+
+fn case_insensitive_regex(pattern: &str) -> Result<Regex> {
+    Ok(RegexBuilder::new(pattern).case_insensitive(true).build()?)
+}
+
+/// Turns a glob with `*` and `?` into a regex matching the whole module name.
+fn glob_regex(glob: &str) -> Result<Regex> {
+    let pattern: String = glob
+        .chars()
+        .map(|character| match character {
+            '*' => ".*".to_owned(),
+            '?' => ".".to_owned(),
+            other => regex::escape(&other.to_string()),
+        })
+        .collect();
+
+    case_insensitive_regex(&format!("^{pattern}$"))
+}
+
+/// Parses English weekday names like `sat` or `Saturday` as well as German abbreviations like `Sa`.
+fn parse_weekday(value: &str) -> Option<Weekday> {
+    match value.trim().to_lowercase().as_str() {
+        "mo" => Some(Weekday::Mon),
+        "di" => Some(Weekday::Tue),
+        "mi" => Some(Weekday::Wed),
+        "do" => Some(Weekday::Thu),
+        "fr" => Some(Weekday::Fri),
+        "sa" => Some(Weekday::Sat),
+        "so" => Some(Weekday::Sun),
+        other => other.parse().ok(),
+    }
+}
+
+fn parse_time(value: &str) -> Option<NaiveTime> {
+    NaiveTime::parse_from_str(value.trim(), "%H:%M").ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    fn event(summary: &str, location: &str, start: &str) -> IcalEvent {
+        let calendar = testing::calendar_of(&[testing::event(summary, start)
+            .replace("LOCATION:C-12.01", &format!("LOCATION:{location}"))]);
+
+        ical::IcalParser::new(calendar.as_bytes())
+            .flat_map(|calendar| calendar.unwrap().events)
+            .next()
+            .unwrap()
+    }
+
+    fn filter(rules: &[&str]) -> EventFilter {
+        let rules: Vec<EventFilterRule> = rules
+            .iter()
+            .map(|rule| EventFilterRule::parse(rule).unwrap())
+            .collect();
+
+        EventFilter::new(&rules).unwrap()
+    }
+
+    /// Whether `event` of `module` is dropped by a single `rule`.
+    fn drops(rule: &str, module: &str, event: &IcalEvent) -> bool {
+        filter(&[rule]).find_match(module, event).is_some()
+    }
+
+    #[test]
+    fn every_field_is_matched() {
+        // Monday 2024-10-14 at 08:15
+        let online = event("Mathe Übung", "Online", "20241014T081500");
+
+        assert!(
+            drops("module=mathe*", "Mathematik 1", &online),
+            "the module glob should match ignoring case"
+        );
+        assert!(
+            !drops("module=Mathe", "Mathematik 1", &online),
+            "the module glob should match the whole name"
+        );
+        assert!(
+            drops("summary=übung$", "Mathematik 1", &online),
+            "the summary regex should match ignoring case"
+        );
+        assert!(
+            drops("location=^Online$", "Mathematik 1", &online),
+            "the location regex should match"
+        );
+        assert!(
+            !drops("location=^C-", "Mathematik 1", &online),
+            "another location shouldn't match"
+        );
+        assert!(
+            drops("weekday=Mo", "Mathematik 1", &online),
+            "the German weekday should match"
+        );
+        assert!(
+            drops("weekday=monday", "Mathematik 1", &online),
+            "the English weekday should match"
+        );
+        assert!(
+            !drops("weekday=sat", "Mathematik 1", &online),
+            "another weekday shouldn't match"
+        );
+        assert!(
+            drops("time=08:00-08:16", "Mathematik 1", &online),
+            "a start inside the range should match"
+        );
+        assert!(
+            !drops("time=07:00-08:15", "Mathematik 1", &online),
+            "the end of the range should be excluded"
+        );
+    }
+
+    #[test]
+    fn all_day_events_never_match_a_time_range() {
+        let calendar = testing::calendar_of(&[
+            "BEGIN:VEVENT\r\nUID:a\r\nSUMMARY:Feiertag\r\nDTSTART;VALUE=DATE:20241014\r\nEND:VEVENT\r\n".to_owned(),
+        ]);
+        let all_day = ical::IcalParser::new(calendar.as_bytes())
+            .flat_map(|calendar| calendar.unwrap().events)
+            .next()
+            .unwrap();
+
+        assert!(
+            !drops("time=00:00-23:59", "Feiertag", &all_day),
+            "an all-day event has no start time"
+        );
+        assert!(
+            drops("weekday=mo", "Feiertag", &all_day),
+            "an all-day event still has a weekday"
+        );
+    }
+
+    #[test]
+    fn all_conditions_of_a_rule_must_match() {
+        let online = event("Mathe", "Online", "20241014T081500");
+        let room = event("Mathe", "C-12.01", "20241014T081500");
+
+        assert!(
+            drops("module=Mathe;location=Online", "Mathe", &online),
+            "an event matching every condition should be dropped"
+        );
+        assert!(
+            !drops("module=Mathe;location=Online", "Mathe", &room),
+            "an event matching only some conditions should be kept"
+        );
+        assert!(
+            !drops("module=Physik;location=Online", "Mathe", &online),
+            "an event of another module should be kept"
+        );
+    }
+
+    #[test]
+    fn any_rule_may_match_and_matches_are_counted_per_rule() {
+        let mut filter = filter(&["location=Online", "weekday=sa", "module=Physik"]);
+        let events = [
+            event("Mathe", "Online", "20241014T081500"),
+            event("Mathe", "C-12.01", "20241019T081500"),
+            event("Mathe", "Online", "20241019T081500"),
+            event("Mathe", "C-12.01", "20241014T081500"),
+        ];
+
+        let matches: Vec<Option<String>> = events
+            .iter()
+            .map(|event| filter.find_match("Mathe", event).map(str::to_owned))
+            .collect();
+
+        assert_eq!(
+            matches,
+            [
+                Some("location=Online".to_owned()),
+                Some("weekday=sa".to_owned()),
+                Some("location=Online".to_owned()),
+                None
+            ],
+            "an event should be dropped by the first rule it matches"
+        );
+        let counts: Vec<usize> = filter.rules.iter().map(|rule| rule.matched).collect();
+        assert_eq!(counts, [2, 1, 0], "the matches should be counted per rule");
+    }
+
+    #[test]
+    fn invalid_rules_are_rejected() {
+        for rule in [
+            "",
+            "room=Online",
+            "weekday=someday",
+            "time=8-9",
+            "summary=(",
+        ] {
+            let result = EventFilterRule::parse(rule)
+                .and_then(|rule| EventFilter::new(std::slice::from_ref(&rule)));
+
+            assert!(result.is_err(), "'{rule}' should be rejected");
+        }
+    }
+}
@@ -0,0 +1,228 @@
+//! Exit codes of the command line tool, kept stable so scripts can rely on them
+
+use crate::prelude::*;
+use std::any::Any;
+use std::panic::UnwindSafe;
+
+/// Why the process ended. The numbers are part of the interface and must never change, new
+/// codes get a new number.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum ExitCode {
+    Success = 0,
+    /// Any failure without a more specific code, like an invalid argument.
+    Failure = 1,
+    /// The run stopped early or failed to write some files and only produced partial output.
+    Partial = 2,
+    /// The site could not be reached at all, e.g. because the network is down.
+    Unreachable = 3,
+    /// A check enabled with `--strict`, `--strict-input` or `--cross-validate` failed.
+    StrictFailure = 4,
+    /// Another run holds the lock.
+    LockHeld = 5,
+    /// A response needed with `--replay` was never recorded.
+    NotRecorded = 6,
+    /// The published calendars don't match the local output.
+    VerificationFailed = 7,
+    /// No module or several modules match the name given to `next`.
+    NoSuchModule = 8,
+    /// The program panicked, which is always a bug.
+    Panic = 101,
+}
+
+impl ExitCode {
+    pub const ALL: [Self; 10] = [
+        Self::Success,
+        Self::Failure,
+        Self::Partial,
+        Self::Unreachable,
+        Self::StrictFailure,
+        Self::LockHeld,
+        Self::NotRecorded,
+        Self::VerificationFailed,
+        Self::NoSuchModule,
+        Self::Panic,
+    ];
+
+    /// Returns the exit code a run failing with `err` ends with.
+    #[must_use]
+    pub const fn for_error(err: &Error) -> Self {
+        match err {
+            Error::Unreachable { .. } => Self::Unreachable,
+            Error::AlreadyRunning(_) => Self::LockHeld,
+            Error::UnrecordedUrl(_) => Self::NotRecorded,
+            Error::InputAnomalies(_)
+            | Error::OutputWriteFailures(_)
+            | Error::IndexWriteFailures(_)
+            | Error::OutputSizeGrowth { .. }
+            | Error::CrossValidationFailures(_) => Self::StrictFailure,
+            Error::RequestFailed(_)
+            | Error::EmptyResponse(_)
+            | Error::ErrorPage { .. }
+            | Error::ResponseTooLarge { .. }
+            | Error::UnsupportedContentEncoding(_)
+            | Error::UnsafePathComponent { .. }
+            | Error::PathOutsideOutput(_)
+            | Error::IO(_)
+            | Error::ColorEyreReport(_)
+            | Error::Reqwest(_)
+            | Error::Tls(_)
+            | Error::ThreadPool(_)
+            | Error::TracingDispatcherSetGlobalDefault(_)
+            | Error::RegexPattern(_)
+            | Error::Json(_)
+            | Error::Fmt(_)
+            | Error::Xml(_)
+            | Error::InvalidUrl(_)
+            | Error::InvalidArgument(_)
+            | Error::MissingArgumentValue(_)
+            | Error::InvalidArgumentValue(..)
+            | Error::InvalidDuration(_)
+            | Error::InvalidOutputTemplate(..)
+            | Error::InvalidSummaryTemplate(..)
+            | Error::InvalidEventFilter(_)
+            | Error::InvalidSourceExclusion(..)
+            | Error::InvalidErrorPageMarker(..)
+            | Error::InvalidNamePattern(..)
+            | Error::InvalidRevisionPattern(..)
+            | Error::InvalidProxy(_)
+            | Error::InvalidProxyUser
+            | Error::InvalidCaCertificate { .. }
+            | Error::InvalidConfigFile { .. }
+            | Error::CertificateMismatch { .. }
+            | Error::InvalidUserAgent(_)
+            | Error::ManifestFormat
+            | Error::OutputWrite { .. }
+            | Error::NothingToClear
+            | Error::RedirectOffHost { .. }
+            | Error::DeadlineExceeded(_) => Self::Failure,
+        }
+    }
+
+    /// Name of the code as shown by `--print-exit-codes`.
+    #[must_use]
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Success => "success",
+            Self::Failure => "failure",
+            Self::Partial => "partial",
+            Self::Unreachable => "unreachable",
+            Self::StrictFailure => "strict-failure",
+            Self::LockHeld => "lock-held",
+            Self::NotRecorded => "not-recorded",
+            Self::VerificationFailed => "verification-failed",
+            Self::NoSuchModule => "no-such-module",
+            Self::Panic => "panic",
+        }
+    }
+
+    /// What the code means, as shown by `--print-exit-codes`.
+    #[must_use]
+    pub const fn description(self) -> &'static str {
+        match self {
+            Self::Success => "Everything worked",
+            Self::Failure => "Failed for a reason without a more specific code",
+            Self::Partial => "Only partial output was produced",
+            Self::Unreachable => "The site could not be reached at all",
+            Self::StrictFailure => {
+                "A check enabled with --strict, --strict-input or --cross-validate failed"
+            }
+            Self::LockHeld => "Another run is already in progress",
+            Self::NotRecorded => "A response needed with --replay was never recorded",
+            Self::VerificationFailed => "The published calendars don't match the local output",
+            Self::NoSuchModule => "No module or several modules match the name given to next",
+            Self::Panic => "The program crashed, please report this as a bug",
+        }
+    }
+}
+
+impl From<ExitCode> for std::process::ExitCode {
+    fn from(code: ExitCode) -> Self {
+        Self::from(code as u8)
+    }
+}
+
+/// Prints every exit code with its name and description, one per line.
+pub fn print_exit_codes() {
+    for code in ExitCode::ALL {
+        println!(
+            "{:>3}  {:<19}  {}",
+            code as u8,
+            code.name(),
+            code.description()
+        );
+    }
+}
+
+/// Runs `run` and returns its exit code, or `ExitCode::Panic` after logging the message if it
+/// panicked. Scripts rely on the exit code, so a panic must not end with the generic code of the
+/// runtime.
+pub fn catch_panic<F: FnOnce() -> ExitCode + UnwindSafe>(run: F) -> ExitCode {
+    match std::panic::catch_unwind(run) {
+        Ok(code) => code,
+        Err(payload) => {
+            error!("Panicked: {}", panic_message(payload.as_ref()));
+            ExitCode::Panic
+        }
+    }
+}
+
+/// Extracts the message of a panic, which is a `&str` or a `String` unless the panic was raised
+/// with a custom payload.
+fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    payload
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("unknown cause")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Panics like a bug deep inside the pipeline would, without printing the panic.
+    fn broken_module(payload: Box<dyn Any + Send>) -> ExitCode {
+        std::panic::resume_unwind(payload)
+    }
+
+    #[test]
+    fn a_panic_is_contained_and_mapped_to_its_code() {
+        assert_eq!(
+            catch_panic(|| broken_module(Box::new("index out of bounds"))),
+            ExitCode::Panic,
+            "a panic should end with the panic code"
+        );
+        assert_eq!(
+            catch_panic(|| ExitCode::Partial),
+            ExitCode::Partial,
+            "the code of a run without a panic should be kept"
+        );
+    }
+
+    #[test]
+    fn the_panic_message_is_reported() {
+        let message = |payload: Box<dyn Any + Send>| {
+            // The payload is only moved into the closure, nothing is left half updated
+            std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| broken_module(payload)))
+                .map_err(|payload| panic_message(payload.as_ref()).to_owned())
+                .err()
+        };
+
+        assert_eq!(
+            message(Box::new("index out of bounds")),
+            Some("index out of bounds".to_owned()),
+            "the message of a panic with a literal should be reported"
+        );
+        assert_eq!(
+            message(Box::new(format!("no module '{}'", "Mathe"))),
+            Some("no module 'Mathe'".to_owned()),
+            "the message of a panic with a formatted message should be reported"
+        );
+        assert_eq!(
+            message(Box::new(42)),
+            Some("unknown cause".to_owned()),
+            "a custom payload should still be reported"
+        );
+    }
+}
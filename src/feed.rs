@@ -0,0 +1,461 @@
+//! Atom feed of the events which changed in the module calendars, one entry per module and run
+
+use crate::build_info::GENERATOR;
+use crate::datetime;
+use crate::manifest::sha256_hex;
+use crate::prelude::*;
+use crate::state::EventState;
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+use quick_xml::events::{BytesDecl, BytesEnd, BytesStart, BytesText, Event};
+use quick_xml::{Reader, Writer};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Path of the Atom feed in the output folder.
+pub const FEED_FILE: &str = "feed.xml";
+
+/// Number of runs whose changes stay in the feed.
+const FEED_MAX_RUNS: usize = 20;
+
+const ATOM_NAMESPACE: &str = "http://www.w3.org/2005/Atom";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EventChangeKind {
+    Added,
+    Removed,
+    /// The start of the event changed.
+    Moved,
+    /// Anything but the start of the event changed.
+    Changed,
+}
+
+impl EventChangeKind {
+    const fn label(self) -> &'static str {
+        match self {
+            Self::Added => "Neu",
+            Self::Removed => "Entfällt",
+            Self::Moved => "Verschoben",
+            Self::Changed => "Geändert",
+        }
+    }
+}
+
+/// A single event which changed since the previous run.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EventChange {
+    pub kind: EventChangeKind,
+    /// DTSTART of the event, the previous one for removed events.
+    pub start: String,
+    /// DTSTART before the event was moved.
+    #[serde(default)]
+    pub previous_start: Option<String>,
+}
+
+/// The changes of a module in a single run.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FeedEntry {
+    /// Atom ID of the entry, assigned once so readers don't show the entry twice.
+    pub id: String,
+    pub module: String,
+    /// Path of the module calendar in the output folder.
+    pub path: String,
+    pub updated: DateTime<Utc>,
+    pub changes: Vec<EventChange>,
+}
+
+impl FeedEntry {
+    pub fn new(
+        key: &str,
+        module: String,
+        path: String,
+        updated: DateTime<Utc>,
+        changes: Vec<EventChange>,
+    ) -> Self {
+        let id = format!(
+            "urn:fh-kiel-ical-splitter:{}",
+            sha256_hex(format!("{key}\n{}", updated.to_rfc3339()).as_bytes())
+        );
+
+        Self {
+            id,
+            module,
+            path,
+            updated,
+            changes,
+        }
+    }
+}
+
+/// Returns the changes of the events of the module `key` compared to the previous run, ordered by
+/// their start.
+pub fn event_changes(
+    previous: &BTreeMap<String, EventState>,
+    current: &BTreeMap<String, EventState>,
+    key: &str,
+) -> Vec<EventChange> {
+    let mut changes: Vec<EventChange> = current
+        .iter()
+        .filter_map(|(uid, event)| {
+            let Some(previous) = previous.get(uid) else {
+                return Some(EventChange {
+                    kind: EventChangeKind::Added,
+                    start: event.start.clone(),
+                    previous_start: None,
+                });
+            };
+
+            if previous.start != event.start {
+                Some(EventChange {
+                    kind: EventChangeKind::Moved,
+                    start: event.start.clone(),
+                    previous_start: Some(previous.start.clone()),
+                })
+            } else if previous.content_hash == event.content_hash {
+                None
+            } else {
+                Some(EventChange {
+                    kind: EventChangeKind::Changed,
+                    start: event.start.clone(),
+                    previous_start: None,
+                })
+            }
+        })
+        .collect();
+
+    changes.extend(
+        previous
+            .iter()
+            .filter(|(uid, event)| event.module == key && !current.contains_key(*uid))
+            .map(|(_, event)| EventChange {
+                kind: EventChangeKind::Removed,
+                start: event.start.clone(),
+                previous_start: None,
+            }),
+    );
+
+    changes.sort_by(|a, b| a.start.cmp(&b.start));
+
+    changes
+}
+
+/// Adds the entries of this run to the ones of previous runs, keeping the last runs only.
+pub fn update_feed(previous: &[FeedEntry], current: Vec<FeedEntry>) -> Vec<FeedEntry> {
+    let mut entries: Vec<FeedEntry> = current
+        .into_iter()
+        .chain(previous.iter().cloned())
+        .collect();
+
+    let runs: BTreeSet<DateTime<Utc>> = entries.iter().map(|entry| entry.updated).collect();
+    if let Some(oldest) = runs.iter().rev().nth(FEED_MAX_RUNS.saturating_sub(1)) {
+        let oldest = *oldest;
+        entries.retain(|entry| entry.updated >= oldest);
+    }
+
+    // Newest first
+    entries.sort_by(|a, b| {
+        b.updated
+            .cmp(&a.updated)
+            .then_with(|| a.module.cmp(&b.module))
+    });
+
+    entries
+}
+
+/// Renders the feed, linking every entry to the module calendar below `link_prefix` and showing
+/// the times of the events in `display_zone`. The result is parsed again to make sure no reader
+/// chokes on it.
+pub fn render_feed(
+    entries: &[FeedEntry],
+    link_prefix: &str,
+    now: DateTime<Utc>,
+    display_zone: Tz,
+) -> Result<String> {
+    let mut writer = Writer::new_with_indent(Vec::new(), b' ', 2);
+    let updated = entries.first().map_or(now, |entry| entry.updated);
+
+    writer.write_event(Event::Decl(BytesDecl::new("1.0", Some("utf-8"), None)))?;
+    writer.write_event(Event::Start(
+        BytesStart::new("feed").with_attributes([("xmlns", ATOM_NAMESPACE)]),
+    ))?;
+    write_text_element(&mut writer, "id", "urn:fh-kiel-ical-splitter:feed")?;
+    write_text_element(
+        &mut writer,
+        "title",
+        "Änderungen der Vorlesungspläne der FH-Kiel",
+    )?;
+    write_text_element(&mut writer, "updated", &updated.to_rfc3339())?;
+    write_text_element(&mut writer, "generator", GENERATOR)?;
+    writer.write_event(Event::Empty(BytesStart::new("link").with_attributes([
+        ("rel", "self"),
+        ("href", format!("{link_prefix}{FEED_FILE}").as_str()),
+    ])))?;
+    writer.write_event(Event::Start(BytesStart::new("author")))?;
+    write_text_element(&mut writer, "name", "fh_kiel_ical_splitter")?;
+    writer.write_event(Event::End(BytesEnd::new("author")))?;
+
+    for entry in entries {
+        let content = entry
+            .changes
+            .iter()
+            .map(|change| describe_change(change, display_zone))
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        writer.write_event(Event::Start(BytesStart::new("entry")))?;
+        write_text_element(&mut writer, "id", &entry.id)?;
+        write_text_element(&mut writer, "title", &format!("Geändert: {}", entry.module))?;
+        write_text_element(&mut writer, "updated", &entry.updated.to_rfc3339())?;
+        writer.write_event(Event::Empty(BytesStart::new("link").with_attributes([(
+            "href",
+            format!("{link_prefix}{}", entry.path).as_str(),
+        )])))?;
+        writer.write_event(Event::Start(
+            BytesStart::new("content").with_attributes([("type", "text")]),
+        ))?;
+        writer.write_event(Event::Text(BytesText::new(&content)))?;
+        writer.write_event(Event::End(BytesEnd::new("content")))?;
+        writer.write_event(Event::End(BytesEnd::new("entry")))?;
+    }
+
+    writer.write_event(Event::End(BytesEnd::new("feed")))?;
+
+    let feed = String::from_utf8_lossy(&writer.into_inner()).into_owned();
+    check_well_formed(&feed)?;
+
+    Ok(feed)
+}
+
+fn write_text_element(writer: &mut Writer<Vec<u8>>, name: &str, text: &str) -> Result<()> {
+    writer.write_event(Event::Start(BytesStart::new(name)))?;
+    writer.write_event(Event::Text(BytesText::new(text)))?;
+    writer.write_event(Event::End(BytesEnd::new(name)))?;
+
+    Ok(())
+}
+
+/// Parses `xml` completely, failing on mismatched tags or invalid escapes.
+fn check_well_formed(xml: &str) -> Result<()> {
+    let mut reader = Reader::from_str(xml);
+
+    loop {
+        match reader.read_event()? {
+            Event::Text(text) => {
+                text.unescape()?;
+            }
+            Event::Eof => return Ok(()),
+            Event::Start(_)
+            | Event::End(_)
+            | Event::Empty(_)
+            | Event::CData(_)
+            | Event::Comment(_)
+            | Event::Decl(_)
+            | Event::PI(_)
+            | Event::DocType(_) => {}
+        }
+    }
+}
+
+/// One line of the content of an entry, e.g. `Verschoben: 03.04.2025 10:00 → 10.04.2025 10:00`.
+pub fn describe_change(change: &EventChange, display_zone: Tz) -> String {
+    let label = change.kind.label();
+    let start = format_start(&change.start, display_zone);
+
+    change.previous_start.as_ref().map_or_else(
+        || format!("{label}: {start}"),
+        |previous_start| {
+            format!(
+                "{label}: {} → {start}",
+                format_start(previous_start, display_zone)
+            )
+        },
+    )
+}
+
+fn format_start(value: &str, display_zone: Tz) -> String {
+    datetime::parse_display_time(value, None, display_zone).map_or_else(
+        || value.to_owned(),
+        |date_time| date_time.format("%d.%m.%Y %H:%M").to_string(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+    use chrono::TimeZone;
+
+    fn event(module: &str, start: &str, content_hash: &str) -> EventState {
+        EventState {
+            content_hash: content_hash.to_owned(),
+            sequence: 0,
+            module: module.to_owned(),
+            start: start.to_owned(),
+        }
+    }
+
+    fn run_at(hour: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 10, 14, hour, 0, 0).unwrap()
+    }
+
+    fn entry(module: &str, updated: DateTime<Utc>) -> FeedEntry {
+        FeedEntry::new(
+            module,
+            module.to_owned(),
+            format!("iue/WiSe_2425/semester_1/{module}.ics"),
+            updated,
+            vec![EventChange {
+                kind: EventChangeKind::Added,
+                start: "20241014T081500".to_owned(),
+                previous_start: None,
+            }],
+        )
+    }
+
+    #[test]
+    fn event_changes_are_classified_and_sorted_by_start() {
+        let previous = BTreeMap::from([
+            ("moved".to_owned(), event("Mathe", "20241015T081500", "a")),
+            ("changed".to_owned(), event("Mathe", "20241016T081500", "b")),
+            ("kept".to_owned(), event("Mathe", "20241017T081500", "c")),
+            ("removed".to_owned(), event("Mathe", "20241018T081500", "d")),
+            ("other".to_owned(), event("Physik", "20241019T081500", "e")),
+        ]);
+        let current = BTreeMap::from([
+            ("moved".to_owned(), event("Mathe", "20241022T081500", "a")),
+            ("changed".to_owned(), event("Mathe", "20241016T081500", "x")),
+            ("kept".to_owned(), event("Mathe", "20241017T081500", "c")),
+            ("added".to_owned(), event("Mathe", "20241014T081500", "f")),
+        ]);
+
+        let changes = event_changes(&previous, &current, "Mathe");
+
+        let kinds: Vec<(EventChangeKind, &str)> = changes
+            .iter()
+            .map(|change| (change.kind, change.start.as_str()))
+            .collect();
+        assert_eq!(
+            kinds,
+            [
+                (EventChangeKind::Added, "20241014T081500"),
+                (EventChangeKind::Changed, "20241016T081500"),
+                (EventChangeKind::Removed, "20241018T081500"),
+                (EventChangeKind::Moved, "20241022T081500"),
+            ],
+            "unchanged events and those of other modules shouldn't be listed"
+        );
+        assert_eq!(
+            changes
+                .last()
+                .and_then(|change| change.previous_start.clone()),
+            Some("20241015T081500".to_owned()),
+            "a moved event should keep its previous start"
+        );
+    }
+
+    #[test]
+    fn entry_ids_are_stable_and_unique_per_run() {
+        assert_eq!(
+            entry("Mathe", run_at(8)).id,
+            entry("Mathe", run_at(8)).id,
+            "the same module and run should get the same ID"
+        );
+        assert_ne!(
+            entry("Mathe", run_at(8)).id,
+            entry("Mathe", run_at(9)).id,
+            "another run should get another ID"
+        );
+        assert_ne!(
+            entry("Mathe", run_at(8)).id,
+            entry("Physik", run_at(8)).id,
+            "another module should get another ID"
+        );
+    }
+
+    #[test]
+    fn only_the_last_runs_are_kept_newest_first() {
+        let previous: Vec<FeedEntry> = (0..u32::try_from(FEED_MAX_RUNS).unwrap())
+            .rev()
+            .map(|hour| entry("Mathe", run_at(hour)))
+            .collect();
+
+        let entries = update_feed(&previous, vec![entry("Physik", run_at(23))]);
+
+        assert_eq!(
+            entries.len(),
+            FEED_MAX_RUNS,
+            "the oldest run should be dropped"
+        );
+        assert_eq!(
+            entries.first().map(|entry| entry.module.as_str()),
+            Some("Physik"),
+            "the entry of this run should come first"
+        );
+        assert!(
+            entries.iter().all(|entry| entry.updated > run_at(0)),
+            "the entry of the oldest run should be gone"
+        );
+        assert_eq!(
+            update_feed(&previous, Vec::new()),
+            previous,
+            "a run without changes shouldn't add entries"
+        );
+    }
+
+    #[test]
+    fn the_rendered_feed_can_be_parsed_back() {
+        let mut moved = entry("Mathe & <Statistik>", run_at(9));
+        moved.changes = vec![
+            EventChange {
+                kind: EventChangeKind::Moved,
+                start: "20241021T081500".to_owned(),
+                previous_start: Some("20241014T081500".to_owned()),
+            },
+            EventChange {
+                kind: EventChangeKind::Removed,
+                start: "20241028T081500".to_owned(),
+                previous_start: None,
+            },
+        ];
+        let entries = [moved.clone(), entry("Physik", run_at(8))];
+
+        let feed = render_feed(&entries, "/", run_at(10), chrono_tz::Europe::Berlin).unwrap();
+
+        let parsed = testing::feed_entries(&feed);
+        let field = |index: usize, name: &str| {
+            parsed
+                .get(index)
+                .and_then(|entry| entry.get(name))
+                .cloned()
+                .unwrap_or_default()
+        };
+        assert_eq!(parsed.len(), 2, "every entry should be in the feed");
+        assert_eq!(field(0, "id"), moved.id, "the ID should be kept");
+        assert_eq!(
+            field(0, "title"),
+            "Geändert: Mathe & <Statistik>",
+            "special characters in the module name should survive"
+        );
+        assert_eq!(
+            field(0, "link"),
+            "/iue/WiSe_2425/semester_1/Mathe & <Statistik>.ics",
+            "the entry should link the module calendar"
+        );
+        assert_eq!(
+            field(0, "updated"),
+            "2024-10-14T09:00:00+00:00",
+            "the time of the run should be the update time"
+        );
+        assert_eq!(
+            field(0, "content"),
+            "Verschoben: 14.10.2024 08:15 → 21.10.2024 08:15\nEntfällt: 28.10.2024 08:15",
+            "every change should be a line of the content"
+        );
+        assert_eq!(
+            field(1, "title"),
+            "Geändert: Physik",
+            "the second entry should follow"
+        );
+    }
+}
@@ -0,0 +1,499 @@
+//! Fetching of websites, over HTTP, from recorded fixtures or from a local folder
+
+use crate::cache::CacheMetadata;
+use crate::manifest::sha256_hex;
+use crate::pinning;
+use crate::prelude::*;
+use crate::scrape;
+use chrono::{DateTime, Utc};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io::Read;
+use std::path::{Component, Path, PathBuf};
+
+/// Regexes matching the maintenance and error pages the server sometimes sends with a `200 OK`
+/// status instead of the requested page or calendar.
+pub const DEFAULT_ERROR_PAGE_MARKERS: &[&str] = &["Wartungsarbeiten", "Service Unavailable"];
+
+/// Content encodings the server may compress responses with. Decoded here instead of by reqwest,
+/// which drops the `Content-Encoding` and `Content-Length` headers while decoding.
+const ACCEPT_ENCODING: &str = "gzip, deflate, br";
+
+/// Buffer size of the brotli decoder.
+const BROTLI_BUFFER_SIZE: usize = 4096;
+
+/// Largest response body accepted unless overridden, before and after decoding.
+pub const DEFAULT_MAX_RESPONSE_SIZE: u64 = 10 * 1024 * 1024;
+
+/// A response with its body already read.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FetchResponse {
+    pub url: String,
+    /// Status code as a number, so fixtures stay readable.
+    pub status_code: u16,
+    /// Headers of the response as sent by the server, with lowercase names. `Content-Encoding`
+    /// and `Content-Length` describe the body as transferred, not the decoded `body`.
+    pub headers: BTreeMap<String, String>,
+    /// The body, always decoded no matter how it was transferred.
+    pub body: String,
+    /// Size of the body as transferred in bytes, `None` if not read from the network.
+    #[serde(default)]
+    pub encoded_size: Option<usize>,
+}
+
+impl FetchResponse {
+    /// Returns the status of the response. Invalid codes in edited fixtures count as server errors.
+    pub fn status(&self) -> StatusCode {
+        StatusCode::from_u16(self.status_code).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR)
+    }
+
+    /// Returns the value of the header `name`, which must be lowercase.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(name).map(String::as_str)
+    }
+
+    /// Reads the status, headers and body of `response`. Bodies larger than `max_size` bytes are
+    /// rejected without reading them completely.
+    fn read(url: &str, response: reqwest::blocking::Response, max_size: u64) -> Result<Self> {
+        let status_code = response.status().as_u16();
+        let headers: BTreeMap<String, String> = response
+            .headers()
+            .iter()
+            .filter_map(|(name, value)| {
+                value
+                    .to_str()
+                    .ok()
+                    .map(|value| (name.as_str().to_owned(), value.to_owned()))
+            })
+            .collect();
+
+        let too_large = || Error::ResponseTooLarge {
+            url: url.to_owned(),
+            limit: max_size,
+        };
+
+        // Don't even start reading a body which is announced to be too large
+        if headers
+            .get("content-length")
+            .and_then(|length| length.parse::<u64>().ok())
+            .is_some_and(|length| length > max_size)
+        {
+            return Err(too_large());
+        }
+
+        // The announced length can't be trusted, so stop reading one byte past the limit
+        let mut encoded = Vec::new();
+        response
+            .take(max_size.saturating_add(1))
+            .read_to_end(&mut encoded)
+            .map_err(map_read_error)?;
+        if exceeds(&encoded, max_size) {
+            return Err(too_large());
+        }
+
+        // A small compressed body can still expand enormously
+        let decoded = decode_body(&encoded, headers.get("content-encoding"), max_size)?;
+        if exceeds(&decoded, max_size) {
+            return Err(too_large());
+        }
+
+        Ok(Self {
+            url: url.to_owned(),
+            status_code,
+            headers,
+            body: String::from_utf8_lossy(&decoded).into_owned(),
+            encoded_size: Some(encoded.len()),
+        })
+    }
+}
+
+/// Whether `body` is longer than `max_size` bytes.
+fn exceeds(body: &[u8], max_size: u64) -> bool {
+    u64::try_from(body.len()).map_or(true, |length| length > max_size)
+}
+
+/// Error wrapped by an `io::Error`.
+type InnerError = dyn std::error::Error + Send + Sync;
+
+/// Keeps the underlying reqwest error of a failed read, so timeouts are still retried.
+fn map_read_error(err: std::io::Error) -> Error {
+    if !err.get_ref().is_some_and(InnerError::is::<reqwest::Error>) {
+        return Error::IO(err);
+    }
+
+    let kind = err.kind();
+    match err.into_inner().map(InnerError::downcast::<reqwest::Error>) {
+        Some(Ok(inner)) => Error::Reqwest(*inner),
+        Some(Err(inner)) => Error::IO(std::io::Error::new(kind, inner)),
+        None => Error::IO(kind.into()),
+    }
+}
+
+/// Decodes a body transferred with the content encoding `encoding`, which is a comma separated
+/// list of the encodings in the order they were applied. Decoding stops one byte past
+/// `max_size`.
+fn decode_body(body: &[u8], encoding: Option<&String>, max_size: u64) -> Result<Vec<u8>> {
+    // HEAD and 304 responses carry the header without a body
+    if body.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let limit = max_size.saturating_add(1);
+    let mut decoded = body.to_vec();
+
+    for encoding in encoding.into_iter().flat_map(|value| value.rsplit(',')) {
+        let encoding = encoding.trim().to_ascii_lowercase();
+        let mut output = Vec::new();
+
+        match encoding.as_str() {
+            "" | "identity" => continue,
+            "gzip" | "x-gzip" => {
+                flate2::read::MultiGzDecoder::new(decoded.as_slice())
+                    .take(limit)
+                    .read_to_end(&mut output)?;
+            }
+            // Supposed to be zlib wrapped, but some servers send raw deflate data
+            "deflate" => {
+                if flate2::read::ZlibDecoder::new(decoded.as_slice())
+                    .take(limit)
+                    .read_to_end(&mut output)
+                    .is_err()
+                {
+                    output.clear();
+                    flate2::read::DeflateDecoder::new(decoded.as_slice())
+                        .take(limit)
+                        .read_to_end(&mut output)?;
+                }
+            }
+            "br" => {
+                brotli_decompressor::Decompressor::new(decoded.as_slice(), BROTLI_BUFFER_SIZE)
+                    .take(limit)
+                    .read_to_end(&mut output)?;
+            }
+            _ => return Err(Error::UnsupportedContentEncoding(encoding)),
+        }
+
+        decoded = output;
+    }
+
+    Ok(decoded)
+}
+
+/// Fetches the content of a URL.
+pub trait Fetcher: Send + Sync {
+    /// Fetches `url`. With `conditional`, the server may answer with `304 Not Modified` if the
+    /// content didn't change since it was cached.
+    fn fetch(&self, url: &str, conditional: Option<&CacheMetadata>) -> Result<FetchResponse>;
+
+    /// Requests only the headers of `url`. Fetchers without HEAD support answer like a server
+    /// which doesn't allow it.
+    fn head(&self, url: &str) -> Result<FetchResponse> {
+        Ok(FetchResponse {
+            url: url.to_owned(),
+            status_code: StatusCode::METHOD_NOT_ALLOWED.as_u16(),
+            headers: BTreeMap::new(),
+            body: String::new(),
+            encoded_size: None,
+        })
+    }
+}
+
+/// Fetches over the network.
+pub struct HttpFetcher {
+    client: reqwest::blocking::Client,
+    /// Largest response body accepted, in bytes.
+    max_response_size: u64,
+}
+
+impl HttpFetcher {
+    pub const fn new(client: reqwest::blocking::Client) -> Self {
+        Self {
+            client,
+            max_response_size: DEFAULT_MAX_RESPONSE_SIZE,
+        }
+    }
+
+    /// Rejects response bodies larger than `max_response_size` bytes.
+    pub const fn with_max_response_size(mut self, max_response_size: u64) -> Self {
+        self.max_response_size = max_response_size;
+        self
+    }
+}
+
+impl Fetcher for HttpFetcher {
+    fn fetch(&self, url: &str, conditional: Option<&CacheMetadata>) -> Result<FetchResponse> {
+        let mut request = self
+            .client
+            .get(url)
+            .header(reqwest::header::ACCEPT_ENCODING, ACCEPT_ENCODING);
+
+        // Ask the server to only send the content if it changed since we cached it
+        if let Some(metadata) = conditional {
+            if let Some(etag) = &metadata.etag {
+                request = request.header(reqwest::header::IF_NONE_MATCH, etag);
+            }
+            if let Some(last_modified) = &metadata.last_modified {
+                request = request.header(reqwest::header::IF_MODIFIED_SINCE, last_modified);
+            }
+        }
+
+        let response = request.send().map_err(map_send_error)?;
+
+        FetchResponse::read(url, response, self.max_response_size)
+    }
+
+    fn head(&self, url: &str) -> Result<FetchResponse> {
+        // Same encodings as the GET, so the Content-Length can be compared with the cached one
+        let response = self
+            .client
+            .head(url)
+            .header(reqwest::header::ACCEPT_ENCODING, ACCEPT_ENCODING)
+            .send()
+            .map_err(map_send_error)?;
+
+        FetchResponse::read(url, response, self.max_response_size)
+    }
+}
+
+fn map_send_error(err: reqwest::Error) -> Error {
+    // Keep the reason why a redirect wasn't followed
+    if let Some(Error::RedirectOffHost { from, to }) =
+        std::error::Error::source(&err).and_then(|source| source.downcast_ref::<Error>())
+    {
+        return Error::RedirectOffHost {
+            from: from.clone(),
+            to: to.clone(),
+        };
+    }
+    // The handshake was aborted by the certificate pinning
+    if let Some(fingerprint) = pinning::unpinned_fingerprint(&err) {
+        return Error::CertificateMismatch {
+            host: err
+                .url()
+                .and_then(reqwest::Url::host_str)
+                .unwrap_or("unknown")
+                .to_owned(),
+            fingerprint,
+        };
+    }
+
+    Error::from(err)
+}
+
+/// Fetches using another fetcher and saves every response as a fixture for `ReplayFetcher`.
+pub struct RecordingFetcher {
+    inner: Box<dyn Fetcher>,
+    folder: PathBuf,
+}
+
+impl RecordingFetcher {
+    pub fn new(inner: Box<dyn Fetcher>, folder: &Path) -> Result<Self> {
+        std::fs::create_dir_all(folder)?;
+
+        Ok(Self {
+            inner,
+            folder: folder.to_owned(),
+        })
+    }
+}
+
+impl Fetcher for RecordingFetcher {
+    fn fetch(&self, url: &str, _conditional: Option<&CacheMetadata>) -> Result<FetchResponse> {
+        // Always record the full content, a replay has no cache to fall back to
+        let response = self.inner.fetch(url, None)?;
+
+        std::fs::write(
+            fixture_path(&self.folder, url),
+            serde_json::to_string_pretty(&response)?,
+        )?;
+        debug!("Recorded '{url}'");
+
+        Ok(response)
+    }
+}
+
+/// Serves the responses saved by `RecordingFetcher` without touching the network.
+pub struct ReplayFetcher {
+    folder: PathBuf,
+}
+
+impl ReplayFetcher {
+    pub fn new(folder: &Path) -> Self {
+        Self {
+            folder: folder.to_owned(),
+        }
+    }
+}
+
+impl Fetcher for ReplayFetcher {
+    fn fetch(&self, url: &str, _conditional: Option<&CacheMetadata>) -> Result<FetchResponse> {
+        let fixture = std::fs::read_to_string(fixture_path(&self.folder, url))
+            .map_err(|_| Error::UnrecordedUrl(url.to_owned()))?;
+
+        Ok(serde_json::from_str(&fixture)?)
+    }
+}
+
+/// Reads the calendar files from a local folder laid out like the site, see `--source-dir`. The
+/// path of a URL below one of the base URLs is the path of the file in the folder.
+pub struct DirectoryFetcher {
+    folder: PathBuf,
+    base_urls: Vec<String>,
+}
+
+impl DirectoryFetcher {
+    pub fn new(folder: &Path, base_urls: &[String]) -> Self {
+        Self {
+            folder: folder.to_owned(),
+            base_urls: base_urls.to_vec(),
+        }
+    }
+
+    /// Returns the file `url` refers to, `None` if the path would leave the folder.
+    fn file_path(&self, url: &str) -> Option<PathBuf> {
+        let path = self
+            .base_urls
+            .iter()
+            .find_map(|base_url| url.strip_prefix(base_url.as_str()))
+            .unwrap_or(url);
+        let relative: PathBuf = scrape::normalize_link(path, false)
+            .split('/')
+            .filter(|segment| !segment.is_empty())
+            .map(scrape::percent_decode)
+            .collect();
+
+        relative
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)))
+            .then(|| self.folder.join(relative))
+    }
+}
+
+impl Fetcher for DirectoryFetcher {
+    fn fetch(&self, url: &str, _conditional: Option<&CacheMetadata>) -> Result<FetchResponse> {
+        let not_found = || FetchResponse {
+            url: url.to_owned(),
+            status_code: StatusCode::NOT_FOUND.as_u16(),
+            headers: BTreeMap::new(),
+            body: String::new(),
+            encoded_size: None,
+        };
+
+        let Some(path) = self.file_path(url) else {
+            return Ok(not_found());
+        };
+        let body = match std::fs::read_to_string(&path) {
+            Ok(body) => body,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(not_found()),
+            Err(err) => return Err(err.into()),
+        };
+
+        // The modification time stands in for the Last-Modified header of the site
+        let headers = std::fs::metadata(&path)
+            .and_then(|metadata| metadata.modified())
+            .ok()
+            .map(|modified| {
+                let modified: DateTime<Utc> = modified.into();
+                (
+                    "last-modified".to_owned(),
+                    modified.format("%a, %d %b %Y %H:%M:%S GMT").to_string(),
+                )
+            })
+            .into_iter()
+            .collect();
+
+        Ok(FetchResponse {
+            url: url.to_owned(),
+            status_code: StatusCode::OK.as_u16(),
+            headers,
+            body,
+            encoded_size: None,
+        })
+    }
+}
+
+/// Fixtures are named after the hash of the URL, like the cache files.
+fn fixture_path(folder: &Path, url: &str) -> PathBuf {
+    folder.join(format!("{}.json", sha256_hex(url.as_bytes())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn gzip(body: &[u8]) -> Vec<u8> {
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::best());
+        encoder.write_all(body).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn decoding_stops_one_byte_past_the_limit() {
+        let bomb = gzip(&vec![b'A'; 100_000]);
+
+        let decoded = decode_body(&bomb, Some(&"gzip".to_owned()), 1000).unwrap();
+
+        assert!(
+            bomb.len() < 1000,
+            "the compressed body should be below the limit"
+        );
+        assert_eq!(
+            decoded.len(),
+            1001,
+            "decoding should stop right after exceeding the limit"
+        );
+        assert!(exceeds(&decoded, 1000), "the body should be too large");
+    }
+
+    #[test]
+    fn bodies_within_the_limit_are_decoded_completely() {
+        let body = gzip(b"BEGIN:VCALENDAR");
+
+        assert_eq!(
+            decode_body(&body, Some(&"gzip".to_owned()), 15).unwrap(),
+            b"BEGIN:VCALENDAR",
+            "a body right at the limit should be decoded"
+        );
+        assert_eq!(
+            decode_body(b"BEGIN:VCALENDAR", None, 15).unwrap(),
+            b"BEGIN:VCALENDAR",
+            "a body without encoding should be kept"
+        );
+    }
+
+    #[test]
+    fn files_are_read_below_the_source_folder_only() {
+        let folder = crate::testing::temp_dir("files_are_read_below_the_source_folder");
+        let calendar = folder.join("files/iue/WiSe_2425/semester_1/Mathe 1.ics");
+        std::fs::create_dir_all(calendar.parent().unwrap()).unwrap();
+        std::fs::write(&calendar, "BEGIN:VCALENDAR").unwrap();
+        std::fs::write(folder.join("secret.txt"), "secret").unwrap();
+        let base_url = "https://www.fh-kiel.de/";
+        let fetcher = DirectoryFetcher::new(&folder, &[base_url.to_owned()]);
+        let fetch = |path: &str| fetcher.fetch(&format!("{base_url}{path}"), None).unwrap();
+
+        let response = fetch("files/iue/WiSe_2425/semester_1/Mathe%201.ics");
+        assert_eq!(
+            (response.status_code, response.body.as_str()),
+            (200, "BEGIN:VCALENDAR"),
+            "the file should be read with its percent-encoded path"
+        );
+        assert!(
+            response.headers.contains_key("last-modified"),
+            "the modification time should be sent as Last-Modified"
+        );
+        for path in [
+            "iue/missing.ics",
+            "../secret.txt",
+            "iue/%2E%2E/../secret.txt",
+        ] {
+            assert_eq!(
+                fetch(path).status_code,
+                404,
+                "'{path}' should be answered with 404"
+            );
+        }
+    }
+}
@@ -0,0 +1,229 @@
+//! Matching of event names against pattern lists like the ignore list
+
+use crate::names::canonical_key;
+use crate::prelude::*;
+use regex::{Regex, RegexBuilder};
+
+/// How a pattern is compared against an event name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MatchMode {
+    /// The whole name has to match the pattern.
+    Exact,
+    /// The name has to start with the pattern.
+    Prefix,
+    /// The pattern has to appear anywhere in the name.
+    Substring,
+    /// The pattern is a regular expression which has to match somewhere in the name.
+    Regex,
+}
+
+impl MatchMode {
+    /// Parses the mode names accepted on the command line.
+    #[must_use]
+    pub fn parse(mode: &str) -> Option<Self> {
+        match mode {
+            "exact" => Some(Self::Exact),
+            "prefix" => Some(Self::Prefix),
+            "substring" => Some(Self::Substring),
+            "regex" => Some(Self::Regex),
+            _ => None,
+        }
+    }
+}
+
+/// Events which are ignored unless overridden on the command line.
+pub const IGNORED_EVENT_NAMES: &[(MatchMode, &str)] = &[
+    (MatchMode::Substring, "Feiertag"),
+    (MatchMode::Substring, "Markt der Möglichkeiten"),
+    (MatchMode::Substring, "Jobmesse"),
+];
+
+/// Informational events which don't block busy time unless overridden on the command line.
+pub const TRANSPARENT_EVENT_NAMES: &[(MatchMode, &str)] = &[
+    (MatchMode::Substring, "Anmeldezeitraum"),
+    (MatchMode::Substring, "Anmeldung"),
+    (MatchMode::Substring, "Rückmeldung"),
+    (MatchMode::Substring, "Vorlesungsfreie Zeit"),
+    (MatchMode::Substring, "Informationsveranstaltung"),
+];
+
+#[derive(Debug)]
+enum Matcher {
+    Exact(String),
+    Prefix(String),
+    Substring(String),
+    Regex(Regex),
+}
+
+#[derive(Debug)]
+struct NamePattern {
+    pattern: String,
+    matcher: Matcher,
+}
+
+/// Compiled list of name patterns, e.g. the ignore list. All comparisons are done on the canonical form of the names, so they are
+/// case-insensitive including non-ASCII letters.
+#[derive(Debug)]
+pub struct PatternList {
+    patterns: Vec<NamePattern>,
+}
+
+impl PatternList {
+    /// Compiles the given patterns, building any regular expressions up front.
+    pub fn new(patterns: &[(MatchMode, String)]) -> Result<Self> {
+        let patterns = patterns
+            .iter()
+            .map(|(mode, pattern)| {
+                let folded = canonical_key(pattern);
+                let matcher = match mode {
+                    MatchMode::Exact => Matcher::Exact(folded),
+                    MatchMode::Prefix => Matcher::Prefix(folded),
+                    MatchMode::Substring => Matcher::Substring(folded),
+                    MatchMode::Regex => {
+                        Matcher::Regex(RegexBuilder::new(pattern).case_insensitive(true).build()?)
+                    }
+                };
+
+                Ok(NamePattern {
+                    pattern: pattern.clone(),
+                    matcher,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Self { patterns })
+    }
+
+    /// Returns the first pattern matching `name`, if any.
+    pub fn find_match(&self, name: &str) -> Option<&str> {
+        let folded = canonical_key(name);
+
+        self.patterns
+            .iter()
+            .find(|p| match &p.matcher {
+                Matcher::Exact(pattern) => folded == *pattern,
+                Matcher::Prefix(pattern) => folded.starts_with(pattern.as_str()),
+                Matcher::Substring(pattern) => folded.contains(pattern.as_str()),
+                Matcher::Regex(regex) => regex.is_match(name),
+            })
+            .map(|p| p.pattern.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn list(patterns: &[(MatchMode, &str)]) -> PatternList {
+        let patterns: Vec<(MatchMode, String)> = patterns
+            .iter()
+            .map(|(mode, pattern)| (*mode, (*pattern).to_owned()))
+            .collect();
+
+        PatternList::new(&patterns).unwrap()
+    }
+
+    #[test]
+    fn exact_patterns_match_the_whole_name() {
+        let list = list(&[(MatchMode::Exact, "Feiertag")]);
+
+        assert_eq!(list.find_match("Feiertag"), Some("Feiertag"), "same name");
+        assert_eq!(list.find_match("FEIERTAG"), Some("Feiertag"), "other case");
+        assert_eq!(
+            list.find_match("  feiertag "),
+            Some("Feiertag"),
+            "whitespace"
+        );
+        assert_eq!(list.find_match("Feiertage"), None, "longer name");
+    }
+
+    #[test]
+    fn prefix_patterns_match_the_start() {
+        let list = list(&[(MatchMode::Prefix, "Tutorium")]);
+
+        assert_eq!(
+            list.find_match("tutorium Mathe"),
+            Some("Tutorium"),
+            "prefix"
+        );
+        assert_eq!(list.find_match("Mathe Tutorium"), None, "not at the start");
+    }
+
+    #[test]
+    fn substring_patterns_match_anywhere() {
+        let list = list(&[(MatchMode::Substring, "Feiertag")]);
+
+        assert_eq!(
+            list.find_match("Gesetzlicher FEIERTAG (Reformationstag)"),
+            Some("Feiertag"),
+            "substring in another case"
+        );
+        assert_eq!(list.find_match("Feier"), None, "part of the pattern");
+    }
+
+    #[test]
+    fn matching_folds_unicode_case() {
+        let list = list(&[
+            (MatchMode::Substring, "Straße"),
+            (MatchMode::Exact, "Übung"),
+        ]);
+
+        assert_eq!(
+            list.find_match("HAUPTSTRASSE 1"),
+            Some("Straße"),
+            "ß and SS"
+        );
+        assert_eq!(list.find_match("ÜBUNG"), Some("Übung"), "uppercase umlaut");
+    }
+
+    #[test]
+    fn regex_patterns_are_case_insensitive() {
+        let list = list(&[(MatchMode::Regex, "^klausur.*mathe")]);
+
+        assert_eq!(
+            list.find_match("Klausur Mathe 1"),
+            Some("^klausur.*mathe"),
+            "regex in another case"
+        );
+        assert_eq!(list.find_match("Mathe Klausur"), None, "anchored regex");
+    }
+
+    #[test]
+    fn first_matching_pattern_is_reported() {
+        let list = list(&[
+            (MatchMode::Exact, "Jobmesse"),
+            (MatchMode::Substring, "messe"),
+        ]);
+
+        assert_eq!(
+            list.find_match("Jobmesse"),
+            Some("Jobmesse"),
+            "first pattern"
+        );
+        assert_eq!(
+            list.find_match("Kontaktmesse"),
+            Some("messe"),
+            "second pattern"
+        );
+    }
+
+    #[test]
+    fn invalid_regexes_are_rejected() {
+        let patterns = [(MatchMode::Regex, "(unclosed".to_owned())];
+
+        assert!(
+            PatternList::new(&patterns).is_err(),
+            "an invalid regex should be rejected"
+        );
+    }
+
+    #[test]
+    fn match_modes_are_parsed() {
+        assert_eq!(
+            MatchMode::parse("prefix"),
+            Some(MatchMode::Prefix),
+            "prefix"
+        );
+        assert_eq!(MatchMode::parse("Prefix"), None, "modes are lowercase");
+    }
+}
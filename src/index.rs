@@ -0,0 +1,744 @@
+//! The index page listing every generated calendar, rendered once per language
+
+use crate::build_info::GENERATOR;
+use crate::datetime;
+use crate::label::{self, SemesterSortKey};
+use crate::names;
+use crate::prelude::*;
+use crate::scrape::escape_html;
+use crate::stats::{self, DepartmentStats};
+use chrono::{DateTime, NaiveDate, Utc};
+use chrono_tz::Tz;
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+
+/// Languages the index is generated in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Language {
+    German,
+    English,
+}
+
+impl Language {
+    pub const ALL: [Self; 2] = [Self::German, Self::English];
+
+    /// Name of the index file in this language.
+    pub const fn file_name(self) -> &'static str {
+        match self {
+            Self::German => "index.html",
+            Self::English => "index_en.html",
+        }
+    }
+
+    const fn code(self) -> &'static str {
+        match self {
+            Self::German => "de",
+            Self::English => "en",
+        }
+    }
+
+    /// The language the toggle in the header links to.
+    const fn other(self) -> Self {
+        match self {
+            Self::German => Self::English,
+            Self::English => Self::German,
+        }
+    }
+
+    const fn date_format(self) -> &'static str {
+        match self {
+            Self::German => "%d.%m.%Y",
+            Self::English => "%Y-%m-%d",
+        }
+    }
+
+    const fn date_time_format(self) -> &'static str {
+        match self {
+            Self::German => "%d.%m.%Y %H:%M:%S",
+            Self::English => "%Y-%m-%d %H:%M:%S",
+        }
+    }
+}
+
+/// The strings of the index page. Every text has to be translated to every language, a missing
+/// translation fails the build.
+#[derive(Debug, Clone, Copy)]
+enum Text {
+    Description,
+    Title,
+    Heading,
+    Disclaimer,
+    LanguageName,
+    Modules,
+    ModulesColumn,
+    AlsoListedIn,
+    LastChanged,
+    EarlierSemesters,
+    WinterSemester,
+    SummerSemester,
+    Department,
+    Calendars,
+    Events,
+    Failures,
+    Statistics,
+    Changes,
+    Source,
+    GeneratedAt,
+    GeneratedBy,
+    CreatedBy,
+}
+
+impl Text {
+    const fn get(self, language: Language) -> &'static str {
+        match (self, language) {
+            (Self::Description, Language::German) => "iCalender der Vorlesungspläne der FH-Kiel",
+            (Self::Description, Language::English) => "iCalendar files of the FH Kiel timetables",
+            (Self::Title, Language::German) => {
+                "iCalender der Vorlesungspläne der FH-Kiel für jedes Module"
+            }
+            (Self::Title, Language::English) => {
+                "iCalendar files of the FH Kiel timetables for every module"
+            }
+            (Self::Heading, Language::German) => "Vorlesungspläne der FH-Kiel für jedes Module",
+            (Self::Heading, Language::English) => "FH Kiel timetables for every module",
+            (Self::Disclaimer, Language::German) => {
+                "Absolut kein Gewähr auf <b>Richtigkeit</b> oder <b>Vollständigkeit</b>. Überprüft vor der Nutzung bitte die offiziellen FH-Kiel-Pläne."
+            }
+            (Self::Disclaimer, Language::English) => {
+                "No guarantee whatsoever of <b>correctness</b> or <b>completeness</b>. Please check the official FH Kiel timetables before use."
+            }
+            (Self::LanguageName, Language::German) => "Deutsch",
+            (Self::LanguageName, Language::English) => "English",
+            (Self::Modules | Self::ModulesColumn, Language::German) => "Module",
+            (Self::Modules, Language::English) => "modules",
+            (Self::ModulesColumn, Language::English) => "Modules",
+            (Self::AlsoListedIn, Language::German) => "auch in",
+            (Self::AlsoListedIn, Language::English) => "also in",
+            (Self::LastChanged, Language::German) => "zuletzt geändert",
+            (Self::LastChanged, Language::English) => "last changed",
+            (Self::EarlierSemesters, Language::German) => "frühere Semester",
+            (Self::EarlierSemesters, Language::English) => "earlier semesters",
+            (Self::WinterSemester, Language::German) => "Wintersemester",
+            (Self::WinterSemester, Language::English) => "Winter semester",
+            (Self::SummerSemester, Language::German) => "Sommersemester",
+            (Self::SummerSemester, Language::English) => "Summer semester",
+            (Self::Department, Language::German) => "Fachbereich",
+            (Self::Department, Language::English) => "Department",
+            (Self::Calendars, Language::German) => "Kalender",
+            (Self::Calendars, Language::English) => "Calendars",
+            (Self::Events, Language::German) => "Termine",
+            (Self::Events, Language::English) => "Events",
+            (Self::Failures, Language::German) => "Fehler",
+            (Self::Failures, Language::English) => "Failures",
+            (Self::Statistics, Language::German) => "Statistiken",
+            (Self::Statistics, Language::English) => "Statistics",
+            (Self::Changes, Language::German) => "Was ist neu?",
+            (Self::Changes, Language::English) => "What's new?",
+            (Self::Source, Language::German) => "Quelle",
+            (Self::Source, Language::English) => "Source",
+            (Self::GeneratedAt, Language::German) => "Generiert am",
+            (Self::GeneratedAt, Language::English) => "Generated on",
+            (Self::GeneratedBy, Language::German) => "von",
+            (Self::GeneratedBy, Language::English) => "by",
+            (Self::CreatedBy, Language::German) => "Erstellt von",
+            (Self::CreatedBy, Language::English) => "Created by",
+        }
+    }
+}
+
+/// Everything shown on the index, independent of the language.
+pub struct IndexModel<'a> {
+    /// Sections in the order they are shown.
+    pub sections: Vec<IndexSection>,
+    pub department_stats: &'a BTreeMap<String, DepartmentStats>,
+    pub base_urls: &'a [String],
+    /// Link to the statistics page.
+    pub statistics_href: &'a str,
+    /// Link to the page listing the changes of the last runs.
+    pub changes_href: &'a str,
+    pub generated_at: DateTime<Utc>,
+    /// Time zone `generated_at` is shown in.
+    pub display_timezone: Tz,
+}
+
+/// A collapsible section of the index listing the modules of a group.
+pub struct IndexSection {
+    pub semester: String,
+    pub department: String,
+    pub group_label: String,
+    pub entries: Vec<IndexEntry>,
+}
+
+/// The link to a module calendar.
+pub struct IndexEntry {
+    pub href: String,
+    /// Texts of the links to the source calendars, shown as tooltip.
+    pub title: Option<String>,
+    pub module: String,
+    pub group_label: String,
+    /// Labels of the other groups the module is listed in.
+    pub also_listed_in: Vec<String>,
+    pub last_changed: NaiveDate,
+    /// Labels and links of the calendars of the single months of a large module.
+    pub months: Vec<(String, String)>,
+    /// Calendars of the module in earlier semesters, newest first.
+    pub history: Vec<PastSemester>,
+}
+
+/// The calendar of a module in an earlier semester, listed below the current one.
+#[derive(Clone)]
+pub struct PastSemester {
+    pub semester: String,
+    pub href: String,
+}
+
+/// Collapses the entries of a module found in several semesters into the entries of its latest
+/// semester, listing the calendars of the earlier semesters in their history. Modules are matched
+/// by their canonical name, sections left empty are dropped.
+pub fn collapse_semesters(mut sections: Vec<IndexSection>) -> Vec<IndexSection> {
+    let mut latest: BTreeMap<String, SemesterSortKey> = BTreeMap::new();
+    for section in &sections {
+        let semester = label::semester_sort_key(&section.semester);
+        for entry in &section.entries {
+            let latest = latest
+                .entry(names::canonical_key(&entry.module))
+                .or_insert_with(|| semester.clone());
+            if semester > *latest {
+                latest.clone_from(&semester);
+            }
+        }
+    }
+
+    // Move the entries of earlier semesters out of their sections
+    let mut history: BTreeMap<String, Vec<(SemesterSortKey, PastSemester)>> = BTreeMap::new();
+    for section in &mut sections {
+        let semester = label::semester_sort_key(&section.semester);
+        section.entries.retain(|entry| {
+            let key = names::canonical_key(&entry.module);
+            if latest.get(&key).map_or(true, |latest| *latest <= semester) {
+                return true;
+            }
+
+            history.entry(key).or_default().push((
+                semester.clone(),
+                PastSemester {
+                    semester: section.semester.clone(),
+                    href: entry.href.clone(),
+                },
+            ));
+            false
+        });
+    }
+
+    for past_semesters in history.values_mut() {
+        past_semesters.sort_by(|(a, _), (b, _)| b.cmp(a));
+    }
+    for entry in sections.iter_mut().flat_map(|section| &mut section.entries) {
+        if let Some(past_semesters) = history.get(&names::canonical_key(&entry.module)) {
+            entry.history = past_semesters
+                .iter()
+                .map(|(_, past)| past.clone())
+                .collect();
+        }
+    }
+
+    sections.retain(|section| !section.entries.is_empty());
+
+    sections
+}
+
+/// Renders the index page in `language`.
+pub fn render(model: &IndexModel, language: Language) -> Result<String> {
+    let text = |key: Text| key.get(language);
+    let other = language.other();
+    let mut index = String::new();
+
+    write_head(
+        &mut index,
+        language,
+        text(Text::Description),
+        text(Text::Title),
+    )?;
+    writeln!(
+        &mut index,
+        "<p><a href=\"{}\" hreflang=\"{}\">{}</a></p>
+<h1>{}</h1>
+<h4>{}</h4>
+<div>",
+        other.file_name(),
+        other.code(),
+        Text::LanguageName.get(other),
+        text(Text::Heading),
+        text(Text::Disclaimer),
+    )?;
+
+    for section in &model.sections {
+        writeln!(
+            &mut index,
+            "<details open>
+<summary>{} – {} – {} ({} {})</summary>
+<ul>",
+            semester_label(&section.semester, language),
+            section.department,
+            section.group_label,
+            section.entries.len(),
+            text(Text::Modules),
+        )?;
+
+        for entry in &section.entries {
+            render_entry(&mut index, entry, language)?;
+        }
+
+        writeln!(&mut index, "</ul>\n</details>")?;
+    }
+
+    let sources = model
+        .base_urls
+        .iter()
+        .map(|base_url| format!("<a href=\"{base_url}\">{base_url}</a>"))
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    let department_table = stats::render_department_table(
+        model.department_stats,
+        [
+            text(Text::Department),
+            text(Text::Calendars),
+            text(Text::ModulesColumn),
+            text(Text::Events),
+            text(Text::Failures),
+        ],
+    )?;
+
+    writeln!(
+        &mut index,
+        "</div>
+<footer>
+{department_table}
+<p><a href=\"{}\">{}</a> | <a href=\"{}\">{}</a></p>
+<p>{}: {sources}</p>
+<p>{}: {} {} {GENERATOR}</p>
+</footer>
+</body>
+</html>",
+        model.statistics_href,
+        text(Text::Statistics),
+        model.changes_href,
+        text(Text::Changes),
+        text(Text::Source),
+        text(Text::GeneratedAt),
+        datetime::format_in_zone(
+            model.generated_at,
+            model.display_timezone,
+            language.date_time_format()
+        ),
+        text(Text::GeneratedBy),
+    )?;
+
+    Ok(index)
+}
+
+/// Renders a page linked from the index in `language`, with the head of the index, `heading`
+/// above `body` and a footer naming the generator.
+pub fn render_page(
+    language: Language,
+    description: &str,
+    title: &str,
+    heading: &str,
+    body: &str,
+) -> Result<String> {
+    let mut html = String::new();
+
+    write_head(&mut html, language, description, title)?;
+    writeln!(&mut html, "<h1>{}</h1>", escape_html(heading))?;
+    html.push_str(body);
+    writeln!(
+        &mut html,
+        "<footer>\n<p>{} {GENERATOR}</p>\n</footer>\n</body>\n</html>",
+        Text::CreatedBy.get(language)
+    )?;
+
+    Ok(html)
+}
+
+/// Writes everything up to and including the opening body tag of a page in `language`.
+fn write_head(html: &mut String, language: Language, description: &str, title: &str) -> Result<()> {
+    writeln!(
+        html,
+        "<!DOCTYPE html><html lang=\"{}\">
+<head>
+<meta charset=\"UTF-8\">
+<meta name=\"description\" content=\"{}\">
+<meta name=\"generator\" content=\"{GENERATOR}\">
+<title>{}</title>
+
+<style>
+</style>
+
+</head>
+
+<body>",
+        language.code(),
+        escape_html(description),
+        escape_html(title),
+    )?;
+
+    Ok(())
+}
+
+/// Renders the list item of a single module.
+fn render_entry(index: &mut String, entry: &IndexEntry, language: Language) -> Result<()> {
+    let text = |key: Text| key.get(language);
+
+    let title = entry.title.as_ref().map_or_else(String::new, |title| {
+        format!(" title=\"{}\"", escape_html(title))
+    });
+    let also_listed_in = if entry.also_listed_in.is_empty() {
+        String::new()
+    } else {
+        format!(
+            ", {}: {}",
+            text(Text::AlsoListedIn),
+            entry.also_listed_in.join(", ")
+        )
+    };
+
+    write!(
+        index,
+        "<li> <a href=\"{}\"{title}>{}</a> ({}{also_listed_in}, {}: {})",
+        entry.href,
+        entry.module,
+        entry.group_label,
+        text(Text::LastChanged),
+        entry.last_changed.format(language.date_format()),
+    )?;
+
+    // Large modules also link the calendars of their single months
+    if !entry.months.is_empty() {
+        write!(index, " <ul>")?;
+        for (label, href) in &entry.months {
+            write!(index, "<li><a href=\"{href}\">{label}</a></li>")?;
+        }
+        write!(index, "</ul>")?;
+    }
+
+    // Calendars of earlier semesters are tucked away below the current one
+    if !entry.history.is_empty() {
+        write!(
+            index,
+            " <details><summary>{}</summary><ul>",
+            text(Text::EarlierSemesters)
+        )?;
+        for past in &entry.history {
+            write!(
+                index,
+                "<li><a href=\"{}\">{}</a></li>",
+                past.href,
+                semester_label(&past.semester, language)
+            )?;
+        }
+        write!(index, "</ul></details>")?;
+    }
+
+    writeln!(index, " </li>")?;
+
+    Ok(())
+}
+
+/// Label of a semester like `label::semester_label`, in `language`.
+fn semester_label(semester: &str, language: Language) -> String {
+    match label::parse_semester(semester) {
+        Some((year, true)) => format!(
+            "{} {year}/{:02}",
+            Text::WinterSemester.get(language),
+            (year + 1) % 100
+        ),
+        Some((year, false)) => format!("{} {year}", Text::SummerSemester.get(language)),
+        None => label::semester_label(semester),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(module: &str, group_label: &str) -> IndexEntry {
+        IndexEntry {
+            href: format!("/files/{module}.ics"),
+            title: None,
+            module: module.to_owned(),
+            group_label: group_label.to_owned(),
+            also_listed_in: Vec::new(),
+            last_changed: NaiveDate::from_ymd_opt(2024, 10, 14).unwrap(),
+            months: Vec::new(),
+            history: Vec::new(),
+        }
+    }
+
+    fn section(semester: &str, group_label: &str, modules: &[&str]) -> IndexSection {
+        IndexSection {
+            semester: semester.to_owned(),
+            department: "iue".to_owned(),
+            group_label: group_label.to_owned(),
+            entries: modules
+                .iter()
+                .map(|module| entry(module, group_label))
+                .collect(),
+        }
+    }
+
+    /// Renders the index with `sections` in `language`.
+    fn render_sections(sections: Vec<IndexSection>, language: Language) -> String {
+        let model = IndexModel {
+            sections,
+            department_stats: &BTreeMap::new(),
+            base_urls: &[],
+            statistics_href: "stats.html",
+            changes_href: "changes.html",
+            generated_at: DateTime::UNIX_EPOCH,
+            display_timezone: chrono_tz::Europe::Berlin,
+        };
+
+        render(&model, language).unwrap()
+    }
+
+    /// The `<details>` and `<summary>` lines of `index`.
+    fn section_lines(index: &str) -> Vec<&str> {
+        index
+            .lines()
+            .filter(|line| {
+                line.starts_with("<details")
+                    || line.starts_with("<summary>")
+                    || *line == "</details>"
+            })
+            .collect()
+    }
+
+    #[test]
+    fn sections_are_collapsible_and_count_their_modules() {
+        let sections = || {
+            vec![
+                section("WiSe_2425", "1. Semester", &["Mathe", "Physik"]),
+                section("SoSe_25", "Master", &["Robotik"]),
+            ]
+        };
+
+        assert_eq!(
+            section_lines(&render_sections(sections(), Language::German)),
+            [
+                "<details open>",
+                "<summary>Wintersemester 2024/25 – iue – 1. Semester (2 Module)</summary>",
+                "</details>",
+                "<details open>",
+                "<summary>Sommersemester 2025 – iue – Master (1 Module)</summary>",
+                "</details>",
+            ],
+            "every section should be a details element with its heading"
+        );
+        assert_eq!(
+            section_lines(&render_sections(sections(), Language::English)),
+            [
+                "<details open>",
+                "<summary>Winter semester 2024/25 – iue – 1. Semester (2 modules)</summary>",
+                "</details>",
+                "<details open>",
+                "<summary>Summer semester 2025 – iue – Master (1 modules)</summary>",
+                "</details>",
+            ],
+            "the headings should be translated"
+        );
+    }
+
+    #[test]
+    fn entries_are_listed_in_their_section() {
+        let index = render_sections(
+            vec![
+                section("WiSe_2425", "1. Semester", &["Mathe"]),
+                section("WiSe_2425", "2. Semester", &["Physik"]),
+            ],
+            Language::German,
+        );
+
+        let second = index.split("<details open>").nth(2).unwrap_or_default();
+        assert!(
+            second.contains("<a href=\"/files/Physik.ics\">Physik</a> (2. Semester, zuletzt geändert: 14.10.2024)")
+                && !second.contains("Mathe"),
+            "the section should list only its own modules:\n{second}"
+        );
+    }
+
+    #[test]
+    fn unknown_semesters_are_shown_prettified() {
+        let index = render_sections(
+            vec![section("Sonderprogramm", "Master", &["Robotik"])],
+            Language::English,
+        );
+
+        assert!(
+            index.contains("<summary>Sonderprogramm – iue – Master (1 modules)</summary>"),
+            "the raw semester should be prettified:\n{index}"
+        );
+    }
+
+    #[test]
+    fn both_languages_link_to_each_other() {
+        let german = render_sections(Vec::new(), Language::German);
+        let english = render_sections(Vec::new(), Language::English);
+
+        assert!(
+            german.contains("<html lang=\"de\">")
+                && german.contains("<a href=\"index_en.html\" hreflang=\"en\">English</a>"),
+            "the German index should link the English one:\n{german}"
+        );
+        assert!(
+            english.contains("<html lang=\"en\">")
+                && english.contains("<a href=\"index.html\" hreflang=\"de\">Deutsch</a>"),
+            "the English index should link the German one:\n{english}"
+        );
+    }
+
+    #[test]
+    fn texts_and_dates_follow_the_language() {
+        let sections = || {
+            vec![section(
+                "WiSe_2425",
+                "1. Semester",
+                &["Analysis für Ingenieure"],
+            )]
+        };
+        let german = render_sections(sections(), Language::German);
+        let english = render_sections(sections(), Language::English);
+
+        for (index, entry, generated) in [
+            (
+                &german,
+                "Analysis für Ingenieure</a> (1. Semester, zuletzt geändert: 14.10.2024)",
+                "Generiert am: 01.01.1970 01:00:00",
+            ),
+            (
+                &english,
+                "Analysis für Ingenieure</a> (1. Semester, last changed: 2024-10-14)",
+                "Generated on: 1970-01-01 01:00:00",
+            ),
+        ] {
+            assert!(
+                index.contains(entry) && index.contains(generated),
+                "the module name should be kept and the texts and dates translated:\n{index}"
+            );
+        }
+    }
+
+    /// `(semester, module, semesters of the history)` of every entry left after collapsing.
+    fn collapsed(sections: Vec<IndexSection>) -> Vec<(String, String, Vec<String>)> {
+        collapse_semesters(sections)
+            .into_iter()
+            .flat_map(|section| {
+                section.entries.into_iter().map(move |entry| {
+                    (
+                        section.semester.clone(),
+                        entry.module,
+                        entry
+                            .history
+                            .into_iter()
+                            .map(|past| past.semester)
+                            .collect(),
+                    )
+                })
+            })
+            .collect()
+    }
+
+    #[test]
+    fn modules_are_collapsed_into_their_latest_semester() {
+        let entries = collapsed(vec![
+            section("WiSe_2324", "1. Semester", &["Mathematik 1", "Physik"]),
+            section("SoSe_24", "2. Semester", &["Mathematik  1", "Chemie"]),
+            section("WiSe_2425", "1. Semester", &["mathematik 1"]),
+        ]);
+
+        assert_eq!(
+            entries,
+            [
+                (
+                    "WiSe_2324".to_owned(),
+                    "Physik".to_owned(),
+                    Vec::<String>::new()
+                ),
+                ("SoSe_24".to_owned(), "Chemie".to_owned(), Vec::new()),
+                (
+                    "WiSe_2425".to_owned(),
+                    "mathematik 1".to_owned(),
+                    vec!["SoSe_24".to_owned(), "WiSe_2324".to_owned()]
+                ),
+            ],
+            "modules of one semester should stay, the others move to their latest semester with \
+             the earlier ones newest first"
+        );
+    }
+
+    #[test]
+    fn modules_in_two_semesters_keep_the_earlier_one_in_their_history() {
+        let entries = collapsed(vec![
+            section("WiSe_2425", "1. Semester", &["Mathe"]),
+            section("WiSe_2324", "1. Semester", &["Mathe"]),
+        ]);
+
+        assert_eq!(
+            entries,
+            [(
+                "WiSe_2425".to_owned(),
+                "Mathe".to_owned(),
+                vec!["WiSe_2324".to_owned()]
+            )],
+            "the section left empty should be dropped"
+        );
+    }
+
+    #[test]
+    fn earlier_semesters_are_listed_below_the_entry() {
+        let index = render_sections(
+            collapse_semesters(vec![
+                section("SoSe_24", "2. Semester", &["Mathe"]),
+                section("WiSe_2425", "1. Semester", &["Mathe"]),
+            ]),
+            Language::German,
+        );
+
+        assert!(
+            index.contains(
+                " <details><summary>frühere Semester</summary><ul><li><a href=\"/files/Mathe.ics\">Sommersemester 2024</a></li></ul></details>"
+            ),
+            "the earlier semester should be listed in a details block:\n{index}"
+        );
+    }
+
+    #[test]
+    fn generation_time_is_shown_in_the_display_zone() {
+        let footer = |display_timezone: Tz| {
+            let model = IndexModel {
+                sections: Vec::new(),
+                department_stats: &BTreeMap::new(),
+                base_urls: &[],
+                statistics_href: "stats.html",
+                changes_href: "changes.html",
+                generated_at: DateTime::UNIX_EPOCH,
+                display_timezone,
+            };
+            let index = render(&model, Language::German).unwrap();
+
+            index
+                .lines()
+                .find(|line| line.starts_with("<p>Generiert am: "))
+                .map(|line| line.replace(GENERATOR, ""))
+        };
+
+        assert_eq!(
+            [chrono_tz::Europe::Berlin, chrono_tz::UTC].map(footer),
+            [
+                Some("<p>Generiert am: 01.01.1970 01:00:00 von </p>".to_owned()),
+                Some("<p>Generiert am: 01.01.1970 00:00:00 von </p>".to_owned()),
+            ],
+            "the time should be shown in the configured zone"
+        );
+    }
+}
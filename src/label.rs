@@ -0,0 +1,149 @@
+//! Human-readable labels for URL path segments
+
+/// Turns the group directory segment of a calendar URL (e.g. `semester_1` or `master`) into a
+/// label suitable for display.
+///
+/// Known patterns are mapped explicitly, everything else falls back to a prettified version of the
+/// raw segment.
+pub fn group_label(group_dir: &str) -> String {
+    let lowercase = group_dir.to_lowercase();
+
+    // semester_1 -> 1. Semester
+    if let Some(number) = lowercase.strip_prefix("semester_") {
+        if !number.is_empty() && number.chars().all(|c| c.is_ascii_digit()) {
+            return format!("{number}. Semester");
+        }
+    }
+
+    match lowercase.as_str() {
+        "master" => "Master".to_owned(),
+        "bachelor" => "Bachelor".to_owned(),
+        _ => prettify(group_dir),
+    }
+}
+
+/// Replaces underscores with spaces and capitalizes the first letter of every word.
+fn prettify(segment: &str) -> String {
+    segment
+        .split('_')
+        .filter(|word| !word.is_empty())
+        .map(|word| {
+            let mut chars = word.chars();
+            chars.next().map_or_else(String::new, |first| {
+                first.to_uppercase().chain(chars).collect::<String>()
+            })
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Turns the semester segment of a calendar URL (e.g. `WiSe_2425` or `SoSe_25`) into a label
+/// suitable for display.
+pub fn semester_label(semester: &str) -> String {
+    match parse_semester(semester) {
+        Some((year, true)) => format!("Wintersemester {year}/{:02}", (year + 1) % 100),
+        Some((year, false)) => format!("Sommersemester {year}"),
+        None => prettify(semester),
+    }
+}
+
+/// Orders semesters chronologically: start year, whether it's the winter semester and the name of
+/// unknown semesters.
+pub type SemesterSortKey = (u32, bool, String);
+
+/// Sort key ordering semesters chronologically, unknown semesters last by name.
+pub fn semester_sort_key(semester: &str) -> SemesterSortKey {
+    parse_semester(semester).map_or_else(
+        || (u32::MAX, false, semester.to_lowercase()),
+        |(year, is_winter)| (year, is_winter, String::new()),
+    )
+}
+
+/// Sort key ordering group directories by their number, e.g. `semester_2` before `semester_10`,
+/// everything else after them by name.
+pub fn group_sort_key(group_dir: &str) -> (u32, String) {
+    let lowercase = group_dir.to_lowercase();
+
+    let number = lowercase
+        .strip_prefix("semester_")
+        .and_then(|number| number.parse().ok());
+
+    number.map_or_else(|| (u32::MAX, lowercase), |number| (number, String::new()))
+}
+
+/// Returns the year the semester starts in and whether it is a winter semester.
+pub fn parse_semester(semester: &str) -> Option<(u32, bool)> {
+    let lowercase = semester.to_lowercase();
+    let (is_winter, digits) = if let Some(digits) = lowercase.strip_prefix("wise_") {
+        (true, digits)
+    } else {
+        (false, lowercase.strip_prefix("sose_")?)
+    };
+    if !digits.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+
+    // WiSe_2425 starts in 2024, SoSe_25 and SoSe_2025 in 2025
+    let year: u32 = match (is_winter, digits.len()) {
+        (true, 4) | (false, 2) => 2000 + digits.get(..2)?.parse::<u32>().ok()?,
+        (false, 4) => digits.parse().ok()?,
+        _ => return None,
+    };
+
+    Some((year, is_winter))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn group_labels() {
+        let cases = [
+            ("semester_1", "1. Semester"),
+            ("semester_12", "12. Semester"),
+            ("Semester_3", "3. Semester"),
+            ("master", "Master"),
+            ("MASTER", "Master"),
+            ("bachelor", "Bachelor"),
+            ("semester_", "Semester"),
+            ("semester_x", "Semester X"),
+            ("wahlpflicht_module", "Wahlpflicht Module"),
+            ("__doppel__unterstrich", "Doppel Unterstrich"),
+            ("übung", "Übung"),
+            ("", ""),
+        ];
+
+        for (group_dir, expected) in cases {
+            assert_eq!(group_label(group_dir), expected, "label of '{group_dir}'");
+        }
+    }
+
+    #[test]
+    fn semester_labels() {
+        let cases = [
+            ("WiSe_2425", "Wintersemester 2024/25"),
+            ("wise_9900", "Wintersemester 2099/00"),
+            ("SoSe_25", "Sommersemester 2025"),
+            ("SoSe_2025", "Sommersemester 2025"),
+            ("WiSe_24", "WiSe 24"),
+            ("sonstiges", "Sonstiges"),
+        ];
+
+        for (semester, expected) in cases {
+            assert_eq!(semester_label(semester), expected, "label of '{semester}'");
+        }
+    }
+
+    #[test]
+    fn group_sort_keys_order_by_number() {
+        let mut groups = ["master", "semester_10", "semester_2", "bachelor"];
+        groups.sort_by_key(|group| group_sort_key(group));
+
+        assert_eq!(
+            groups,
+            ["semester_2", "semester_10", "bachelor", "master"],
+            "numbered groups should come first in numeric order"
+        );
+    }
+}
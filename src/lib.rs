@@ -0,0 +1,70 @@
+//! Splits the lecture calendars of the FH Kiel into one calendar per module.
+//!
+//! Besides the command line tool, the aggregated events can be used directly through
+//! [`Scraper::collect_events`].
+
+mod anonymize;
+mod api;
+mod build_info;
+mod cache;
+mod calendar;
+mod change_history;
+mod changes;
+mod clock;
+mod compat;
+mod config;
+mod config_file;
+mod contact;
+mod cross_validate;
+mod datetime;
+mod diagnostics;
+mod error;
+mod event_filter;
+mod exit_code;
+mod feed;
+mod fetch;
+mod ignore;
+mod index;
+mod label;
+mod lock;
+mod lookup;
+mod manifest;
+mod merge;
+mod minify;
+mod month_split;
+mod names;
+mod parse_cache;
+mod paths;
+mod pinning;
+mod pipeline;
+mod politeness;
+mod prelude;
+mod props;
+mod report;
+mod revision;
+mod robots;
+mod scrape;
+mod snapshot;
+mod spill;
+mod state;
+mod statistics;
+mod stats;
+mod template;
+#[cfg(test)]
+mod testing;
+mod text_export;
+mod tombstone;
+mod validate;
+mod verify;
+
+pub use crate::api::{clear_cache, load_output, Event, ModuleCalendar, ModuleKey, Scraper};
+pub use crate::config::{Command, Config, WeekLabelPosition};
+pub use crate::diagnostics::RepeatedWarnings;
+pub use crate::error::Error;
+pub use crate::exit_code::{catch_panic, print_exit_codes, ExitCode};
+pub use crate::ignore::MatchMode;
+pub use crate::lock::{InstanceLock, LOCK_FILE};
+pub use crate::lookup::{find_module, show_next_events, ModuleMatch};
+pub use crate::prelude::Result;
+pub use crate::template::{OutputTemplate, PathValues};
+pub use crate::verify::verify_deployment;
@@ -0,0 +1,134 @@
+//! Lock keeping overlapping runs from working on the same files
+
+use crate::clock::{Deadline, Sleeper, SystemClock, ThreadSleeper};
+use crate::prelude::*;
+use fs2::FileExt;
+use std::fs::{File, OpenOptions};
+use std::io::{Seek, Write};
+use std::path::Path;
+use std::time::Duration;
+
+/// Lock file in the working directory, which also holds the cache and the output.
+pub const LOCK_FILE: &str = ".fh_kiel_ical_splitter.lock";
+
+/// How often to try again while waiting for the lock.
+const LOCK_RETRY_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Exclusive advisory lock held for the whole run.
+///
+/// The lock is released when this is dropped, which also happens while unwinding from a panic. The
+/// operating system releases it as well if the process dies.
+#[derive(Debug)]
+pub struct InstanceLock {
+    file: File,
+}
+
+impl InstanceLock {
+    /// Acquires the lock at `path`, waiting up to `wait` for another run to release it.
+    pub fn acquire(path: &Path, wait: Option<Duration>) -> Result<Self> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+
+        let deadline = Deadline::new(Box::new(SystemClock), Some(wait.unwrap_or_default()));
+        let mut is_waiting = false;
+        loop {
+            match file.try_lock_exclusive() {
+                Ok(()) => break,
+                Err(err) if err.raw_os_error() == fs2::lock_contended_error().raw_os_error() => {}
+                Err(err) => return Err(err.into()),
+            }
+
+            if deadline.is_expired() {
+                return Err(Error::AlreadyRunning(lock_holder(&mut file)));
+            }
+            if !is_waiting {
+                info!(
+                    "Waiting for the run of {} to finish",
+                    lock_holder(&mut file)
+                );
+                is_waiting = true;
+            }
+
+            ThreadSleeper.sleep(LOCK_RETRY_INTERVAL);
+        }
+
+        // Let other runs know who holds the lock
+        file.set_len(0)?;
+        file.rewind()?;
+        file.write_all(std::process::id().to_string().as_bytes())?;
+        file.flush()?;
+
+        Ok(Self { file })
+    }
+}
+
+impl Drop for InstanceLock {
+    fn drop(&mut self) {
+        if let Err(err) = FileExt::unlock(&self.file) {
+            warn!("Failed to release the lock: {err}");
+        }
+    }
+}
+
+/// Describes the process holding the lock, based on the PID it wrote into the lock file.
+fn lock_holder(file: &mut File) -> String {
+    match file
+        .rewind()
+        .and_then(|()| std::io::read_to_string(&mut *file))
+    {
+        Ok(content) if !content.trim().is_empty() => format!("PID {}", content.trim()),
+        Ok(_) | Err(_) => "an unknown process".to_owned(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+    use std::thread;
+
+    #[test]
+    fn second_instance_fails_while_the_lock_is_held() {
+        let path = testing::temp_dir("second_instance_fails").join(LOCK_FILE);
+        let lock = InstanceLock::acquire(&path, None).unwrap();
+
+        let second = InstanceLock::acquire(&path, None);
+
+        assert!(
+            matches!(
+                &second,
+                Err(Error::AlreadyRunning(holder))
+                    if *holder == format!("PID {}", std::process::id())
+            ),
+            "the second instance should fail naming the holder, got {second:?}"
+        );
+
+        drop(lock);
+        assert!(
+            InstanceLock::acquire(&path, None).is_ok(),
+            "the lock should be free once released"
+        );
+    }
+
+    #[test]
+    fn waiting_instance_gets_the_lock_once_released() {
+        let path = testing::temp_dir("waiting_instance").join(LOCK_FILE);
+        let lock = InstanceLock::acquire(&path, None).unwrap();
+
+        let release = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(100));
+            drop(lock);
+        });
+        let second = InstanceLock::acquire(&path, Some(Duration::from_secs(10)));
+        release.join().unwrap();
+
+        assert!(
+            second.is_ok(),
+            "the waiting instance should get the lock, got {second:?}"
+        );
+    }
+}
@@ -0,0 +1,281 @@
+//! Lookup of the upcoming events of a module by a part of its name
+
+use crate::api::{self, Event, ModuleCalendar, ModuleKey};
+use crate::config::Config;
+use crate::exit_code::ExitCode;
+use crate::names;
+use crate::prelude::*;
+use chrono::Utc;
+use chrono_tz::Tz;
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// Number of events shown by `next` unless overridden.
+pub const DEFAULT_NEXT_COUNT: usize = 5;
+
+/// Outcome of looking up a module by a part of its name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ModuleMatch<'a> {
+    Found(&'a ModuleCalendar),
+    /// Several modules match equally well, ordered by their key.
+    Ambiguous(Vec<&'a ModuleCalendar>),
+    NotFound,
+}
+
+/// How well the name of a module matches the query, better matches last.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum MatchQuality {
+    /// The name contains every word of the query, in any order.
+    Words,
+    /// The name contains the query.
+    Substring,
+    /// The name starts with the query.
+    Prefix,
+    Exact,
+}
+
+/// Finds the module whose name matches `query` best. Both are compared by their canonical key,
+/// so case and whitespace don't matter.
+pub fn find_module<'a>(
+    modules: &'a BTreeMap<ModuleKey, ModuleCalendar>,
+    query: &str,
+) -> ModuleMatch<'a> {
+    let query = names::canonical_key(query);
+    if query.is_empty() {
+        return ModuleMatch::NotFound;
+    }
+
+    let mut best: Option<MatchQuality> = None;
+    let mut candidates: Vec<&ModuleCalendar> = Vec::new();
+    for module in modules.values() {
+        let quality = match_quality(&names::canonical_key(&module.display_name), &query);
+
+        match quality.cmp(&best) {
+            Ordering::Greater => {
+                best = quality;
+                candidates = vec![module];
+            }
+            Ordering::Equal if quality.is_some() => candidates.push(module),
+            Ordering::Equal | Ordering::Less => {}
+        }
+    }
+
+    if candidates.len() > 1 {
+        return ModuleMatch::Ambiguous(candidates);
+    }
+
+    candidates
+        .pop()
+        .map_or(ModuleMatch::NotFound, ModuleMatch::Found)
+}
+
+fn match_quality(name: &str, query: &str) -> Option<MatchQuality> {
+    if name == query {
+        Some(MatchQuality::Exact)
+    } else if name.starts_with(query) {
+        Some(MatchQuality::Prefix)
+    } else if name.contains(query) {
+        Some(MatchQuality::Substring)
+    } else if query.split(' ').all(|word| name.contains(word)) {
+        Some(MatchQuality::Words)
+    } else {
+        None
+    }
+}
+
+/// Prints the next `count` events of the module matching `query` from the output of the last run
+/// in the current folder. Ambiguous queries list the matching modules and fail.
+pub fn show_next_events(config: &Config, query: &str, count: usize) -> Result<ExitCode> {
+    let modules = api::load_output(Path::new("."))?;
+
+    match find_module(&modules, query) {
+        ModuleMatch::Found(module) => {
+            let events = module.upcoming_events(Utc::now(), count);
+            if events.is_empty() {
+                info!("'{}' has no upcoming events", module.display_name);
+            }
+
+            for event in events {
+                println!("{}", format_event(event, config.display_timezone));
+            }
+
+            Ok(ExitCode::Success)
+        }
+        ModuleMatch::Ambiguous(candidates) => {
+            error!("'{query}' matches {} modules:", candidates.len());
+            for module in candidates {
+                error!(
+                    "  {} ({}, {})",
+                    module.display_name, module.semester, module.group
+                );
+            }
+
+            Ok(ExitCode::NoSuchModule)
+        }
+        ModuleMatch::NotFound => {
+            error!("No module matches '{query}'");
+
+            Ok(ExitCode::NoSuchModule)
+        }
+    }
+}
+
+/// Formats an event as a single line, e.g. `07.04.2025 08:15–09:45  C12-2.01  Mathematik 1`.
+fn format_event(event: &Event, display_zone: Tz) -> String {
+    let start = event.start.with_timezone(&display_zone);
+    let end = event.end.map_or_else(String::new, |end| {
+        end.with_timezone(&display_zone)
+            .format("–%H:%M")
+            .to_string()
+    });
+
+    format!(
+        "{}{end}  {}  {}",
+        start.format("%d.%m.%Y %H:%M"),
+        event.location.as_deref().unwrap_or("-"),
+        event.summary
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::calendar;
+    use crate::testing;
+    use chrono::TimeZone;
+    use std::fmt::Write as _;
+
+    /// Writes an output tree with a calendar for every `(group, module, starts)` and a manifest
+    /// listing them, like a run would, and loads it back.
+    fn output(
+        name: &str,
+        modules: &[(&str, &str, &[&str])],
+    ) -> BTreeMap<ModuleKey, ModuleCalendar> {
+        let folder = testing::temp_dir(name);
+        let mut manifest = String::new();
+        for (group, module, starts) in modules {
+            let path = format!(
+                "files/WiSe_2425/iue/{group}/{}.ics",
+                names::module_slug(module, 64)
+            );
+            let events: Vec<String> = starts
+                .iter()
+                .map(|start| testing::event(module, start))
+                .collect();
+            let content = testing::calendar_of(&events).replace(
+                "PRODID:-//Test//EN",
+                &format!(
+                    "PRODID:{}",
+                    calendar::module_product_id("iue", "WiSe_2425", group, module)
+                ),
+            );
+
+            let file = folder.join(&path);
+            std::fs::create_dir_all(file.parent().unwrap()).unwrap();
+            std::fs::write(file, content).unwrap();
+            writeln!(manifest, "0  0  {path}").unwrap();
+        }
+        std::fs::write(folder.join(crate::manifest::MANIFEST_FILE), manifest).unwrap();
+
+        api::load_output(&folder).unwrap()
+    }
+
+    /// Display name of the found module, or the names of the candidates.
+    fn matched(modules: &BTreeMap<ModuleKey, ModuleCalendar>, query: &str) -> Vec<String> {
+        match find_module(modules, query) {
+            ModuleMatch::Found(module) => vec![module.display_name.clone()],
+            ModuleMatch::Ambiguous(candidates) => candidates
+                .into_iter()
+                .map(|module| format!("? {}", module.display_name))
+                .collect(),
+            ModuleMatch::NotFound => Vec::new(),
+        }
+    }
+
+    fn modules(name: &str) -> BTreeMap<ModuleKey, ModuleCalendar> {
+        output(
+            name,
+            &[
+                ("semester_1", "Mathematik 1", &["20241014T081500"]),
+                ("semester_1", "Mathematik 1 Übung", &["20241015T081500"]),
+                ("semester_2", "Physik für Ingenieure", &["20241016T081500"]),
+            ],
+        )
+    }
+
+    #[test]
+    fn exact_names_are_found_regardless_of_case_and_whitespace() {
+        let modules = modules("lookup_exact");
+
+        assert_eq!(
+            matched(&modules, "  mathematik   1 "),
+            ["Mathematik 1"],
+            "the exact name should win over the longer one"
+        );
+    }
+
+    #[test]
+    fn parts_of_names_are_found() {
+        let modules = modules("lookup_fuzzy");
+
+        for (query, expected) in [
+            ("physik", "Physik für Ingenieure"),
+            ("ingenieure", "Physik für Ingenieure"),
+            ("übung mathematik", "Mathematik 1 Übung"),
+        ] {
+            assert_eq!(matched(&modules, query), [expected], "looking up '{query}'");
+        }
+        assert!(
+            matched(&modules, "chemie").is_empty(),
+            "an unknown name shouldn't match"
+        );
+    }
+
+    #[test]
+    fn equally_good_matches_are_ambiguous() {
+        let modules = modules("lookup_ambiguous");
+
+        assert_eq!(
+            matched(&modules, "mathe"),
+            ["? Mathematik 1", "? Mathematik 1 Übung"],
+            "both modules starting with the query should be candidates"
+        );
+    }
+
+    #[test]
+    fn the_next_events_are_shown_with_time_and_room() {
+        let modules = output(
+            "lookup_next",
+            &[(
+                "semester_1",
+                "Mathematik 1",
+                &[
+                    "20241021T081500",
+                    "20241007T081500",
+                    "20241014T081500",
+                    "20241028T081500",
+                ],
+            )],
+        );
+        let now = Utc.with_ymd_and_hms(2024, 10, 10, 12, 0, 0).unwrap();
+
+        let events = match find_module(&modules, "mathematik 1") {
+            ModuleMatch::Found(module) => module
+                .upcoming_events(now, 2)
+                .into_iter()
+                .map(|event| format_event(event, chrono_tz::Europe::Berlin))
+                .collect::<Vec<_>>(),
+            ModuleMatch::Ambiguous(_) | ModuleMatch::NotFound => Vec::new(),
+        };
+
+        assert_eq!(
+            events,
+            [
+                "14.10.2024 08:15–09:45  C-12.01  Mathematik 1",
+                "21.10.2024 08:15–09:45  C-12.01  Mathematik 1",
+            ],
+            "the next two events should be shown in order"
+        );
+    }
+}
@@ -0,0 +1,155 @@
+//! Checksum manifest of the generated calendar files
+
+use crate::build_info::BuildInfo;
+use crate::paths;
+use crate::prelude::*;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::fmt::Write as _;
+use std::path::Path;
+
+/// Manifest of the files written by the last run, in the output folder.
+pub const MANIFEST_FILE: &str = "manifest.txt";
+
+/// Number of hex digits of the hash used as version query parameter.
+const VERSION_LENGTH: usize = 16;
+
+/// A single generated file together with its checksum.
+#[derive(Debug, Clone, Serialize)]
+pub struct ManifestEntry {
+    pub path: String,
+    pub sha256: String,
+    pub size: usize,
+    /// Latest `Last-Modified` of the source calendars the file was generated from.
+    pub source_last_modified: Option<DateTime<Utc>>,
+}
+
+impl ManifestEntry {
+    /// Creates an entry from the exact bytes that were written to `path`.
+    pub fn new(path: String, bytes: &[u8]) -> Self {
+        Self {
+            path,
+            sha256: sha256_hex(bytes),
+            size: bytes.len(),
+            source_last_modified: None,
+        }
+    }
+
+    /// Short version string suitable for cache-busting query parameters.
+    pub fn version(&self) -> &str {
+        self.sha256.get(..VERSION_LENGTH).unwrap_or(&self.sha256)
+    }
+}
+
+/// Returns the lowercase hex encoded SHA-256 of `bytes`.
+pub fn sha256_hex(bytes: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(bytes))
+}
+
+/// Writes the manifest in the `<sha256>  <size>  <path>` format, one file per line.
+pub fn write_manifest(path: &Path, entries: &[ManifestEntry]) -> Result<()> {
+    let mut content = String::new();
+
+    for entry in entries {
+        writeln!(
+            &mut content,
+            "{}  {}  {}",
+            entry.sha256, entry.size, entry.path
+        )
+        .map_err(|_| Error::ManifestFormat)?;
+    }
+
+    paths::write_atomically(path, &content)?;
+
+    Ok(())
+}
+
+/// Returns the size of all files in bytes.
+pub fn total_size(entries: &[ManifestEntry]) -> usize {
+    entries.iter().map(|entry| entry.size).sum()
+}
+
+/// Reads the paths of the files listed in a manifest written by `write_manifest`.
+pub fn read_manifest_paths(path: &Path) -> Result<Vec<String>> {
+    Ok(std::fs::read_to_string(path)?
+        .lines()
+        .filter_map(|line| line.splitn(3, "  ").nth(2))
+        .map(str::to_owned)
+        .collect())
+}
+
+/// The JSON manifest, describing the run together with the files.
+#[derive(Debug, Serialize)]
+struct JsonManifest<'a> {
+    build_info: &'a BuildInfo,
+    /// Size of all files in bytes.
+    total_size: usize,
+    files: &'a [ManifestEntry],
+}
+
+/// Writes the manifest including the source modification dates and the build information as
+/// JSON.
+pub fn write_manifest_json(
+    path: &Path,
+    build_info: &BuildInfo,
+    entries: &[ManifestEntry],
+) -> Result<()> {
+    let json = serde_json::to_string_pretty(&JsonManifest {
+        build_info,
+        total_size: total_size(entries),
+        files: entries,
+    })?;
+    paths::write_atomically(path, &json)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    #[test]
+    fn hash_only_changes_with_the_content() {
+        let entry = ManifestEntry::new("a.ics".to_owned(), b"BEGIN:VCALENDAR");
+
+        assert_eq!(
+            entry.sha256,
+            ManifestEntry::new("b.ics".to_owned(), b"BEGIN:VCALENDAR").sha256,
+            "the same bytes should have the same hash"
+        );
+        assert_ne!(
+            entry.sha256,
+            ManifestEntry::new("a.ics".to_owned(), b"BEGIN:VCALENDAR\r\n").sha256,
+            "different bytes should have a different hash"
+        );
+        assert_eq!(
+            entry.version().len(),
+            VERSION_LENGTH,
+            "the version should be a prefix of the hash"
+        );
+        assert!(
+            entry.sha256.starts_with(entry.version()),
+            "the version should be a prefix of the hash"
+        );
+    }
+
+    #[test]
+    fn manifest_paths_can_be_read_back() {
+        let path = testing::temp_dir("manifest_paths_can_be_read_back").join(MANIFEST_FILE);
+        let entries = vec![
+            ManifestEntry::new("files/semester 1/Mathe.ics".to_owned(), b"Mathe"),
+            ManifestEntry::new("index.html".to_owned(), b"<html>"),
+        ];
+
+        write_manifest(&path, &entries).unwrap();
+
+        assert_eq!(
+            read_manifest_paths(&path).unwrap(),
+            ["files/semester 1/Mathe.ics", "index.html"],
+            "every path should be read back, including ones with spaces"
+        );
+        assert_eq!(total_size(&entries), 11, "the sizes should be summed up");
+    }
+}
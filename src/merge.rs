@@ -0,0 +1,234 @@
+//! Merging of back-to-back events into single longer events
+
+use crate::calendar::{
+    self, PROPERTY_NAME_DESCRIPTION, PROPERTY_NAME_DTEND, PROPERTY_NAME_DTSTART,
+    PROPERTY_NAME_LOCATION, PROPERTY_NAME_SUMMARY,
+};
+use crate::{datetime, props};
+use chrono::NaiveDateTime;
+use ical::parser::ical::component::IcalEvent;
+use std::time::Duration;
+
+/// Merges consecutive events with the same summary and location where the next one starts at
+/// most `max_gap` after the previous one ended, e.g. a lab exported as two 90 minute slots.
+///
+/// The merged event spans the full range and lists the original slots in its DESCRIPTION.
+/// All-day events and events without a parseable start or end are never merged.
+pub fn merge_adjacent(mut events: Vec<IcalEvent>, max_gap: Duration) -> Vec<IcalEvent> {
+    let max_gap = chrono::Duration::from_std(max_gap).unwrap_or(chrono::Duration::MAX);
+
+    events.sort_by_key(|event| parse_time(event, PROPERTY_NAME_DTSTART));
+
+    let mut merged: Vec<(IcalEvent, Vec<String>)> = Vec::with_capacity(events.len());
+    for event in events {
+        if let Some((previous, slots)) = merged.last_mut() {
+            let gap = parse_time(previous, PROPERTY_NAME_DTEND)
+                .zip(parse_time(&event, PROPERTY_NAME_DTSTART))
+                .map(|(previous_end, start)| start - previous_end);
+
+            if gap.is_some_and(|gap| gap >= chrono::Duration::zero() && gap <= max_gap)
+                && is_mergeable(previous, &event)
+            {
+                if slots.is_empty() {
+                    slots.push(describe_slot(previous));
+                }
+                slots.push(describe_slot(&event));
+
+                if let Some(end) = props::get_value(&event, PROPERTY_NAME_DTEND) {
+                    props::set_value_preserving_params(
+                        previous,
+                        PROPERTY_NAME_DTEND,
+                        end.to_owned(),
+                    );
+                }
+                continue;
+            }
+        }
+
+        merged.push((event, Vec::new()));
+    }
+
+    merged
+        .into_iter()
+        .map(|(mut event, slots)| {
+            if !slots.is_empty() {
+                note_merge(&mut event, &slots);
+            }
+            event
+        })
+        .collect()
+}
+
+/// Only events which differ in nothing but their time are merged.
+fn is_mergeable(previous: &IcalEvent, next: &IcalEvent) -> bool {
+    props::get_value(previous, PROPERTY_NAME_SUMMARY)
+        == props::get_value(next, PROPERTY_NAME_SUMMARY)
+        && props::get_value(previous, PROPERTY_NAME_LOCATION)
+            == props::get_value(next, PROPERTY_NAME_LOCATION)
+        && props::get_param(previous, PROPERTY_NAME_DTSTART, "TZID")
+            == props::get_param(next, PROPERTY_NAME_DTSTART, "TZID")
+        && calendar::is_cancelled(previous) == calendar::is_cancelled(next)
+}
+
+/// Parses a date-time property, all-day values are treated as missing.
+fn parse_time(event: &IcalEvent, name: &str) -> Option<NaiveDateTime> {
+    props::get_value(event, name)
+        .filter(|value| !datetime::is_date_only(value))
+        .and_then(datetime::parse_date_time)
+}
+
+fn describe_slot(event: &IcalEvent) -> String {
+    let format = |time: Option<NaiveDateTime>| {
+        time.map(|time| time.format("%H:%M").to_string())
+            .unwrap_or_default()
+    };
+
+    format!(
+        "{}–{}",
+        format(parse_time(event, PROPERTY_NAME_DTSTART)),
+        format(parse_time(event, PROPERTY_NAME_DTEND))
+    )
+}
+
+/// Appends the merged slots to the DESCRIPTION of the event.
+fn note_merge(event: &mut IcalEvent, slots: &[String]) {
+    let note = format!(
+        "Zusammengefasst aus {} Terminen: {}",
+        slots.len(),
+        slots.join(", ")
+    );
+
+    let description = match props::get_value(event, PROPERTY_NAME_DESCRIPTION) {
+        Some(description) if !description.trim().is_empty() => {
+            format!("{description}\\n\\n{note}")
+        }
+        Some(_) | None => note,
+    };
+    props::upsert_property(event, PROPERTY_NAME_DESCRIPTION, description);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    /// Parses the events built by `testing::event`, all 90 minutes long in room C-12.01.
+    fn events(events: &[String]) -> Vec<IcalEvent> {
+        ical::IcalParser::new(testing::calendar_of(events).as_bytes())
+            .flat_map(|calendar| calendar.unwrap().events)
+            .collect()
+    }
+
+    fn lecture(start: &str) -> String {
+        testing::event("Labor", start)
+    }
+
+    /// Returns `(DTSTART, DTEND)` of every event.
+    fn ranges(events: &[IcalEvent]) -> Vec<(&str, &str)> {
+        events
+            .iter()
+            .map(|event| {
+                (
+                    props::get_value(event, PROPERTY_NAME_DTSTART).unwrap_or_default(),
+                    props::get_value(event, PROPERTY_NAME_DTEND).unwrap_or_default(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn chains_of_slots_are_merged_into_one_event() {
+        // Out of order, merging sorts by start
+        let slots = events(&[
+            lecture("20241014T111500"),
+            lecture("20241014T081500"),
+            lecture("20241014T094500"),
+        ]);
+
+        let merged = merge_adjacent(slots, Duration::ZERO);
+
+        assert_eq!(
+            ranges(&merged),
+            [("20241014T081500", "20241014T124500")],
+            "the three slots should become one event"
+        );
+        assert_eq!(
+            merged
+                .first()
+                .and_then(|event| props::get_value(event, PROPERTY_NAME_DESCRIPTION)),
+            Some("Zusammengefasst aus 3 Terminen: 08:15–09:45, 09:45–11:15, 11:15–12:45"),
+            "the merge should be noted"
+        );
+        assert_eq!(
+            merged
+                .first()
+                .and_then(|event| props::get_param(event, PROPERTY_NAME_DTEND, "TZID")),
+            Some("Europe/Berlin"),
+            "the end should keep its time zone"
+        );
+    }
+
+    #[test]
+    fn slots_are_only_merged_within_the_gap() {
+        // 15 minutes between the slots
+        let slots = || events(&[lecture("20241014T081500"), lecture("20241014T100000")]);
+
+        assert_eq!(
+            merge_adjacent(slots(), Duration::from_secs(15 * 60)).len(),
+            1,
+            "a gap of 15 minutes should be merged with a threshold of 15 minutes"
+        );
+        assert_eq!(
+            merge_adjacent(slots(), Duration::from_secs(10 * 60)).len(),
+            2,
+            "a gap of 15 minutes should not be merged with a threshold of 10 minutes"
+        );
+    }
+
+    #[test]
+    fn differing_or_overlapping_events_are_not_merged() {
+        let cases = [
+            (
+                "different rooms",
+                vec![
+                    lecture("20241014T081500"),
+                    lecture("20241014T094500").replace("C-12.01", "C-13.02"),
+                ],
+            ),
+            (
+                "different summaries",
+                vec![
+                    lecture("20241014T081500"),
+                    testing::event("Übung", "20241014T094500"),
+                ],
+            ),
+            (
+                "overlapping slots",
+                vec![lecture("20241014T081500"), lecture("20241014T090000")],
+            ),
+            (
+                "all-day events",
+                vec![
+                    lecture("20241014T081500")
+                        .replace(
+                            "DTSTART;TZID=Europe/Berlin:20241014T081500",
+                            "DTSTART;VALUE=DATE:20241014",
+                        )
+                        .replace(
+                            "DTEND;TZID=Europe/Berlin:20241014T094500",
+                            "DTEND;VALUE=DATE:20241015",
+                        ),
+                    lecture("20241015T000000"),
+                ],
+            ),
+        ];
+
+        for (case, slots) in cases {
+            assert_eq!(
+                merge_adjacent(events(&slots), Duration::from_secs(15 * 60)).len(),
+                2,
+                "{case} should not be merged"
+            );
+        }
+    }
+}
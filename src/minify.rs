@@ -0,0 +1,180 @@
+//! Removal of redundant properties to keep the generated calendars small
+
+use crate::calendar::{
+    self, PROPERTY_NAME_DTEND, PROPERTY_NAME_DTSTAMP, PROPERTY_NAME_DTSTART, PROPERTY_NAME_SUMMARY,
+    PROPERTY_NAME_UID,
+};
+use crate::prelude::*;
+use ical::parser::ical::component::IcalEvent;
+use std::collections::BTreeSet;
+
+/// Properties removed by `--minify-ics` unless overridden. A trailing `*` matches any suffix.
+pub const DEFAULT_MINIFY_DENYLIST: &[&str] = &["X-MICROSOFT-*"];
+
+/// Properties which are never removed, even if they are on the denylist.
+const ESSENTIAL_PROPERTIES: &[&str] = &[
+    PROPERTY_NAME_UID,
+    PROPERTY_NAME_DTSTART,
+    PROPERTY_NAME_DTEND,
+    PROPERTY_NAME_SUMMARY,
+    PROPERTY_NAME_DTSTAMP,
+    "RRULE",
+    "RDATE",
+    "EXRULE",
+    "EXDATE",
+    "RECURRENCE-ID",
+];
+
+/// Strips upstream noise from events.
+#[derive(Debug)]
+pub struct Minifier {
+    denylist: Vec<String>,
+}
+
+impl Minifier {
+    /// Creates a minifier removing the properties on `denylist`.
+    pub fn new(denylist: &[String]) -> Self {
+        for name in denylist {
+            if is_essential(name) {
+                warn!("Property '{name}' is essential and is never removed when minifying");
+            }
+        }
+
+        Self {
+            denylist: denylist.iter().map(|name| name.to_uppercase()).collect(),
+        }
+    }
+
+    /// Removes denylisted and empty properties as well as exact duplicates from `event`. Returns
+    /// the number of bytes saved, not counting line folding.
+    pub fn minify_event(&self, event: &mut IcalEvent) -> usize {
+        let mut seen: BTreeSet<String> = BTreeSet::new();
+        let mut saved = 0;
+
+        event.properties.retain(|property| {
+            let line = calendar::property_line(property);
+            let is_empty = property
+                .value
+                .as_deref()
+                .map_or(true, |value| value.trim().is_empty());
+
+            let keep = is_essential(&property.name)
+                || (!self.is_denied(&property.name) && !is_empty && seen.insert(line.clone()));
+            if !keep {
+                // The line itself and its CRLF
+                saved += line.len() + 2;
+            }
+
+            keep
+        });
+
+        saved
+    }
+
+    fn is_denied(&self, name: &str) -> bool {
+        let name = name.to_uppercase();
+
+        self.denylist.iter().any(|pattern| {
+            pattern
+                .strip_suffix('*')
+                .map_or_else(|| name == *pattern, |prefix| name.starts_with(prefix))
+        })
+    }
+}
+
+fn is_essential(name: &str) -> bool {
+    ESSENTIAL_PROPERTIES
+        .iter()
+        .any(|essential| essential.eq_ignore_ascii_case(name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::props;
+
+    fn event(properties: &str) -> IcalEvent {
+        let calendar = format!(
+            "BEGIN:VCALENDAR\r\nVERSION:2.0\r\nBEGIN:VEVENT\r\n{properties}END:VEVENT\r\nEND:VCALENDAR\r\n"
+        );
+
+        ical::IcalParser::new(calendar.as_bytes())
+            .flat_map(|calendar| calendar.unwrap().events)
+            .next()
+            .unwrap()
+    }
+
+    fn names(event: &IcalEvent) -> Vec<&str> {
+        event
+            .properties
+            .iter()
+            .map(|property| property.name.as_str())
+            .collect()
+    }
+
+    fn denylist(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| (*name).to_owned()).collect()
+    }
+
+    #[test]
+    fn denied_empty_and_duplicate_properties_are_removed() {
+        let mut event = event(
+            "UID:1\r\nSUMMARY:Mathe\r\nX-MICROSOFT-CDO-BUSYSTATUS:BUSY\r\nx-microsoft-cdo-importance:1\r\nDESCRIPTION:\r\nCLASS:PUBLIC\r\nCLASS:PUBLIC\r\nLOCATION:C-12.01\r\n",
+        );
+
+        let saved = Minifier::new(&denylist(DEFAULT_MINIFY_DENYLIST)).minify_event(&mut event);
+
+        assert_eq!(
+            names(&event),
+            ["UID", "SUMMARY", "CLASS", "LOCATION"],
+            "the denied, empty and duplicate properties should be removed"
+        );
+        assert_eq!(
+            saved,
+            [
+                "X-MICROSOFT-CDO-BUSYSTATUS:BUSY",
+                "X-MICROSOFT-CDO-IMPORTANCE:1",
+                "DESCRIPTION:",
+                "CLASS:PUBLIC"
+            ]
+            .iter()
+            .map(|line| line.len() + 2)
+            .sum::<usize>(),
+            "the removed lines should be counted with their line endings"
+        );
+    }
+
+    #[test]
+    fn denylist_matches_exact_names_ignoring_case() {
+        let mut event = event("UID:1\r\nCATEGORIES:Vorlesung\r\nCATEGORIES-X:1\r\n");
+
+        Minifier::new(&denylist(&["categories"])).minify_event(&mut event);
+
+        assert_eq!(
+            names(&event),
+            ["UID", "CATEGORIES-X"],
+            "only the property of that name should be removed"
+        );
+    }
+
+    #[test]
+    fn essential_properties_are_never_removed() {
+        let mut event = event(
+            "UID:1\r\nDTSTART:20241014T081500\r\nDTEND:20241014T094500\r\nSUMMARY:Mathe\r\nDTSTAMP:20241001T000000Z\r\nRRULE:FREQ=WEEKLY;COUNT=3\r\n",
+        );
+        let before = names(&event).len();
+
+        let saved = Minifier::new(&denylist(&[
+            "UID", "DTSTART", "DTEND", "SUMMARY", "DTSTAMP", "RRULE",
+        ]))
+        .minify_event(&mut event);
+
+        assert_eq!(saved, 0, "nothing should be saved");
+        assert_eq!(names(&event).len(), before, "every property should be kept");
+        assert_eq!(
+            props::get_value(&event, "RRULE"),
+            Some("FREQ=WEEKLY;COUNT=3"),
+            "the recurrence should be kept"
+        );
+    }
+}
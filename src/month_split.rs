@@ -0,0 +1,115 @@
+//! Splitting of large module calendars into one file per month
+
+use crate::calendar::PROPERTY_NAME_DTSTART;
+use crate::{datetime, props};
+use chrono::Datelike;
+use ical::parser::ical::component::IcalEvent;
+use std::collections::BTreeMap;
+
+/// A month of a module calendar, e.g. `2025-04`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Month {
+    pub year: i32,
+    pub month: u32,
+}
+
+impl Month {
+    /// Label of the month as used in file names, e.g. `2025-04`.
+    pub fn label(self) -> String {
+        format!("{:04}-{:02}", self.year, self.month)
+    }
+}
+
+/// Groups the events by the month they start in. Events spanning several months belong to the
+/// month of their start, events without a valid start are left out.
+pub fn bucket_by_month(events: &[IcalEvent]) -> BTreeMap<Month, Vec<IcalEvent>> {
+    let mut months: BTreeMap<Month, Vec<IcalEvent>> = BTreeMap::new();
+
+    for event in events {
+        let Some(start) =
+            props::get_value(event, PROPERTY_NAME_DTSTART).and_then(datetime::parse_date_time)
+        else {
+            continue;
+        };
+
+        months
+            .entry(Month {
+                year: start.year(),
+                month: start.month(),
+            })
+            .or_default()
+            .push(event.clone());
+    }
+
+    months
+}
+
+/// Path of the file of a single month, in a folder named after the full calendar, e.g.
+/// `files/.../module/2025-04.ics` for `files/.../module.ics`.
+pub fn month_path(file_name: &str, month: Month) -> String {
+    let folder = file_name.strip_suffix(".ics").unwrap_or(file_name);
+
+    format!("{folder}/{}.ics", month.label())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::calendar::PROPERTY_NAME_SUMMARY;
+    use crate::testing;
+
+    fn events(events: &[String]) -> Vec<IcalEvent> {
+        ical::IcalParser::new(testing::calendar_of(events).as_bytes())
+            .flat_map(|calendar| calendar.unwrap().events)
+            .collect()
+    }
+
+    #[test]
+    fn events_are_bucketed_by_the_month_they_start_in() {
+        let events = events(&[
+            testing::event("Mai", "20250502T081500"),
+            // Ends on the 1st of April
+            testing::event("Nacht", "20250331T233000"),
+            testing::event("April", "20250401T081500"),
+            testing::event("Ohne Beginn", "20250402T081500")
+                .replace("DTSTART;TZID=Europe/Berlin:20250402T081500\r\n", ""),
+        ]);
+
+        let months = bucket_by_month(&events)
+            .into_iter()
+            .map(|(month, events)| {
+                let summaries = events
+                    .iter()
+                    .filter_map(|event| props::get_value(event, PROPERTY_NAME_SUMMARY))
+                    .map(str::to_owned)
+                    .collect::<Vec<_>>();
+                (month.label(), summaries)
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            months,
+            [
+                ("2025-03".to_owned(), vec!["Nacht".to_owned()]),
+                ("2025-04".to_owned(), vec!["April".to_owned()]),
+                ("2025-05".to_owned(), vec!["Mai".to_owned()]),
+            ],
+            "events should be grouped by their start, events without one left out"
+        );
+    }
+
+    #[test]
+    fn month_files_are_placed_in_a_folder_named_after_the_calendar() {
+        assert_eq!(
+            month_path(
+                "files/WiSe_2425/iue/semester_1/Mathe.ics",
+                Month {
+                    year: 2025,
+                    month: 4
+                }
+            ),
+            "files/WiSe_2425/iue/semester_1/Mathe/2025-04.ics",
+            "the month should be a file in the folder of the module"
+        );
+    }
+}
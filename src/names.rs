@@ -0,0 +1,236 @@
+//! Normalization of module names
+
+use crate::manifest::sha256_hex;
+
+/// How finely events with the same name are split into calendars.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum GroupBy {
+    /// One calendar per module, placed in the group most of its events come from.
+    #[default]
+    Module,
+    /// One calendar per module and group directory.
+    ModuleGroup,
+    /// One calendar per module and semester.
+    ModuleSemester,
+}
+
+impl GroupBy {
+    /// Returns the key of the calendar an event named `name` from the given semester and group
+    /// directory goes to.
+    pub fn key_for(self, name: &str, semester: &str, group_dir: &str) -> String {
+        let key = canonical_key(name);
+
+        match self {
+            Self::Module => key,
+            Self::ModuleGroup => format!("{key}/{semester}/{group_dir}"),
+            Self::ModuleSemester => format!("{key}/{semester}"),
+        }
+    }
+}
+
+/// Returns the key under which events with the given name are aggregated.
+///
+/// The key is case folded (including German `ß`), has its whitespace collapsed into single spaces
+/// and is trimmed, so that `"Mathematik 1"` and `" mathematik  1"` end up in the same calendar.
+pub fn canonical_key(name: &str) -> String {
+    name.split_whitespace()
+        .map(|word| word.to_lowercase().replace('ß', "ss"))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Decides whether `candidate` is a better name to display than `current`.
+///
+/// The variant with more uppercase letters wins, on a tie the name seen first is kept.
+pub fn is_better_display_name(candidate: &str, current: &str) -> bool {
+    count_uppercase(candidate) > count_uppercase(current)
+}
+
+fn count_uppercase(name: &str) -> usize {
+    name.chars().filter(|c| c.is_uppercase()).count()
+}
+
+/// Number of hex digits of the hash appended to truncated slugs.
+const SLUG_HASH_LENGTH: usize = 8;
+
+/// Characters replaced by an underscore in slugs, either for readability or because Windows
+/// doesn't allow them in file names.
+const REPLACED_CHARACTERS: [char; 11] = ['/', '\\', ' ', '-', ':', '*', '?', '"', '<', '>', '|'];
+
+/// Device names which can't be used as file names on Windows, regardless of the extension.
+const WINDOWS_RESERVED_NAMES: [&str; 22] = [
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// Returns the file name (without extension) used for a module.
+///
+/// The slug is valid on every platform we care about, which means Windows: characters NTFS
+/// doesn't allow are replaced, trailing dots are removed and reserved device names get a leading
+/// underscore. Slugs longer than `max_length` characters are truncated and suffixed with a hash of
+/// the full name, so that they stay unique.
+pub fn module_slug(name: &str, max_length: usize) -> String {
+    let slug: String = name
+        .chars()
+        .map(|c| {
+            if c.is_control() || REPLACED_CHARACTERS.contains(&c) {
+                '_'
+            } else {
+                c
+            }
+        })
+        .collect();
+    let slug = make_windows_safe(slug.trim_end_matches('.'));
+
+    if slug.chars().count() <= max_length {
+        return slug;
+    }
+
+    let hash = sha256_hex(name.as_bytes());
+    let hash = hash.get(..SLUG_HASH_LENGTH).unwrap_or(&hash);
+    let prefix: String = slug
+        .chars()
+        .take(max_length.saturating_sub(SLUG_HASH_LENGTH + 1))
+        .collect();
+
+    format!("{prefix}_{hash}")
+}
+
+/// Keeps Windows from interpreting a file name as a device, e.g. `NUL.ics`.
+fn make_windows_safe(slug: &str) -> String {
+    if slug.is_empty() {
+        return "_".to_owned();
+    }
+
+    let stem = slug.split('.').next().unwrap_or_default();
+    if WINDOWS_RESERVED_NAMES
+        .iter()
+        .any(|reserved| reserved.eq_ignore_ascii_case(stem))
+    {
+        return format!("_{slug}");
+    }
+
+    slug.to_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn canonical_keys_fold_case_and_whitespace() {
+        let cases = [
+            ("Mathematik 1", "mathematik 1"),
+            (" mathematik  1", "mathematik 1"),
+            ("MATHEMATIK\t1", "mathematik 1"),
+            ("Straße", "strasse"),
+            ("STRASSE", "strasse"),
+            ("Übung Ölhydraulik", "übung ölhydraulik"),
+            ("", ""),
+        ];
+
+        for (name, expected) in cases {
+            assert_eq!(canonical_key(name), expected, "key of '{name}'");
+        }
+    }
+
+    #[test]
+    fn spellings_of_the_same_module_share_a_key() {
+        assert_eq!(
+            canonical_key("Grundlagen der Elektrotechnik"),
+            canonical_key("GRUNDLAGEN DER  ELEKTROTECHNIK "),
+            "different spellings should share a key"
+        );
+    }
+
+    #[test]
+    fn display_name_with_more_uppercase_letters_wins() {
+        assert!(
+            is_better_display_name("Mathematik", "mathematik"),
+            "capitalized name should win"
+        );
+        assert!(
+            !is_better_display_name("mathematik", "Mathematik"),
+            "lowercase name should lose"
+        );
+        assert!(
+            !is_better_display_name("Mathematik", "Mathematik"),
+            "the name seen first should be kept on a tie"
+        );
+        assert!(
+            is_better_display_name("Übung", "übung"),
+            "uppercase umlauts should count"
+        );
+    }
+
+    #[test]
+    fn group_by_keys() {
+        let cases = [
+            (GroupBy::Module, "mathe"),
+            (GroupBy::ModuleGroup, "mathe/WiSe_2425/semester_1"),
+            (GroupBy::ModuleSemester, "mathe/WiSe_2425"),
+        ];
+
+        for (group_by, expected) in cases {
+            assert_eq!(
+                group_by.key_for("Mathe", "WiSe_2425", "semester_1"),
+                expected,
+                "key for {group_by:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn long_module_names_get_truncated_unique_slugs() {
+        let name = "Mathematik ".repeat(27) + "A";
+        let other = "Mathematik ".repeat(27) + "B";
+
+        let slug = module_slug(&name, 80);
+
+        assert_eq!(name.chars().count(), 298, "the name should be long");
+        assert_eq!(slug.chars().count(), 80, "the slug should be truncated");
+        assert_eq!(slug, module_slug(&name, 80), "the slug should be stable");
+        assert_ne!(
+            slug,
+            module_slug(&other, 80),
+            "names with the same prefix should get different slugs"
+        );
+    }
+
+    #[test]
+    fn empty_module_names_get_a_usable_slug() {
+        for name in ["", "..."] {
+            assert_eq!(module_slug(name, 80), "_", "slug of '{name}'");
+        }
+    }
+
+    #[test]
+    fn slugs_are_valid_file_names_on_windows() {
+        let cases = [
+            ("Mathe.", "Mathe"),
+            ("Mathe 1...", "Mathe_1"),
+            ("Mathe ", "Mathe_"),
+            ("Physik: 1/2?", "Physik__1_2_"),
+            ("CON", "_CON"),
+            ("nul.txt", "_nul.txt"),
+            ("Lpt1", "_Lpt1"),
+            ("Console", "Console"),
+        ];
+
+        for (name, expected) in cases {
+            assert_eq!(module_slug(name, 80), expected, "slug of '{name}'");
+        }
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn slugs_can_be_created_and_removed_on_windows() {
+        let folder = crate::testing::temp_dir("slugs_on_windows");
+
+        for name in ["Mathe.", "AUX", "Physik: 1/2?", "com1.ics"] {
+            let path = folder.join(format!("{}.ics", module_slug(name, 80)));
+            std::fs::write(&path, "").unwrap();
+            std::fs::remove_file(&path).unwrap();
+        }
+    }
+}
@@ -0,0 +1,314 @@
+//! Cache of parsed calendar files, keyed by the hash of their content
+
+use crate::manifest::sha256_hex;
+use crate::prelude::*;
+use ical::parser::ical::component::{IcalAlarm, IcalEvent};
+use ical::property::Property;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// Folder inside the download cache the parsed files are stored in.
+pub const PARSE_CACHE_FOLDER: &str = "parsed";
+
+/// Version of the stored format, entries of other versions are parsed again.
+const FORMAT_VERSION: u32 = 1;
+
+/// Events of every VCALENDAR block in a file, or the parse error of the block.
+pub type ParsedCalendars = Vec<std::result::Result<Vec<IcalEvent>, String>>;
+
+#[derive(Serialize, Deserialize)]
+struct StoredProperty {
+    name: String,
+    params: Option<Vec<(String, Vec<String>)>>,
+    value: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct StoredEvent {
+    properties: Vec<StoredProperty>,
+    alarms: Vec<Vec<StoredProperty>>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct StoredFile {
+    version: u32,
+    calendars: Vec<std::result::Result<Vec<StoredEvent>, String>>,
+}
+
+/// Stores the events parsed from a calendar file, so unchanged files aren't parsed again.
+#[derive(Debug)]
+pub struct ParseCache {
+    /// `None` if the cache is disabled.
+    folder: Option<PathBuf>,
+}
+
+impl ParseCache {
+    /// Opens the cache in `folder`, falling back to a disabled cache if the folder can't be
+    /// created.
+    pub fn open_or_disable(folder: &Path) -> Self {
+        if let Err(err) = std::fs::create_dir_all(folder) {
+            warn!(
+                "Parse cache folder '{}' can't be created, parsing every file: {err}",
+                folder.display()
+            );
+            return Self::disabled();
+        }
+
+        Self {
+            folder: Some(folder.to_owned()),
+        }
+    }
+
+    /// Returns a cache which never has any content.
+    pub const fn disabled() -> Self {
+        Self { folder: None }
+    }
+
+    /// Parses `content`, using the stored result if the same content was parsed before.
+    pub fn parse(&self, content: &str) -> ParsedCalendars {
+        let Some(folder) = &self.folder else {
+            return parse_calendars(content);
+        };
+
+        let cache_file = folder.join(format!("{}.json", sha256_hex(content.as_bytes())));
+        if let Some(calendars) = load(&cache_file) {
+            return calendars;
+        }
+
+        let calendars = parse_calendars(content);
+        if let Err(err) = store(&cache_file, &calendars) {
+            debug!(
+                "Failed to store the parsed calendars in '{}': {err}",
+                cache_file.display()
+            );
+        }
+
+        calendars
+    }
+}
+
+/// Parses every VCALENDAR block in `content`.
+pub fn parse_calendars(content: &str) -> ParsedCalendars {
+    ical::IcalParser::new(content.as_bytes())
+        .map(|calendar| {
+            calendar
+                .map(|calendar| calendar.events)
+                .map_err(|err| err.to_string())
+        })
+        .collect()
+}
+
+/// Returns the stored calendars, or `None` if there are none in the current format.
+fn load(cache_file: &Path) -> Option<ParsedCalendars> {
+    let stored: StoredFile =
+        serde_json::from_str(&std::fs::read_to_string(cache_file).ok()?).ok()?;
+    if stored.version != FORMAT_VERSION {
+        return None;
+    }
+
+    Some(
+        stored
+            .calendars
+            .into_iter()
+            .map(|calendar| calendar.map(|events| events.into_iter().map(to_event).collect()))
+            .collect(),
+    )
+}
+
+fn store(cache_file: &Path, calendars: &ParsedCalendars) -> Result<()> {
+    let stored = StoredFile {
+        version: FORMAT_VERSION,
+        calendars: calendars
+            .iter()
+            .map(|calendar| {
+                calendar
+                    .as_ref()
+                    .map(|events| events.iter().map(from_event).collect())
+                    .map_err(Clone::clone)
+            })
+            .collect(),
+    };
+    std::fs::write(cache_file, serde_json::to_string(&stored)?)?;
+
+    Ok(())
+}
+
+fn from_properties(properties: &[Property]) -> Vec<StoredProperty> {
+    properties
+        .iter()
+        .map(|property| StoredProperty {
+            name: property.name.clone(),
+            params: property.params.clone(),
+            value: property.value.clone(),
+        })
+        .collect()
+}
+
+fn to_properties(properties: Vec<StoredProperty>) -> Vec<Property> {
+    properties
+        .into_iter()
+        .map(|property| Property {
+            name: property.name,
+            params: property.params,
+            value: property.value,
+        })
+        .collect()
+}
+
+fn from_event(event: &IcalEvent) -> StoredEvent {
+    StoredEvent {
+        properties: from_properties(&event.properties),
+        alarms: event
+            .alarms
+            .iter()
+            .map(|alarm| from_properties(&alarm.properties))
+            .collect(),
+    }
+}
+
+fn to_event(stored: StoredEvent) -> IcalEvent {
+    let mut event = IcalEvent::new();
+    event.properties = to_properties(stored.properties);
+    event.alarms = stored
+        .alarms
+        .into_iter()
+        .map(|properties| {
+            let mut alarm = IcalAlarm::new();
+            alarm.properties = to_properties(properties);
+            alarm
+        })
+        .collect();
+
+    event
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    /// Summaries of the events of every calendar, or the parse error.
+    fn summaries(calendars: &ParsedCalendars) -> Vec<std::result::Result<Vec<String>, String>> {
+        calendars
+            .iter()
+            .map(|calendar| {
+                calendar.clone().map(|events| {
+                    events
+                        .iter()
+                        .filter_map(|event| {
+                            event
+                                .properties
+                                .iter()
+                                .find(|property| property.name == "SUMMARY")
+                                .and_then(|property| property.value.clone())
+                        })
+                        .collect()
+                })
+            })
+            .collect()
+    }
+
+    /// The single file stored in the cache `folder`.
+    fn cache_file(folder: &Path) -> PathBuf {
+        let files: Vec<PathBuf> = std::fs::read_dir(folder)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect();
+        assert_eq!(files.len(), 1, "a single file should be cached: {files:?}");
+
+        files.into_iter().next().unwrap()
+    }
+
+    #[test]
+    fn cached_events_are_the_same_as_the_parsed_ones() {
+        let folder = testing::temp_dir("parse_cache_round_trip");
+        let cache = ParseCache::open_or_disable(&folder);
+        let event = testing::event("Mathe", "20241014T081500").replace(
+            "END:VEVENT",
+            "DESCRIPTION;LANGUAGE=de:Raum\\, Gebäude C\r\nBEGIN:VALARM\r\nACTION:DISPLAY\r\nTRIGGER:-PT15M\r\nEND:VALARM\r\nEND:VEVENT",
+        );
+        let content = testing::calendar_of(&[event]);
+
+        let parsed = cache.parse(&content);
+        let cached = cache.parse(&content);
+
+        assert_eq!(
+            format!("{cached:?}"),
+            format!("{parsed:?}"),
+            "the cached events should have the same properties, parameters and alarms"
+        );
+        assert_eq!(
+            format!("{parsed:?}"),
+            format!("{:?}", parse_calendars(&content)),
+            "the cache shouldn't change the parsed events"
+        );
+    }
+
+    #[test]
+    fn unchanged_content_is_not_parsed_again() {
+        let folder = testing::temp_dir("parse_cache_hit");
+        let cache = ParseCache::open_or_disable(&folder);
+        let content = testing::calendar(&[("Mathe", "20241014T081500")]);
+        cache.parse(&content);
+
+        // Only a cache hit can return the edited summary
+        let cache_file = cache_file(&folder);
+        let stored = std::fs::read_to_string(&cache_file).unwrap();
+        std::fs::write(&cache_file, stored.replace("Mathe", "Physik")).unwrap();
+
+        assert_eq!(
+            summaries(&cache.parse(&content)),
+            [Ok(vec!["Physik".to_owned()])],
+            "the stored events should be used"
+        );
+        assert_eq!(
+            summaries(&cache.parse(&content.replace("Mathe", "Chemie"))),
+            [Ok(vec!["Chemie".to_owned()])],
+            "changed content should be parsed again"
+        );
+    }
+
+    #[test]
+    fn entries_of_another_format_version_are_parsed_again() {
+        let folder = testing::temp_dir("parse_cache_version");
+        let cache = ParseCache::open_or_disable(&folder);
+        let content = testing::calendar(&[("Mathe", "20241014T081500")]);
+        cache.parse(&content);
+
+        let cache_file = cache_file(&folder);
+        let stored = std::fs::read_to_string(&cache_file).unwrap();
+        let outdated = stored
+            .replace(&format!("\"version\":{FORMAT_VERSION}"), "\"version\":0")
+            .replace("Mathe", "Physik");
+        assert_ne!(outdated, stored, "the version should be replaced");
+        std::fs::write(&cache_file, outdated).unwrap();
+
+        assert_eq!(
+            summaries(&cache.parse(&content)),
+            [Ok(vec!["Mathe".to_owned()])],
+            "an outdated entry should be ignored"
+        );
+        assert!(
+            std::fs::read_to_string(&cache_file)
+                .unwrap()
+                .contains(&format!("\"version\":{FORMAT_VERSION}")),
+            "the entry should be replaced in the current format"
+        );
+    }
+
+    #[test]
+    fn parse_errors_are_cached_too() {
+        let folder = testing::temp_dir("parse_cache_errors");
+        let cache = ParseCache::open_or_disable(&folder);
+        let content = "BEGIN:VCALENDAR\r\nBEGIN:VEVENT\r\nSUMMARY:Mathe\r\n";
+
+        let parsed = summaries(&cache.parse(content));
+        let cached = summaries(&cache.parse(content));
+
+        assert!(
+            parsed.first().is_some_and(std::result::Result::is_err),
+            "the broken calendar should fail to parse: {parsed:?}"
+        );
+        assert_eq!(cached, parsed, "the error should be cached");
+    }
+}
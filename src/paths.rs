@@ -0,0 +1,401 @@
+//! Output paths of the module calendars
+
+use crate::calendar::Modules;
+use crate::names;
+use crate::prelude::*;
+use crate::template::{OutputTemplate, PathValues};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Component, Path, PathBuf};
+
+/// Limits on the length of output paths.
+#[derive(Debug)]
+pub struct PathLimits {
+    /// Maximum length of the file name of a module calendar, without extension.
+    pub max_slug: usize,
+    /// Maximum length of the path including the output folder.
+    pub max_path: usize,
+    /// Length of the output folder the paths are relative to.
+    pub output_folder: usize,
+}
+
+impl PathLimits {
+    fn full_length(&self, path: &str) -> usize {
+        // Output folder, separator and relative path
+        self.output_folder + 1 + path.chars().count()
+    }
+}
+
+/// Output paths of all modules, keyed by their canonical name.
+#[derive(Debug, Default)]
+pub struct OutputPaths {
+    pub paths: BTreeMap<String, String>,
+    /// Paths of the modules which collided with another module, to be kept stable across runs.
+    pub resolved: BTreeMap<String, String>,
+}
+
+/// Computes the output path of every module before anything is written.
+///
+/// The file name sanitization is lossy, so different modules can end up with the same path. Paths
+/// are compared case-insensitively since not every file system distinguishes case. Colliding
+/// modules keep the path they had in the previous run, the rest get numbered suffixes in sorted
+/// order.
+pub fn assign_output_paths(
+    modules: &Modules,
+    template: &OutputTemplate,
+    limits: &PathLimits,
+    previous: &BTreeMap<String, String>,
+) -> OutputPaths {
+    // Group the modules by their natural path
+    let mut by_path: BTreeMap<String, Vec<(&str, String)>> = BTreeMap::new();
+    for (key, entry) in modules {
+        let render = |slug_length: usize| {
+            template.render(&PathValues {
+                semester: &entry.semester,
+                department: &entry.department,
+                group: &entry.group_dir,
+                module: &entry.display_name,
+                module_slug: &names::module_slug(&entry.display_name, slug_length),
+            })
+        };
+
+        // Shorten the file name until the full path fits
+        let mut path = render(limits.max_slug);
+        let excess = limits
+            .full_length(&path)
+            .saturating_sub(limits.max_path);
+        if excess > 0 {
+            let slug_length = names::module_slug(&entry.display_name, limits.max_slug)
+                .chars()
+                .count()
+                .saturating_sub(excess);
+            path = render(slug_length);
+
+            if limits.full_length(&path) > limits.max_path {
+                warn!(
+                    "Path of module '{}' is longer than {} characters: '{path}'",
+                    entry.display_name, limits.max_path
+                );
+            }
+        }
+
+        by_path
+            .entry(path.to_lowercase())
+            .or_default()
+            .push((key.as_str(), path));
+    }
+
+    let mut output_paths = OutputPaths::default();
+    let mut claimed: BTreeSet<String> = BTreeSet::new();
+    let mut collisions: Vec<Vec<(&str, String)>> = Vec::new();
+
+    for (lowercase_path, group) in by_path {
+        if group.len() == 1 {
+            for (key, path) in group {
+                output_paths.paths.insert(key.to_owned(), path);
+            }
+            claimed.insert(lowercase_path);
+        } else {
+            collisions.push(group);
+        }
+    }
+
+    for group in collisions {
+        for (index, (key, path)) in group.iter().enumerate() {
+            for (other_key, _) in group.iter().skip(index + 1) {
+                warn!(
+                    "Modules '{}' and '{}' both map to '{path}'",
+                    display_name(modules, key),
+                    display_name(modules, other_key)
+                );
+            }
+        }
+
+        // Modules keep the path of the previous run when nobody else claimed it
+        let mut unresolved = Vec::new();
+        for (key, path) in group {
+            match previous.get(key) {
+                Some(previous_path)
+                    if is_suffixed_variant(&path, previous_path)
+                        && claimed.insert(previous_path.to_lowercase()) =>
+                {
+                    output_paths
+                        .resolved
+                        .insert(key.to_owned(), previous_path.clone());
+                }
+                Some(_) | None => unresolved.push((key, path)),
+            }
+        }
+
+        // The rest is numbered in sorted order
+        for (key, path) in unresolved {
+            let mut candidate = path.clone();
+            let mut number = 2;
+            while !claimed.insert(candidate.to_lowercase()) {
+                candidate = with_suffix(&path, number);
+                number += 1;
+            }
+
+            if candidate != path {
+                info!(
+                    "Writing module '{}' to '{candidate}' instead",
+                    display_name(modules, key)
+                );
+            }
+            output_paths.resolved.insert(key.to_owned(), candidate);
+        }
+    }
+
+    output_paths.paths.extend(
+        output_paths
+            .resolved
+            .iter()
+            .map(|(key, path)| (key.clone(), path.clone())),
+    );
+
+    output_paths
+}
+
+fn display_name<'a>(modules: &'a Modules, key: &'a str) -> &'a str {
+    modules
+        .get(key)
+        .map_or(key, |entry| entry.display_name.as_str())
+}
+
+/// Splits a path into the part before the extension of the file name and the extension.
+fn split_extension(path: &str) -> (&str, &str) {
+    let file_name_start = path.rfind('/').map_or(0, |index| index + 1);
+
+    match path.rfind('.') {
+        Some(index) if index > file_name_start => path.split_at(index),
+        Some(_) | None => (path, ""),
+    }
+}
+
+/// Inserts `_<number>` in front of the extension, e.g. `a/Physik.ics` becomes `a/Physik_2.ics`.
+fn with_suffix(path: &str, number: usize) -> String {
+    let (stem, extension) = split_extension(path);
+
+    format!("{stem}_{number}{extension}")
+}
+
+/// Whether `candidate` is `path` itself or `path` with a numbered suffix.
+fn is_suffixed_variant(path: &str, candidate: &str) -> bool {
+    if path == candidate {
+        return true;
+    }
+
+    let (stem, extension) = split_extension(path);
+    candidate
+        .strip_prefix(stem)
+        .and_then(|rest| rest.strip_suffix(extension))
+        .and_then(|rest| rest.strip_prefix('_'))
+        .is_some_and(|number| !number.is_empty() && number.chars().all(|c| c.is_ascii_digit()))
+}
+
+/// Writes `content` to a temporary file next to `path` and renames it over `path`, so a failed
+/// run never leaves a partially written file behind.
+pub fn write_atomically(path: &Path, content: &str) -> Result<()> {
+    let mut temporary_path = path.as_os_str().to_owned();
+    temporary_path.push(".tmp");
+    let temporary_path = PathBuf::from(temporary_path);
+
+    std::fs::write(&temporary_path, content)?;
+    std::fs::rename(&temporary_path, path)?;
+
+    Ok(())
+}
+
+/// Checks that `path`, relative to the output folder, stays inside of it. Besides `..` this
+/// catches directories which are symlinks to somewhere else.
+pub fn check_inside_output(path: &Path) -> Result<()> {
+    let outside = || Error::PathOutsideOutput(path.to_owned());
+
+    if !path
+        .components()
+        .all(|component| matches!(component, Component::Normal(_) | Component::CurDir))
+    {
+        return Err(outside());
+    }
+
+    // Only the part of the path which already exists can be resolved
+    let root = std::env::current_dir()?.canonicalize()?;
+    let full_path = root.join(path);
+    let Some(existing) = full_path.ancestors().find(|ancestor| ancestor.exists()) else {
+        return Err(outside());
+    };
+
+    if existing.canonicalize()?.starts_with(&root) {
+        Ok(())
+    } else {
+        Err(outside())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::calendar::CalendarEntry;
+    use crate::testing;
+
+    const LIMITS: PathLimits = PathLimits {
+        max_slug: 100,
+        max_path: 1000,
+        output_folder: 0,
+    };
+
+    /// Modules given as `(key, display_name)`, all in the same folder.
+    fn modules(names: &[(&str, &str)]) -> Modules {
+        names
+            .iter()
+            .map(|(key, display_name)| {
+                let entry = CalendarEntry {
+                    events: Vec::new(),
+                    display_name: (*display_name).to_owned(),
+                    department: "iue".to_owned(),
+                    semester: "WiSe_2425".to_owned(),
+                    group_dir: "semester_1".to_owned(),
+                    base_url: String::new(),
+                    source_last_modified: None,
+                    group_dir_events: BTreeMap::new(),
+                    link_texts: Vec::new(),
+                    name_stripped: false,
+                };
+                ((*key).to_owned(), entry)
+            })
+            .collect()
+    }
+
+    fn assign(names: &[(&str, &str)], previous: &BTreeMap<String, String>) -> OutputPaths {
+        let template = OutputTemplate::parse("{module_slug}.ics").unwrap();
+
+        assign_output_paths(&modules(names), &template, &LIMITS, previous)
+    }
+
+    #[test]
+    fn colliding_modules_get_distinct_paths_in_sorted_order() {
+        let output_paths = assign(
+            &[
+                ("physik 1/2", "Physik 1/2"),
+                ("physik 1 2", "Physik 1 2"),
+                ("physik_1_2", "PHYSIK_1_2"),
+                ("mathe", "Mathe"),
+            ],
+            &BTreeMap::new(),
+        );
+
+        assert_eq!(
+            output_paths.paths.into_iter().collect::<Vec<_>>(),
+            [
+                ("mathe".to_owned(), "Mathe.ics".to_owned()),
+                ("physik 1 2".to_owned(), "Physik_1_2.ics".to_owned()),
+                ("physik 1/2".to_owned(), "Physik_1_2_2.ics".to_owned()),
+                ("physik_1_2".to_owned(), "PHYSIK_1_2_3.ics".to_owned()),
+            ],
+            "paths differing in case only should collide as well"
+        );
+        assert!(
+            !output_paths.resolved.contains_key("mathe"),
+            "only colliding modules should be recorded"
+        );
+    }
+
+    #[test]
+    fn resolved_paths_are_stable_across_runs() {
+        let first = assign(
+            &[("physik 1/2", "Physik 1/2"), ("physik 1_2", "Physik 1_2")],
+            &BTreeMap::new(),
+        );
+        // A module sorting before the others collides in the next run
+        let second = assign(
+            &[
+                ("physik 1 2", "Physik 1 2"),
+                ("physik 1/2", "Physik 1/2"),
+                ("physik 1_2", "Physik 1_2"),
+            ],
+            &first.resolved,
+        );
+
+        assert_eq!(
+            second.paths.get("physik 1/2"),
+            first.paths.get("physik 1/2"),
+            "a module should keep its path"
+        );
+        assert_eq!(
+            second.paths.get("physik 1_2"),
+            first.paths.get("physik 1_2"),
+            "a module should keep its suffixed path"
+        );
+        assert_eq!(
+            second.paths.get("physik 1 2").map(String::as_str),
+            Some("Physik_1_2_3.ics"),
+            "the new module should get the next free path"
+        );
+    }
+
+    #[test]
+    fn long_paths_are_shortened_to_the_limit() {
+        let name = "Sehr langer Modulname ".repeat(10);
+        let limits = PathLimits {
+            max_slug: 100,
+            max_path: 60,
+            output_folder: 20,
+        };
+        let template = OutputTemplate::parse("files/{module_slug}.ics").unwrap();
+
+        let output_paths = assign_output_paths(
+            &modules(&[("lang", &name), ("kurz", "Mathe")]),
+            &template,
+            &limits,
+            &BTreeMap::new(),
+        );
+
+        let long = output_paths.paths.get("lang").cloned().unwrap_or_default();
+        assert_eq!(
+            limits.full_length(&long),
+            60,
+            "'{long}' should be shortened to the limit"
+        );
+        assert!(
+            long.starts_with("files/Sehr_langer")
+                && Path::new(&long).extension().is_some_and(|extension| extension == "ics"),
+            "'{long}' should keep the start of the name and the extension"
+        );
+        assert_eq!(
+            output_paths.paths.get("kurz").map(String::as_str),
+            Some("files/Mathe.ics"),
+            "short paths should be left alone"
+        );
+    }
+
+    #[test]
+    fn paths_leaving_the_output_folder_are_rejected() {
+        testing::in_working_dir("paths_leaving_the_output_folder", |output| {
+            assert!(
+                check_inside_output(Path::new("iue/WiSe_2425/semester_1/Mathe.ics")).is_ok(),
+                "a relative path below the output folder should be accepted"
+            );
+            for path in ["../Mathe.ics", "iue/../../Mathe.ics", "/etc/Mathe.ics"] {
+                assert!(
+                    matches!(
+                        check_inside_output(Path::new(path)),
+                        Err(Error::PathOutsideOutput(_))
+                    ),
+                    "'{path}' should be rejected"
+                );
+            }
+
+            // A folder which is a link to somewhere else only shows when resolving the path
+            #[cfg(unix)]
+            {
+                std::os::unix::fs::symlink(std::env::temp_dir(), output.join("iue")).unwrap();
+                assert!(
+                    matches!(
+                        check_inside_output(Path::new("iue/Mathe.ics")),
+                        Err(Error::PathOutsideOutput(_))
+                    ),
+                    "a path through a link leaving the output folder should be rejected"
+                );
+            }
+        });
+    }
+}
@@ -0,0 +1,237 @@
+//! Certificate pinning during the TLS handshake, see `--pinned-cert-sha256`
+
+use crate::manifest::sha256_hex;
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::crypto::{verify_tls12_signature, verify_tls13_signature, CryptoProvider};
+use rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use rustls::{CertificateError, DigitallySignedStruct, OtherError, SignatureScheme};
+use std::sync::Arc;
+
+/// Reason why the handshake with a server was aborted, carried inside the `rustls::Error`.
+#[derive(Debug, thiserror::Error)]
+#[error("The certificate with the SHA-256 fingerprint {fingerprint} isn't pinned")]
+pub struct UnpinnedCertificate {
+    pub fingerprint: String,
+}
+
+/// Accepts exactly the server certificates with one of the pinned fingerprints. The pin replaces
+/// the validation against the root certificates, so a self-signed certificate or the one of an
+/// intercepting proxy can be pinned as well. The handshake signatures are still verified, so the
+/// server has to own the key of the pinned certificate.
+#[derive(Debug)]
+pub struct PinnedCertificateVerifier {
+    /// Lowercase hex SHA-256 fingerprints of the accepted certificates.
+    fingerprints: Vec<String>,
+    provider: Arc<CryptoProvider>,
+}
+
+impl PinnedCertificateVerifier {
+    pub fn new(fingerprints: Vec<String>) -> Self {
+        Self {
+            fingerprints,
+            provider: Arc::new(rustls::crypto::ring::default_provider()),
+        }
+    }
+}
+
+impl ServerCertVerifier for PinnedCertificateVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let fingerprint = sha256_hex(end_entity);
+
+        if self.fingerprints.contains(&fingerprint) {
+            Ok(ServerCertVerified::assertion())
+        } else {
+            Err(rustls::Error::InvalidCertificate(CertificateError::Other(
+                OtherError(Arc::new(UnpinnedCertificate { fingerprint })),
+            )))
+        }
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.provider
+            .signature_verification_algorithms
+            .supported_schemes()
+    }
+}
+
+/// Returns the TLS configuration which only accepts the certificates with these fingerprints.
+pub fn client_config(fingerprints: Vec<String>) -> Result<rustls::ClientConfig, rustls::Error> {
+    let verifier = PinnedCertificateVerifier::new(fingerprints);
+    let mut config = rustls::ClientConfig::builder_with_provider(Arc::clone(&verifier.provider))
+        .with_safe_default_protocol_versions()?
+        .dangerous()
+        .with_custom_certificate_verifier(Arc::new(verifier))
+        .with_no_client_auth();
+    // Like the configuration reqwest builds itself
+    config.alpn_protocols = vec![b"http/1.1".to_vec()];
+
+    Ok(config)
+}
+
+/// Returns the fingerprint of the certificate if `err` or one of its sources is the rejection of an
+/// unpinned certificate.
+pub fn unpinned_fingerprint(err: &(dyn std::error::Error + 'static)) -> Option<String> {
+    let mut source = Some(err);
+
+    while let Some(err) = source {
+        // The source of an `io::Error` is the source of the error it wraps, not that error itself
+        let mut err = err;
+        while let Some(inner) = err
+            .downcast_ref::<std::io::Error>()
+            .and_then(std::io::Error::get_ref)
+        {
+            err = inner;
+        }
+
+        if let Some(rustls::Error::InvalidCertificate(CertificateError::Other(OtherError(other)))) =
+            err.downcast_ref::<rustls::Error>()
+        {
+            if let Some(unpinned) = other.downcast_ref::<UnpinnedCertificate>() {
+                return Some(unpinned.fingerprint.clone());
+            }
+        }
+
+        source = err.source();
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fetch::{Fetcher, HttpFetcher};
+    use crate::prelude::*;
+    use crate::testing;
+    use rustls::pki_types::pem::PemObject;
+
+    const CERTIFICATE: &[u8] = b"certificate of fh-kalender.test";
+
+    fn server_certificate() -> CertificateDer<'static> {
+        CertificateDer::from_pem_slice(testing::SERVER_CERTIFICATE.as_bytes()).unwrap()
+    }
+
+    fn fetch_with_pins(url: &str, fingerprints: Vec<String>) -> Result<String> {
+        let client = reqwest::blocking::Client::builder()
+            .no_proxy()
+            .use_preconfigured_tls(client_config(fingerprints)?)
+            .build()?;
+
+        Ok(HttpFetcher::new(client).fetch(url, None)?.body)
+    }
+
+    fn verify(fingerprints: Vec<String>) -> std::result::Result<ServerCertVerified, rustls::Error> {
+        PinnedCertificateVerifier::new(fingerprints).verify_server_cert(
+            &CertificateDer::from(CERTIFICATE),
+            &[],
+            &ServerName::from(std::net::IpAddr::from(std::net::Ipv4Addr::LOCALHOST)),
+            &[],
+            UnixTime::now(),
+        )
+    }
+
+    #[test]
+    fn pinned_certificates_are_accepted() {
+        assert!(
+            verify(vec!["0".repeat(64), sha256_hex(CERTIFICATE)]).is_ok(),
+            "a certificate with one of the pinned fingerprints should be accepted"
+        );
+    }
+
+    #[test]
+    fn unpinned_certificates_are_rejected_with_their_fingerprint() {
+        let err = verify(vec!["0".repeat(64)]).unwrap_err();
+
+        assert_eq!(
+            unpinned_fingerprint(&err),
+            Some(sha256_hex(CERTIFICATE)),
+            "the fingerprint of the rejected certificate should be reported"
+        );
+    }
+
+    #[test]
+    fn the_rejection_is_found_inside_an_io_error() {
+        let err = verify(vec!["0".repeat(64)]).unwrap_err();
+        let wrapped = std::io::Error::new(
+            std::io::ErrorKind::Other,
+            std::io::Error::new(std::io::ErrorKind::InvalidData, err),
+        );
+
+        assert_eq!(
+            unpinned_fingerprint(&wrapped),
+            Some(sha256_hex(CERTIFICATE)),
+            "the rejection should be found the way the TLS stream reports it"
+        );
+        assert_eq!(
+            unpinned_fingerprint(&std::io::Error::from(std::io::ErrorKind::InvalidData)),
+            None,
+            "other errors shouldn't be mistaken for a rejection"
+        );
+    }
+
+    #[test]
+    fn the_handshake_succeeds_with_a_pinned_certificate() {
+        let url = testing::start_tls_server();
+
+        assert_eq!(
+            fetch_with_pins(&url, vec![sha256_hex(&server_certificate())]).unwrap(),
+            "BEGIN:VCALENDAR",
+            "an untrusted but pinned certificate should be accepted"
+        );
+    }
+
+    #[test]
+    fn the_handshake_fails_with_an_unpinned_certificate() {
+        let url = testing::start_tls_server();
+
+        let err = fetch_with_pins(&url, vec!["0".repeat(64)]).unwrap_err();
+
+        assert!(
+            matches!(
+                &err,
+                Error::CertificateMismatch { host, fingerprint }
+                    if host == "127.0.0.1" && *fingerprint == sha256_hex(&server_certificate())
+            ),
+            "the connection should be refused during the handshake, got {err}"
+        );
+        assert!(
+            !err.is_transient(),
+            "a certificate mismatch shouldn't be retried"
+        );
+    }
+}
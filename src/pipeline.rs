@@ -0,0 +1,8132 @@
+//! The stages of a run, from scraping the websites to writing the calendars
+
+use crate::anonymize::NameStripper;
+use crate::build_info::{BuildInfo, GENERATOR};
+use crate::cache::{Cache, CacheMetadata, CACHE_FOLDER};
+use crate::calendar::{
+    self, is_event_already_present, CalendarEntry, Modules, PROPERTY_NAME_ALSO_LISTED_IN,
+    PROPERTY_NAME_CALENDAR_DESCRIPTION, PROPERTY_NAME_CALENDAR_NAME, PROPERTY_NAME_DESCRIPTION,
+    PROPERTY_NAME_DTSTART, PROPERTY_NAME_GENERATOR, PROPERTY_NAME_LAST_CHANGED,
+    PROPERTY_NAME_SOURCE, PROPERTY_NAME_SUMMARY, UNDATED_SUMMARY_PREFIX,
+};
+use crate::change_history::{self, ChangeRun, ModuleChanges, CHANGES_HTML_FILE};
+use crate::changes::{self, ChangeRecord, CHANGES_FILE};
+use crate::clock::{Clock, Deadline, Sleeper};
+use crate::compat::{self, Compat};
+use crate::config::{Config, WeekLabelPosition};
+use crate::contact;
+use crate::cross_validate::{self, Discrepancy};
+use crate::diagnostics::{
+    CrossValidationFailure, Diagnostics, FetchOrigin, FetchRecord, IgnoredModule,
+    IndexWriteFailure, RepeatedWarnings, SkipReason, UndatedEvent, WriteFailure,
+};
+use crate::event_filter::EventFilter;
+use crate::exit_code::ExitCode;
+use crate::feed::{self, EventChange, FeedEntry, FEED_FILE};
+use crate::fetch::{
+    DirectoryFetcher, FetchResponse, Fetcher, HttpFetcher, RecordingFetcher, ReplayFetcher,
+};
+use crate::ignore::PatternList;
+use crate::index::{self, IndexEntry, IndexModel, IndexSection, Language};
+use crate::manifest::{self, ManifestEntry};
+use crate::minify::Minifier;
+use crate::month_split::{self, Month};
+use crate::parse_cache::{ParseCache, PARSE_CACHE_FOLDER};
+use crate::paths::PathLimits;
+use crate::pinning;
+use crate::politeness::AdaptiveDelay;
+use crate::prelude::*;
+use crate::report::{self, Report};
+use crate::revision::{RevisionTracker, Superseded};
+use crate::robots::Robots;
+use crate::scrape::{self, SourceLink, SourcePath};
+use crate::spill::SpillStore;
+use crate::state::State;
+use crate::stats::{DownloadStats, SourceStats};
+use crate::template::PathValues;
+use crate::text_export::{self, ScheduleEvent};
+use crate::tombstone;
+use crate::validate::{self, EventAnomaly, RangeFix};
+use crate::{datetime, label, merge, names, paths, props, snapshot, statistics, stats};
+use chrono::{DateTime, NaiveDate, Utc};
+use ical::generator::Emitter;
+use ical::generator::IcalCalendarBuilder;
+use ical::parser::ical::component::{IcalCalendar, IcalEvent};
+use ical::property::Property;
+use rayon::prelude::*;
+use regex::Regex;
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::CertificateDer;
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{mpsc, Mutex, PoisonError};
+use std::time::Duration;
+
+const CLIENT_NAME: &str = concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"));
+
+const CLIENT_HOMEPAGE: &str = "https://github.com/AMS21/fh_kiel_ical_splitter";
+
+// How long a cached robots.txt is considered fresh
+const ROBOTS_TXT_MAX_AGE: Duration = Duration::from_secs(60 * 60);
+
+pub const INDEX_FILE: &str = Language::German.file_name();
+
+const MANIFEST_JSON_FILE: &str = "manifest.json";
+
+const STATE_FILE: &str = "state.json";
+
+/// Prefix of the links to the generated files on the published site.
+#[cfg(feature = "github_pages")]
+const LINK_PREFIX: &str = "/fh_kiel_ical_splitter/";
+#[cfg(not(feature = "github_pages"))]
+const LINK_PREFIX: &str = "/";
+
+const STATISTICS_HTML_FILE: &str = "stats.html";
+
+const STATISTICS_JSON_FILE: &str = "stats.json";
+
+// 1 request every 5 second, for the requests made outside of the pipeline
+pub const DOWNLOAD_DELAY: Duration = Duration::from_secs(5);
+
+// How long to wait before retrying a download
+const DOWNLOAD_RETRY_DELAY: Duration = Duration::from_secs(30);
+
+// How often to retry a download before failing
+const MAX_RETRIES: usize = 10;
+
+// Fewer calendars than this on a base URL make us look into the sitemap as well
+const MIN_EXPECTED_CALENDARS: usize = 10;
+
+const SITEMAP_FILE: &str = "sitemap.xml";
+
+// Maximum number of redirects followed for a single request
+const MAX_REDIRECTS: usize = 5;
+
+// How deep to crawl, the department page is at depth 1 and the semester pages linked from it at 2
+const MAX_CRAWL_DEPTH: usize = 2;
+
+// How many departments are crawled at once, the delay between requests is still shared by them
+const MAX_CONCURRENT_DEPARTMENTS: usize = 3;
+
+// Name of the module collecting events without a name when keeping them
+const UNNAMED_MODULE: &str = "Unbenannt";
+
+/// A downloaded calendar file.
+#[derive(Debug)]
+pub struct DownloadedSource {
+    pub link: SourceLink,
+    pub content: String,
+    /// `Last-Modified` header the server sent for the file.
+    pub last_modified: Option<DateTime<Utc>>,
+}
+
+/// The pages of a department, see `Pipeline::crawl_department`.
+#[derive(Default)]
+struct DepartmentCrawl {
+    /// The downloaded pages in the order they were found.
+    pages: Vec<String>,
+    /// Pages which weren't downloaded because the deadline has passed.
+    skipped_urls: Vec<String>,
+}
+
+/// What happened to a single calendar file in `Pipeline::download`.
+enum DownloadOutcome {
+    Downloaded {
+        source: Box<DownloadedSource>,
+        cache_hit: bool,
+    },
+    /// The download failed, the run goes on without the file.
+    Failed {
+        link: SourceLink,
+        reason: SkipReason,
+    },
+    /// The file is excluded or the deadline was exceeded before it was downloaded.
+    Skipped {
+        link: SourceLink,
+        reason: SkipReason,
+    },
+}
+
+/// Name of the module an event belongs to, see `Pipeline::module_name`.
+struct ModuleName {
+    /// Full name the module is found by.
+    name: String,
+    /// Name which is published, without the lecturer names with `--strip-names`.
+    display_name: String,
+}
+
+/// Progress of a department in `Pipeline::download`, kept by the aggregator.
+#[derive(Default)]
+struct DepartmentProgress {
+    downloads: DownloadStats,
+    skipped: usize,
+    /// Calendar files which are still downloading.
+    remaining: usize,
+}
+
+/// Holds everything a run needs, so each stage can also be driven on its own.
+pub struct Pipeline {
+    config: Config,
+    fetcher: Box<dyn Fetcher>,
+    cache: Cache,
+    parse_cache: ParseCache,
+    ignore_list: PatternList,
+    /// Modules which are dropped after aggregation.
+    module_ignore_list: PatternList,
+    /// Modules dropped by the ignore list without their events, to find their old calendars.
+    ignored_modules: Modules,
+    event_filter: EventFilter,
+    transparent_list: PatternList,
+    /// Calendar files which are never downloaded.
+    excluded_sources: Vec<Regex>,
+    /// Maintenance and error pages sent with a successful status.
+    error_page_markers: Vec<Regex>,
+    /// User agent sent with every request, also used to pick the rules from robots.txt.
+    user_agent: String,
+    minifier: Option<Minifier>,
+    /// Removes lecturer names with `--strip-names`.
+    name_stripper: Option<NameStripper>,
+    /// Finds the revisions of calendar files unless every revision is downloaded.
+    revision_tracker: Option<RevisionTracker>,
+    deadline: Deadline,
+    /// Retries left for the rest of the run, shared by all downloads.
+    retry_budget: AtomicUsize,
+    /// Whether a download failed without retrying because the retry budget was used up.
+    retry_budget_exhausted: AtomicBool,
+    sleeper: Box<dyn Sleeper>,
+    /// Delay between requests, adapted to the response times of the server.
+    delay: AdaptiveDelay,
+    /// Held while waiting after a request, so the workers never send a request during the delay.
+    request_gate: Mutex<()>,
+    previous_state: State,
+    diagnostics: Diagnostics,
+    /// Where the events are kept until they are written with `--low-memory`.
+    spill: Option<SpillStore>,
+}
+
+impl Pipeline {
+    /// Creates a pipeline using the real HTTP client and cache folder, or the source folder with
+    /// `--source-dir`.
+    pub fn new(config: Config, clock: Box<dyn Clock>, sleeper: Box<dyn Sleeper>) -> Result<Self> {
+        let source: Box<dyn Fetcher> = match &config.source_dir {
+            Some(source_dir) => {
+                info!("Reading the calendars from '{}'", source_dir.display());
+                Box::new(DirectoryFetcher::new(source_dir, &config.base_urls))
+            }
+            None => Box::new(
+                HttpFetcher::new(build_client(&config)?)
+                    .with_max_response_size(config.max_response_size),
+            ),
+        };
+        let fetcher: Box<dyn Fetcher> = match (&config.record, &config.replay) {
+            (_, Some(replay)) => {
+                info!("Replaying the responses from '{}'", replay.display());
+                Box::new(ReplayFetcher::new(replay))
+            }
+            (Some(record), None) => {
+                info!("Recording the responses to '{}'", record.display());
+                Box::new(RecordingFetcher::new(source, record)?)
+            }
+            (None, None) => source,
+        };
+
+        let cache = open_cache(&config);
+
+        let previous_state = State::load(Path::new(STATE_FILE))?;
+
+        Self::with_dependencies(config, fetcher, cache, previous_state, clock, sleeper)
+    }
+
+    /// Creates a pipeline from explicitly provided dependencies.
+    pub fn with_dependencies(
+        config: Config,
+        fetcher: Box<dyn Fetcher>,
+        cache: Cache,
+        previous_state: State,
+        clock: Box<dyn Clock>,
+        sleeper: Box<dyn Sleeper>,
+    ) -> Result<Self> {
+        // Compile the pattern lists once up front
+        let ignore_list = PatternList::new(&config.ignored_event_names)?;
+        let module_ignore_list = PatternList::new(&config.ignored_modules)?;
+        let event_filter = EventFilter::new(&config.event_filters)?;
+        let transparent_list = PatternList::new(&config.transparent_event_names)?;
+        let excluded_sources = config
+            .excluded_sources
+            .iter()
+            .map(|pattern| {
+                Regex::new(pattern)
+                    .map_err(|err| Error::InvalidSourceExclusion(pattern.clone(), err.to_string()))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let error_page_markers = config
+            .error_page_markers
+            .iter()
+            .map(|marker| {
+                Regex::new(marker)
+                    .map_err(|err| Error::InvalidErrorPageMarker(marker.clone(), err.to_string()))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let user_agent = user_agent(&config)?;
+
+        let minifier = config
+            .minify_ics
+            .then(|| Minifier::new(&config.minify_denylist));
+
+        let name_stripper = if config.strip_names {
+            Some(NameStripper::new(&config.name_patterns)?)
+        } else {
+            None
+        };
+        let revision_tracker = config
+            .revision_pattern
+            .as_deref()
+            .map(RevisionTracker::new)
+            .transpose()?;
+
+        // Start the clock for the global deadline
+        let deadline = Deadline::new(clock, config.max_runtime);
+
+        let retry_budget = AtomicUsize::new(config.retry_budget);
+
+        let delay = AdaptiveDelay::new(config.duty_cycle, config.min_delay, config.max_delay)
+            .with_initial_burst(config.initial_burst, config.burst_delay)
+            .with_prefix_overrides(config.prefix_delays.clone());
+
+        let spill = if config.low_memory {
+            Some(SpillStore::new()?)
+        } else {
+            None
+        };
+
+        let parse_cache = if config.parse_cache && !config.no_cache {
+            ParseCache::open_or_disable(&Path::new(CACHE_FOLDER).join(PARSE_CACHE_FOLDER))
+        } else {
+            ParseCache::disabled()
+        };
+
+        Ok(Self {
+            config,
+            fetcher,
+            cache,
+            parse_cache,
+            ignore_list,
+            module_ignore_list,
+            ignored_modules: Modules::new(),
+            event_filter,
+            transparent_list,
+            excluded_sources,
+            error_page_markers,
+            user_agent,
+            minifier,
+            name_stripper,
+            revision_tracker,
+            deadline,
+            retry_budget,
+            retry_budget_exhausted: AtomicBool::new(false),
+            sleeper,
+            delay,
+            request_gate: Mutex::new(()),
+            previous_state,
+            diagnostics: Diagnostics::default(),
+            spill,
+        })
+    }
+
+    /// Runs all stages and returns the exit code of the process.
+    pub fn run(mut self) -> Result<ExitCode> {
+        let links = self.scrape()?;
+        let downloads = self.download(links);
+        let modules = self.aggregate(downloads)?;
+        let modules = self.drop_ignored_modules(modules);
+
+        let repeated_warnings = self.log_collection(modules.len());
+
+        let number_of_courses = modules.len();
+
+        self.write_statistics(&modules);
+
+        self.emit(modules)?;
+
+        stats::log_department_stats(&self.diagnostics.department_stats);
+
+        info!(
+            "Successfully generated {} calendars for {} departments with a total of {} events",
+            number_of_courses,
+            self.diagnostics
+                .base_stats
+                .iter()
+                .map(|(_, base_stats)| base_stats.departments)
+                .sum::<usize>(),
+            self.diagnostics.run_stats.events_emitted
+        );
+
+        self.write_report(repeated_warnings)?;
+
+        self.exit_code()
+    }
+
+    /// Logs what was collected up to the aggregation and returns how often each repeated warning
+    /// occurred.
+    fn log_collection(&self, number_of_modules: usize) -> BTreeMap<String, usize> {
+        let run_stats = &self.diagnostics.run_stats;
+        info!(
+            "Successfully loaded {} courses from {} calendars in {} files",
+            number_of_modules, run_stats.calendars_parsed, run_stats.calendar_files
+        );
+        info!(
+            "Got {} calendar files from the cache and downloaded {}, {} downloads failed",
+            run_stats.downloads.cache_hit, run_stats.downloads.network, run_stats.downloads.failed
+        );
+        info!(
+            "Saw {} events, ignored {} and dropped {} duplicates",
+            run_stats.events_seen, run_stats.events_ignored, run_stats.events_deduplicated
+        );
+        if run_stats.calendars_broken > 0 {
+            warn!("Failed to parse {} calendars", run_stats.calendars_broken);
+        }
+
+        for url in &self.diagnostics.skipped_urls {
+            warn!("Skipped '{url}' because the maximum runtime was exceeded");
+        }
+
+        if self.retry_budget_exhausted.load(Ordering::Relaxed) {
+            warn!(
+                "The retry budget of {} retries was used up, later failed downloads were not retried",
+                self.config.retry_budget
+            );
+        }
+
+        let repeated_warnings = RepeatedWarnings::global().summarize();
+
+        let delays = self.delay.summarize();
+        if delays.count > 0 {
+            info!(
+                "Waited {} seconds between {} requests, {} to {} ms each",
+                delays.total_ms / 1000,
+                delays.count,
+                delays.min_ms,
+                delays.max_ms
+            );
+        }
+
+        if !self.diagnostics.skipped_links.is_empty() {
+            info!(
+                "Skipped {} calendar links, see the debug log or the report for details",
+                self.diagnostics.skipped_links.len()
+            );
+        }
+
+        stats::log_source_stats(&self.diagnostics.source_stats);
+        self.event_filter.log_matches();
+
+        for (base_url, base_stats) in &self.diagnostics.base_stats {
+            info!(
+                "'{base_url}': {} departments, {} calendars, {} events",
+                base_stats.departments, base_stats.calendars, base_stats.events
+            );
+        }
+
+        repeated_warnings
+    }
+
+    /// Writes the statistics page and its JSON counterpart, recording the files which can't be
+    /// written instead of failing.
+    fn write_statistics(&mut self, modules: &Modules) {
+        let statistics = match statistics::compute(modules, self.spill.as_ref()) {
+            Ok(statistics) => statistics,
+            Err(err) => {
+                for file in [STATISTICS_HTML_FILE, STATISTICS_JSON_FILE] {
+                    self.index_write_failed(file, &err);
+                }
+                return;
+            }
+        };
+
+        if let Err(err) = statistics::render_html(&statistics)
+            .and_then(|html| Ok(std::fs::write(STATISTICS_HTML_FILE, html)?))
+        {
+            self.index_write_failed(STATISTICS_HTML_FILE, &err);
+        }
+        if let Err(err) = serde_json::to_string_pretty(&statistics)
+            .map_err(Error::from)
+            .and_then(|json| Ok(std::fs::write(STATISTICS_JSON_FILE, json)?))
+        {
+            self.index_write_failed(STATISTICS_JSON_FILE, &err);
+        }
+    }
+
+    /// Writes the report and the fetch log if they were asked for.
+    fn write_report(&self, repeated_warnings: BTreeMap<String, usize>) -> Result<()> {
+        if let Some(report_path) = &self.config.report {
+            let report = Report {
+                skipped_links: self.diagnostics.skipped_links.clone(),
+                sources: self.diagnostics.source_stats.clone(),
+                ignored_modules: self.diagnostics.ignored_modules.clone(),
+                write_failures: self.diagnostics.write_failures.clone(),
+                index_write_failures: self.diagnostics.index_write_failures.clone(),
+                undated_events: self.diagnostics.undated_events.clone(),
+                event_anomalies: self.diagnostics.event_anomalies.clone(),
+                cross_validation_failures: self.diagnostics.cross_validation_failures.clone(),
+                fetches: self.diagnostics.fetch_records().clone(),
+                delays: self.delay.summarize(),
+                repeated_warnings,
+                run_stats: self.diagnostics.run_stats.clone(),
+            };
+            report.write(report_path)?;
+        }
+
+        if let Some(fetch_log_path) = &self.config.fetch_log {
+            report::write_fetch_log(&self.diagnostics.fetch_records(), fetch_log_path)?;
+        }
+
+        Ok(())
+    }
+
+    /// Decides how the run ended from the collected diagnostics, failing the checks enabled with
+    /// `--strict`, `--strict-input` and `--cross-validate`.
+    fn exit_code(&self) -> Result<ExitCode> {
+        if !self.diagnostics.undated_events.is_empty() {
+            warn!(
+                "Found {} events without a start, see the report for details",
+                self.diagnostics.undated_events.len()
+            );
+        }
+
+        if !self.diagnostics.event_anomalies.is_empty() {
+            warn!(
+                "Found {} anomalies in the source events, see the report for details",
+                self.diagnostics.event_anomalies.len()
+            );
+
+            if self.config.strict_input {
+                return Err(Error::InputAnomalies(
+                    self.diagnostics.event_anomalies.len(),
+                ));
+            }
+        }
+
+        // Only checked with --cross-validate, which is meant as a gate
+        if !self.diagnostics.cross_validation_failures.is_empty() {
+            return Err(Error::CrossValidationFailures(
+                self.diagnostics.cross_validation_failures.len(),
+            ));
+        }
+
+        if !self.diagnostics.write_failures.is_empty() {
+            warn!(
+                "Failed to write {} calendars",
+                self.diagnostics.write_failures.len()
+            );
+
+            if self.config.strict {
+                return Err(Error::OutputWriteFailures(
+                    self.diagnostics.write_failures.len(),
+                ));
+            }
+        }
+
+        if !self.diagnostics.index_write_failures.is_empty() {
+            warn!(
+                "Failed to write {} index files, the calendars were written anyway",
+                self.diagnostics.index_write_failures.len()
+            );
+
+            if self.config.strict {
+                return Err(Error::IndexWriteFailures(
+                    self.diagnostics.index_write_failures.len(),
+                ));
+            }
+        }
+
+        if let Some((previous, current)) = self.diagnostics.size_growth {
+            warn!(
+                "Total size of the calendars grew from {previous} to {current} bytes, more than {}% since the last run",
+                self.config.max_size_growth
+            );
+
+            if self.config.strict {
+                return Err(Error::OutputSizeGrowth { previous, current });
+            }
+        }
+
+        if !self.diagnostics.skipped_urls.is_empty() {
+            warn!(
+                "Output is partial: {} downloads were skipped because the maximum runtime was exceeded",
+                self.diagnostics.skipped_urls.len()
+            );
+            return Ok(ExitCode::Partial);
+        }
+
+        if !self.diagnostics.failed_urls.is_empty() {
+            warn!(
+                "Output is partial: {} downloads failed",
+                self.diagnostics.failed_urls.len()
+            );
+            return Ok(ExitCode::Partial);
+        }
+
+        if !self.diagnostics.index_write_failures.is_empty() {
+            return Ok(ExitCode::Partial);
+        }
+
+        Ok(ExitCode::Success)
+    }
+
+    /// Collects the links to all calendar files from every base URL, or from the source folder
+    /// with `--source-dir`.
+    pub fn scrape(&mut self) -> Result<Vec<SourceLink>> {
+        let mut links = match self.config.source_dir.clone() {
+            Some(source_dir) => self.scrape_source_dir(&source_dir)?,
+            None => self.scrape_websites()?,
+        };
+
+        self.add_pinned_sources(&mut links)?;
+
+        Ok(self.skip_superseded_revisions(links))
+    }
+
+    /// Lists the calendar files in the source folder as if they were linked on the first base
+    /// URL, so the rest of the pipeline can't tell the difference.
+    fn scrape_source_dir(&mut self, source_dir: &Path) -> Result<Vec<SourceLink>> {
+        let base_url =
+            self.config.base_urls.first().cloned().ok_or_else(|| {
+                Error::InvalidArgument("--source-dir needs a base URL".to_owned())
+            })?;
+
+        let mut links: Vec<SourceLink> = Vec::new();
+        for path in scrape::find_ics_files(source_dir)? {
+            let url = base_url.clone() + path.trim_start_matches('/');
+
+            // Lecturer calendars are left out just like on the site
+            if path.contains("/dozenten/") {
+                self.diagnostics
+                    .skip_link(&base_url, url, SkipReason::Lecturer);
+                continue;
+            }
+
+            match SourceLink::new(&base_url, &path) {
+                Ok(source_link) => links.push(source_link),
+                Err(err) => {
+                    let reason = link_skip_reason(&err);
+                    self.diagnostics.skip_link(&base_url, url, reason);
+                }
+            }
+        }
+
+        info!(
+            "Found {} calendars in '{}'",
+            links.len(),
+            source_dir.display()
+        );
+        self.diagnostics.base_stats_mut(&base_url).departments = links
+            .iter()
+            .map(|link| &link.source_path.department)
+            .collect::<BTreeSet<_>>()
+            .len();
+
+        Ok(links)
+    }
+
+    /// Crawls every base URL for links to calendar files.
+    fn scrape_websites(&mut self) -> Result<Vec<SourceLink>> {
+        let mut links: Vec<SourceLink> = Vec::new();
+        let visited: Mutex<BTreeSet<String>> = Mutex::new(BTreeSet::new());
+        let workers = rayon::ThreadPoolBuilder::new()
+            .num_threads(MAX_CONCURRENT_DEPARTMENTS)
+            .build()?;
+
+        for base_url in self.config.base_urls.clone() {
+            // Respect the wishes of the site operator
+            let robots = if self.config.ignore_robots {
+                warn!("Ignoring robots.txt");
+                Robots::allow_all()
+            } else {
+                self.load_robots(&base_url)
+            };
+
+            // Download main site
+            let main = self.get_main_page(&base_url)?;
+            let institute_links = self.department_links(&base_url, &main);
+
+            info!(
+                "Successfully found {} departments on '{base_url}'",
+                institute_links.len()
+            );
+            self.diagnostics.base_stats_mut(&base_url).departments = institute_links.len();
+
+            // The department pages are few but slow because of the delay between requests, so a
+            // few of them are crawled at once. The links are collected in the order of the
+            // departments afterwards, which keeps them independent of the scheduling.
+            let crawls: Vec<Result<DepartmentCrawl>> = workers.install(|| {
+                institute_links
+                    .par_iter()
+                    .map(|link| self.crawl_department(&base_url, link, &robots, &visited))
+                    .collect()
+            });
+            for crawl in crawls {
+                let crawl = crawl?;
+                self.diagnostics.skipped_urls.extend(crawl.skipped_urls);
+                for page in &crawl.pages {
+                    self.collect_ics_links(&base_url, page, &robots, &mut links);
+                }
+            }
+
+            self.fall_back_to_sitemap(&base_url, &robots, &mut links);
+        }
+
+        Ok(links)
+    }
+
+    /// Downloads the main page of `base_url`, which lists the departments.
+    fn get_main_page(&self, base_url: &str) -> Result<String> {
+        self.get_website(base_url, self.cache_max_age_for(base_url))
+            .map_err(|err| {
+                // Without the main page there is nothing to do, most likely the network is down
+                if err.is_transient() {
+                    Error::Unreachable {
+                        host: reqwest::Url::parse(base_url)
+                            .ok()
+                            .and_then(|url| url.host_str().map(str::to_owned))
+                            .unwrap_or_else(|| base_url.to_owned()),
+                        source: Box::new(err),
+                    }
+                } else {
+                    err
+                }
+            })
+    }
+
+    /// Returns the slugs of the departments listed on the main page of `base_url`.
+    fn department_links(&self, base_url: &str, main: &str) -> Vec<String> {
+        // Prefer the department list embedded as JSON, the buttons change with every redesign
+        match scrape::extract_departments_from_json(main, &self.config.departments_json_id) {
+            Ok(Some(departments)) => {
+                info!("Using the department list embedded as JSON on '{base_url}'");
+                departments
+            }
+            Ok(None) => {
+                info!("No department list embedded as JSON on '{base_url}', using the links");
+                scrape::extract_department_links_from_website(main)
+            }
+            Err(err) => {
+                warn!(
+                    "Failed to parse the department list embedded as JSON on '{base_url}', using the links: {err}"
+                );
+                scrape::extract_department_links_from_website(main)
+            }
+        }
+    }
+
+    /// Downloads the page of the department `link` and the semester pages linked from it. Runs on
+    /// several workers at once, `visited` is shared by all of them so no page is crawled twice.
+    fn crawl_department(
+        &self,
+        base_url: &str,
+        link: &str,
+        robots: &Robots,
+        visited: &Mutex<BTreeSet<String>>,
+    ) -> Result<DepartmentCrawl> {
+        let department_prefix = format!("/{link}/");
+        let is_visited = |path: &str| {
+            visited
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .contains(&(base_url.to_owned() + path))
+        };
+        let mut crawl = DepartmentCrawl::default();
+
+        // Crawl the institute sub page and the semester pages linked from it
+        let mut pending = VecDeque::from([(format!("/{link}"), 1)]);
+        while let Some((path, depth)) = pending.pop_front() {
+            let first_visit = visited
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .insert(base_url.to_owned() + &path);
+            if !first_visit {
+                continue;
+            }
+
+            let page_url = base_url.to_owned() + path.trim_start_matches('/');
+            if !robots.is_allowed(&path) {
+                info!("Skipping '{page_url}' because it is disallowed by robots.txt");
+                continue;
+            }
+            let Some((page, _)) = self.get_website_before_deadline(&page_url)? else {
+                crawl.skipped_urls.push(page_url);
+                continue;
+            };
+
+            if depth < MAX_CRAWL_DEPTH {
+                // Follow links to semester pages of this department
+                for sub_page in scrape::extract_internal_links_from_website(&page) {
+                    let is_semester_page = self.config.semester_page_pattern.as_ref().map_or_else(
+                        || sub_page.starts_with(&department_prefix),
+                        |regex| regex.is_match(sub_page),
+                    );
+                    if is_semester_page && !sub_page.contains("/files/") && !is_visited(sub_page) {
+                        debug!("Following semester page '{sub_page}'");
+                        pending.push_back((sub_page.to_owned(), depth + 1));
+                    }
+                }
+            }
+
+            crawl.pages.push(page);
+        }
+
+        Ok(crawl)
+    }
+
+    /// Adds the calendar files linked on `page` to `links`, skipping the ones which can't or
+    /// mustn't be downloaded.
+    fn collect_ics_links(
+        &mut self,
+        base_url: &str,
+        page: &str,
+        robots: &Robots,
+        links: &mut Vec<SourceLink>,
+    ) {
+        // Iterate through all ics links on the insititutes page
+        for link in scrape::extract_ics_links_from_website(page) {
+            let href = scrape::normalize_link(link.href, !self.config.strip_ics_query);
+            let Some(path) = scrape::site_path(base_url, href) else {
+                self.diagnostics
+                    .skip_link(base_url, link.href.to_owned(), SkipReason::OffHost);
+                continue;
+            };
+            let url = base_url.to_owned() + path.trim_start_matches('/');
+
+            // Ignore any links that only point to teachers
+            if path.contains("/dozenten/") {
+                self.diagnostics
+                    .skip_link(base_url, url, SkipReason::Lecturer);
+                continue;
+            }
+
+            // Extract components from URL
+            let mut source_link = match SourceLink::new(base_url, &path) {
+                Ok(source_link) => source_link,
+                Err(err) => {
+                    let reason = link_skip_reason(&err);
+                    self.diagnostics.skip_link(base_url, url, reason);
+                    continue;
+                }
+            };
+            if !robots.is_allowed(&path) {
+                info!(
+                    "Skipping '{}' because it is disallowed by robots.txt",
+                    source_link.url
+                );
+                self.diagnostics
+                    .skip_link(base_url, url, SkipReason::RobotsDisallowed);
+                continue;
+            }
+            if links.iter().any(|known| known.url == source_link.url) {
+                self.diagnostics
+                    .skip_link(base_url, url, SkipReason::Duplicate);
+                continue;
+            }
+
+            source_link.link_text = link.text;
+            links.push(source_link);
+        }
+    }
+
+    /// Adds the calendar files listed in the sitemap of `base_url` if crawling found too few or
+    /// mostly unexpected links, since the website layout might have changed.
+    fn fall_back_to_sitemap(
+        &mut self,
+        base_url: &str,
+        robots: &Robots,
+        links: &mut Vec<SourceLink>,
+    ) {
+        let found = links
+            .iter()
+            .filter(|link| link.base_url == base_url)
+            .count();
+        let mismatched = self
+            .diagnostics
+            .count_skipped_links(base_url, SkipReason::PatternMismatch);
+        if found >= MIN_EXPECTED_CALENDARS && mismatched <= found {
+            return;
+        }
+
+        if mismatched > found {
+            warn!(
+                "{mismatched} calendar links on '{base_url}' don't match the expected URL layout, falling back to the sitemap"
+            );
+        } else {
+            warn!("Only found {found} calendars on '{base_url}', falling back to the sitemap");
+        }
+
+        for source_link in self.discover_from_sitemap(base_url, robots) {
+            if !links.iter().any(|link| link.url == source_link.url) {
+                links.push(source_link);
+            }
+        }
+
+        info!(
+            "Found {} calendars on '{base_url}' including the sitemap",
+            links
+                .iter()
+                .filter(|link| link.base_url == base_url)
+                .count()
+        );
+    }
+
+    /// Drops the calendar files for which a newer revision was found.
+    fn skip_superseded_revisions(&mut self, links: Vec<SourceLink>) -> Vec<SourceLink> {
+        let Some(revision_tracker) = &self.revision_tracker else {
+            return links;
+        };
+
+        let today = self
+            .deadline
+            .utc_now()
+            .with_timezone(&self.config.display_timezone)
+            .date_naive();
+        let (links, superseded) = revision_tracker.keep_newest(links, today);
+
+        for Superseded { link, newest_url } in superseded {
+            info!(
+                "Skipping '{}' because it is superseded by '{newest_url}'",
+                link.url
+            );
+            self.diagnostics
+                .skip_link(&link.base_url, link.url, SkipReason::Superseded);
+        }
+
+        links
+    }
+
+    /// Adds the pinned sources which were not discovered while scraping.
+    fn add_pinned_sources(&self, links: &mut Vec<SourceLink>) -> Result<()> {
+        for pinned in &self.config.pinned_sources {
+            let (base_url, path) = split_pinned_source(pinned, &self.config.base_urls)
+                .ok_or_else(|| Error::InvalidUrl(pinned.clone()))?;
+            let path = scrape::normalize_link(&path, !self.config.strip_ics_query);
+
+            let mut source_link = SourceLink::new(&base_url, path)?;
+            source_link.pinned = true;
+
+            if let Some(known) = links.iter_mut().find(|link| link.url == source_link.url) {
+                debug!("Pinned source '{pinned}' was also found while scraping");
+                known.pinned = true;
+            } else {
+                info!("Adding pinned source '{pinned}' which was not found while scraping");
+                links.push(source_link);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Fallback discovery strategy collecting the calendar files listed in the sitemap.
+    fn discover_from_sitemap(&mut self, base_url: &str, robots: &Robots) -> Vec<SourceLink> {
+        let sitemap_url = format!("{base_url}{SITEMAP_FILE}");
+        let sitemap = match self.get_website(&sitemap_url, self.cache_max_age_for(&sitemap_url)) {
+            Ok(sitemap) => sitemap,
+            Err(err) => {
+                warn!("Failed to download the sitemap '{sitemap_url}': {err}");
+                return Vec::new();
+            }
+        };
+        // The sitemap is only a fallback, a broken one mustn't cost us the links found already
+        let paths = match scrape::extract_ics_paths_from_sitemap(&sitemap, base_url) {
+            Ok(paths) => paths,
+            Err(err) => {
+                warn!("Failed to parse the sitemap '{sitemap_url}', ignoring it: {err}");
+                return Vec::new();
+            }
+        };
+
+        let mut links = Vec::new();
+        for path in paths {
+            let path = scrape::normalize_link(&path, !self.config.strip_ics_query);
+            let url = base_url.to_owned() + path.trim_start_matches('/');
+
+            let reason = if path.contains("/dozenten/") {
+                SkipReason::Lecturer
+            } else if !robots.is_allowed(path) {
+                SkipReason::RobotsDisallowed
+            } else {
+                match SourceLink::new(base_url, path) {
+                    Ok(source_link) => {
+                        links.push(source_link);
+                        continue;
+                    }
+                    Err(err) => link_skip_reason(&err),
+                }
+            };
+            self.diagnostics.skip_link(base_url, url, reason);
+        }
+
+        links
+    }
+
+    /// Downloads the calendar files, skipping the ones left when the deadline is exceeded.
+    ///
+    /// Each department is downloaded by its own worker, a few at a time, so a department whose
+    /// files are slow or keep failing can't hold up the others. The workers send every outcome
+    /// to this thread, which logs each department as soon as it is done. The result is in the
+    /// order of `links` regardless.
+    pub fn download(&mut self, links: Vec<SourceLink>) -> Vec<DownloadedSource> {
+        let number_of_links = links.len();
+        let mut departments: Vec<VecDeque<(usize, SourceLink)>> = Vec::new();
+        let mut progress: BTreeMap<String, DepartmentProgress> = BTreeMap::new();
+        for (position, link) in links.into_iter().enumerate() {
+            progress
+                .entry(link.source_path.department.clone())
+                .or_default()
+                .remaining += 1;
+
+            let queue = departments.iter_mut().find(|queue| {
+                queue.front().is_some_and(|(_, first)| {
+                    first.source_path.department == link.source_path.department
+                })
+            });
+            match queue {
+                Some(queue) => queue.push_back((position, link)),
+                None => departments.push(VecDeque::from([(position, link)])),
+            }
+        }
+        let number_of_workers = departments.len().min(MAX_CONCURRENT_DEPARTMENTS);
+        let departments = Mutex::new(VecDeque::from(departments));
+
+        let this: &Self = self;
+        let started = this.deadline.now();
+        let mut outcomes = Vec::with_capacity(number_of_links);
+        std::thread::scope(|scope| {
+            let (sender, receiver) = mpsc::channel();
+            for _ in 0..number_of_workers {
+                let sender = sender.clone();
+                let departments = &departments;
+                scope.spawn(move || loop {
+                    let department = departments
+                        .lock()
+                        .unwrap_or_else(PoisonError::into_inner)
+                        .pop_front();
+                    let Some(department) = department else {
+                        break;
+                    };
+
+                    for (position, link) in department {
+                        let name = link.source_path.department.clone();
+                        if sender
+                            .send((position, name, this.download_link(link)))
+                            .is_err()
+                        {
+                            return;
+                        }
+                    }
+                });
+            }
+            drop(sender);
+
+            // Tell about each department as soon as it is done instead of waiting for the others
+            for (position, department, outcome) in receiver {
+                let department_progress = progress.entry(department.clone()).or_default();
+                let counter = match &outcome {
+                    DownloadOutcome::Downloaded {
+                        cache_hit: true, ..
+                    } => &mut department_progress.downloads.cache_hit,
+                    DownloadOutcome::Downloaded { .. } => {
+                        &mut department_progress.downloads.network
+                    }
+                    DownloadOutcome::Failed { .. } => &mut department_progress.downloads.failed,
+                    DownloadOutcome::Skipped { .. } => &mut department_progress.skipped,
+                };
+                *counter += 1;
+                outcomes.push((position, outcome));
+
+                department_progress.remaining = department_progress.remaining.saturating_sub(1);
+                if department_progress.remaining == 0 {
+                    stats::log_department_downloads(
+                        &department,
+                        &department_progress.downloads,
+                        department_progress.skipped,
+                        this.deadline.now().saturating_duration_since(started),
+                    );
+                }
+            }
+        });
+
+        // Aggregating in the original order keeps the output independent of the scheduling
+        outcomes.sort_by_key(|(position, _)| *position);
+
+        outcomes
+            .into_iter()
+            .filter_map(|(_, outcome)| self.record_download(outcome))
+            .collect()
+    }
+
+    /// Adds the outcome of a download to the diagnostics, returning the downloaded file.
+    fn record_download(&mut self, outcome: DownloadOutcome) -> Option<DownloadedSource> {
+        let mut download_stats = DownloadStats::default();
+        let (department, source) = match outcome {
+            DownloadOutcome::Downloaded { source, cache_hit } => {
+                if cache_hit {
+                    download_stats.cache_hit = 1;
+                } else {
+                    download_stats.network = 1;
+                }
+                (source.link.source_path.department.clone(), Some(*source))
+            }
+            DownloadOutcome::Failed { link, reason } => {
+                download_stats.failed = 1;
+                if reason == SkipReason::DownloadFailed {
+                    self.diagnostics.failed_urls.push(link.url.clone());
+                }
+                let department = link.source_path.department.clone();
+                self.diagnostics.skip_link(&link.base_url, link.url, reason);
+                (department, None)
+            }
+            DownloadOutcome::Skipped { link, reason } => {
+                let department = link.source_path.department.clone();
+                if reason == SkipReason::DeadlineExceeded {
+                    self.diagnostics.skipped_urls.push(link.url);
+                    *self
+                        .diagnostics
+                        .run_stats
+                        .urls_skipped
+                        .entry(reason)
+                        .or_default() += 1;
+                } else {
+                    self.diagnostics.skip_link(&link.base_url, link.url, reason);
+                }
+                (department, None)
+            }
+        };
+        self.add_download_stats(&department, download_stats);
+
+        source
+    }
+
+    /// Adds `download_stats` to the totals of the run and of `department`.
+    fn add_download_stats(&mut self, department: &str, download_stats: DownloadStats) {
+        for totals in [
+            &mut self.diagnostics.run_stats.downloads,
+            &mut self
+                .diagnostics
+                .department_stats
+                .entry(department.to_owned())
+                .or_default()
+                .downloads,
+        ] {
+            totals.cache_hit += download_stats.cache_hit;
+            totals.network += download_stats.network;
+            totals.failed += download_stats.failed;
+        }
+    }
+
+    /// Downloads a single calendar file for `download`. Runs on the download workers, the
+    /// outcome is added to the diagnostics by `record_download`.
+    fn download_link(&self, link: SourceLink) -> DownloadOutcome {
+        // Known broken files are skipped without touching the module they belong to
+        if self
+            .excluded_sources
+            .iter()
+            .any(|pattern| pattern.is_match(&link.url))
+        {
+            info!("Skipping '{}' because it is excluded", link.url);
+            return DownloadOutcome::Skipped {
+                link,
+                reason: SkipReason::Excluded,
+            };
+        }
+
+        let download = match self.get_website_before_deadline(&link.url) {
+            Ok(download) => download,
+            // A pinned source might be gone for good, which shouldn't stop the run
+            Err(err) if link.pinned => {
+                warn!("Failed to download the pinned source '{}': {err}", link.url);
+                return DownloadOutcome::Failed {
+                    link,
+                    reason: SkipReason::PinnedUnavailable,
+                };
+            }
+            // Neither should a single broken file, only its modules are incomplete this time
+            Err(err) => {
+                error!("Failed to download '{}': {err}", link.url);
+                return DownloadOutcome::Failed {
+                    link,
+                    reason: SkipReason::DownloadFailed,
+                };
+            }
+        };
+
+        let Some((content, metadata)) = download else {
+            return DownloadOutcome::Skipped {
+                link,
+                reason: SkipReason::DeadlineExceeded,
+            };
+        };
+
+        let cache_hit = self
+            .diagnostics
+            .fetch_records()
+            .iter()
+            .rev()
+            .find(|record| record.url == link.url)
+            .is_some_and(|record| record.origin.is_cache_hit());
+
+        let last_modified = metadata
+            .last_modified
+            .as_deref()
+            .and_then(datetime::parse_http_date);
+
+        DownloadOutcome::Downloaded {
+            source: Box::new(DownloadedSource {
+                link,
+                content,
+                last_modified,
+            }),
+            cache_hit,
+        }
+    }
+
+    /// Parses the downloaded calendars and collects their events per module. With `--low-memory`
+    /// the events go to the spill files instead, see `restore_events`.
+    pub fn aggregate(&mut self, downloads: Vec<DownloadedSource>) -> Result<Modules> {
+        let mut map = Modules::new();
+
+        for download in downloads {
+            self.aggregate_source(&mut map, download)?;
+        }
+
+        // Modules listed in several group directories go where most of their events come from
+        for calendar_entry in map.values_mut() {
+            calendar_entry.settle_group_dir();
+
+            let other_group_dirs = calendar_entry.other_group_dirs();
+            if !other_group_dirs.is_empty() {
+                info!(
+                    "Module '{}' is listed in '{}' and also in '{}'",
+                    calendar_entry.display_name,
+                    calendar_entry.group_dir,
+                    other_group_dirs.join("', '")
+                );
+            }
+        }
+
+        Ok(map)
+    }
+
+    /// Parses a single downloaded calendar file and adds its events to the modules in `map`.
+    fn aggregate_source(&mut self, map: &mut Modules, download: DownloadedSource) -> Result<()> {
+        let DownloadedSource {
+            link,
+            content,
+            last_modified,
+        } = download;
+        let url = link.url.clone();
+
+        let mut number_of_calendars = 0;
+        let mut number_of_broken_calendars = 0;
+        let mut number_of_events = 0;
+
+        let (content, stripped) = calendar::sanitize_source(&content);
+        if !stripped.is_empty() {
+            warn!(
+                "Stripped {} bytes in front of the calendar in '{url}': '{}'",
+                stripped.len(),
+                stripped.escape_debug()
+            );
+        }
+
+        let calendars = self.parse_cache.parse(&content);
+        let mut stats = self
+            .diagnostics
+            .source_stats
+            .get(&url)
+            .copied()
+            .unwrap_or_default();
+
+        // A file can contain several VCALENDAR blocks, which are numbered from 1 in logs
+        for (block_index, calendar) in calendars.into_iter().enumerate() {
+            let block_number = block_index + 1;
+
+            match calendar {
+                Ok(events) => {
+                    number_of_calendars += 1;
+                    self.diagnostics.run_stats.calendars_parsed += 1;
+
+                    // Iterate through all events of that calendar
+                    for mut event in events {
+                        stats.parsed += 1;
+                        self.diagnostics.run_stats.events_seen += 1;
+
+                        self.check_event(&mut event, &url);
+                        let Some(module_name) = self.module_name(&mut event, &url, &mut stats)
+                        else {
+                            continue;
+                        };
+                        if self.is_dropped(&mut event, &module_name.name, &url, &mut stats) {
+                            continue;
+                        }
+
+                        self.add_to_module(
+                            map,
+                            &link,
+                            last_modified,
+                            module_name,
+                            event,
+                            &mut stats,
+                        )?;
+                        number_of_events += 1;
+                    }
+                }
+                Err(err) => {
+                    error!("Parse error in calendar {block_number} of '{url}': {err}");
+                    number_of_broken_calendars += 1;
+                    self.diagnostics.run_stats.calendars_broken += 1;
+                }
+            }
+        }
+        self.diagnostics.source_stats.insert(url.clone(), stats);
+
+        if number_of_calendars + number_of_broken_calendars > 1 {
+            debug!(
+                "'{url}' contains {} calendars",
+                number_of_calendars + number_of_broken_calendars
+            );
+        }
+
+        self.diagnostics.run_stats.calendar_files += 1;
+
+        let base_stats = self.diagnostics.base_stats_mut(&link.base_url);
+        base_stats.calendars += number_of_calendars;
+        base_stats.events += number_of_events;
+
+        let department_stats = self
+            .diagnostics
+            .department_stats
+            .entry(link.source_path.department)
+            .or_default();
+        department_stats.calendars += number_of_calendars;
+        department_stats.events += number_of_events;
+        department_stats.failures += number_of_broken_calendars;
+
+        Ok(())
+    }
+
+    /// Records the anomalies of an event from `url` and fixes its range if it ends before it
+    /// starts.
+    fn check_event(&mut self, event: &mut IcalEvent, url: &str) {
+        // The parser accepts oddities like a second DTSTART without complaint
+        for anomaly in validate::validate_event(event) {
+            let summary = props::get_value(event, PROPERTY_NAME_SUMMARY)
+                .unwrap_or_default()
+                .to_owned();
+            warn!(
+                repeated = "Found anomalies in source events",
+                "{} of event '{summary}' in '{url}' {}", anomaly.property, anomaly.detail
+            );
+            self.diagnostics.event_anomalies.push(EventAnomaly {
+                source: url.to_owned(),
+                summary,
+                anomaly,
+            });
+        }
+
+        // Clients render events ending before they start bizarrely or reject the whole file,
+        // with --strict-input they fail the run instead
+        match validate::normalize_range(event, !self.config.strict_input) {
+            Some(RangeFix::Swapped(start)) => {
+                warn!(
+                    repeated = "Swapped the start and end of events ending before they start",
+                    "Event '{}' on {} in '{url}' ends before it starts, swapped its start and end",
+                    props::get_value(event, PROPERTY_NAME_SUMMARY).unwrap_or_default(),
+                    start.format("%d.%m.%Y")
+                );
+            }
+            Some(RangeFix::AllDayExtended(date)) => {
+                debug!(
+                    "All-day event '{}' on {} in '{url}' ended on its start day, it now lasts that day",
+                    props::get_value(event, PROPERTY_NAME_SUMMARY).unwrap_or_default(),
+                    date.format("%d.%m.%Y")
+                );
+            }
+            None => {}
+        }
+    }
+
+    /// Cleans up the summary of an event from `url` and returns it as the name of its module, or
+    /// `None` if the event has no name and is skipped.
+    fn module_name(
+        &mut self,
+        event: &mut IcalEvent,
+        url: &str,
+        stats: &mut SourceStats,
+    ) -> Option<ModuleName> {
+        // Extract name and clean it up
+        let summary = props::get_value(event, PROPERTY_NAME_SUMMARY).unwrap_or_default();
+        let mut name = clean_summary(summary);
+
+        // Lecturer names are matched before the cleanup removes the dash in front of them. Only
+        // the published name loses them, the module is still found by its full name.
+        let mut display_name = self
+            .name_stripper
+            .as_ref()
+            .and_then(|name_stripper| name_stripper.strip(summary))
+            .map(|stripped| clean_summary(&stripped));
+
+        // Cancellation markers in the summary are turned into a STATUS
+        if let Some(stripped_summary) =
+            calendar::strip_cancelled_prefix(&name, &self.config.cancelled_summary_prefixes)
+        {
+            name = stripped_summary;
+            display_name = display_name.map(|display_name| {
+                calendar::strip_cancelled_prefix(
+                    &display_name,
+                    &self.config.cancelled_summary_prefixes,
+                )
+                .unwrap_or(display_name)
+            });
+            calendar::mark_cancelled(event);
+        }
+
+        // Guard against names which are empty after the cleanup
+        if name.trim().is_empty() {
+            if self.config.keep_unnamed {
+                UNNAMED_MODULE.clone_into(&mut name);
+            } else {
+                warn!(
+                    repeated = "Skipping events without a name",
+                    "Skipping event without a name from '{url}'"
+                );
+                stats.unnamed += 1;
+                self.diagnostics.run_stats.events_ignored += 1;
+                return None;
+            }
+        }
+        props::upsert_property(event, PROPERTY_NAME_SUMMARY, name.clone());
+
+        let display_name = display_name
+            .filter(|display_name| !display_name.trim().is_empty())
+            .unwrap_or_else(|| name.clone());
+        Some(ModuleName { name, display_name })
+    }
+
+    /// Whether the event `name` from `url` is dropped because it is undated, cancelled, ignored
+    /// or filtered. Events which are kept are marked as transparent where applicable.
+    fn is_dropped(
+        &mut self,
+        event: &mut IcalEvent,
+        name: &str,
+        url: &str,
+        stats: &mut SourceStats,
+    ) -> bool {
+        // Events without a start can't be placed in a calendar
+        if props::get_value(event, PROPERTY_NAME_DTSTART).is_none() {
+            stats.undated += 1;
+            self.diagnostics.undated_events.push(UndatedEvent {
+                source: url.to_owned(),
+                properties: calendar::property_lines(event),
+            });
+
+            if self.config.keep_undated {
+                calendar::set_placeholder_date(event, self.config.undated_placeholder_date);
+            } else {
+                warn!("Skipping event '{name}' without a start from '{url}'");
+                self.diagnostics.run_stats.events_ignored += 1;
+                return true;
+            }
+        }
+
+        if self.config.drop_cancelled && calendar::is_cancelled(event) {
+            debug!("Dropping cancelled event '{name}'");
+            stats.cancelled += 1;
+            self.diagnostics.run_stats.events_ignored += 1;
+            return true;
+        }
+
+        // Ignore festive days and other events
+        if let Some(pattern) = self.ignore_list.find_match(name) {
+            if self.config.annotate_ignored {
+                debug!("Annotating event '{name}' matching pattern '{pattern}'");
+                calendar::mark_ignored(event, pattern);
+            } else {
+                debug!("Ignoring event '{name}' matching pattern '{pattern}'");
+                stats.ignored += 1;
+                self.diagnostics.run_stats.events_ignored += 1;
+                return true;
+            }
+        }
+
+        if let Some(rule) = self.event_filter.find_match(name, event) {
+            debug!("Dropping event '{name}' matching event filter '{rule}'");
+            stats.filtered += 1;
+            self.diagnostics.run_stats.events_ignored += 1;
+            return true;
+        }
+
+        // Informational events shouldn't block busy time
+        calendar::set_default_transparency(event, self.transparent_list.find_match(name).is_some());
+
+        false
+    }
+
+    /// Adds the event named `module_name` found in the calendar file `link` to its module in
+    /// `map`, unless the module already contains an identical event.
+    fn add_to_module(
+        &mut self,
+        map: &mut Modules,
+        link: &SourceLink,
+        last_modified: Option<DateTime<Utc>>,
+        module_name: ModuleName,
+        mut event: IcalEvent,
+        stats: &mut SourceStats,
+    ) -> Result<()> {
+        let SourcePath {
+            department,
+            semester,
+            group_dir,
+        } = &link.source_path;
+
+        let ModuleName { name, display_name } = module_name;
+        let name_stripped = display_name != name;
+
+        // Remember where the event came from
+        let key = self.config.group_by.key_for(&name, semester, group_dir);
+        if self.config.no_provenance {
+            props::remove_property(&mut event, PROPERTY_NAME_SOURCE);
+        } else {
+            calendar::set_source(&mut event, &link.path);
+        }
+
+        // Append to map
+        let Some(calendar_entry) = map.get_mut(&key) else {
+            // Create new map entry for this course
+            let events = match &mut self.spill {
+                Some(spill) => {
+                    spill.push(&key, &event)?;
+                    Vec::new()
+                }
+                None => vec![event],
+            };
+            map.insert(
+                key,
+                CalendarEntry {
+                    events,
+                    display_name,
+                    department: department.clone(),
+                    semester: semester.clone(),
+                    group_dir: group_dir.clone(),
+                    base_url: link.base_url.clone(),
+                    source_last_modified: last_modified,
+                    group_dir_events: BTreeMap::from([(group_dir.clone(), 1)]),
+                    link_texts: link.link_text.iter().cloned().collect(),
+                    name_stripped,
+                },
+            );
+            stats.accepted += 1;
+
+            return Ok(());
+        };
+
+        // Prefer the data of the first base if the bases disagree
+        if calendar_entry.base_url != link.base_url
+            && (calendar_entry.department != *department
+                || calendar_entry.semester != *semester
+                || calendar_entry.group_dir != *group_dir)
+            && self.diagnostics.conflicting_modules.insert(key.clone())
+        {
+            warn!(
+                "Module '{name}' from '{}' conflicts with the data from '{}', keeping the first",
+                link.base_url, calendar_entry.base_url
+            );
+        }
+
+        calendar_entry.source_last_modified =
+            calendar_entry.source_last_modified.max(last_modified);
+        calendar_entry.add_link_text(link.link_text.as_deref());
+
+        // Keep the best spelling of the name for display
+        if names::is_better_display_name(&display_name, &calendar_entry.display_name) {
+            calendar_entry.display_name = display_name;
+            calendar_entry.name_stripped = name_stripped;
+        }
+
+        // Don't add any duplicate events
+        let group_dir_events = calendar_entry
+            .group_dir_events
+            .entry(group_dir.clone())
+            .or_default();
+        let added = match &mut self.spill {
+            Some(spill) => spill.push(&key, &event)?,
+            None if is_event_already_present(&event, &calendar_entry.events) => false,
+            None => {
+                calendar_entry.events.push(event);
+                true
+            }
+        };
+        if added {
+            *group_dir_events += 1;
+            stats.accepted += 1;
+        } else {
+            stats.deduplicated += 1;
+            self.diagnostics.run_stats.events_deduplicated += 1;
+        }
+
+        Ok(())
+    }
+
+    /// Reads the events of the module `key` back from its spill file with `--low-memory`.
+    pub fn restore_events(&self, key: &str, entry: &mut CalendarEntry) -> Result<()> {
+        if let Some(spill) = &self.spill {
+            entry.events = spill.load(key)?;
+        }
+
+        Ok(())
+    }
+
+    /// Drops the modules matching the module ignore list. Their calendars from earlier runs are
+    /// deleted without a tombstone, they didn't move anywhere.
+    fn drop_ignored_modules(&mut self, mut modules: Modules) -> Modules {
+        let ignored: Vec<(String, String)> = modules
+            .iter()
+            .filter_map(|(key, entries)| {
+                let pattern = self.module_ignore_list.find_match(&entries.display_name)?;
+                Some((key.clone(), pattern.to_owned()))
+            })
+            .collect();
+
+        for (key, pattern) in ignored {
+            let Some(mut entries) = modules.remove(&key) else {
+                continue;
+            };
+
+            // The events might be in the spill files, the counters know them either way
+            let events: u32 = entries.group_dir_events.values().sum();
+            info!(
+                "Ignoring module '{}' with {events} events, it matches '{pattern}'",
+                entries.display_name
+            );
+            self.diagnostics.ignored_modules.push(IgnoredModule {
+                module: entries.display_name.clone(),
+                pattern,
+                events,
+            });
+
+            // Only the path of the module is needed later on
+            entries.events = Vec::new();
+            self.ignored_modules.insert(key, entries);
+        }
+
+        modules
+    }
+
+    /// Writes a calendar file for every module together with the index and the manifest.
+    pub fn emit(&mut self, modules: Modules) -> Result<Vec<ManifestEntry>> {
+        let mut state = State::default();
+        let run_timestamp = chrono::Utc::now();
+        let today = run_timestamp
+            .with_timezone(&self.config.display_timezone)
+            .date_naive();
+
+        // Resolve colliding paths before anything is written
+        let limits = PathLimits {
+            max_slug: self.config.max_slug_length,
+            max_path: self.config.max_path_length,
+            output_folder: std::env::current_dir()?.as_os_str().len(),
+        };
+        let output_paths = paths::assign_output_paths(
+            &modules,
+            &self.config.output_template,
+            &limits,
+            &self.previous_state.output_paths,
+        );
+        state.output_paths = output_paths.resolved;
+        let ignored_paths: BTreeSet<String> = paths::assign_output_paths(
+            &self.ignored_modules,
+            &self.config.output_template,
+            &limits,
+            &self.previous_state.output_paths,
+        )
+        .paths
+        .into_values()
+        .collect();
+
+        // Build and write the calendars in parallel, the index and the logs follow in order
+        let context = EmitContext {
+            config: &self.config,
+            previous_state: &self.previous_state,
+            minifier: self.minifier.as_ref(),
+            name_stripper: self.name_stripper.as_ref(),
+            output_paths: &output_paths.paths,
+            today,
+            spill: self.spill.as_ref(),
+        };
+        let outputs: Vec<ModuleOutput> = modules
+            .into_iter()
+            .collect::<Vec<_>>()
+            .into_par_iter()
+            .filter_map(|(key, entries)| emit_module(&context, &key, entries))
+            .collect();
+
+        let mut collection = EmitCollection::default();
+        for output in outputs {
+            self.collect_output(output, &mut collection, &mut state, run_timestamp, today);
+        }
+
+        // Modules missing from a partial run or failing to write keep their state, otherwise
+        // their events would start over at SEQUENCE 0 when they are back, which clients ignore
+        let partial = self.diagnostics.is_partial();
+        for key in self.previous_state.modules.keys() {
+            if partial || collection.failed_modules.contains(key) {
+                state.carry_over_module(&self.previous_state, key);
+            }
+        }
+
+        if self.config.strip_names {
+            info!(
+                "Removed lecturer names from {} events",
+                collection.events_with_names
+            );
+        }
+
+        // The index is only written once every calendar it links to exists
+        let (indexes, snapshot) = self.render_indexes(
+            collection.index_sections,
+            &collection.schedules,
+            run_timestamp,
+        )?;
+
+        self.write_change_files(
+            &mut state,
+            collection.changed_modules,
+            collection.feed_entries,
+            run_timestamp,
+            today,
+        );
+
+        let mut manifest_entries = collection.manifest_entries;
+        if self.config.tombstone_days > 0 {
+            self.write_tombstones(
+                &mut state,
+                &output_paths.paths,
+                &ignored_paths,
+                &mut manifest_entries,
+                today,
+            );
+        }
+
+        self.remove_stale_files(&mut state, &output_paths.paths, &manifest_entries);
+        self.check_size_growth(&mut state, &manifest_entries);
+
+        self.write_index_files(
+            &manifest_entries,
+            &indexes,
+            snapshot.as_ref(),
+            run_timestamp,
+        );
+        state.save(Path::new(STATE_FILE))?;
+
+        Ok(manifest_entries)
+    }
+
+    /// Logs the outcome of emitting a single module and adds it to the state, the index and the
+    /// manifest.
+    fn collect_output(
+        &mut self,
+        output: ModuleOutput,
+        collection: &mut EmitCollection,
+        state: &mut State,
+        run_timestamp: DateTime<Utc>,
+        today: NaiveDate,
+    ) {
+        let ModuleOutput {
+            key,
+            placement,
+            minified_bytes,
+            events_with_names,
+            result,
+        } = output;
+        let module = placement.module.clone();
+        collection.events_with_names += events_with_names;
+
+        if let Some(minified_bytes) = minified_bytes {
+            info!("Minifying the calendar of module '{module}' saved {minified_bytes} bytes");
+        }
+
+        // A single failing module shouldn't cost us all the others
+        let EmittedModule {
+            module_state,
+            manifest_entry,
+            month_entries,
+            event_keys,
+            event_changes,
+            number_of_events,
+            text_export_error,
+            schedule,
+            discrepancies,
+        } = match result {
+            Ok(emitted) => emitted,
+            Err(err) => {
+                error!("Failed to write calendar for module '{module}': {err}");
+                self.diagnostics
+                    .department_stats
+                    .entry(placement.department)
+                    .or_default()
+                    .failures += 1;
+                self.diagnostics
+                    .write_failures
+                    .push(WriteFailure { module, error: err });
+                collection.failed_modules.insert(key);
+                return;
+            }
+        };
+        collection.changed_modules.extend(
+            module_state
+                .modules
+                .iter()
+                .filter(|(_, module_state)| module_state.last_changed == today)
+                .map(|(key, _)| ChangeRecord {
+                    key: key.clone(),
+                    module: module.clone(),
+                    path: manifest_entry.path.clone(),
+                    date: today,
+                }),
+        );
+        // On the first run every event would be new, which is no news
+        if !event_changes.is_empty() && !self.previous_state.events.is_empty() {
+            collection.feed_entries.push(FeedEntry::new(
+                &key,
+                module.clone(),
+                manifest_entry.path.clone(),
+                run_timestamp,
+                event_changes,
+            ));
+        }
+        state.events.extend(module_state.events);
+        state.modules.extend(module_state.modules);
+        self.diagnostics
+            .department_stats
+            .entry(placement.department.clone())
+            .or_default()
+            .modules += 1;
+
+        if let Some(err) = text_export_error {
+            error!("Failed to write text export for module '{module}': {err}");
+        }
+
+        for discrepancy in discrepancies {
+            error!(
+                "Cross-validation of module '{module}' failed on {}: {}",
+                discrepancy.property, discrepancy.detail
+            );
+            self.diagnostics
+                .cross_validation_failures
+                .push(CrossValidationFailure {
+                    module: module.clone(),
+                    discrepancy,
+                });
+        }
+
+        state
+            .calendar_events
+            .insert(manifest_entry.path.clone(), event_keys);
+        self.add_index_entry(
+            collection,
+            placement,
+            manifest_entry,
+            month_entries,
+            schedule,
+        );
+
+        info!("Successfully created calendar for module '{module}' with {number_of_events} events");
+        self.diagnostics.run_stats.events_emitted += number_of_events;
+    }
+
+    /// Adds the link to a written module calendar to its section of the index, and the calendar
+    /// with its month files to the manifest.
+    fn add_index_entry(
+        &self,
+        collection: &mut EmitCollection,
+        placement: IndexPlacement,
+        manifest_entry: ManifestEntry,
+        month_entries: Vec<(Month, ManifestEntry)>,
+        schedule: Vec<ScheduleEvent>,
+    ) {
+        let IndexPlacement {
+            module,
+            department,
+            semester,
+            group_dir,
+            group_label,
+            link_text,
+            also_listed_in,
+            last_changed,
+        } = placement;
+        let version_query = |entry: &ManifestEntry| {
+            if self.config.cache_busting {
+                format!("?v={}", entry.version())
+            } else {
+                String::new()
+            }
+        };
+
+        let section = collection
+            .index_sections
+            .entry((
+                label::semester_sort_key(&semester),
+                semester.clone(),
+                department.clone(),
+                label::group_sort_key(&group_dir),
+                group_dir,
+            ))
+            .or_insert_with(|| IndexSection {
+                semester,
+                department,
+                group_label: group_label.clone(),
+                entries: Vec::new(),
+            });
+
+        let months = month_entries
+            .iter()
+            .map(|(month, month_entry)| {
+                (
+                    month.label(),
+                    format!(
+                        "{LINK_PREFIX}{}{}",
+                        month_entry.path,
+                        version_query(month_entry)
+                    ),
+                )
+            })
+            .collect();
+
+        let href = format!(
+            "{LINK_PREFIX}{}{}",
+            manifest_entry.path,
+            version_query(&manifest_entry)
+        );
+        if self.config.single_file_output.is_some() {
+            collection.schedules.insert(href.clone(), schedule);
+        }
+        section.entries.push(IndexEntry {
+            href,
+            title: link_text,
+            module,
+            group_label,
+            also_listed_in,
+            last_changed,
+            months,
+            history: Vec::new(),
+        });
+
+        collection.manifest_entries.push(manifest_entry);
+        collection.manifest_entries.extend(
+            month_entries
+                .into_iter()
+                .map(|(_, month_entry)| month_entry),
+        );
+    }
+
+    /// Renders the index in every language, and the snapshot with `--single-file-output`.
+    fn render_indexes(
+        &self,
+        index_sections: BTreeMap<IndexSectionKey, IndexSection>,
+        schedules: &BTreeMap<String, Vec<ScheduleEvent>>,
+        run_timestamp: DateTime<Utc>,
+    ) -> Result<RenderedIndexes> {
+        let index_model = IndexModel {
+            sections: index::collapse_semesters(index_sections.into_values().collect()),
+            department_stats: &self.diagnostics.department_stats,
+            base_urls: &self.config.base_urls,
+            statistics_href: STATISTICS_HTML_FILE,
+            changes_href: CHANGES_HTML_FILE,
+            generated_at: run_timestamp,
+            display_timezone: self.config.display_timezone,
+        };
+        let indexes = Language::ALL
+            .into_iter()
+            .map(|language| Ok((language, index::render(&index_model, language)?)))
+            .collect::<Result<Vec<_>>>()?;
+        let snapshot = self
+            .config
+            .single_file_output
+            .as_ref()
+            .map(|path| snapshot::render(&index_model, schedules).map(|html| (path.clone(), html)))
+            .transpose()?;
+
+        Ok((indexes, snapshot))
+    }
+
+    /// Updates the recent changes, the change history and the feed, and writes them. Files which
+    /// can't be written are recorded instead of failing.
+    fn write_change_files(
+        &mut self,
+        state: &mut State,
+        changed_modules: Vec<ChangeRecord>,
+        feed_entries: Vec<FeedEntry>,
+        run_timestamp: DateTime<Utc>,
+        today: NaiveDate,
+    ) {
+        state.changes =
+            changes::update_changes(&self.previous_state.changes, changed_modules, today);
+        if let Err(err) = write_output_file(
+            Path::new(CHANGES_FILE),
+            &changes::render_changes(&state.changes, LINK_PREFIX),
+        ) {
+            self.index_write_failed(CHANGES_FILE, &err);
+        }
+        state.change_history = change_history::update_history(
+            &self.previous_state.change_history,
+            ChangeRun {
+                at: run_timestamp,
+                modules: feed_entries.iter().map(ModuleChanges::from).collect(),
+            },
+            self.config.display_timezone,
+            self.config.change_history_runs,
+        );
+        if let Err(err) = change_history::render_html(
+            &state.change_history,
+            LINK_PREFIX,
+            self.config.display_timezone,
+        )
+        .and_then(|html| write_output_file(Path::new(CHANGES_HTML_FILE), &html))
+        {
+            self.index_write_failed(CHANGES_HTML_FILE, &err);
+        }
+        state.feed = feed::update_feed(&self.previous_state.feed, feed_entries);
+        if let Err(err) = feed::render_feed(
+            &state.feed,
+            LINK_PREFIX,
+            run_timestamp,
+            self.config.display_timezone,
+        )
+        .and_then(|feed| write_output_file(Path::new(FEED_FILE), &feed))
+        {
+            self.index_write_failed(FEED_FILE, &err);
+        }
+    }
+
+    /// Remembers the total size of the calendars, noting if it grew by more than allowed.
+    fn check_size_growth(&mut self, state: &mut State, manifest_entries: &[ManifestEntry]) {
+        // Sudden growth usually means events were duplicated
+        let total_size = manifest::total_size(manifest_entries);
+        info!("Total size of the calendars: {total_size} bytes");
+        if let Some(previous) = self.previous_state.total_size {
+            if previous > 0
+                && total_size.saturating_mul(100)
+                    > previous.saturating_mul(100 + self.config.max_size_growth)
+            {
+                self.diagnostics.size_growth = Some((previous, total_size));
+            }
+        }
+        state.total_size = Some(total_size);
+    }
+
+    /// Writes the manifests, the indexes and the snapshot, recording the files which can't be
+    /// written instead of failing.
+    fn write_index_files(
+        &mut self,
+        manifest_entries: &[ManifestEntry],
+        indexes: &[(Language, String)],
+        snapshot: Option<&(PathBuf, String)>,
+        run_timestamp: DateTime<Utc>,
+    ) {
+        // The calendars are already on disk, so a file listing them which can't be written, e.g.
+        // because it is locked by an editor on Windows, only makes the output partial
+        let mut index_results = vec![(
+            manifest::MANIFEST_FILE,
+            manifest::write_manifest(Path::new(manifest::MANIFEST_FILE), manifest_entries),
+        )];
+        index_results.extend(indexes.iter().map(|(language, index)| {
+            (
+                language.file_name(),
+                paths::write_atomically(Path::new(language.file_name()), index),
+            )
+        }));
+        if let Some((path, snapshot)) = snapshot {
+            index_results.push((
+                path.to_str().unwrap_or("snapshot"),
+                write_output_file(path, snapshot),
+            ));
+        }
+        index_results.push((
+            MANIFEST_JSON_FILE,
+            manifest::write_manifest_json(
+                Path::new(MANIFEST_JSON_FILE),
+                &BuildInfo::new(&self.config.arguments, run_timestamp),
+                manifest_entries,
+            ),
+        ));
+        for (file, result) in index_results {
+            if let Err(err) = result {
+                self.index_write_failed(file, &err);
+            }
+        }
+    }
+
+    /// Records that `file`, which isn't a calendar, couldn't be written. The run goes on since
+    /// the calendars themselves are fine, but its output is partial.
+    fn index_write_failed(&mut self, file: &str, err: &Error) {
+        error!("Failed to write '{file}': {err}");
+        self.diagnostics
+            .index_write_failures
+            .push(IndexWriteFailure {
+                file: file.to_owned(),
+                error: err.to_string(),
+            });
+    }
+
+    /// Writes tombstones at the paths of the calendars of the previous run which disappeared, so
+    /// subscriptions to them learn where the calendar moved to. The calendars of ignored modules
+    /// get none, `remove_stale_files` deletes them.
+    fn write_tombstones(
+        &self,
+        state: &mut State,
+        assigned_paths: &BTreeMap<String, String>,
+        ignored_paths: &BTreeSet<String>,
+        manifest_entries: &mut Vec<ManifestEntry>,
+        today: NaiveDate,
+    ) {
+        // Calendars which failed to write or weren't downloaded this time didn't disappear
+        let partial = self.diagnostics.is_partial();
+        let mut current_calendars = state.calendar_events.clone();
+        for (path, keys) in &self.previous_state.calendar_events {
+            if partial || assigned_paths.values().any(|assigned| assigned == path) {
+                current_calendars
+                    .entry(path.clone())
+                    .or_insert_with(|| keys.clone());
+            }
+        }
+
+        let mut previous_calendars = self.previous_state.calendar_events.clone();
+        previous_calendars.retain(|path, _| !ignored_paths.contains(path));
+        let mut previous_tombstones = self.previous_state.tombstones.clone();
+        previous_tombstones.retain(|path, _| !ignored_paths.contains(path));
+
+        state.tombstones = tombstone::update_tombstones(
+            &previous_tombstones,
+            &previous_calendars,
+            &current_calendars,
+            today,
+            self.config.tombstone_days,
+        );
+
+        for (path, tombstone) in &state.tombstones {
+            if tombstone.since == today {
+                if let Some(new_path) = &tombstone.new_path {
+                    info!("'{path}' disappeared, it seems to have moved to '{new_path}'");
+                } else {
+                    info!("'{path}' disappeared, publishing a tombstone");
+                }
+            }
+
+            let content = tombstone::render_tombstone(path, tombstone, LINK_PREFIX);
+            match write_output_file(Path::new(path), &content) {
+                Ok(()) => {
+                    manifest_entries.push(ManifestEntry::new(path.clone(), content.as_bytes()));
+                }
+                Err(err) => error!("Failed to write the tombstone '{path}': {err}"),
+            }
+        }
+
+        // Keep the calendars of a partial run, they might be back next time
+        state.calendar_events = current_calendars;
+    }
+
+    /// Deletes the calendar files of the previous run which weren't written again, e.g. because a
+    /// changed output template moved them. The files of modules which failed to write and all
+    /// files of a partial run are kept, they might be written again next time.
+    fn remove_stale_files(
+        &self,
+        state: &mut State,
+        assigned_paths: &BTreeMap<String, String>,
+        manifest_entries: &[ManifestEntry],
+    ) {
+        let partial = self.diagnostics.is_partial();
+        state.output_files = manifest_entries
+            .iter()
+            .map(|entry| entry.path.clone())
+            .collect();
+
+        for path in &self.previous_state.output_files {
+            if state.output_files.contains(path) {
+                continue;
+            }
+
+            // Month files are stored in a folder named like the calendar
+            let is_assigned = assigned_paths.values().any(|assigned| {
+                assigned == path
+                    || assigned
+                        .strip_suffix(".ics")
+                        .and_then(|folder| path.strip_prefix(folder))
+                        .is_some_and(|rest| rest.starts_with('/'))
+            });
+            if partial || is_assigned {
+                state.output_files.insert(path.clone());
+                continue;
+            }
+
+            info!("Removing '{path}' which is no longer generated");
+            if let Err(err) = remove_output_file(Path::new(path)) {
+                warn!("Failed to remove '{path}': {err}");
+            }
+        }
+    }
+
+    fn load_robots(&self, base_url: &str) -> Robots {
+        let robots_url = base_url.to_owned() + "robots.txt";
+
+        match self.get_website(&robots_url, Some(ROBOTS_TXT_MAX_AGE)) {
+            Ok(content) => Robots::parse(&content, &self.user_agent),
+            Err(err) => {
+                warn!("Failed to fetch '{robots_url}', assuming everything is allowed: {err}");
+                Robots::allow_all()
+            }
+        }
+    }
+
+    /// Downloads `url` unless the deadline has passed, in which case `None` is returned and the
+    /// caller records the URL as skipped.
+    fn get_website_before_deadline(&self, url: &str) -> Result<Option<(String, CacheMetadata)>> {
+        if self.deadline.is_expired() {
+            return Ok(None);
+        }
+
+        match self.get_website_with_metadata(url, self.cache_max_age_for(url)) {
+            Ok(download) => Ok(Some(download)),
+            Err(Error::DeadlineExceeded(_)) => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// How long the cached content of `url` may be used without asking the server again.
+    ///
+    /// Calendar files rarely change and are cached for longer, while the listing pages are
+    /// revalidated on every run by default so new links show up.
+    fn cache_max_age_for(&self, url: &str) -> Option<Duration> {
+        if scrape::is_ics_url(url) {
+            self.config.ics_cache_max_age
+        } else {
+            self.config.html_cache_max_age
+        }
+    }
+
+    fn send_request(
+        &self,
+        url: &str,
+        cached: Option<&(String, CacheMetadata)>,
+    ) -> (Result<FetchResponse>, Duration) {
+        self.timed(|| {
+            self.fetcher
+                .fetch(url, cached.map(|(_, metadata)| metadata))
+        })
+    }
+
+    /// Runs a request and returns how long it took, for the delay after it. The request isn't
+    /// sent while another worker waits after its request.
+    fn timed<T, F: FnOnce() -> T>(&self, request: F) -> (T, Duration) {
+        drop(
+            self.request_gate
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner),
+        );
+
+        let start = self.deadline.now();
+        let result = request();
+
+        (result, self.deadline.now().saturating_duration_since(start))
+    }
+
+    /// Waits after a request to `url`, the longer the slower the server responded to it. The
+    /// delay is added to the fetch record of the request, if there is one.
+    fn wait_after_request(&self, url: &str, response_time: Duration) {
+        // Reading local files bothers no one
+        if self.config.source_dir.is_some() {
+            return;
+        }
+
+        let delay = self.delay.after_response(url, response_time);
+        debug!(
+            "Request for '{url}' took {} ms, waiting {} ms (at least {} ms)",
+            response_time.as_millis(),
+            delay.as_millis(),
+            self.delay.min_delay_for(url).as_millis()
+        );
+
+        let mut fetch_records = self.diagnostics.fetch_records();
+        if let Some(record) = fetch_records
+            .iter_mut()
+            .rev()
+            .find(|record| record.url == url)
+        {
+            record.delay_ms = Some(delay.as_millis());
+        }
+        drop(fetch_records);
+
+        // The delay is shared by all workers, like it was a single client
+        let _gate = self
+            .request_gate
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        self.sleeper.sleep(delay);
+    }
+
+    /// Asks the server with a HEAD request whether the cached content of `url` is still current.
+    /// Servers without HEAD support and failed requests fall back to the conditional GET.
+    /// Also returns how long the request took.
+    fn is_unchanged(&self, url: &str, metadata: &CacheMetadata) -> (bool, Duration) {
+        let (head, response_time) = self.timed(|| self.fetcher.head(url));
+        let unchanged = match head {
+            Ok(head) if head.status() == reqwest::StatusCode::METHOD_NOT_ALLOWED => {
+                debug!("'{url}' doesn't support HEAD requests");
+                false
+            }
+            Ok(head) => metadata.matches_head(&head),
+            Err(err) => {
+                debug!("HEAD request for '{url}' failed: {err}");
+                false
+            }
+        };
+
+        (unchanged, response_time)
+    }
+
+    /// Remembers the response metadata of a calendar file, so the report tells which version of
+    /// the file was seen and how much was transferred. `response` is `None` if no request was
+    /// sent.
+    fn record_fetch(
+        &self,
+        url: &str,
+        response: Option<&FetchResponse>,
+        metadata: &CacheMetadata,
+        origin: FetchOrigin,
+    ) {
+        if !scrape::is_ics_url(url) {
+            return;
+        }
+
+        self.diagnostics.fetch_records().push(FetchRecord {
+            url: url.to_owned(),
+            status: response.map(|response| response.status_code),
+            origin,
+            content_length: metadata.content_length.clone(),
+            last_modified: metadata.last_modified.clone(),
+            etag: metadata.etag.clone(),
+            content_encoding: response
+                .and_then(|response| response.header("content-encoding"))
+                .map(str::to_owned),
+            encoded_size: response.and_then(|response| response.encoded_size),
+            decoded_size: response.map(|response| response.body.len()),
+            delay_ms: None,
+        });
+    }
+
+    /// Returns the first error page marker matching `body`. Calendar files are only checked if
+    /// they contain no calendar at all, since events may well mention maintenance work.
+    fn error_page_marker(&self, url: &str, body: &str) -> Option<&str> {
+        if scrape::is_ics_url(url) && calendar::contains_calendar(body) {
+            return None;
+        }
+
+        self.error_page_markers
+            .iter()
+            .find(|marker| marker.is_match(body))
+            .map(Regex::as_str)
+    }
+
+    fn get_website(&self, url: &str, max_age: Option<Duration>) -> Result<String> {
+        self.get_website_with_metadata(url, max_age)
+            .map(|(content, _)| content)
+    }
+
+    /// Like `get_website`, but also returns the metadata of the response.
+    fn get_website_with_metadata(
+        &self,
+        url: &str,
+        max_age: Option<Duration>,
+    ) -> Result<(String, CacheMetadata)> {
+        // Check if the cache file exists and load content from disk if it does, unless it is too old
+        if let Some(content) = self.cache.load(url, max_age)? {
+            let metadata = self.cache.load_metadata(url);
+            self.record_fetch(url, None, &metadata, FetchOrigin::Cache);
+
+            return Ok((content, metadata));
+        }
+
+        // Don't start any new downloads once the deadline has passed
+        if self.deadline.is_expired() {
+            return Err(Error::DeadlineExceeded(url.to_owned()));
+        }
+
+        // Outdated cache content is revalidated using a conditional request
+        let cached = self.cache.load_with_metadata(url)?;
+        if let Some(download) = self.probe_cached(url, cached.as_ref())? {
+            return Ok(download);
+        }
+
+        // If the cache file doesn't exist, actually send a request and cache it
+        match self.send_with_retries(url, cached)? {
+            Attempts::NotModified(download) => Ok(download),
+            Attempts::Last {
+                attempt,
+                number_of_attempts,
+                response_time,
+            } => {
+                let download = attempt
+                    .and_then(|response| self.accept_response(url, response, number_of_attempts));
+
+                // Wait a bit to not spam the server, failed requests bother it just the same
+                self.wait_after_request(url, response_time);
+
+                download
+            }
+        }
+    }
+
+    /// Returns the cached content of a calendar file if a HEAD request shows it is unchanged, so
+    /// it doesn't need to be downloaded at all. Only done with `--head-probe`.
+    fn probe_cached(
+        &self,
+        url: &str,
+        cached: Option<&(String, CacheMetadata)>,
+    ) -> Result<Option<(String, CacheMetadata)>> {
+        let Some((content, metadata)) = cached else {
+            return Ok(None);
+        };
+        if !self.config.head_probe || !scrape::is_ics_url(url) {
+            return Ok(None);
+        }
+        let (unchanged, response_time) = self.is_unchanged(url, metadata);
+        if unchanged {
+            debug!("'{url}' is unchanged according to a HEAD request, using the cached content");
+            self.cache.store(url, content, metadata)?;
+            self.record_fetch(url, None, metadata, FetchOrigin::HeadProbe);
+        }
+
+        // Wait a bit to not spam the server, also before downloading a changed file
+        self.wait_after_request(url, response_time);
+
+        Ok(unchanged.then(|| (content.clone(), metadata.clone())))
+    }
+
+    /// Sends the request for `url`, retrying while the failure might be temporary.
+    fn send_with_retries(
+        &self,
+        url: &str,
+        cached: Option<(String, CacheMetadata)>,
+    ) -> Result<Attempts> {
+        let (mut attempt, mut response_time) = self.send_request(url, cached.as_ref());
+        let mut number_of_attempts = 1;
+
+        for try_count in 0..MAX_RETRIES {
+            // The cached content is still up to date
+            let is_not_modified = attempt
+                .as_ref()
+                .is_ok_and(|response| response.status() == reqwest::StatusCode::NOT_MODIFIED);
+            if is_not_modified {
+                if let Some((content, metadata)) = cached {
+                    debug!("'{url}' was not modified, using the cached content");
+                    self.cache.store(url, &content, &metadata)?;
+                    self.record_fetch(
+                        url,
+                        attempt.as_ref().ok(),
+                        &metadata,
+                        FetchOrigin::NotModified,
+                    );
+
+                    // Wait a bit to not spam the server when downloading
+                    self.wait_after_request(url, response_time);
+
+                    return Ok(Attempts::NotModified((content, metadata)));
+                }
+            }
+
+            let Some(failure) = self.retry_reason(url, &attempt) else {
+                break;
+            };
+
+            // The same failure tends to hit every request while the server is struggling
+            warn!(
+                repeated = %format_args!("Requests failed with {failure}"),
+                "[{}/{}] Request for '{}' failed with {}, waiting {} seconds before retrying",
+                try_count + 1,
+                MAX_RETRIES,
+                url,
+                failure,
+                DOWNLOAD_RETRY_DELAY.as_secs()
+            );
+            if try_count == MAX_RETRIES - 1 || !self.wait_before_retry(url)? {
+                break;
+            }
+
+            // Send next request
+            (attempt, response_time) = self.send_request(url, cached.as_ref());
+            number_of_attempts += 1;
+        }
+
+        Ok(Attempts::Last {
+            attempt,
+            number_of_attempts,
+            response_time,
+        })
+    }
+
+    /// Describes why `attempt` should be retried, or returns `None` if it succeeded or retrying
+    /// wouldn't help.
+    fn retry_reason(&self, url: &str, attempt: &Result<FetchResponse>) -> Option<String> {
+        match attempt {
+            // Check if the request was successful
+            Ok(response) if response.status().is_success() && !response.body.trim().is_empty() => {
+                // Maintenance pages are sent with a successful status as well
+                self.error_page_marker(url, &response.body)
+                    .map(|marker| format!("an error page matching '{marker}'"))
+            }
+            // The CDN serves empty bodies for a moment after a deploy
+            Ok(response) if response.status().is_success() => Some("an empty body".to_owned()),
+            // A missing page won't appear by retrying
+            Ok(response) if response.status() == reqwest::StatusCode::NOT_FOUND => None,
+            Ok(response) => Some(format!("status: {}", response.status())),
+            // Network problems like DNS or TLS failures are often gone after a moment
+            Err(err) if err.is_transient() => Some(format!("error: {err}")),
+            Err(_) => None,
+        }
+    }
+
+    /// Waits before retrying `url`. Returns `false` if the retry budget is used up, and fails if
+    /// the deadline would pass while waiting.
+    fn wait_before_retry(&self, url: &str) -> Result<bool> {
+        // Retries are shared by the whole run, so a flapping server can't stall every download
+        let Ok(retries) =
+            self.retry_budget
+                .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |retries| {
+                    retries.checked_sub(1)
+                })
+        else {
+            warn!("Retry budget is used up, giving up on '{url}'");
+            self.retry_budget_exhausted.store(true, Ordering::Relaxed);
+            return Ok(false);
+        };
+        let retries_left = retries - 1;
+        debug!("{retries_left} retries left in the retry budget");
+
+        // Give up instead of waiting past the deadline
+        if self.deadline.expires_within(DOWNLOAD_RETRY_DELAY) {
+            warn!("Deadline reached while retrying '{url}', giving up");
+            return Err(Error::DeadlineExceeded(url.to_owned()));
+        }
+
+        // Wait before retrying the download
+        self.sleeper.sleep(DOWNLOAD_RETRY_DELAY);
+
+        Ok(true)
+    }
+
+    /// Checks the response of the last attempt for `url` and caches it if it is usable.
+    fn accept_response(
+        &self,
+        url: &str,
+        response: FetchResponse,
+        number_of_attempts: usize,
+    ) -> Result<(String, CacheMetadata)> {
+        let metadata = CacheMetadata::from_response(&response);
+        self.record_fetch(url, Some(&response), &metadata, FetchOrigin::Network);
+        if !response.status().is_success() {
+            return Err(Error::RequestFailed(response.status()));
+        }
+
+        // An empty body is never cached, the next run tries again
+        let response_body = response.body;
+        if response_body.trim().is_empty() {
+            error!("Response body of '{url}' is still empty after {number_of_attempts} attempts");
+            return Err(Error::EmptyResponse(number_of_attempts));
+        }
+
+        // Neither is an error page, it would replace the cached content until it expires
+        if let Some(marker) = self.error_page_marker(url, &response_body) {
+            error!("'{url}' still returns an error page after {number_of_attempts} attempts");
+            return Err(Error::ErrorPage {
+                url: url.to_owned(),
+                marker: marker.to_owned(),
+            });
+        }
+
+        // Cache the response
+        self.cache.store(url, &response_body, &metadata)?;
+
+        Ok((response_body, metadata))
+    }
+}
+
+/// Outcome of sending a request with retries, see `Pipeline::send_with_retries`.
+enum Attempts {
+    /// The server confirmed that the cached content is current.
+    NotModified((String, CacheMetadata)),
+    /// The last attempt, which decides whether the download succeeded.
+    Last {
+        attempt: Result<FetchResponse>,
+        number_of_attempts: usize,
+        /// How long the last attempt took.
+        response_time: Duration,
+    },
+}
+
+/// Everything the emission of a single module needs, shared by the worker threads.
+struct EmitContext<'a> {
+    config: &'a Config,
+    previous_state: &'a State,
+    minifier: Option<&'a Minifier>,
+    name_stripper: Option<&'a NameStripper>,
+    output_paths: &'a BTreeMap<String, String>,
+    today: NaiveDate,
+    spill: Option<&'a SpillStore>,
+}
+
+/// Outcome of emitting a single module, logged and added to the index afterwards.
+struct ModuleOutput {
+    /// Canonical name of the module.
+    key: String,
+    placement: IndexPlacement,
+    /// Bytes saved by `--minify-ics`, `None` if it is disabled.
+    minified_bytes: Option<usize>,
+    /// Events whose lecturer names were removed by `--strip-names`.
+    events_with_names: usize,
+    /// The written calendar, or why it could not be written.
+    result: std::result::Result<EmittedModule, String>,
+}
+
+/// Where and how a module is listed in the index.
+struct IndexPlacement {
+    module: String,
+    department: String,
+    semester: String,
+    group_dir: String,
+    group_label: String,
+    /// Texts of the links to the source calendars, shown as tooltip in the index.
+    link_text: Option<String>,
+    /// Labels of the other groups the module is listed in.
+    also_listed_in: Vec<String>,
+    last_changed: NaiveDate,
+}
+
+/// What the emitted modules contribute to the files listing them, in the order of the modules.
+#[derive(Default)]
+struct EmitCollection {
+    manifest_entries: Vec<ManifestEntry>,
+    /// Index entries grouped by semester, department and group directory.
+    index_sections: BTreeMap<IndexSectionKey, IndexSection>,
+    /// Events of every module for the snapshot, keyed by the link in the index.
+    schedules: BTreeMap<String, Vec<ScheduleEvent>>,
+    /// Modules whose calendar changed in this run.
+    changed_modules: Vec<ChangeRecord>,
+    feed_entries: Vec<FeedEntry>,
+    events_with_names: usize,
+    /// Modules whose calendar failed to write.
+    failed_modules: BTreeSet<String>,
+}
+
+/// The index in every language, and the snapshot with its path if one is written.
+type RenderedIndexes = (Vec<(Language, String)>, Option<(PathBuf, String)>);
+
+/// Orders the index sections chronologically by semester, then by department and numerically by
+/// group directory.
+type IndexSectionKey = (
+    label::SemesterSortKey,
+    String,
+    String,
+    (u32, String),
+    String,
+);
+
+/// A module calendar which was written successfully.
+struct EmittedModule {
+    module_state: State,
+    manifest_entry: ManifestEntry,
+    /// The calendars of the single months of a large module.
+    month_entries: Vec<(Month, ManifestEntry)>,
+    /// Keys of the events, to recognize the calendar if it is renamed later.
+    event_keys: Vec<String>,
+    /// Events which changed since the last run.
+    event_changes: Vec<EventChange>,
+    number_of_events: usize,
+    text_export_error: Option<String>,
+    /// The events for the text export and the snapshot, empty if neither is written.
+    schedule: Vec<ScheduleEvent>,
+    /// What the `icalendar` crate reads differently, with `--cross-validate`.
+    discrepancies: Vec<Discrepancy>,
+}
+
+/// Builds and writes the calendar of a single module. Returns `None` if the module has no output
+/// path.
+fn emit_module(
+    context: &EmitContext,
+    key: &str,
+    mut entries: CalendarEntry,
+) -> Option<ModuleOutput> {
+    // Location of the calendar
+    let file_name = context.output_paths.get(key)?.clone();
+
+    let also_listed_in: Vec<String> = entries
+        .other_group_dirs()
+        .into_iter()
+        .map(str::to_owned)
+        .collect();
+    let module = entries.display_name.clone();
+    let group_label = label::group_label(&entries.group_dir);
+
+    let mut calendar = IcalCalendarBuilder::version("2.0")
+        .gregorian()
+        .prodid(calendar::module_product_id(
+            &entries.department,
+            &entries.semester,
+            &group_label,
+            &module,
+        ))
+        .build();
+
+    // Spilled events are read back one module at a time
+    let (events, loaded) = match context.spill.map(|spill| spill.load(key)) {
+        Some(Ok(events)) => (events, Ok(())),
+        Some(Err(err)) => (Vec::new(), Err(err)),
+        None => (std::mem::take(&mut entries.events), Ok(())),
+    };
+    let PreparedEvents {
+        events,
+        mut module_state,
+        minified_bytes,
+        events_with_names,
+    } = prepare_events(context, key, &entries, &module, &group_label, events);
+    calendar.events = events;
+
+    let event_changes =
+        feed::event_changes(&context.previous_state.events, &module_state.events, key);
+
+    // Track when the content of the module last changed
+    let mut event_hashes: Vec<&str> = module_state
+        .events
+        .values()
+        .map(|event| event.content_hash.as_str())
+        .collect();
+    event_hashes.sort_unstable();
+    let module_dates = module_state.assign_module_dates(
+        context.previous_state,
+        key,
+        manifest::sha256_hex(event_hashes.join("\n").as_bytes()),
+        context.today,
+    );
+    let link_text = add_calendar_properties(
+        context,
+        &mut calendar,
+        &entries,
+        &module,
+        &group_label,
+        module_dates.last_changed,
+        &also_listed_in,
+    );
+    let calendar_content = generate_calendar(context.config, &mut calendar);
+
+    // Directories are created with `create_dir_all`, which is fine to race on, and the paths were
+    // made unique before
+    let result = loaded
+        .and_then(|()| paths::check_inside_output(Path::new(&file_name)))
+        .and_then(|()| write_output_file(Path::new(&file_name), &calendar_content))
+        .and_then(|()| write_month_files(context.config, &calendar, &file_name, &module))
+        .map_err(|err| err.to_string())
+        .map(|month_entries| {
+            let (schedule, text_export_error) =
+                export_schedule(context, &calendar, &file_name, &module);
+
+            let mut manifest_entry = ManifestEntry::new(file_name, calendar_content.as_bytes());
+            manifest_entry.source_last_modified = entries.source_last_modified;
+
+            EmittedModule {
+                module_state,
+                manifest_entry,
+                month_entries,
+                event_keys: calendar.events.iter().map(tombstone::event_key).collect(),
+                event_changes,
+                number_of_events: calendar.events.len(),
+                text_export_error,
+                schedule,
+                discrepancies: if context.config.cross_validate {
+                    cross_validate::compare(&calendar.events, &calendar_content)
+                } else {
+                    Vec::new()
+                },
+            }
+        });
+
+    Some(ModuleOutput {
+        key: key.to_owned(),
+        placement: IndexPlacement {
+            module,
+            department: entries.department,
+            semester: entries.semester,
+            group_dir: entries.group_dir,
+            group_label,
+            link_text,
+            also_listed_in: also_listed_in
+                .iter()
+                .map(|group_dir| label::group_label(group_dir))
+                .collect(),
+            last_changed: module_dates.last_changed,
+        },
+        minified_bytes: context.minifier.map(|_| minified_bytes),
+        events_with_names,
+        result,
+    })
+}
+
+/// The events of a module as they are published, see `prepare_events`.
+struct PreparedEvents {
+    events: Vec<IcalEvent>,
+    /// Sequences and content hashes of the events.
+    module_state: State,
+    /// Bytes saved by `--minify-ics`.
+    minified_bytes: usize,
+    /// Events whose lecturer names were removed by `--strip-names`.
+    events_with_names: usize,
+}
+
+/// Turns the aggregated events of the module `key` into the events which are published, all
+/// using the same spelling of the module name.
+fn prepare_events(
+    context: &EmitContext,
+    key: &str,
+    entries: &CalendarEntry,
+    module: &str,
+    group_label: &str,
+    events: Vec<IcalEvent>,
+) -> PreparedEvents {
+    let events = if context.config.merge_adjacent {
+        merge::merge_adjacent(events, context.config.merge_max_gap)
+    } else {
+        events
+    };
+    // The summary is rebuilt from the module name, so the suffix is never applied twice
+    let summary_suffix = context.config.summary_suffix.as_ref().map(|template| {
+        template.render(&PathValues {
+            semester: &label::semester_label(&entries.semester),
+            department: &entries.department,
+            group: group_label,
+            module,
+            module_slug: &names::module_slug(module, context.config.max_slug_length),
+        })
+    });
+    let department_contact = context.config.department_contacts.get(&entries.department);
+
+    let mut prepared = PreparedEvents {
+        events: Vec::with_capacity(events.len()),
+        module_state: State::default(),
+        minified_bytes: 0,
+        events_with_names: 0,
+    };
+    let mut generated_uids = BTreeSet::new();
+    for mut entry in events {
+        let mut summary = if calendar::is_undated(&entry) {
+            format!("{UNDATED_SUMMARY_PREFIX} {module}")
+        } else {
+            with_academic_week(context.config, &entries.semester, &entry, module)
+        };
+        if let Some(summary_suffix) = &summary_suffix {
+            summary = format!("{summary} {summary_suffix}");
+        }
+        if calendar::is_ignored(&entry) {
+            summary = format!("{} {summary}", context.config.ignored_summary_prefix);
+        }
+        props::set_value_preserving_params(&mut entry, PROPERTY_NAME_SUMMARY, summary);
+
+        // Only the published text changes, the module was assigned by its full name already.
+        // The summary lost the name along with the module name it is built from.
+        if context
+            .name_stripper
+            .is_some_and(|name_stripper| name_stripper.strip_event(&mut entry))
+            || entries.name_stripped
+        {
+            prepared.events_with_names += 1;
+        }
+
+        // Tell students whom to ask about errors in the plan of the department
+        if let Some(contact) = department_contact {
+            contact::add_organizer(&mut entry, contact);
+        }
+
+        if let Some(minifier) = context.minifier {
+            prepared.minified_bytes += minifier.minify_event(&mut entry);
+        }
+
+        // Bump the SEQUENCE of events which changed since the last run
+        let uid = calendar::ensure_uid(&mut entry, key, &mut generated_uids);
+        let content_hash = calendar::event_content_hash(&entry);
+        let start = props::get_value(&entry, PROPERTY_NAME_DTSTART).unwrap_or_default();
+        let sequence = prepared.module_state.assign_sequence(
+            context.previous_state,
+            &uid,
+            key,
+            start,
+            content_hash,
+        );
+        calendar::set_sequence(&mut entry, sequence);
+
+        if context.config.compat == Some(Compat::Outlook) {
+            compat::adjust_event(&mut entry);
+        }
+
+        // Keep the output stable regardless of the upstream property order
+        calendar::sort_properties(&mut entry);
+
+        prepared.events.push(entry);
+    }
+
+    prepared
+}
+
+/// Adds the properties describing the calendar of a module. Returns the texts of the links to
+/// the source calendars, if there are any.
+fn add_calendar_properties(
+    context: &EmitContext,
+    calendar: &mut IcalCalendar,
+    entries: &CalendarEntry,
+    module: &str,
+    group_label: &str,
+    last_changed: NaiveDate,
+    also_listed_in: &[String],
+) -> Option<String> {
+    let source_last_modified = entries.source_last_modified.map_or_else(
+        || "unbekannt".to_owned(),
+        |date_time| {
+            datetime::format_in_zone(date_time, context.config.display_timezone, "%d.%m.%Y")
+        },
+    );
+
+    // The link texts on the listing pages tell how fresh the upstream plan is
+    let link_text = (!entries.link_texts.is_empty()).then(|| entries.link_texts.join("; "));
+    if let Some(link_text) = &link_text {
+        calendar.properties.push(Property {
+            name: PROPERTY_NAME_DESCRIPTION.to_owned(),
+            params: None,
+            value: Some(link_text.clone()),
+        });
+    }
+
+    // Describe the calendar using the human-readable group label
+    calendar.properties.push(Property {
+        name: PROPERTY_NAME_CALENDAR_DESCRIPTION.to_owned(),
+        params: None,
+        value: Some(format!(
+            "{module} ({group_label}, {}), zuletzt geändert am {}, Quelle zuletzt geändert: {source_last_modified}",
+            entries.semester,
+            last_changed.format("%d.%m.%Y")
+        )),
+    });
+    calendar.properties.push(Property {
+        name: PROPERTY_NAME_LAST_CHANGED.to_owned(),
+        params: Some(vec![("VALUE".to_owned(), vec!["DATE".to_owned()])]),
+        value: Some(last_changed.format("%Y%m%d").to_string()),
+    });
+    if !also_listed_in.is_empty() {
+        calendar.properties.push(Property {
+            name: PROPERTY_NAME_ALSO_LISTED_IN.to_owned(),
+            params: None,
+            value: Some(also_listed_in.join(",")),
+        });
+    }
+    calendar.properties.push(Property {
+        name: PROPERTY_NAME_GENERATOR.to_owned(),
+        params: None,
+        value: Some(GENERATOR.to_owned()),
+    });
+
+    link_text
+}
+
+/// Returns the events of a written calendar for the text export and the snapshot, empty if
+/// neither is written, and writes the text export. The error is `None` if it was written or
+/// isn't enabled.
+fn export_schedule(
+    context: &EmitContext,
+    calendar: &IcalCalendar,
+    file_name: &str,
+    module: &str,
+) -> (Vec<ScheduleEvent>, Option<String>) {
+    let schedule: Vec<ScheduleEvent> = if context.config.text_export.is_some()
+        || context.config.single_file_output.is_some()
+    {
+        calendar
+            .events
+            .iter()
+            .filter_map(|event| ScheduleEvent::from_ical(event, context.config.display_timezone))
+            .collect()
+    } else {
+        Vec::new()
+    };
+
+    // Plain-text overview next to the calendar, mirroring its layout
+    let text_export_error = context.config.text_export.as_ref().and_then(|text_export| {
+        let text_path = text_export.join(file_name).with_extension("txt");
+
+        write_output_file(&text_path, &text_export::format_schedule(module, &schedule))
+            .err()
+            .map(|err| err.to_string())
+    });
+
+    (schedule, text_export_error)
+}
+
+/// Returns the content of `calendar`, adjusted to the client selected with `--compat`.
+fn generate_calendar(config: &Config, calendar: &mut IcalCalendar) -> String {
+    if config.compat == Some(Compat::Outlook) {
+        compat::ensure_publish_method(calendar);
+        compat::refold(&calendar.generate())
+    } else {
+        calendar.generate()
+    }
+}
+
+/// Writes one calendar per month next to the calendar of a module with more events than
+/// `--split-large-modules` allows, for clients which struggle with large calendars. Returns the
+/// manifest entries of the written files.
+fn write_month_files(
+    config: &Config,
+    calendar: &IcalCalendar,
+    file_name: &str,
+    module: &str,
+) -> Result<Vec<(Month, ManifestEntry)>> {
+    if config
+        .split_large_modules
+        .map_or(true, |max_events| calendar.events.len() <= max_events)
+    {
+        return Ok(Vec::new());
+    }
+
+    let without_events = IcalCalendar {
+        events: Vec::new(),
+        ..calendar.clone()
+    };
+
+    month_split::bucket_by_month(&calendar.events)
+        .into_iter()
+        .map(|(month, events)| {
+            let mut month_calendar = IcalCalendar {
+                events,
+                ..without_events.clone()
+            };
+            month_calendar.properties.push(Property {
+                name: PROPERTY_NAME_CALENDAR_NAME.to_owned(),
+                params: None,
+                value: Some(format!("{module} ({})", month.label())),
+            });
+
+            let path = month_split::month_path(file_name, month);
+            let content = generate_calendar(config, &mut month_calendar);
+            write_output_file(Path::new(&path), &content)?;
+
+            Ok((month, ManifestEntry::new(path, content.as_bytes())))
+        })
+        .collect()
+}
+
+/// Cleans up the summary of a source event into the name of its module.
+fn clean_summary(summary: &str) -> String {
+    summary.replace("- ", "").replace("  ", " ")
+}
+
+/// Adds the lecture week of the event to `summary` if enabled and the start of the semester is
+/// known. All-day events use their date, other events the date of their start.
+fn with_academic_week(config: &Config, semester: &str, event: &IcalEvent, summary: &str) -> String {
+    let week = config
+        .academic_week
+        .zip(config.semester_starts.get(semester))
+        .and_then(|(position, semester_start)| {
+            props::get_value(event, PROPERTY_NAME_DTSTART)
+                .and_then(datetime::parse_date_time)
+                .and_then(|start| datetime::academic_week(start.date(), *semester_start))
+                .map(|week| (position, week))
+        });
+
+    match week {
+        Some((WeekLabelPosition::Prefix, week)) => format!("VW {week}: {summary}"),
+        Some((WeekLabelPosition::Suffix, week)) => format!("{summary} (VW {week})"),
+        None => summary.to_owned(),
+    }
+}
+
+/// Opens the cache folder unless the run has to go without it.
+fn open_cache(config: &Config) -> Cache {
+    // Local files are always read again, a cached copy could only be outdated. Recording must
+    // see every response and a replay must only serve recorded ones, neither goes via the cache.
+    if config.no_cache
+        || config.source_dir.is_some()
+        || config.record.is_some()
+        || config.replay.is_some()
+    {
+        info!("Caching is disabled");
+        Cache::disabled()
+    } else {
+        Cache::open_or_disable(Path::new(CACHE_FOLDER))
+    }
+}
+
+/// Builds the blocking HTTP client used for all downloads.
+pub fn build_client(config: &Config) -> Result<reqwest::blocking::Client> {
+    Ok(client_builder(config)?.build()?)
+}
+
+/// Configures the client of `build_client`.
+fn client_builder(config: &Config) -> Result<reqwest::blocking::ClientBuilder> {
+    let mut builder = reqwest::blocking::Client::builder()
+        .user_agent(user_agent(config)?)
+        .https_only(true)
+        .redirect(redirect_policy(&config.base_urls));
+
+    if config.no_proxy {
+        builder = builder.no_proxy();
+    }
+    // An explicit proxy replaces the ones from the environment
+    if let Some(proxy) = proxy(config)? {
+        builder = builder.proxy(proxy);
+    }
+    if let Some(path) = &config.proxy_ca_cert {
+        builder = builder.add_root_certificate(ca_certificate(path)?);
+    }
+    // Pinned certificates replace the root certificates, including the one of the proxy
+    if !config.pinned_cert_sha256.is_empty() {
+        builder = builder
+            .use_preconfigured_tls(pinning::client_config(config.pinned_cert_sha256.clone())?);
+    }
+
+    Ok(builder)
+}
+
+/// Splits a pinned source into the base URL and the path below it. Paths belong to the first base
+/// URL, absolute URLs to the base URL they start with, or are split at the start of the expected
+/// `/files/` layout.
+fn split_pinned_source(pinned: &str, base_urls: &[String]) -> Option<(String, String)> {
+    if !pinned.contains("://") {
+        return base_urls
+            .first()
+            .map(|base_url| (base_url.clone(), pinned.to_owned()));
+    }
+
+    base_urls
+        .iter()
+        .find_map(|base_url| {
+            scrape::site_path(base_url, pinned).map(|path| (base_url.clone(), path))
+        })
+        .or_else(|| {
+            let start = pinned.find("/files/")?;
+            Some((
+                pinned.get(..=start)?.to_owned(),
+                pinned.get(start..)?.to_owned(),
+            ))
+        })
+}
+
+/// Returns the proxy from the configuration, with the separately given credentials if any.
+fn proxy(config: &Config) -> Result<Option<reqwest::Proxy>> {
+    let Some(proxy_url) = &config.proxy else {
+        return Ok(None);
+    };
+
+    // Check the URL up front, the proxy only complains about some problems when it is used
+    if reqwest::Url::parse(proxy_url).is_err() {
+        return Err(Error::InvalidProxy(proxy_url.clone()));
+    }
+    let mut proxy =
+        reqwest::Proxy::all(proxy_url).map_err(|_| Error::InvalidProxy(proxy_url.clone()))?;
+
+    if let Some(proxy_user) = &config.proxy_user {
+        let (user, password) = proxy_user.split_once(':').ok_or(Error::InvalidProxyUser)?;
+        proxy = proxy.basic_auth(user, password);
+    }
+
+    Ok(Some(proxy))
+}
+
+/// Loads a PEM certificate to trust in addition to the built-in roots.
+fn ca_certificate(path: &Path) -> Result<reqwest::Certificate> {
+    let invalid = |reason: String| Error::InvalidCaCertificate {
+        path: path.to_owned(),
+        reason,
+    };
+
+    let pem = std::fs::read(path).map_err(|err| invalid(err.to_string()))?;
+
+    // reqwest only decodes the certificate when the client is built, without naming the file
+    let mut roots = rustls::RootCertStore::empty();
+    for certificate in CertificateDer::pem_slice_iter(&pem) {
+        let certificate = certificate.map_err(|err| invalid(err.to_string()))?;
+        roots
+            .add(certificate)
+            .map_err(|err| invalid(err.to_string()))?;
+    }
+    if roots.is_empty() {
+        return Err(invalid("no certificate found".to_owned()));
+    }
+
+    reqwest::Certificate::from_pem(&pem).map_err(|err| invalid(err.to_string()))
+}
+
+/// Returns the user agent from the configuration, or the default one including the contact
+/// information if given. Fails if it isn't a valid header value, e.g. due to control characters.
+fn user_agent(config: &Config) -> Result<String> {
+    let user_agent = match (&config.user_agent, &config.contact) {
+        (Some(user_agent), _) => user_agent.clone(),
+        (None, Some(contact)) => format!("{CLIENT_NAME} ({CLIENT_HOMEPAGE}; {contact})"),
+        (None, None) => format!("{CLIENT_NAME} ({CLIENT_HOMEPAGE})"),
+    };
+
+    if reqwest::header::HeaderValue::from_str(&user_agent).is_err() {
+        return Err(Error::InvalidUserAgent(user_agent));
+    }
+
+    Ok(user_agent)
+}
+
+/// Only follows redirects staying on the host of one of the base URLs, so no other host can
+/// publish content under the name of the FH. Switching the scheme on the same host is fine.
+fn redirect_policy(base_urls: &[String]) -> reqwest::redirect::Policy {
+    let allowed_hosts: BTreeSet<String> = base_urls
+        .iter()
+        .filter_map(|base_url| reqwest::Url::parse(base_url).ok())
+        .filter_map(|base_url| base_url.host_str().map(str::to_lowercase))
+        .collect();
+
+    reqwest::redirect::Policy::custom(move |attempt| {
+        if attempt.previous().len() > MAX_REDIRECTS {
+            return attempt.error(format!("more than {MAX_REDIRECTS} redirects"));
+        }
+
+        let is_allowed = attempt
+            .url()
+            .host_str()
+            .is_some_and(|host| allowed_hosts.contains(&host.to_lowercase()));
+        if is_allowed {
+            return attempt.follow();
+        }
+
+        let from = attempt
+            .previous()
+            .last()
+            .map(ToString::to_string)
+            .unwrap_or_default();
+        let to = attempt.url().to_string();
+        warn!("Refusing to follow redirect from '{from}' to '{to}'");
+
+        attempt.error(Error::RedirectOffHost { from, to })
+    })
+}
+
+/// Returns why a link whose components couldn't be extracted is skipped. Crafted links are warned
+/// about, since they could point outside of the output folder.
+fn link_skip_reason(err: &Error) -> SkipReason {
+    if matches!(err, Error::UnsafePathComponent { .. }) {
+        warn!("Skipping link: {err}");
+        return SkipReason::UnsafePath;
+    }
+
+    SkipReason::PatternMismatch
+}
+
+/// Creates the directory of an output file and writes its content.
+fn write_output_file(file_name: &Path, content: &str) -> Result<()> {
+    if let Some(directory_path) = file_name.parent() {
+        std::fs::create_dir_all(directory_path).map_err(|source| Error::OutputWrite {
+            path: directory_path.into(),
+            source,
+        })?;
+    }
+
+    std::fs::write(file_name, content).map_err(|source| Error::OutputWrite {
+        path: file_name.into(),
+        source,
+    })?;
+
+    Ok(())
+}
+
+/// Deletes a file written by an earlier run together with the folders it leaves empty.
+fn remove_output_file(file_name: &Path) -> Result<()> {
+    paths::check_inside_output(file_name)?;
+
+    match std::fs::remove_file(file_name) {
+        Ok(()) => {}
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+        Err(err) => return Err(err.into()),
+    }
+
+    // Removing a folder which isn't empty fails, which ends the walk up
+    for folder in file_name.ancestors().skip(1) {
+        if folder.as_os_str().is_empty() || std::fs::remove_dir(folder).is_err() {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::calendar::{
+        PROPERTY_NAME_TRANSPARENCY, TRANSPARENCY_OPAQUE, TRANSPARENCY_TRANSPARENT,
+    };
+    use crate::testing::{self, FakeClock, FakeFetcher};
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    /// Configuration for the fake site without any delays between requests.
+    fn config() -> Config {
+        Config {
+            min_delay: Duration::ZERO,
+            max_delay: Duration::ZERO,
+            ..testing::config()
+        }
+    }
+
+    fn links(paths: &[&str]) -> Vec<SourceLink> {
+        paths
+            .iter()
+            .map(|path| SourceLink::new(testing::BASE_URL.trim_end_matches('/'), path).unwrap())
+            .collect()
+    }
+
+    fn download(path: &str, content: &str) -> DownloadedSource {
+        DownloadedSource {
+            link: links(&[path]).remove(0),
+            content: content.to_owned(),
+            last_modified: None,
+        }
+    }
+
+    fn aggregate(config: Config, downloads: Vec<DownloadedSource>) -> (Pipeline, Modules) {
+        let clock = FakeClock::new();
+        let mut pipeline = testing::pipeline(config, &FakeFetcher::new(&clock), &clock);
+        let modules = pipeline.aggregate(downloads).unwrap();
+
+        (pipeline, modules)
+    }
+
+    /// Aggregates and emits `downloads` in the working directory, continuing from the state the
+    /// previous run left there.
+    fn emit_run(
+        config: Config,
+        downloads: Vec<DownloadedSource>,
+    ) -> (Pipeline, Vec<ManifestEntry>) {
+        let clock = FakeClock::new();
+        let mut pipeline = Pipeline::with_dependencies(
+            config,
+            Box::new(FakeFetcher::new(&clock)),
+            Cache::disabled(),
+            State::load(Path::new(STATE_FILE)).unwrap(),
+            Box::new(clock.clone()),
+            Box::new(testing::FakeSleeper::new(&clock)),
+        )
+        .unwrap();
+        let modules = pipeline.aggregate(downloads).unwrap();
+        let modules = pipeline.drop_ignored_modules(modules);
+        let manifest_entries = pipeline.emit(modules).unwrap();
+
+        (pipeline, manifest_entries)
+    }
+
+    /// Returns the hash of the calendar of `module` in the manifest.
+    fn module_hash(manifest_entries: &[ManifestEntry], module: &str) -> String {
+        manifest_entries
+            .iter()
+            .find(|entry| entry.path.ends_with(&format!("{module}.ics")))
+            .map(|entry| entry.sha256.clone())
+            .unwrap_or_default()
+    }
+
+    /// Site with a department whose semester page links to the calendar of every path.
+    fn site(clock: &FakeClock, paths: &[&str]) -> FakeFetcher {
+        let calendars: Vec<(&str, String)> = paths
+            .iter()
+            .enumerate()
+            .map(|(index, path)| {
+                let calendar = testing::calendar(&[(&format!("Modul {index}"), "20241014T081500")]);
+                (*path, calendar)
+            })
+            .collect();
+
+        site_at(FakeFetcher::new(clock), testing::BASE_URL, &calendars)
+    }
+
+    /// Adds the pages of a site at `base_url` with a department whose semester page links to
+    /// every `(path, calendar)`.
+    fn site_at(
+        mut fetcher: FakeFetcher,
+        base_url: &str,
+        calendars: &[(&str, String)],
+    ) -> FakeFetcher {
+        let links = calendars
+            .iter()
+            .map(|(path, _)| format!("<a href=\"{path}\">{path}</a>"))
+            .collect::<Vec<_>>()
+            .join("\n");
+        let semester_page = format!("<h1>Wintersemester</h1>\n{links}");
+
+        fetcher = fetcher
+            .page(
+                base_url,
+                r#"<a href="/informatik-elektrotechnik" role="button"> Informatik und Elektrotechnik </a>"#,
+            )
+            .page(
+                &format!("{base_url}informatik-elektrotechnik"),
+                r#"<a href="/informatik-elektrotechnik/wise-2425">Wintersemester</a>"#,
+            )
+            .page(
+                &format!("{base_url}informatik-elektrotechnik/wise-2425"),
+                &semester_page,
+            );
+        // Scraped links are the base URL followed by the absolute path of the link
+        for (path, calendar) in calendars {
+            fetcher = fetcher.page(&format!("{base_url}{path}"), calendar);
+        }
+
+        fetcher
+    }
+
+    #[test]
+    fn links_which_would_leave_the_output_folder_are_skipped() {
+        let safe = "/files/iue/WiSe_2425/semester_1/a.ics";
+        let clock = FakeClock::new();
+        let fetcher = site(
+            &clock,
+            &[
+                safe,
+                "/files/%2e%2e/WiSe_2425/semester_1/b.ics",
+                "/files/..\\..\\etc/WiSe_2425/semester_1/c.ics",
+            ],
+        );
+
+        let mut pipeline = testing::pipeline(config(), &fetcher, &clock);
+
+        let links = pipeline.scrape().unwrap();
+
+        assert_eq!(
+            links
+                .iter()
+                .map(|link| link.source_path.department.as_str())
+                .collect::<Vec<_>>(),
+            ["iue"],
+            "only the safe link should be kept"
+        );
+        assert_eq!(
+            pipeline
+                .diagnostics
+                .count_skipped_links(testing::BASE_URL, SkipReason::UnsafePath),
+            2,
+            "both crafted links should be reported as unsafe"
+        );
+    }
+
+    #[test]
+    fn scraping_follows_the_semester_pages_of_each_department() {
+        let paths = [
+            "/files/iue/WiSe_2425/semester_1/a.ics",
+            "/files/iue/WiSe_2425/semester_3/b.ics",
+        ];
+        let clock = FakeClock::new();
+        let fetcher = site(&clock, &paths);
+        let mut pipeline = testing::pipeline(config(), &fetcher, &clock);
+
+        let links = pipeline.scrape().unwrap();
+
+        assert_eq!(
+            links
+                .iter()
+                .map(|link| link.path.as_str())
+                .collect::<Vec<_>>(),
+            paths,
+            "the calendars linked on the semester page should be found"
+        );
+        assert_eq!(
+            links.last().unwrap().source_path.group_dir,
+            "semester_3",
+            "the components of the path should be extracted"
+        );
+    }
+
+    /// Departments of `departments_site`.
+    const DEPARTMENTS: [&str; 6] = [
+        "agrar",
+        "bau",
+        "iue",
+        "maschinenwesen",
+        "medien",
+        "wirtschaft",
+    ];
+
+    /// A site with the `DEPARTMENTS`, each linking to a calendar on its page.
+    fn departments_site(clock: &FakeClock) -> FakeFetcher {
+        let buttons = DEPARTMENTS
+            .iter()
+            .map(|department| format!(r#"<a href="/{department}" role="button">{department}</a>"#))
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        let mut fetcher = FakeFetcher::new(clock).page(testing::BASE_URL, &buttons);
+        for department in DEPARTMENTS {
+            let path = format!("/files/{department}/WiSe_2425/semester_1/a.ics");
+            fetcher = fetcher.page(
+                &format!("{}{department}", testing::BASE_URL),
+                &format!("<a href=\"{path}\">{path}</a>"),
+            );
+        }
+
+        fetcher
+    }
+
+    /// Lets every request take a moment of real time and counts how many of them overlap.
+    struct OverlapCounter {
+        fetcher: FakeFetcher,
+        in_flight: Arc<AtomicUsize>,
+        most_in_flight: Arc<AtomicUsize>,
+    }
+
+    impl Fetcher for OverlapCounter {
+        fn fetch(&self, url: &str, conditional: Option<&CacheMetadata>) -> Result<FetchResponse> {
+            let in_flight = self.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+            self.most_in_flight.fetch_max(in_flight, Ordering::SeqCst);
+            std::thread::sleep(Duration::from_millis(50));
+            self.in_flight.fetch_sub(1, Ordering::SeqCst);
+
+            self.fetcher.fetch(url, conditional)
+        }
+    }
+
+    #[test]
+    fn departments_are_crawled_a_few_at_a_time() {
+        let clock = FakeClock::new();
+        let most_in_flight = Arc::new(AtomicUsize::new(0));
+        let fetcher = OverlapCounter {
+            fetcher: departments_site(&clock),
+            in_flight: Arc::new(AtomicUsize::new(0)),
+            most_in_flight: Arc::clone(&most_in_flight),
+        };
+        let mut pipeline = Pipeline::with_dependencies(
+            config(),
+            Box::new(fetcher),
+            Cache::disabled(),
+            State::default(),
+            Box::new(clock.clone()),
+            Box::new(testing::FakeSleeper::new(&clock)),
+        )
+        .unwrap();
+
+        let links = pipeline.scrape().unwrap();
+
+        let most_in_flight = most_in_flight.load(Ordering::SeqCst);
+        assert!(
+            (2..=MAX_CONCURRENT_DEPARTMENTS).contains(&most_in_flight),
+            "a few department pages should be requested at once, not {most_in_flight}"
+        );
+        assert_eq!(
+            links
+                .iter()
+                .map(|link| link.source_path.department.as_str())
+                .collect::<Vec<_>>(),
+            DEPARTMENTS,
+            "the links should be in the order of the departments"
+        );
+    }
+
+    #[test]
+    fn crawling_departments_stops_once_the_deadline_expires() {
+        let clock = FakeClock::new();
+        let mut fetcher = departments_site(&clock);
+        for department in DEPARTMENTS {
+            fetcher = fetcher.latency(&format!("/{department}"), Duration::from_secs(60));
+        }
+        let config = Config {
+            max_runtime: Some(Duration::from_secs(30)),
+            ..config()
+        };
+        let mut pipeline = testing::pipeline(config, &fetcher, &clock);
+
+        pipeline.scrape().unwrap();
+
+        let department_pages: Vec<String> = DEPARTMENTS
+            .iter()
+            .map(|department| format!("{}{department}", testing::BASE_URL))
+            .collect();
+        let requested: Vec<String> = fetcher
+            .requests()
+            .into_iter()
+            .filter(|url| department_pages.contains(url))
+            .collect();
+        assert!(
+            !requested.is_empty() && requested.len() <= MAX_CONCURRENT_DEPARTMENTS,
+            "only the departments started before the deadline should be requested, not {requested:?}"
+        );
+        for page in &department_pages {
+            assert!(
+                requested.contains(page) != pipeline.diagnostics.skipped_urls.contains(page),
+                "'{page}' should either be requested or reported as skipped"
+            );
+        }
+    }
+
+    #[test]
+    fn revisions_are_dated_by_the_clock_of_the_pipeline() {
+        let paths = [
+            "/files/iue/WiSe_2425/semester_1/Gruppe_1_Stand_20_12.ics",
+            "/files/iue/WiSe_2425/semester_1/Gruppe_1_Stand_10_03.ics",
+        ];
+        let clock = FakeClock::at("2025-03-05T12:00:00Z".parse().unwrap());
+        let fetcher = site(&clock, &paths);
+        let mut pipeline = testing::pipeline(config(), &fetcher, &clock);
+
+        let links = pipeline.scrape().unwrap();
+
+        // On the 5th of March, a plan revised on the 10th of March must be from the year before
+        assert_eq!(
+            links
+                .iter()
+                .map(|link| link.path.as_str())
+                .collect::<Vec<_>>(),
+            [paths[0]],
+            "the revision from December should be the newest"
+        );
+        assert_eq!(
+            pipeline
+                .diagnostics
+                .count_skipped_links(testing::BASE_URL, SkipReason::Superseded),
+            1,
+            "the other revision should be reported as superseded"
+        );
+    }
+
+    #[test]
+    fn modules_of_several_base_urls_are_merged() {
+        let mirror = "https://mirror.fh-kalender.test/";
+        let path = "/files/iue/WiSe_2425/semester_1/a.ics";
+        let clock = FakeClock::new();
+        let fetcher = site_at(
+            site(&clock, &[path]),
+            mirror,
+            &[
+                (
+                    path,
+                    testing::calendar(&[
+                        ("Modul 0", "20241014T081500"),
+                        ("Physik", "20241015T100000"),
+                    ]),
+                ),
+                (
+                    "/files/iue/WiSe_2425/semester_2/b.ics",
+                    testing::calendar(&[("Modul 0", "20241021T081500")]),
+                ),
+            ],
+        );
+        let config = Config {
+            base_urls: vec![testing::BASE_URL.to_owned(), mirror.to_owned()],
+            ..config()
+        };
+        let mut pipeline = testing::pipeline(config, &fetcher, &clock);
+
+        let links = pipeline.scrape().unwrap();
+        let downloads = pipeline.download(links);
+        let modules = pipeline.aggregate(downloads).unwrap();
+
+        let module = |name: &str| {
+            modules
+                .values()
+                .find(|module| module.display_name == name)
+                .unwrap()
+        };
+        assert_eq!(
+            modules.len(),
+            2,
+            "the modules of both bases should be merged"
+        );
+        assert_eq!(
+            module("Modul 0").events.len(),
+            2,
+            "the event found on both bases should only be kept once"
+        );
+        assert_eq!(
+            module("Modul 0").base_url,
+            testing::BASE_URL,
+            "the data of the first base should be kept"
+        );
+        assert_eq!(
+            pipeline.diagnostics.conflicting_modules.len(),
+            1,
+            "the module in a different semester on the mirror should be reported"
+        );
+        assert_eq!(
+            pipeline
+                .diagnostics
+                .base_stats
+                .iter()
+                .map(|(base_url, stats)| (base_url.as_str(), stats.calendars))
+                .collect::<Vec<_>>(),
+            [(testing::BASE_URL, 1), (mirror, 2)],
+            "the calendars should be counted per base"
+        );
+    }
+
+    /// Sitemap of the fake site listing the calendar files at `paths`.
+    fn sitemap(paths: &[&str]) -> String {
+        let urls = paths
+            .iter()
+            .map(|path| {
+                format!(
+                    "<url><loc>{}{}</loc></url>",
+                    testing::BASE_URL,
+                    path.trim_start_matches('/')
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        format!(r#"<?xml version="1.0" encoding="UTF-8"?><urlset>{urls}</urlset>"#)
+    }
+
+    /// Paths of the links scraped from `fetcher`.
+    fn scraped_paths(fetcher: &FakeFetcher, clock: &FakeClock) -> Vec<String> {
+        let mut pipeline = testing::pipeline(config(), fetcher, clock);
+
+        pipeline
+            .scrape()
+            .unwrap()
+            .into_iter()
+            .map(|link| link.path)
+            .collect()
+    }
+
+    #[test]
+    fn sitemap_is_not_used_when_the_pages_link_enough_calendars() {
+        let paths: Vec<String> = (0..MIN_EXPECTED_CALENDARS)
+            .map(|index| format!("/files/iue/WiSe_2425/semester_1/{index}.ics"))
+            .collect();
+        let paths: Vec<&str> = paths.iter().map(String::as_str).collect();
+        let clock = FakeClock::new();
+        let fetcher = site(&clock, &paths).page(
+            SITEMAP_FILE,
+            &sitemap(&["/files/iue/WiSe_2425/semester_2/extra.ics"]),
+        );
+
+        let scraped = scraped_paths(&fetcher, &clock);
+
+        assert_eq!(scraped, paths, "only the linked calendars should be found");
+        assert!(
+            !fetcher
+                .requests()
+                .iter()
+                .any(|url| url.ends_with(SITEMAP_FILE)),
+            "the sitemap should not be requested"
+        );
+    }
+
+    #[test]
+    fn sitemap_supplies_the_calendars_when_the_pages_link_none() {
+        let path = "/files/iue/WiSe_2425/semester_1/a.ics";
+        let clock = FakeClock::new();
+        let fetcher = site(&clock, &[]).page(SITEMAP_FILE, &sitemap(&[path]));
+
+        let scraped = scraped_paths(&fetcher, &clock);
+
+        assert_eq!(scraped, [path], "the sitemap should supply the calendar");
+    }
+
+    #[test]
+    fn malformed_sitemap_keeps_the_links_found_on_the_pages() {
+        let path = "/files/iue/WiSe_2425/semester_1/a.ics";
+        let clock = FakeClock::new();
+        let fetcher = site(&clock, &[path]).page(
+            SITEMAP_FILE,
+            "<urlset><url><loc>https://fh-kalender.test/files/b.ics</url></urlset>",
+        );
+
+        let scraped = scraped_paths(&fetcher, &clock);
+
+        assert_eq!(
+            scraped,
+            [path],
+            "the broken sitemap should be ignored instead of failing the run"
+        );
+    }
+
+    #[test]
+    fn paths_disallowed_by_robots_txt_are_skipped() {
+        let paths = [
+            "/files/iue/WiSe_2425/semester_1/a.ics",
+            "/files/iue/WiSe_2425/semester_3/b.ics",
+        ];
+        let clock = FakeClock::new();
+        let fetcher = site(&clock, &paths).page(
+            "robots.txt",
+            "User-agent: *\nDisallow: /files/iue/WiSe_2425/semester_3/\n",
+        );
+        let config = Config {
+            ignore_robots: false,
+            ..config()
+        };
+        let mut pipeline = testing::pipeline(config, &fetcher, &clock);
+
+        let links = pipeline.scrape().unwrap();
+
+        assert_eq!(
+            links
+                .iter()
+                .map(|link| link.path.as_str())
+                .collect::<Vec<_>>(),
+            ["/files/iue/WiSe_2425/semester_1/a.ics"],
+            "the disallowed calendar should be skipped"
+        );
+        assert_eq!(
+            pipeline
+                .diagnostics
+                .run_stats
+                .urls_skipped
+                .get(&SkipReason::RobotsDisallowed)
+                .copied()
+                .unwrap_or_default(),
+            1,
+            "the skipped calendar should be reported"
+        );
+    }
+
+    #[test]
+    fn links_with_a_query_or_fragment_are_scraped() {
+        let paths = [
+            "/files/iue/WiSe_2425/semester_1/Plan.ics?v=3",
+            "/files/iue/WiSe_2425/semester_2/Plan.ICS#section",
+        ];
+
+        for (strip_ics_query, expected) in [
+            (
+                false,
+                [
+                    "/files/iue/WiSe_2425/semester_1/Plan.ics?v=3",
+                    "/files/iue/WiSe_2425/semester_2/Plan.ICS",
+                ],
+            ),
+            (
+                true,
+                [
+                    "/files/iue/WiSe_2425/semester_1/Plan.ics",
+                    "/files/iue/WiSe_2425/semester_2/Plan.ICS",
+                ],
+            ),
+        ] {
+            let clock = FakeClock::new();
+            let config = Config {
+                strip_ics_query,
+                ..config()
+            };
+            let mut pipeline = testing::pipeline(config, &site(&clock, &paths), &clock);
+
+            let links = pipeline.scrape().unwrap();
+
+            assert_eq!(
+                links
+                    .iter()
+                    .map(|link| link.path.as_str())
+                    .collect::<Vec<_>>(),
+                expected,
+                "the fragment should always be removed, the query only with --strip-ics-query"
+            );
+            assert_eq!(
+                links
+                    .iter()
+                    .map(|link| link.source_path.group_dir.as_str())
+                    .collect::<Vec<_>>(),
+                ["semester_1", "semester_2"],
+                "the components should be extracted without the query"
+            );
+        }
+    }
+
+    #[test]
+    fn calendars_with_a_query_are_cached_per_version() {
+        let path = "/files/iue/WiSe_2425/semester_1/Plan.ics";
+        let clock = FakeClock::new();
+        let fetcher = FakeFetcher::new(&clock)
+            .page(
+                &format!("{path}?v=3"),
+                &testing::calendar(&[("Mathe", "20241014T081500")]),
+            )
+            .page(
+                &format!("{path}?v=4"),
+                &testing::calendar(&[("Mathe", "20241021T081500")]),
+            );
+
+        testing::in_working_dir("query_versions", |_| {
+            let download = |query: &str| {
+                let config = Config {
+                    no_cache: false,
+                    ics_cache_max_age: Some(Duration::from_secs(3600)),
+                    ..config()
+                };
+                let cache = open_cache(&config);
+                let mut pipeline = Pipeline::with_dependencies(
+                    config,
+                    Box::new(fetcher.clone()),
+                    cache,
+                    State::default(),
+                    Box::new(clock.clone()),
+                    Box::new(testing::FakeSleeper::new(&clock)),
+                )
+                .unwrap();
+                let link = SourceLink::new(
+                    testing::BASE_URL.trim_end_matches('/'),
+                    &format!("{path}{query}"),
+                )
+                .unwrap();
+
+                pipeline.download(vec![link]).pop().unwrap().content
+            };
+
+            let third = download("?v=3");
+            let third_again = download("?v=3");
+            let fourth = download("?v=4");
+
+            assert_eq!(
+                fetcher.requests(),
+                [
+                    format!("{}{path}?v=3", testing::BASE_URL.trim_end_matches('/')),
+                    format!("{}{path}?v=4", testing::BASE_URL.trim_end_matches('/')),
+                ],
+                "each version should be downloaded once and then served from the cache"
+            );
+            assert_eq!(third, third_again, "the cached version should be served");
+            assert_ne!(third, fourth, "the new version shouldn't be the cached one");
+        });
+    }
+
+    #[test]
+    fn missing_robots_txt_allows_everything() {
+        let paths = ["/files/iue/WiSe_2425/semester_1/a.ics"];
+        let clock = FakeClock::new();
+        let fetcher = site(&clock, &paths);
+        let config = Config {
+            ignore_robots: false,
+            ..config()
+        };
+        let mut pipeline = testing::pipeline(config, &fetcher, &clock);
+
+        let links = pipeline.scrape().unwrap();
+
+        assert!(
+            fetcher
+                .requests()
+                .iter()
+                .any(|url| url.ends_with("/robots.txt")),
+            "robots.txt should be requested"
+        );
+        assert_eq!(links.len(), 1, "every calendar should be allowed");
+    }
+
+    #[test]
+    fn failed_requests_are_retried_until_they_succeed() {
+        let path = "/files/iue/WiSe_2425/semester_1/a.ics";
+        let content = testing::calendar(&[("Mathe", "20241014T081500")]);
+        let clock = FakeClock::new();
+        let fetcher = FakeFetcher::new(&clock)
+            .respond(path, testing::response(path, 503, ""))
+            .respond(path, testing::response(path, 200, ""))
+            .page(path, &content);
+        let mut pipeline = testing::pipeline(config(), &fetcher, &clock);
+
+        let downloads = pipeline.download(links(&[path]));
+
+        assert_eq!(
+            fetcher.requests().len(),
+            3,
+            "the file should be requested three times"
+        );
+        assert_eq!(
+            downloads.first().unwrap().content,
+            content,
+            "the last response should be used"
+        );
+    }
+
+    /// Fetcher talking plain HTTP to `server`, following the redirects allowed for its host.
+    const MAINTENANCE_PAGE: &str =
+        "<html><body><h1>Wartungsarbeiten</h1><p>Bitte versuchen Sie es später.</p></body></html>";
+
+    #[test]
+    fn maintenance_pages_are_retried_until_the_real_content_is_served() {
+        let path = "/files/iue/WiSe_2425/semester_1/a.ics";
+        // Events may well be called like a marker
+        let content = testing::calendar(&[("Wartungsarbeiten", "20241014T081500")]);
+        let clock = FakeClock::new();
+        let fetcher = FakeFetcher::new(&clock)
+            .page("informatik-elektrotechnik/wise-2425", MAINTENANCE_PAGE)
+            .page(&format!("{}{path}", testing::BASE_URL), MAINTENANCE_PAGE);
+        let fetcher = site_at(fetcher, testing::BASE_URL, &[(path, content.clone())]);
+        let config = || Config {
+            no_cache: false,
+            ..config()
+        };
+
+        testing::in_working_dir("maintenance_recovery", |_| {
+            let mut pipeline = Pipeline::with_dependencies(
+                config(),
+                Box::new(fetcher.clone()),
+                open_cache(&config()),
+                State::default(),
+                Box::new(clock.clone()),
+                Box::new(testing::FakeSleeper::new(&clock)),
+            )
+            .unwrap();
+
+            let links = pipeline.scrape().unwrap();
+            let downloads = pipeline.download(links);
+
+            assert_eq!(
+                downloads
+                    .iter()
+                    .map(|download| download.content.as_str())
+                    .collect::<Vec<_>>(),
+                [content.as_str()],
+                "the calendar should be found and downloaded after the maintenance"
+            );
+            let url = format!("{}{path}", testing::BASE_URL);
+            assert_eq!(
+                fetcher
+                    .requests()
+                    .iter()
+                    .filter(|request| **request == url)
+                    .count(),
+                2,
+                "the calendar should be requested again after the maintenance page"
+            );
+            assert_eq!(
+                open_cache(&config()).load(&url, None).unwrap(),
+                Some(content.clone()),
+                "the real content should be cached instead of the maintenance page"
+            );
+        });
+    }
+
+    #[test]
+    fn maintenance_pages_are_never_cached() {
+        let path = "/files/iue/WiSe_2425/semester_1/a.ics";
+        let clock = FakeClock::new();
+        let fetcher = FakeFetcher::new(&clock).page(path, MAINTENANCE_PAGE);
+        let config = || Config {
+            no_cache: false,
+            retry_budget: 1,
+            ..config()
+        };
+
+        testing::in_working_dir("maintenance_not_cached", |_| {
+            let mut pipeline = Pipeline::with_dependencies(
+                config(),
+                Box::new(fetcher.clone()),
+                open_cache(&config()),
+                State::default(),
+                Box::new(clock.clone()),
+                Box::new(testing::FakeSleeper::new(&clock)),
+            )
+            .unwrap();
+
+            let downloads = pipeline.download(links(&[path]));
+
+            assert!(downloads.is_empty(), "the error page shouldn't be used");
+            assert_eq!(
+                fetcher.requests().len(),
+                2,
+                "the error page should be retried with the budget"
+            );
+            let url = format!("{}{path}", testing::BASE_URL.trim_end_matches('/'));
+            assert_eq!(
+                open_cache(&config()).load(&url, None).unwrap(),
+                None,
+                "the error page shouldn't be cached"
+            );
+        });
+    }
+
+    fn mock_server_fetcher(server: &testing::MockServer) -> HttpFetcher {
+        let client = reqwest::blocking::Client::builder()
+            .redirect(redirect_policy(std::slice::from_ref(&server.base_url)))
+            .build()
+            .unwrap();
+
+        HttpFetcher::new(client)
+    }
+
+    /// The `User-Agent` the client of `config` sends to the mock server.
+    fn sent_user_agent(config: &Config) -> Option<String> {
+        let server = testing::MockServer::start(|_| testing::MockResponse::ok(""));
+        // The mock server only speaks plain HTTP
+        let client = client_builder(config)
+            .unwrap()
+            .https_only(false)
+            .build()
+            .unwrap();
+
+        HttpFetcher::new(client)
+            .fetch(&server.base_url, None)
+            .unwrap();
+
+        server
+            .request_headers("User-Agent")
+            .into_iter()
+            .flatten()
+            .next()
+    }
+
+    #[test]
+    fn client_sends_the_default_user_agent_with_the_contact() {
+        assert_eq!(
+            sent_user_agent(&config()),
+            Some(format!("{CLIENT_NAME} ({CLIENT_HOMEPAGE})")),
+            "the default user agent should name the client and its homepage"
+        );
+
+        let config = Config {
+            contact: Some("+mailto:me@example.com".to_owned()),
+            ..config()
+        };
+        assert_eq!(
+            sent_user_agent(&config),
+            Some(format!(
+                "{CLIENT_NAME} ({CLIENT_HOMEPAGE}; +mailto:me@example.com)"
+            )),
+            "the contact should be appended"
+        );
+    }
+
+    #[test]
+    fn client_sends_the_overridden_user_agent() {
+        let config = Config {
+            user_agent: Some("Stundenplan-Spiegel/1.0".to_owned()),
+            contact: Some("+mailto:me@example.com".to_owned()),
+            ..config()
+        };
+
+        assert_eq!(
+            sent_user_agent(&config).as_deref(),
+            Some("Stundenplan-Spiegel/1.0"),
+            "the override should replace the whole user agent"
+        );
+    }
+
+    /// Fetches a page through the mock server acting as the proxy of `config`. Returns the
+    /// requested target and the `Proxy-Authorization` header the proxy received.
+    fn proxied_request(
+        server: &testing::MockServer,
+        config: &Config,
+    ) -> (Vec<String>, Vec<Option<String>>) {
+        // The mock server only speaks plain HTTP
+        let client = client_builder(config)
+            .unwrap()
+            .https_only(false)
+            .build()
+            .unwrap();
+
+        HttpFetcher::new(client)
+            .fetch("http://fh-kalender.test/informatik-elektrotechnik", None)
+            .unwrap();
+
+        (
+            server.requests(),
+            server.request_headers("Proxy-Authorization"),
+        )
+    }
+
+    #[test]
+    fn requests_go_through_the_proxy_with_the_separate_credentials() {
+        let server = testing::MockServer::start(|_| testing::MockResponse::ok(""));
+        let config = Config {
+            proxy: Some(server.base_url.clone()),
+            proxy_user: Some("student:geheim".to_owned()),
+            no_proxy: true,
+            ..config()
+        };
+
+        assert_eq!(
+            proxied_request(&server, &config),
+            (
+                vec!["http://fh-kalender.test/informatik-elektrotechnik".to_owned()],
+                vec![Some("Basic c3R1ZGVudDpnZWhlaW0=".to_owned())]
+            ),
+            "the proxy should be asked for the page with the credentials"
+        );
+    }
+
+    #[test]
+    fn credentials_embedded_in_the_proxy_url_are_sent() {
+        let server = testing::MockServer::start(|_| testing::MockResponse::ok(""));
+        let config = Config {
+            proxy: Some(server.base_url.replace("http://", "http://student:geheim@")),
+            ..config()
+        };
+
+        assert_eq!(
+            proxied_request(&server, &config).1,
+            [Some("Basic c3R1ZGVudDpnZWhlaW0=".to_owned())],
+            "the credentials from the URL should be sent to the proxy"
+        );
+    }
+
+    #[test]
+    fn servers_signed_by_the_proxy_ca_certificate_are_trusted() {
+        let folder = testing::temp_dir("proxy_ca_certificate");
+        let certificate = folder.join("proxy.pem");
+        std::fs::write(&certificate, testing::CA_CERTIFICATE).unwrap();
+        let fetch = |proxy_ca_cert: Option<PathBuf>| {
+            let config = Config {
+                proxy_ca_cert,
+                no_proxy: true,
+                ..config()
+            };
+
+            HttpFetcher::new(build_client(&config).unwrap())
+                .fetch(&testing::start_tls_server(), None)
+                .map(|response| response.body)
+        };
+
+        assert!(
+            fetch(None).is_err(),
+            "the certificate of the test authority shouldn't be trusted by default"
+        );
+        assert_eq!(
+            fetch(Some(certificate)).unwrap(),
+            "BEGIN:VCALENDAR",
+            "the certificate should be trusted once it is added"
+        );
+    }
+
+    #[test]
+    fn misconfigured_proxies_fail_when_building_the_client() {
+        let folder = testing::temp_dir("misconfigured_proxies");
+        let invalid_certificate = folder.join("invalid.pem");
+        std::fs::write(
+            &invalid_certificate,
+            "-----BEGIN CERTIFICATE-----\nkein Zertifikat\n-----END CERTIFICATE-----\n",
+        )
+        .unwrap();
+        let proxy = || Some("http://proxy.fh-kiel.test:3128".to_owned());
+
+        assert!(
+            matches!(
+                build_client(&Config {
+                    proxy: Some("proxy.fh-kiel.test 3128".to_owned()),
+                    ..config()
+                }),
+                Err(Error::InvalidProxy(_))
+            ),
+            "a malformed proxy URL should be rejected"
+        );
+        assert!(
+            matches!(
+                build_client(&Config {
+                    proxy: proxy(),
+                    proxy_user: Some("student".to_owned()),
+                    ..config()
+                }),
+                Err(Error::InvalidProxyUser)
+            ),
+            "credentials without a password should be rejected"
+        );
+        for path in [folder.join("missing.pem"), invalid_certificate] {
+            assert!(
+                matches!(
+                    build_client(&Config {
+                        proxy: proxy(),
+                        proxy_ca_cert: Some(path.clone()),
+                        ..config()
+                    }),
+                    Err(Error::InvalidCaCertificate { path: failed, .. }) if failed == path
+                ),
+                "'{}' should be rejected as CA certificate",
+                path.display()
+            );
+        }
+    }
+
+    #[test]
+    fn user_agents_with_control_characters_are_rejected() {
+        let config = Config {
+            contact: Some("me@example.com\r\nX-Injected: 1".to_owned()),
+            ..config()
+        };
+
+        assert!(
+            matches!(build_client(&config), Err(Error::InvalidUserAgent(_))),
+            "the client shouldn't be built with an illegal header value"
+        );
+    }
+
+    #[test]
+    fn redirects_on_the_same_host_are_followed() {
+        let server = testing::MockServer::start(|path| match path {
+            "/old.ics" => testing::MockResponse::redirect("/new.ics"),
+            _ => testing::MockResponse::ok("BEGIN:VCALENDAR"),
+        });
+
+        let response = mock_server_fetcher(&server)
+            .fetch(&format!("{}old.ics", server.base_url), None)
+            .unwrap();
+
+        assert_eq!(
+            response.body, "BEGIN:VCALENDAR",
+            "the redirect should be followed"
+        );
+        assert_eq!(
+            server.requests(),
+            ["/old.ics", "/new.ics"],
+            "both paths should be requested"
+        );
+    }
+
+    #[test]
+    fn redirects_to_other_hosts_are_rejected() {
+        let server = testing::MockServer::start(|path| match path {
+            "/old.ics" => testing::MockResponse::redirect("http://other.fh-kalender.test/new.ics"),
+            _ => testing::MockResponse::ok("BEGIN:VCALENDAR"),
+        });
+        let url = format!("{}old.ics", server.base_url);
+
+        let result = mock_server_fetcher(&server).fetch(&url, None);
+
+        assert!(
+            matches!(
+                result,
+                Err(Error::RedirectOffHost { from, to })
+                    if from == url && to == "http://other.fh-kalender.test/new.ics"
+            ),
+            "the redirect to another host should be rejected"
+        );
+    }
+
+    #[test]
+    fn redirect_loops_are_cut_off() {
+        let server = testing::MockServer::start(|path| {
+            let hop: usize = path.trim_start_matches("/hop/").parse().unwrap_or_default();
+            testing::MockResponse::redirect(&format!("/hop/{}", hop + 1))
+        });
+
+        let result = mock_server_fetcher(&server).fetch(&format!("{}hop/0", server.base_url), None);
+
+        assert!(
+            matches!(result, Err(Error::Reqwest(err)) if err.is_redirect()),
+            "the redirects should stop at the limit"
+        );
+        assert_eq!(
+            server.requests().len(),
+            MAX_REDIRECTS + 1,
+            "only the allowed number of redirects should be followed"
+        );
+    }
+
+    #[test]
+    fn pinned_sources_are_downloaded_even_if_not_linked() {
+        let linked = "/files/iue/WiSe_2425/semester_1/a.ics";
+        let unlinked = "/files/iue/WiSe_2425/semester_2/b.ics";
+        let gone = "/files/iue/WiSe_2425/semester_3/c.ics";
+        let clock = FakeClock::new();
+        let fetcher = site(&clock, &[linked]).page(
+            &format!("{}{unlinked}", testing::BASE_URL),
+            &testing::calendar(&[("Physik", "20241015T100000")]),
+        );
+        let config = Config {
+            pinned_sources: vec![
+                linked.to_owned(),
+                unlinked.to_owned(),
+                format!("{}{gone}", testing::BASE_URL.trim_end_matches('/')),
+            ],
+            ..config()
+        };
+        let mut pipeline = testing::pipeline(config, &fetcher, &clock);
+
+        let links = pipeline.scrape().unwrap();
+        let urls: Vec<&str> = links.iter().map(|link| link.url.as_str()).collect();
+        assert_eq!(
+            urls.iter().filter(|url| url.ends_with(linked)).count(),
+            1,
+            "a pinned source which is also linked should be downloaded once"
+        );
+        assert_eq!(links.len(), 3, "every pinned source should be added");
+
+        let downloads = pipeline.download(links);
+        let modules = pipeline.aggregate(downloads).unwrap();
+
+        assert!(
+            modules
+                .values()
+                .any(|calendar_entry| calendar_entry.display_name == "Physik"),
+            "the module of the unlinked pinned source should be generated"
+        );
+        assert_eq!(
+            pipeline
+                .diagnostics
+                .count_skipped_links(testing::BASE_URL, SkipReason::PinnedUnavailable),
+            1,
+            "the missing pinned source should be reported"
+        );
+        assert!(
+            matches!(pipeline.exit_code(), Ok(ExitCode::Success)),
+            "a missing pinned source shouldn't fail the run"
+        );
+    }
+
+    #[test]
+    fn excluded_sources_are_not_downloaded() {
+        let broken = "/files/iue/WiSe_2425/semester_1/broken.ics";
+        let correct = "/files/iue/WiSe_2425/semester_2/a.ics";
+        let clock = FakeClock::new();
+        let fetcher = FakeFetcher::new(&clock)
+            .page(broken, &testing::calendar(&[("Mathe", "20241014T081500")]))
+            .page(correct, &testing::calendar(&[("Mathe", "20241021T081500")]));
+        let config = Config {
+            excluded_sources: vec![r"/broken\.ics$".to_owned()],
+            ..config()
+        };
+        let mut pipeline = testing::pipeline(config, &fetcher, &clock);
+
+        let downloads = pipeline.download(links(&[broken, correct]));
+        let modules = pipeline.aggregate(downloads).unwrap();
+
+        let starts: Vec<&str> = modules
+            .values()
+            .flat_map(|calendar_entry| &calendar_entry.events)
+            .filter_map(|event| props::get_value(event, calendar::PROPERTY_NAME_DTSTART))
+            .collect();
+        assert_eq!(
+            starts,
+            ["20241021T081500"],
+            "only the events of the other source should be kept"
+        );
+        assert!(
+            !fetcher.requests().iter().any(|url| url.ends_with(broken)),
+            "the excluded source shouldn't be requested"
+        );
+        assert_eq!(
+            pipeline
+                .diagnostics
+                .run_stats
+                .urls_skipped
+                .get(&SkipReason::Excluded)
+                .copied(),
+            Some(1),
+            "the excluded source should be counted"
+        );
+    }
+
+    #[test]
+    fn invalid_source_exclusions_fail_at_startup() {
+        let clock = FakeClock::new();
+        let config = Config {
+            excluded_sources: vec!["(broken".to_owned()],
+            ..config()
+        };
+
+        let result = Pipeline::with_dependencies(
+            config,
+            Box::new(FakeFetcher::new(&clock)),
+            Cache::disabled(),
+            State::default(),
+            Box::new(clock.clone()),
+            Box::new(testing::FakeSleeper::new(&clock)),
+        );
+
+        assert!(
+            matches!(result, Err(Error::InvalidSourceExclusion(pattern, _)) if pattern == "(broken"),
+            "the invalid pattern should be named"
+        );
+    }
+
+    #[test]
+    fn an_unreachable_site_is_retried_and_reported_as_unreachable() {
+        // Nothing listens on the port once the listener is dropped
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .and_then(|listener| listener.local_addr())
+            .unwrap()
+            .port();
+        let base_url = format!("http://127.0.0.1:{port}/");
+        let clock = FakeClock::new();
+        let config = Config {
+            base_urls: vec![base_url],
+            retry_budget: 2,
+            ..config()
+        };
+        let client = reqwest::blocking::Client::builder()
+            .redirect(redirect_policy(&config.base_urls))
+            .build()
+            .unwrap();
+        let mut pipeline = Pipeline::with_dependencies(
+            config,
+            Box::new(HttpFetcher::new(client)),
+            Cache::disabled(),
+            State::default(),
+            Box::new(clock.clone()),
+            Box::new(testing::FakeSleeper::new(&clock)),
+        )
+        .unwrap();
+
+        let result = pipeline.scrape();
+
+        assert!(
+            matches!(
+                &result,
+                Err(Error::Unreachable { host, source })
+                    if host == "127.0.0.1" && source.is_transient()
+            ),
+            "a refused connection should be reported as unreachable, got {result:?}"
+        );
+        assert_eq!(
+            result.map_or_else(|err| ExitCode::for_error(&err), |_| ExitCode::Success),
+            ExitCode::Unreachable,
+            "an unreachable site should have its own exit code"
+        );
+        assert_eq!(
+            pipeline.retry_budget.load(Ordering::Relaxed),
+            0,
+            "the main page should be retried until the budget is used up"
+        );
+    }
+
+    /// Mock server with a department linking to two calendars with three modules.
+    fn mock_site() -> testing::MockServer {
+        testing::MockServer::start(|path| match path.trim_start_matches('/') {
+            "" => testing::MockResponse::ok(
+                r#"<a href="/informatik-elektrotechnik" role="button"> Informatik </a>"#,
+            ),
+            "informatik-elektrotechnik" => testing::MockResponse::ok(
+                r#"<a href="/informatik-elektrotechnik/wise-2425">Wintersemester</a>"#,
+            ),
+            "informatik-elektrotechnik/wise-2425" => testing::MockResponse::ok(
+                r#"<h1>Wintersemester</h1>
+                <a href="/files/iue/WiSe_2425/semester_1/a.ics">a.ics</a>
+                <a href="/files/iue/WiSe_2425/semester_2/b.ics">b.ics</a>"#,
+            ),
+            "files/iue/WiSe_2425/semester_1/a.ics" => {
+                testing::MockResponse::ok(&testing::calendar(&[
+                    ("Mathe", "20241014T081500"),
+                    ("Physik", "20241015T100000"),
+                ]))
+            }
+            "files/iue/WiSe_2425/semester_2/b.ics" => {
+                testing::MockResponse::ok(&testing::calendar(&[("Chemie", "20241016T100000")]))
+            }
+            _ => testing::MockResponse {
+                status_code: 404,
+                headers: Vec::new(),
+                body: Vec::new(),
+            },
+        })
+    }
+
+    #[test]
+    fn replaying_a_recording_writes_the_same_calendars() {
+        let server = mock_site();
+        let fixtures = testing::temp_dir("replaying_a_recording_fixtures");
+        let run = |name: &str, config: Config, fetcher: Box<dyn Fetcher>| {
+            testing::in_working_dir(name, |output| {
+                let clock = FakeClock::new();
+                let cache = open_cache(&config);
+                let mut pipeline = Pipeline::with_dependencies(
+                    config,
+                    fetcher,
+                    cache,
+                    State::default(),
+                    Box::new(clock.clone()),
+                    Box::new(testing::FakeSleeper::new(&clock)),
+                )
+                .unwrap();
+                let links = pipeline.scrape().unwrap();
+                let downloads = pipeline.download(links);
+                let modules = pipeline.aggregate(downloads).unwrap();
+                let manifest_entries = pipeline.emit(modules).unwrap();
+
+                assert!(
+                    !output.join(CACHE_FOLDER).exists(),
+                    "'{name}' shouldn't use the cache"
+                );
+                manifest_entries
+                    .into_iter()
+                    .map(|entry| {
+                        let content = std::fs::read_to_string(output.join(&entry.path)).unwrap();
+                        (entry.path, content)
+                    })
+                    .collect::<Vec<_>>()
+            })
+        };
+
+        let recorded = run(
+            "replaying_a_recording_record",
+            Config {
+                base_urls: vec![server.base_url.clone()],
+                record: Some(fixtures.clone()),
+                ..config()
+            },
+            Box::new(
+                RecordingFetcher::new(Box::new(mock_server_fetcher(&server)), &fixtures).unwrap(),
+            ),
+        );
+        let requests = server.requests().len();
+        let replayed = run(
+            "replaying_a_recording_replay",
+            Config {
+                base_urls: vec![server.base_url.clone()],
+                replay: Some(fixtures.clone()),
+                ..config()
+            },
+            Box::new(ReplayFetcher::new(&fixtures)),
+        );
+
+        assert_eq!(
+            recorded.len(),
+            3,
+            "a calendar should be recorded per module"
+        );
+        assert_eq!(
+            recorded, replayed,
+            "the replay should write the same calendars as the recording"
+        );
+        assert_eq!(
+            server.requests().len(),
+            requests,
+            "the replay shouldn't touch the network"
+        );
+    }
+
+    /// Mock server with a department linking to every `(path, calendar)` without a link text,
+    /// sent with a Last-Modified header.
+    fn calendars_site(calendars: Vec<(&'static str, String)>) -> testing::MockServer {
+        testing::MockServer::start(move |path| {
+            let path = path.trim_start_matches('/');
+            let calendar = calendars.iter().find(|(file, _)| *file == path);
+            match (path, calendar) {
+                ("", _) => testing::MockResponse::ok(
+                    r#"<a href="/informatik-elektrotechnik" role="button"> Informatik </a>"#,
+                ),
+                ("informatik-elektrotechnik", _) => testing::MockResponse::ok(
+                    r#"<a href="/informatik-elektrotechnik/wise-2425">Wintersemester</a>"#,
+                ),
+                ("informatik-elektrotechnik/wise-2425", _) => testing::MockResponse::ok(
+                    &calendars
+                        .iter()
+                        .map(|(file, _)| format!("<a href=\"/{file}\"></a>"))
+                        .collect::<Vec<_>>()
+                        .join("\n"),
+                ),
+                (_, Some((_, calendar))) => testing::MockResponse {
+                    status_code: 200,
+                    headers: vec![(
+                        "Last-Modified".to_owned(),
+                        "Wed, 12 Mar 2025 10:00:00 GMT".to_owned(),
+                    )],
+                    body: calendar.clone().into_bytes(),
+                },
+                _ => testing::MockResponse {
+                    status_code: 404,
+                    headers: Vec::new(),
+                    body: Vec::new(),
+                },
+            }
+        })
+    }
+
+    #[test]
+    fn source_dir_writes_the_same_calendars_as_the_site() {
+        let calendars = vec![
+            (
+                "files/iue/WiSe_2425/semester_1/a.ics",
+                testing::calendar(&[("Mathe", "20241014T081500"), ("Physik", "20241015T100000")]),
+            ),
+            (
+                "files/iue/WiSe_2425/semester_2/b.ics",
+                testing::calendar(&[("Chemie", "20241016T100000")]),
+            ),
+            // Left out in both cases
+            (
+                "files/iue/dozenten/mueller.ics",
+                testing::calendar(&[("Sprechstunde", "20241017T100000")]),
+            ),
+        ];
+        // The folder has no link texts and the modification time stands in for Last-Modified
+        let modified = std::time::UNIX_EPOCH + Duration::from_secs(1_741_773_600);
+        let source_dir = testing::temp_dir("source_dir_fixtures");
+        for (path, calendar) in &calendars {
+            let path = source_dir.join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(&path, calendar).unwrap();
+            std::fs::File::options()
+                .write(true)
+                .open(&path)
+                .and_then(|file| file.set_modified(modified))
+                .unwrap();
+        }
+        let server = calendars_site(calendars);
+        let run = |name: &str, config: Config, fetcher: Box<dyn Fetcher>| {
+            testing::in_working_dir(name, |output| {
+                let clock = FakeClock::new();
+                let mut pipeline = Pipeline::with_dependencies(
+                    config,
+                    fetcher,
+                    Cache::disabled(),
+                    State::default(),
+                    Box::new(clock.clone()),
+                    Box::new(testing::FakeSleeper::new(&clock)),
+                )
+                .unwrap();
+                let links = pipeline.scrape().unwrap();
+                let downloads = pipeline.download(links);
+                let modules = pipeline.aggregate(downloads).unwrap();
+                let manifest_entries = pipeline.emit(modules).unwrap();
+
+                manifest_entries
+                    .into_iter()
+                    .map(|entry| {
+                        let content = std::fs::read_to_string(output.join(&entry.path)).unwrap();
+                        (entry.path, content)
+                    })
+                    .collect::<Vec<_>>()
+            })
+        };
+
+        let scraped = run(
+            "source_dir_site",
+            Config {
+                base_urls: vec![server.base_url.clone()],
+                ..config()
+            },
+            Box::new(mock_server_fetcher(&server)),
+        );
+        let requests = server.requests().len();
+        let base_urls = vec![server.base_url.clone()];
+        let read = run(
+            "source_dir_folder",
+            Config {
+                base_urls: base_urls.clone(),
+                source_dir: Some(source_dir.clone()),
+                ..config()
+            },
+            Box::new(DirectoryFetcher::new(&source_dir, &base_urls)),
+        );
+
+        assert_eq!(scraped.len(), 3, "a calendar should be written per module");
+        assert_eq!(
+            scraped, read,
+            "the folder should give the same calendars as the site"
+        );
+        assert_eq!(
+            server.requests().len(),
+            requests,
+            "the folder shouldn't be read over the network"
+        );
+    }
+
+    #[test]
+    fn runs_without_a_writable_cache_write_the_same_calendars() {
+        let server = mock_site();
+        let run = |name: &str, no_cache: bool, block_cache: bool| {
+            testing::in_working_dir(name, |output| {
+                // A file in place of the cache folder makes it impossible to create
+                if block_cache {
+                    std::fs::write(output.join(CACHE_FOLDER), "").unwrap();
+                }
+                let config = Config {
+                    base_urls: vec![server.base_url.clone()],
+                    no_cache,
+                    ..config()
+                };
+                let clock = FakeClock::new();
+                let cache = open_cache(&config);
+                let mut pipeline = Pipeline::with_dependencies(
+                    config,
+                    Box::new(mock_server_fetcher(&server)),
+                    cache,
+                    State::default(),
+                    Box::new(clock.clone()),
+                    Box::new(testing::FakeSleeper::new(&clock)),
+                )
+                .unwrap();
+                let links = pipeline.scrape().unwrap();
+                let downloads = pipeline.download(links);
+                let modules = pipeline.aggregate(downloads).unwrap();
+                let manifest_entries = pipeline.emit(modules).unwrap();
+
+                assert_eq!(
+                    output.join(CACHE_FOLDER).is_dir(),
+                    !no_cache && !block_cache,
+                    "'{name}' should only use the cache if it can"
+                );
+                manifest_entries
+                    .into_iter()
+                    .map(|entry| {
+                        let content = std::fs::read_to_string(output.join(&entry.path)).unwrap();
+                        (entry.path, content)
+                    })
+                    .collect::<Vec<_>>()
+            })
+        };
+
+        let cached = run("runs_without_a_writable_cache_cached", false, false);
+        let unwritable = run("runs_without_a_writable_cache_unwritable", false, true);
+        let uncached = run("runs_without_a_writable_cache_no_cache", true, false);
+
+        assert_eq!(cached.len(), 3, "a calendar should be written per module");
+        assert_eq!(
+            unwritable, cached,
+            "an unwritable cache should fall back to running without it"
+        );
+        assert_eq!(
+            uncached, cached,
+            "--no-cache should write the same calendars"
+        );
+    }
+
+    #[test]
+    fn runs_with_the_parse_cache_write_the_same_calendars() {
+        let downloads = || {
+            vec![
+                download(
+                    "/files/iue/WiSe_2425/semester_1/a.ics",
+                    &testing::calendar(&[
+                        ("Mathe", "20241014T081500"),
+                        ("Physik", "20241015T100000"),
+                    ]),
+                ),
+                download(
+                    "/files/iue/WiSe_2425/semester_2/b.ics",
+                    &testing::calendar(&[("Chemie", "20241016T081500")]),
+                ),
+            ]
+        };
+        let written = |output: &Path, parse_cache: bool| {
+            let config = Config {
+                no_cache: false,
+                parse_cache,
+                ..config()
+            };
+            let (_, manifest_entries) = emit_run(config, downloads());
+
+            manifest_entries
+                .into_iter()
+                .map(|entry| {
+                    let content = std::fs::read_to_string(output.join(&entry.path)).unwrap();
+                    (entry.path, content)
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let uncached =
+            testing::in_working_dir("parse_cache_uncached", |output| written(output, false));
+        let (cold, warm) = testing::in_working_dir("parse_cache_cached", |output| {
+            let cold = written(output, true);
+            let parsed_files = std::fs::read_dir(Path::new(CACHE_FOLDER).join(PARSE_CACHE_FOLDER))
+                .unwrap()
+                .count();
+            assert_eq!(parsed_files, 2, "every downloaded file should be cached");
+
+            (cold, written(output, true))
+        });
+
+        assert_eq!(uncached.len(), 3, "a calendar should be written per module");
+        assert_eq!(
+            cold, uncached,
+            "filling the parse cache should write the same calendars"
+        );
+        assert_eq!(
+            warm, uncached,
+            "using the parse cache should write the same calendars"
+        );
+    }
+
+    #[test]
+    fn summary_suffix_is_appended_once_after_matching_the_plain_names() {
+        let downloads = || {
+            vec![download(
+                "/files/iue/WiSe_2425/semester_1/a.ics",
+                &testing::calendar(&[
+                    ("Mathematik 1", "20241014T081500"),
+                    ("Feiertag", "20241015T100000"),
+                    ("Physik", "20241016T081500"),
+                ]),
+            )]
+        };
+        let config = || Config {
+            no_cache: false,
+            parse_cache: true,
+            summary_suffix: Some(
+                crate::template::SummaryTemplate::parse("({department}, {group})").unwrap(),
+            ),
+            ignored_event_names: vec![(crate::ignore::MatchMode::Exact, "Feiertag".to_owned())],
+            ignored_modules: vec![(crate::ignore::MatchMode::Exact, "Physik".to_owned())],
+            ..config()
+        };
+
+        testing::in_working_dir("summary_suffix", |_| {
+            for run in ["first", "second"] {
+                let (_, manifest_entries) = emit_run(config(), downloads());
+
+                let paths = manifest_entries
+                    .iter()
+                    .map(|entry| entry.path.as_str())
+                    .collect::<Vec<_>>();
+                assert_eq!(
+                    paths,
+                    ["files/WiSe_2425/iue/semester_1/Mathematik_1.ics"],
+                    "the ignore lists should match the names without the suffix in the {run} run"
+                );
+                let summaries = written_events(&manifest_entries, "Mathematik_1")
+                    .iter()
+                    .map(|event| props::get_value(event, PROPERTY_NAME_SUMMARY).map(str::to_owned))
+                    .collect::<Vec<_>>();
+                assert_eq!(
+                    summaries,
+                    [Some("Mathematik 1 (iue, 1. Semester)".to_owned())],
+                    "the suffix should be appended exactly once in the {run} run"
+                );
+            }
+        });
+    }
+
+    /// Mock server with a module from two calendars with a Last-Modified header and a module
+    /// from one without.
+    fn last_modified_site() -> testing::MockServer {
+        let modified = |body: String, last_modified: &str| testing::MockResponse {
+            status_code: 200,
+            headers: vec![("Last-Modified".to_owned(), last_modified.to_owned())],
+            body: body.into_bytes(),
+        };
+        testing::MockServer::start(move |path| match path.trim_start_matches('/') {
+            "" => testing::MockResponse::ok(
+                r#"<a href="/informatik-elektrotechnik" role="button"> Informatik </a>"#,
+            ),
+            "informatik-elektrotechnik" => testing::MockResponse::ok(
+                r#"<a href="/informatik-elektrotechnik/wise-2425">Wintersemester</a>"#,
+            ),
+            "informatik-elektrotechnik/wise-2425" => testing::MockResponse::ok(
+                r#"<h1>Wintersemester</h1>
+                <a href="/files/iue/WiSe_2425/semester_1/a.ics">a.ics</a>
+                <a href="/files/iue/WiSe_2425/semester_1/b.ics">b.ics</a>
+                <a href="/files/iue/WiSe_2425/semester_1/c.ics">c.ics</a>"#,
+            ),
+            "files/iue/WiSe_2425/semester_1/a.ics" => modified(
+                testing::calendar(&[("Mathe", "20241014T081500")]),
+                "Wed, 12 Mar 2025 10:00:00 GMT",
+            ),
+            "files/iue/WiSe_2425/semester_1/b.ics" => modified(
+                testing::calendar(&[("Mathe", "20241021T081500")]),
+                "Mon, 10 Mar 2025 10:00:00 GMT",
+            ),
+            "files/iue/WiSe_2425/semester_1/c.ics" => {
+                testing::MockResponse::ok(&testing::calendar(&[("Physik", "20241015T100000")]))
+            }
+            _ => testing::MockResponse {
+                status_code: 404,
+                headers: Vec::new(),
+                body: Vec::new(),
+            },
+        })
+    }
+
+    /// The parts of `manifest.json` the tests look at.
+    #[derive(serde::Deserialize)]
+    struct JsonManifest {
+        build_info: JsonBuildInfo,
+        files: Vec<JsonManifestFile>,
+    }
+
+    #[derive(serde::Deserialize)]
+    struct JsonBuildInfo {
+        generator: String,
+        version: String,
+        git_sha: String,
+        run_timestamp: DateTime<Utc>,
+        config_hash: String,
+    }
+
+    #[derive(serde::Deserialize)]
+    struct JsonManifestFile {
+        path: String,
+        source_last_modified: Option<DateTime<Utc>>,
+    }
+
+    #[test]
+    fn modules_listed_in_several_groups_are_placed_where_most_events_are() {
+        let downloads = vec![
+            download(
+                "/files/iue/WiSe_2425/semester_1/a.ics",
+                &testing::calendar(&[("Mathe", "20241014T081500")]),
+            ),
+            download(
+                "/files/iue/WiSe_2425/semester_3/b.ics",
+                &testing::calendar(&[
+                    ("Mathe", "20241015T081500"),
+                    ("Mathe", "20241016T081500"),
+                    ("Mathe", "20241017T081500"),
+                ]),
+            ),
+        ];
+
+        testing::in_working_dir("modules_in_several_groups", |output| {
+            let (_, manifest_entries) = emit_run(config(), downloads);
+
+            let entry = manifest_entries
+                .iter()
+                .find(|entry| entry.path.ends_with("Mathe.ics"))
+                .unwrap();
+            assert!(
+                entry.path.contains("/semester_3/"),
+                "the module should be placed in the group with 3 of its 4 events: {}",
+                entry.path
+            );
+
+            let content = std::fs::read_to_string(&entry.path).unwrap();
+            let calendar = ical::IcalParser::new(content.as_bytes())
+                .next()
+                .unwrap()
+                .unwrap();
+            let also_listed_in = calendar
+                .properties
+                .into_iter()
+                .find(|property| property.name == PROPERTY_NAME_ALSO_LISTED_IN)
+                .and_then(|property| property.value);
+            assert_eq!(
+                also_listed_in.as_deref(),
+                Some("semester_1"),
+                "the calendar should name the other group"
+            );
+            assert_eq!(
+                calendar.events.len(),
+                4,
+                "the events of both groups should be in the calendar"
+            );
+
+            let index = std::fs::read_to_string(output.join(INDEX_FILE)).unwrap();
+            assert!(
+                index.contains("(3. Semester, auch in: 1. Semester,"),
+                "the index should annotate the other group:\n{index}"
+            );
+        });
+    }
+
+    #[test]
+    fn calendars_are_placed_by_semester_department_and_group() {
+        let path = "/files/iue/WiSe_2425/semester_1/a.ics";
+        let calendar = testing::calendar(&[("Mathematik 1", "20241014T081500")]);
+
+        testing::in_working_dir("canonical_layout", |_| {
+            let (_, manifest_entries) = emit_run(config(), vec![download(path, &calendar)]);
+
+            let paths: Vec<&str> = manifest_entries
+                .iter()
+                .map(|entry| entry.path.as_str())
+                .collect();
+            assert_eq!(
+                paths,
+                ["files/WiSe_2425/iue/semester_1/Mathematik_1.ics"],
+                "the calendar should be in the folder of its semester, department and group"
+            );
+
+            let content = std::fs::read_to_string(paths.first().unwrap()).unwrap();
+            let product_id = ical::IcalParser::new(content.as_bytes())
+                .next()
+                .unwrap()
+                .unwrap()
+                .properties
+                .into_iter()
+                .find(|property| property.name == "PRODID")
+                .and_then(|property| property.value);
+            assert_eq!(
+                product_id.as_deref(),
+                Some("-//Veranstaltungsplan der FH Kiel//iue//WiSe_2425//1. Semester//Mathematik 1//"),
+                "the PRODID should name department and semester in the order of the source path"
+            );
+        });
+    }
+
+    #[test]
+    fn index_is_grouped_by_semester_and_group_in_order() {
+        let downloads = [
+            ("/files/iue/SoSe_25/semester_2/a.ics", "Analysis"),
+            ("/files/iue/SoSe_25/master/b.ics", "Robotik"),
+            ("/files/iue/WiSe_2425/semester_10/c.ics", "Chemie"),
+            ("/files/iue/WiSe_2425/semester_2/d.ics", "Datenbanken"),
+            ("/files/iue/WiSe_2425/semester_2/e.ics", "Elektronik"),
+            ("/files/iue/WiSe_2425/semester_1/f.ics", "Fertigung"),
+        ]
+        .into_iter()
+        .map(|(path, module)| download(path, &testing::calendar(&[(module, "20241014T081500")])))
+        .collect();
+
+        testing::in_working_dir("index_sections", |output| {
+            emit_run(config(), downloads);
+
+            let index = std::fs::read_to_string(output.join(INDEX_FILE)).unwrap();
+            let sections: Vec<&str> = index
+                .lines()
+                .filter(|line| line.starts_with("<details") || line.starts_with("<summary>"))
+                .collect();
+            assert_eq!(
+                sections,
+                [
+                    "<details open>",
+                    "<summary>Wintersemester 2024/25 – iue – 1. Semester (1 Module)</summary>",
+                    "<details open>",
+                    "<summary>Wintersemester 2024/25 – iue – 2. Semester (2 Module)</summary>",
+                    "<details open>",
+                    "<summary>Wintersemester 2024/25 – iue – 10. Semester (1 Module)</summary>",
+                    "<details open>",
+                    "<summary>Sommersemester 2025 – iue – 2. Semester (1 Module)</summary>",
+                    "<details open>",
+                    "<summary>Sommersemester 2025 – iue – Master (1 Module)</summary>",
+                ],
+                "the sections should be ordered chronologically and by group number"
+            );
+
+            let section = |heading: &str| {
+                index
+                    .split("<details open>")
+                    .find(|section| section.contains(heading))
+                    .unwrap_or_default()
+                    .to_owned()
+            };
+            let second_semester = section("Wintersemester 2024/25 – iue – 2. Semester");
+            assert!(
+                second_semester.contains(">Datenbanken</a>")
+                    && second_semester.contains(">Elektronik</a>")
+                    && !second_semester.contains(">Fertigung</a>"),
+                "the section should list exactly the modules of its group:\n{second_semester}"
+            );
+        });
+    }
+
+    #[test]
+    fn link_texts_become_the_description_and_the_index_tooltip() {
+        let clock = FakeClock::new();
+        let fetcher = FakeFetcher::new(&clock)
+            .page(
+                testing::BASE_URL,
+                r#"<a href="/informatik-elektrotechnik" role="button"> Informatik </a>"#,
+            )
+            .page(
+                "informatik-elektrotechnik",
+                r#"<a href="/informatik-elektrotechnik/wise-2425">Wintersemester</a>"#,
+            )
+            .page(
+                "informatik-elektrotechnik/wise-2425",
+                r#"<h1>Wintersemester</h1>
+            <a href="/files/iue/WiSe_2425/semester_1/a.ics"><b>1. Semester</b>
+                Elektrotechnik Gruppe&nbsp;1 &ndash; Stand 12.03.</a>
+            <a href="/files/iue/WiSe_2425/semester_1/b.ics"> </a>"#,
+            );
+        let fetcher = [
+            ("a.ics", ("Mathe", "20241014T081500")),
+            ("b.ics", ("Physik", "20241015T100000")),
+        ]
+        .into_iter()
+        .fold(fetcher, |fetcher, (file, event)| {
+            fetcher.page(
+                &format!(
+                    "{}/files/iue/WiSe_2425/semester_1/{file}",
+                    testing::BASE_URL
+                ),
+                &testing::calendar(&[event]),
+            )
+        });
+
+        testing::in_working_dir("link_texts", |output| {
+            testing::pipeline(config(), &fetcher, &clock).run().unwrap();
+
+            let manifest: JsonManifest = serde_json::from_str(
+                &std::fs::read_to_string(output.join(MANIFEST_JSON_FILE)).unwrap(),
+            )
+            .unwrap();
+            let description = |module: &str| {
+                let file = manifest
+                    .files
+                    .iter()
+                    .find(|file| file.path.ends_with(&format!("{module}.ics")))
+                    .unwrap();
+                let content = std::fs::read_to_string(output.join(&file.path)).unwrap();
+                ical::IcalParser::new(content.as_bytes())
+                    .next()
+                    .unwrap()
+                    .unwrap()
+                    .properties
+                    .into_iter()
+                    .find(|property| property.name == PROPERTY_NAME_DESCRIPTION)
+                    .and_then(|property| property.value)
+            };
+            let link_text = "1. Semester Elektrotechnik Gruppe 1 – Stand 12.03.";
+
+            assert_eq!(
+                description("Mathe").as_deref(),
+                Some(link_text),
+                "the link text should be the description of the calendar"
+            );
+            assert_eq!(
+                description("Physik"),
+                None,
+                "an empty link text shouldn't become a description"
+            );
+            let index = std::fs::read_to_string(output.join(INDEX_FILE)).unwrap();
+            assert!(
+                index.contains(&format!(" title=\"{link_text}\">Mathe</a>")),
+                "the link text should be the tooltip in the index:\n{index}"
+            );
+            assert!(
+                index.contains(">Physik</a>") && !index.contains(" title=\"\">Physik</a>"),
+                "a module without link text shouldn't get a tooltip:\n{index}"
+            );
+        });
+    }
+
+    #[test]
+    fn generator_is_embedded_in_every_output() {
+        let path = "/files/iue/WiSe_2425/semester_1/a.ics";
+        let calendar = testing::calendar(&[("Mathe", "20241014T081500")]);
+        let config = Config {
+            arguments: vec!["--strip-names".to_owned()],
+            ..config()
+        };
+
+        testing::in_working_dir("generator_is_embedded", |output| {
+            let started = Utc::now();
+            let (_, manifest_entries) = emit_run(config, vec![download(path, &calendar)]);
+
+            let entry = manifest_entries
+                .iter()
+                .find(|entry| entry.path.ends_with("Mathe.ics"))
+                .unwrap();
+            let content = std::fs::read_to_string(&entry.path).unwrap();
+            let generator = ical::IcalParser::new(content.as_bytes())
+                .next()
+                .unwrap()
+                .unwrap()
+                .properties
+                .into_iter()
+                .find(|property| property.name == PROPERTY_NAME_GENERATOR)
+                .and_then(|property| property.value);
+            assert_eq!(
+                generator.as_deref(),
+                Some(GENERATOR),
+                "the calendar should name its generator"
+            );
+
+            let index = std::fs::read_to_string(output.join(INDEX_FILE)).unwrap();
+            assert!(
+                index.contains(&format!(
+                    "<meta name=\"generator\" content=\"{GENERATOR}\">"
+                )),
+                "the index should name its generator in a meta tag:\n{index}"
+            );
+            assert!(
+                index
+                    .split_once("<footer>")
+                    .is_some_and(|(_, footer)| footer.contains(GENERATOR)),
+                "the index footer should name the generator:\n{index}"
+            );
+
+            let manifest: JsonManifest = serde_json::from_str(
+                &std::fs::read_to_string(output.join(MANIFEST_JSON_FILE)).unwrap(),
+            )
+            .unwrap();
+            let build_info = manifest.build_info;
+            assert_eq!(
+                build_info.generator, GENERATOR,
+                "the manifest should name the generator"
+            );
+            assert_eq!(
+                build_info.version,
+                env!("CARGO_PKG_VERSION"),
+                "the manifest should contain the version"
+            );
+            assert!(
+                GENERATOR.ends_with(&format!("(git {})", build_info.git_sha)),
+                "the manifest should contain the git hash of the generator: {}",
+                build_info.git_sha
+            );
+            assert!(
+                (started..=Utc::now()).contains(&build_info.run_timestamp),
+                "the run timestamp should be the time of the run: {}",
+                build_info.run_timestamp
+            );
+            assert_eq!(
+                build_info.config_hash,
+                manifest::sha256_hex(b"--strip-names"),
+                "the config hash should be the hash of the arguments"
+            );
+        });
+    }
+
+    #[test]
+    fn last_modified_of_the_sources_is_shown_in_the_manifest_and_the_description() {
+        let server = last_modified_site();
+        let config = Config {
+            base_urls: vec![server.base_url.clone()],
+            ..config()
+        };
+
+        testing::in_working_dir("last_modified_of_the_sources", |output| {
+            let clock = FakeClock::new();
+            Pipeline::with_dependencies(
+                config,
+                Box::new(mock_server_fetcher(&server)),
+                Cache::disabled(),
+                State::default(),
+                Box::new(clock.clone()),
+                Box::new(testing::FakeSleeper::new(&clock)),
+            )
+            .unwrap()
+            .run()
+            .unwrap();
+
+            let manifest: JsonManifest = serde_json::from_str(
+                &std::fs::read_to_string(output.join(MANIFEST_JSON_FILE)).unwrap(),
+            )
+            .unwrap();
+            let file = |module: &str| {
+                manifest
+                    .files
+                    .iter()
+                    .find(|file| file.path.ends_with(&format!("{module}.ics")))
+                    .unwrap()
+            };
+            let description = |module: &str| {
+                let content = std::fs::read_to_string(output.join(&file(module).path)).unwrap();
+                let calendar = ical::IcalParser::new(content.as_bytes())
+                    .next()
+                    .unwrap()
+                    .unwrap();
+                calendar
+                    .properties
+                    .into_iter()
+                    .find(|property| property.name == PROPERTY_NAME_CALENDAR_DESCRIPTION)
+                    .and_then(|property| property.value)
+                    .unwrap()
+            };
+
+            assert_eq!(
+                file("Mathe").source_last_modified,
+                "2025-03-12T10:00:00Z".parse().ok(),
+                "the latest Last-Modified of the sources should be in the manifest"
+            );
+            assert!(
+                description("Mathe").ends_with("Quelle zuletzt geändert: 12.03.2025"),
+                "the description should show the latest Last-Modified: {}",
+                description("Mathe")
+            );
+            assert_eq!(
+                file("Physik").source_last_modified,
+                None,
+                "a source without Last-Modified should leave it out"
+            );
+            assert!(
+                description("Physik").ends_with("Quelle zuletzt geändert: unbekannt"),
+                "the description should show that the date is unknown: {}",
+                description("Physik")
+            );
+        });
+    }
+
+    #[test]
+    fn failed_downloads_are_counted_once_the_retry_budget_is_used_up() {
+        let paths = [
+            "/files/iue/WiSe_2425/semester_1/a.ics",
+            "/files/iue/WiSe_2425/semester_1/b.ics",
+            "/files/iue/WiSe_2425/semester_1/c.ics",
+        ];
+        let clock = FakeClock::new();
+        let fetcher = paths
+            .iter()
+            .fold(FakeFetcher::new(&clock), |fetcher, path| {
+                fetcher.respond(path, testing::response(path, 503, ""))
+            });
+        let config = Config {
+            retry_budget: 4,
+            ..config()
+        };
+        let mut pipeline = testing::pipeline(config, &fetcher, &clock);
+
+        let downloads = pipeline.download(links(&paths));
+
+        assert!(downloads.is_empty(), "every download should fail");
+        assert_eq!(
+            fetcher.requests().len(),
+            paths.len() + 4,
+            "only the first file should be retried until the budget is used up"
+        );
+        assert_eq!(
+            pipeline.diagnostics.run_stats.downloads.failed, 3,
+            "every failed download should be counted"
+        );
+        assert_eq!(
+            pipeline.diagnostics.failed_urls.len(),
+            3,
+            "every failed download should be recorded"
+        );
+        assert!(
+            matches!(pipeline.exit_code(), Ok(ExitCode::Partial)),
+            "the output should be marked as partial"
+        );
+    }
+
+    #[test]
+    fn the_delay_is_applied_after_failed_requests_as_well() {
+        let missing = "/files/iue/WiSe_2425/semester_1/a.ics";
+        let broken = "/files/iue/WiSe_2425/semester_1/b.ics";
+        let fine = "/files/iue/WiSe_2425/semester_1/c.ics";
+        let clock = FakeClock::new();
+        let fetcher = FakeFetcher::new(&clock)
+            .respond(broken, testing::response(broken, 503, ""))
+            .page(fine, &testing::calendar(&[("Mathe", "20241014T081500")]));
+        let config = Config {
+            min_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(60),
+            initial_burst: 0,
+            retry_budget: 0,
+            ..config()
+        };
+        let mut pipeline = testing::pipeline(config, &fetcher, &clock);
+        let start = clock.now();
+
+        let downloads = pipeline.download(links(&[missing, broken, fine]));
+
+        assert_eq!(
+            downloads.len(),
+            1,
+            "only the last file should be downloaded"
+        );
+        let url = |path: &str| format!("{}{path}", testing::BASE_URL.trim_end_matches('/'));
+        assert_eq!(
+            pipeline
+                .diagnostics
+                .fetch_records()
+                .iter()
+                .map(|record| (record.url.clone(), record.status, record.delay_ms))
+                .collect::<Vec<_>>(),
+            [
+                (url(missing), Some(404), Some(1_000)),
+                (url(broken), Some(503), Some(1_000)),
+                (url(fine), Some(200), Some(1_000)),
+            ],
+            "the delay after every request should be recorded"
+        );
+        assert_eq!(
+            clock.now() - start,
+            Duration::from_secs(3),
+            "the run should have waited after every request"
+        );
+    }
+
+    #[test]
+    fn the_first_requests_only_wait_the_burst_delay() {
+        let paths =
+            ["a", "b", "c", "d"].map(|file| format!("/files/iue/WiSe_2425/semester_1/{file}.ics"));
+        let clock = FakeClock::new();
+        let fetcher = paths
+            .iter()
+            .fold(FakeFetcher::new(&clock), |fetcher, path| {
+                fetcher.page(path, &testing::calendar(&[("Mathe", "20241014T081500")]))
+            });
+        let config = Config {
+            min_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(60),
+            initial_burst: 2,
+            burst_delay: Duration::from_millis(500),
+            ..config()
+        };
+        let mut pipeline = testing::pipeline(config, &fetcher, &clock);
+        let start = clock.now();
+
+        pipeline.download(links(&paths.each_ref().map(String::as_str)));
+
+        assert_eq!(
+            pipeline
+                .diagnostics
+                .fetch_records()
+                .iter()
+                .map(|record| record.delay_ms)
+                .collect::<Vec<_>>(),
+            [Some(500), Some(500), Some(1_000), Some(1_000)],
+            "only the first two requests should wait the burst delay"
+        );
+        assert_eq!(
+            clock.now() - start,
+            Duration::from_secs(3),
+            "the run should have waited the burst delays and then the normal ones"
+        );
+    }
+
+    #[test]
+    fn parallel_emission_writes_the_same_files_as_sequential_emission() {
+        let downloads = || {
+            (0..3)
+                .map(|semester| {
+                    let events: Vec<String> = (0..100)
+                        .map(|module| {
+                            testing::event(
+                                &format!("Modul {semester}{module:02}"),
+                                &format!("202410{:02}T081500", module % 28 + 1),
+                            )
+                        })
+                        .collect();
+                    download(
+                        &format!("/files/iue/WiSe_2425/semester_{semester}/a.ics"),
+                        &testing::calendar_of(&events),
+                    )
+                })
+                .collect::<Vec<_>>()
+        };
+        let emit_with_threads = |threads: usize| {
+            testing::in_working_dir(&format!("emission_with_{threads}_threads"), |output| {
+                let pool = rayon::ThreadPoolBuilder::new()
+                    .num_threads(threads)
+                    .build()
+                    .unwrap();
+                let manifest_entries = pool.install(|| emit_run(config(), downloads()).1);
+                manifest_entries
+                    .into_iter()
+                    .map(|entry| {
+                        let content = std::fs::read_to_string(output.join(&entry.path)).unwrap();
+                        (entry.path, content)
+                    })
+                    .collect::<Vec<_>>()
+            })
+        };
+
+        let sequential = emit_with_threads(1);
+        let parallel = emit_with_threads(8);
+
+        assert_eq!(
+            sequential.len(),
+            300,
+            "a calendar should be written per module"
+        );
+        assert!(
+            sequential == parallel,
+            "parallel emission should write the same files in the same order"
+        );
+    }
+
+    #[test]
+    fn ignored_events_are_annotated_instead_of_dropped_on_request() {
+        let downloads = || {
+            let holiday = testing::event("Feiertag", "20241003T081500")
+                .replace("LOCATION:", "TRANSP:OPAQUE\r\nLOCATION:");
+            vec![
+                download(
+                    "/files/iue/WiSe_2425/semester_1/a.ics",
+                    &testing::calendar_of(&[
+                        testing::event("Mathe", "20241014T081500"),
+                        holiday.clone(),
+                        testing::event("Feiertag", "20241031T081500"),
+                    ]),
+                ),
+                download(
+                    "/files/iue/WiSe_2425/semester_2/b.ics",
+                    &testing::calendar_of(&[holiday]),
+                ),
+            ]
+        };
+        let config = |annotate_ignored: bool| Config {
+            annotate_ignored,
+            ignored_event_names: vec![(crate::ignore::MatchMode::Exact, "Feiertag".to_owned())],
+            ..config()
+        };
+
+        testing::in_working_dir("ignored_dropped", |_| {
+            let (_, manifest_entries) = emit_run(config(false), downloads());
+
+            assert_eq!(
+                manifest_entries
+                    .iter()
+                    .map(|entry| entry.path.as_str())
+                    .collect::<Vec<_>>(),
+                ["files/WiSe_2425/iue/semester_1/Mathe.ics"],
+                "ignored events should be dropped by default"
+            );
+        });
+        testing::in_working_dir("ignored_annotated", |_| {
+            let (_, manifest_entries) = emit_run(config(true), downloads());
+
+            let events = written_events(&manifest_entries, "Feiertag")
+                .iter()
+                .map(|event| {
+                    [
+                        PROPERTY_NAME_SUMMARY,
+                        PROPERTY_NAME_TRANSPARENCY,
+                        calendar::PROPERTY_NAME_IGNORED,
+                    ]
+                    .map(|name| props::get_value(event, name).map(str::to_owned))
+                })
+                .collect::<Vec<_>>();
+            let annotated = [
+                Some("[AUSGEBLENDET] Feiertag".to_owned()),
+                Some(TRANSPARENCY_TRANSPARENT.to_owned()),
+                Some("Feiertag".to_owned()),
+            ];
+            assert_eq!(
+                events,
+                [annotated.clone(), annotated],
+                "the ignored events should be kept once each, prefixed and transparent"
+            );
+            assert_eq!(
+                written_events(&manifest_entries, "Mathe").len(),
+                1,
+                "the other modules should be unaffected"
+            );
+        });
+    }
+
+    #[test]
+    fn group_by_selects_how_finely_modules_are_split() {
+        let downloads = || {
+            vec![
+                download(
+                    "/files/iue/WiSe_2425/semester_1/a.ics",
+                    &testing::calendar(&[
+                        ("Mathe", "20241014T081500"),
+                        ("Mathe", "20241021T081500"),
+                    ]),
+                ),
+                download(
+                    "/files/iue/WiSe_2425/semester_2/b.ics",
+                    &testing::calendar(&[("Mathe", "20241015T081500")]),
+                ),
+                download(
+                    "/files/iue/SoSe_25/semester_1/c.ics",
+                    &testing::calendar(&[("Mathe", "20250414T081500")]),
+                ),
+            ]
+        };
+
+        for (group_by, expected) in [
+            (
+                names::GroupBy::Module,
+                vec![("files/WiSe_2425/iue/semester_1/Mathe.ics", 4)],
+            ),
+            (
+                names::GroupBy::ModuleGroup,
+                vec![
+                    ("files/SoSe_25/iue/semester_1/Mathe.ics", 1),
+                    ("files/WiSe_2425/iue/semester_1/Mathe.ics", 2),
+                    ("files/WiSe_2425/iue/semester_2/Mathe.ics", 1),
+                ],
+            ),
+            (
+                names::GroupBy::ModuleSemester,
+                vec![
+                    ("files/SoSe_25/iue/semester_1/Mathe.ics", 1),
+                    ("files/WiSe_2425/iue/semester_1/Mathe.ics", 3),
+                ],
+            ),
+        ] {
+            testing::in_working_dir(&format!("group_by_{group_by:?}"), |output| {
+                let config = Config {
+                    group_by,
+                    ..config()
+                };
+                let (_, manifest_entries) = emit_run(config, downloads());
+
+                let mut files = manifest_entries
+                    .iter()
+                    .map(|entry| {
+                        let content = std::fs::read_to_string(output.join(&entry.path)).unwrap();
+                        (entry.path.as_str(), content.matches("BEGIN:VEVENT").count())
+                    })
+                    .collect::<Vec<_>>();
+                files.sort_unstable();
+                assert_eq!(
+                    files, expected,
+                    "the calendars and their events for {group_by:?}"
+                );
+                let index = std::fs::read_to_string(output.join(INDEX_FILE)).unwrap();
+                assert_eq!(
+                    index.matches(".ics\">").count(),
+                    expected.len(),
+                    "the index should link every calendar once for {group_by:?}"
+                );
+            });
+        }
+    }
+
+    #[test]
+    fn low_memory_mode_writes_the_same_calendars() {
+        // 200 modules with 100 events each, the first file listed twice to have duplicates
+        let downloads = || {
+            let first = chrono::NaiveDate::from_ymd_opt(2024, 10, 1)
+                .unwrap()
+                .and_hms_opt(8, 0, 0)
+                .unwrap();
+            let files = (0..4)
+                .map(|semester| {
+                    let events: Vec<String> = (0..50)
+                        .flat_map(|module| {
+                            (0..100).map(move |lecture| {
+                                testing::event(
+                                    &format!("Modul {semester}{module:02}"),
+                                    &(first + chrono::Duration::hours(lecture * 3))
+                                        .format("%Y%m%dT%H%M%S")
+                                        .to_string(),
+                                )
+                            })
+                        })
+                        .collect();
+                    (
+                        format!("/files/iue/WiSe_2425/semester_{semester}/a.ics"),
+                        testing::calendar_of(&events),
+                    )
+                })
+                .collect::<Vec<_>>();
+
+            files
+                .iter()
+                .chain(
+                    files
+                        .first()
+                        .map(|(path, calendar)| (path.replace("a.ics", "b.ics"), calendar.clone()))
+                        .as_slice(),
+                )
+                .map(|(path, calendar)| download(path, calendar))
+                .collect::<Vec<_>>()
+        };
+        let written = |low_memory: bool| {
+            testing::in_working_dir(&format!("low_memory_{low_memory}"), |output| {
+                let config = Config {
+                    low_memory,
+                    ..config()
+                };
+                emit_run(config, downloads())
+                    .1
+                    .into_iter()
+                    .map(|entry| {
+                        let content = std::fs::read_to_string(output.join(&entry.path)).unwrap();
+                        (entry.path, content)
+                    })
+                    .collect::<Vec<_>>()
+            })
+        };
+
+        let in_memory = written(false);
+        let low_memory = written(true);
+
+        assert_eq!(
+            in_memory
+                .iter()
+                .map(|(_, content)| content.matches("BEGIN:VEVENT").count())
+                .sum::<usize>(),
+            20_000,
+            "every event should be written once"
+        );
+        assert!(
+            in_memory == low_memory,
+            "the low-memory mode should write the same calendars"
+        );
+    }
+
+    #[test]
+    fn statistics_are_counted_per_department() {
+        let informatik = "/files/iue/WiSe_2425/semester_1/a.ics";
+        let broken = "/files/iue/WiSe_2425/semester_1/b.ics";
+        let maschinenbau = "/files/mbau/WiSe_2425/semester_1/a.ics";
+        let clock = FakeClock::new();
+        let fetcher = FakeFetcher::new(&clock)
+            .page(
+                informatik,
+                &testing::calendar(&[("Mathe", "20241014T081500"), ("Physik", "20241015T100000")]),
+            )
+            .page(
+                maschinenbau,
+                &testing::calendar(&[("Mechanik", "20241014T081500")]),
+            );
+        let mut pipeline = testing::pipeline(config(), &fetcher, &clock);
+
+        testing::in_working_dir("statistics_are_counted_per_department", |_| {
+            let downloads = pipeline.download(links(&[informatik, broken, maschinenbau]));
+            let modules = pipeline.aggregate(downloads).unwrap();
+            pipeline.emit(modules).unwrap();
+        });
+
+        let stats = &pipeline.diagnostics.department_stats;
+        let iue = stats.get("iue").copied().unwrap_or_default();
+        let mbau = stats.get("mbau").copied().unwrap_or_default();
+        assert_eq!(
+            (iue.calendars, iue.modules, iue.events),
+            (1, 2, 2),
+            "the calendar, modules and events of iue should be counted"
+        );
+        assert_eq!(
+            (mbau.calendars, mbau.modules, mbau.events),
+            (1, 1, 1),
+            "the calendar, module and event of mbau should be counted"
+        );
+        assert_eq!(
+            iue.downloads.failed, 1,
+            "the failed download should be counted for iue"
+        );
+        assert_eq!(
+            mbau.downloads.failed, 0,
+            "the failed download shouldn't be counted for mbau"
+        );
+        assert_eq!(
+            (iue.downloads.network, mbau.downloads.network),
+            (1, 1),
+            "the successful downloads should be counted per department"
+        );
+    }
+
+    #[test]
+    fn missing_pages_are_not_retried() {
+        let clock = FakeClock::new();
+        let fetcher = FakeFetcher::new(&clock);
+        let pipeline = testing::pipeline(config(), &fetcher, &clock);
+
+        let result = pipeline.get_website(testing::BASE_URL, None);
+
+        assert!(
+            matches!(
+                result,
+                Err(Error::RequestFailed(reqwest::StatusCode::NOT_FOUND))
+            ),
+            "a missing page should fail"
+        );
+        assert_eq!(
+            fetcher.requests().len(),
+            1,
+            "a missing page should not be retried"
+        );
+    }
+
+    /// Creates a pipeline fetching with `fetcher` with a cache in `cache_folder`.
+    fn mock_server_pipeline(
+        fetcher: HttpFetcher,
+        cache_folder: &Path,
+        clock: &FakeClock,
+    ) -> Pipeline {
+        Pipeline::with_dependencies(
+            config(),
+            Box::new(fetcher),
+            Cache::new(cache_folder).unwrap(),
+            State::default(),
+            Box::new(clock.clone()),
+            Box::new(testing::FakeSleeper::new(clock)),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn empty_bodies_are_retried_and_the_real_body_is_cached() {
+        let calendar = testing::calendar(&[("Mathe", "20241014T081500")]);
+        let responses = Arc::new(AtomicUsize::new(0));
+        let server = testing::MockServer::start({
+            let calendar = calendar.clone();
+            let responses = Arc::clone(&responses);
+            move |_| match responses.fetch_add(1, Ordering::SeqCst) {
+                0 => testing::MockResponse::ok(""),
+                1 => testing::MockResponse::ok(" \r\n"),
+                _ => testing::MockResponse::ok(&calendar),
+            }
+        });
+        let url = format!("{}a.ics", server.base_url);
+        let cache_folder = testing::temp_dir("empty_bodies_are_retried");
+        let clock = FakeClock::new();
+        let pipeline = mock_server_pipeline(mock_server_fetcher(&server), &cache_folder, &clock);
+
+        assert_eq!(
+            pipeline.get_website(&url, None).unwrap(),
+            calendar,
+            "the body served after the empty ones should be returned"
+        );
+        assert_eq!(
+            server.requests().len(),
+            3,
+            "the empty bodies should be retried"
+        );
+        assert_eq!(
+            pipeline.cache.load(&url, None).unwrap(),
+            Some(calendar.clone()),
+            "the real body should be cached"
+        );
+        assert_eq!(
+            pipeline.get_website(&url, None).unwrap(),
+            calendar,
+            "the cached body should be used"
+        );
+        assert_eq!(
+            server.requests().len(),
+            3,
+            "the cached body should be used without asking the server"
+        );
+    }
+
+    #[test]
+    fn bodies_which_stay_empty_fail_with_the_number_of_attempts() {
+        let server = testing::MockServer::start(|_| testing::MockResponse::ok(""));
+        let url = format!("{}a.ics", server.base_url);
+        let cache_folder = testing::temp_dir("bodies_which_stay_empty");
+        let clock = FakeClock::new();
+        let pipeline = mock_server_pipeline(mock_server_fetcher(&server), &cache_folder, &clock);
+
+        let result = pipeline.get_website(&url, None);
+
+        let requests = server.requests().len();
+        assert!(
+            requests > 1,
+            "the empty body should be retried, got {requests} requests"
+        );
+        assert!(
+            matches!(result, Err(Error::EmptyResponse(attempts)) if attempts == requests),
+            "the error should count every attempt"
+        );
+        assert_eq!(
+            pipeline.cache.load(&url, None).unwrap(),
+            None,
+            "an empty body should never be cached"
+        );
+    }
+
+    #[test]
+    fn responses_larger_than_the_limit_fail_and_are_not_cached() {
+        let calendar = testing::calendar(&[("Mathe", "20241014T081500")]);
+        let limit = u64::try_from(calendar.len()).unwrap();
+        let server = testing::MockServer::start({
+            let calendar = calendar.clone();
+            move |path| match path {
+                "/small.ics" => testing::MockResponse::ok(&calendar),
+                _ => testing::MockResponse::ok(&format!("{calendar}\n")),
+            }
+        });
+        let url = |file: &str| format!("{}{file}", server.base_url);
+        let cache_folder = testing::temp_dir("responses_larger_than_the_limit");
+        let clock = FakeClock::new();
+        let pipeline = mock_server_pipeline(
+            mock_server_fetcher(&server).with_max_response_size(limit),
+            &cache_folder,
+            &clock,
+        );
+
+        let result = pipeline.get_website(&url("large.ics"), None);
+
+        assert!(
+            matches!(
+                &result,
+                Err(Error::ResponseTooLarge { url: too_large, limit: reported })
+                    if *too_large == url("large.ics") && *reported == limit
+            ),
+            "a body one byte over the limit should fail, got {result:?}"
+        );
+        assert_eq!(
+            server.requests(),
+            ["/large.ics"],
+            "a response which is too large shouldn't be retried"
+        );
+        assert_eq!(
+            pipeline.cache.load(&url("large.ics"), None).unwrap(),
+            None,
+            "nothing of the response should be cached"
+        );
+        assert_eq!(
+            pipeline.get_website(&url("small.ics"), None).unwrap(),
+            calendar,
+            "a body right at the limit should be accepted"
+        );
+    }
+
+    #[test]
+    fn emit_writes_a_calendar_for_every_module_and_the_index() {
+        let downloads = vec![download(
+            "/files/iue/WiSe_2425/semester_1/a.ics",
+            &testing::calendar(&[("Mathe", "20241014T081500"), ("Physik", "20241015T100000")]),
+        )];
+        let (mut pipeline, modules) = aggregate(config(), downloads);
+
+        testing::in_working_dir("emit_writes_a_calendar_for_every_module", |output| {
+            let manifest_entries = pipeline.emit(modules).unwrap();
+
+            assert_eq!(
+                manifest_entries.len(),
+                2,
+                "a calendar should be written per module"
+            );
+            for entry in &manifest_entries {
+                let content = std::fs::read_to_string(output.join(&entry.path)).unwrap();
+                assert!(
+                    content.contains("BEGIN:VEVENT"),
+                    "'{}' should contain the event of its module",
+                    entry.path
+                );
+            }
+            for file in [INDEX_FILE, STATE_FILE, FEED_FILE, manifest::MANIFEST_FILE] {
+                assert!(output.join(file).exists(), "'{file}' should be written");
+            }
+        });
+        assert_eq!(
+            pipeline.diagnostics.run_stats.events_emitted, 2,
+            "every event should be emitted"
+        );
+    }
+
+    #[test]
+    fn manifest_hashes_only_change_with_the_events() {
+        let path = "/files/iue/WiSe_2425/semester_1/a.ics";
+        let run = |physik_start: &str| {
+            emit_run(
+                config(),
+                vec![download(
+                    path,
+                    &testing::calendar(&[("Mathe", "20241014T081500"), ("Physik", physik_start)]),
+                )],
+            )
+            .1
+        };
+
+        testing::in_working_dir("manifest_hashes_only_change", |_| {
+            let first = run("20241015T100000");
+            let second = run("20241015T100000");
+            let third = run("20241016T100000");
+
+            assert!(
+                !module_hash(&first, "Mathe").is_empty(),
+                "the calendar of Mathe should be listed"
+            );
+            assert_eq!(
+                module_hash(&first, "Physik"),
+                module_hash(&second, "Physik"),
+                "an unchanged calendar should keep its hash"
+            );
+            assert_ne!(
+                module_hash(&second, "Physik"),
+                module_hash(&third, "Physik"),
+                "a moved event should change the hash"
+            );
+            assert_eq!(
+                module_hash(&second, "Mathe"),
+                module_hash(&third, "Mathe"),
+                "the other calendars should keep their hash"
+            );
+        });
+    }
+
+    /// Returns the events of the calendar written for `module`.
+    fn written_events(manifest_entries: &[ManifestEntry], module: &str) -> Vec<IcalEvent> {
+        let entry = manifest_entries
+            .iter()
+            .find(|entry| entry.path.ends_with(&format!("{module}.ics")))
+            .unwrap();
+        let content = std::fs::read_to_string(&entry.path).unwrap();
+
+        ical::IcalParser::new(content.as_bytes())
+            .flat_map(|calendar| calendar.unwrap().events)
+            .collect()
+    }
+
+    #[test]
+    fn lecturer_names_are_removed_from_the_published_module_name() {
+        let path = "/files/iue/WiSe_2425/semester_1/a.ics";
+        let calendar = testing::calendar(&[
+            ("Mathematik 1 - Müller-Lüdenscheidt", "20241014T081500"),
+            ("Physik", "20241015T100000"),
+        ]);
+        let config = Config {
+            strip_names: true,
+            ..config()
+        };
+
+        testing::in_working_dir("stripped_lecturer_names", |output| {
+            let (_, manifest_entries) = emit_run(config, vec![download(path, &calendar)]);
+
+            let events = written_events(&manifest_entries, "Mathematik_1");
+            assert_eq!(
+                events
+                    .iter()
+                    .map(|event| props::get_value(event, PROPERTY_NAME_SUMMARY))
+                    .collect::<Vec<_>>(),
+                [Some("Mathematik 1")],
+                "the summary should be published without the name"
+            );
+            let entry = manifest_entries
+                .iter()
+                .find(|entry| entry.path.ends_with("Mathematik_1.ics"))
+                .unwrap();
+            let content = std::fs::read_to_string(output.join(&entry.path)).unwrap();
+            assert!(
+                content.contains("X-WR-CALDESC:Mathematik 1 ("),
+                "the calendar should be described without the name"
+            );
+            let index = std::fs::read_to_string(output.join(INDEX_FILE)).unwrap();
+            assert!(
+                index.contains("Mathematik 1") && !index.contains("Müller"),
+                "the index should list the module without the name"
+            );
+        });
+    }
+
+    #[test]
+    fn large_modules_are_also_split_into_monthly_calendars() {
+        let downloads = vec![download(
+            "/files/iue/WiSe_2425/semester_1/a.ics",
+            &testing::calendar(&[
+                ("Mathe", "20250331T233000"),
+                ("Mathe", "20250401T081500"),
+                ("Mathe", "20250502T081500"),
+                ("Physik", "20250401T100000"),
+            ]),
+        )];
+        let config = Config {
+            split_large_modules: Some(2),
+            ..config()
+        };
+
+        testing::in_working_dir("month_split", |output| {
+            let (_, manifest_entries) = emit_run(config, downloads);
+
+            let mut paths = manifest_entries
+                .iter()
+                .map(|entry| entry.path.as_str())
+                .collect::<Vec<_>>();
+            paths.sort_unstable();
+            assert_eq!(
+                paths,
+                [
+                    "files/WiSe_2425/iue/semester_1/Mathe.ics",
+                    "files/WiSe_2425/iue/semester_1/Mathe/2025-03.ics",
+                    "files/WiSe_2425/iue/semester_1/Mathe/2025-04.ics",
+                    "files/WiSe_2425/iue/semester_1/Mathe/2025-05.ics",
+                    "files/WiSe_2425/iue/semester_1/Physik.ics",
+                ],
+                "only the large module should be split, by the month the events start in"
+            );
+            let april = std::fs::read_to_string(
+                output.join("files/WiSe_2425/iue/semester_1/Mathe/2025-04.ics"),
+            )
+            .unwrap();
+            assert!(
+                april.contains("X-WR-CALNAME:Mathe (2025-04)\r\n")
+                    && april.matches("BEGIN:VEVENT").count() == 1,
+                "the monthly calendar should be named after its month:\n{april}"
+            );
+            let index = std::fs::read_to_string(output.join(INDEX_FILE)).unwrap();
+            let mathe = index
+                .lines()
+                .find(|line| line.contains(">Mathe</a>"))
+                .unwrap_or_default();
+            assert!(
+                mathe.starts_with("<li> <a href=\"/files/WiSe_2425/iue/semester_1/Mathe.ics")
+                    && mathe.contains(
+                        " <ul><li><a href=\"/files/WiSe_2425/iue/semester_1/Mathe/2025-03.ics"
+                    )
+                    && mathe.contains(">2025-05</a></li></ul>"),
+                "the index should link the full calendar first and the months below it:\n{mathe}"
+            );
+        });
+    }
+
+    #[test]
+    fn index_is_written_in_german_and_english_with_the_same_calendars() {
+        let downloads = vec![
+            download(
+                "/files/iue/WiSe_2425/semester_1/a.ics",
+                &testing::calendar(&[("Mathe", "20241014T081500"), ("Physik", "20241015T100000")]),
+            ),
+            download(
+                "/files/iue/SoSe_25/semester_2/b.ics",
+                &testing::calendar(&[("Chemie", "20250414T081500")]),
+            ),
+        ];
+        let calendar_links = |index: &str| {
+            index
+                .split("<a href=\"")
+                .skip(1)
+                .filter_map(|link| link.split_once("\">"))
+                .filter(|(href, _)| {
+                    Path::new(href)
+                        .extension()
+                        .is_some_and(|extension| extension == "ics")
+                })
+                .map(|(href, rest)| {
+                    format!("{href} {}", rest.split('<').next().unwrap_or_default())
+                })
+                .collect::<Vec<_>>()
+        };
+
+        testing::in_working_dir("index_languages", |output| {
+            emit_run(config(), downloads);
+
+            let indexes = Language::ALL.map(|language| {
+                std::fs::read_to_string(output.join(language.file_name())).unwrap()
+            });
+            let [german, english] = &indexes;
+            assert_eq!(
+                calendar_links(german).len(),
+                3,
+                "every calendar should be listed:\n{german}"
+            );
+            assert_eq!(
+                calendar_links(german),
+                calendar_links(english),
+                "both languages should list the same calendars"
+            );
+            assert!(
+                german.contains("Wintersemester 2024/25")
+                    && english.contains("Winter semester 2024/25"),
+                "the semesters should be translated"
+            );
+        });
+    }
+
+    #[test]
+    fn anomalies_are_reported_with_their_source_and_fail_strict_input() {
+        let path = "/files/iue/WiSe_2425/semester_1/a.ics";
+        let calendar = testing::calendar_of(&[
+            testing::event("Mathe", "20241014T081500").replace(
+                "LOCATION:C-12.01\r\n",
+                "LOCATION:C-12.01\r\nDTSTART;TZID=Europe/Berlin:20241015T081500\r\n",
+            ),
+            testing::event("Physik", "20241015T100000"),
+        ]);
+        let run = |strict_input: bool| {
+            let config = Config {
+                strict_input,
+                ..config()
+            };
+            let (pipeline, _) = aggregate(config, vec![download(path, &calendar)]);
+            pipeline
+        };
+
+        let pipeline = run(false);
+        let anomalies: Vec<(&str, &str, &str)> = pipeline
+            .diagnostics
+            .event_anomalies
+            .iter()
+            .map(|anomaly| {
+                (
+                    anomaly.source.as_str(),
+                    anomaly.summary.as_str(),
+                    anomaly.anomaly.property.as_str(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            anomalies,
+            [(
+                format!("{}{path}", testing::BASE_URL.trim_end_matches('/')).as_str(),
+                "Mathe",
+                "DTSTART"
+            )],
+            "the duplicate DTSTART should be reported with its source and summary"
+        );
+        assert!(
+            matches!(pipeline.exit_code(), Ok(ExitCode::Success)),
+            "anomalies should only be reported by default"
+        );
+        assert!(
+            matches!(run(true).exit_code(), Err(Error::InputAnomalies(1))),
+            "anomalies should fail the run with --strict-input"
+        );
+    }
+
+    #[test]
+    fn events_ending_before_they_start_are_swapped_unless_the_input_is_strict() {
+        let path = "/files/iue/WiSe_2425/semester_1/a.ics";
+        let calendar = testing::calendar_of(&[
+            testing::event("Mathe", "20241014T081500").replace(
+                "DTEND;TZID=Europe/Berlin:20241014T094500",
+                "DTEND;TZID=Europe/Berlin:20241014T071500",
+            ),
+            testing::event("Physik", "20241015T100000"),
+        ]);
+        let run = |strict_input: bool| {
+            let config = Config {
+                strict_input,
+                ..config()
+            };
+            aggregate(config, vec![download(path, &calendar)])
+        };
+        let range = |modules: &Modules| {
+            modules
+                .values()
+                .flat_map(|entries| &entries.events)
+                .find(|event| props::get_value(event, PROPERTY_NAME_SUMMARY) == Some("Mathe"))
+                .map(|event| {
+                    [PROPERTY_NAME_DTSTART, calendar::PROPERTY_NAME_DTEND]
+                        .map(|name| props::get_value(event, name).unwrap_or_default().to_owned())
+                })
+        };
+
+        let (pipeline, modules) = run(false);
+        assert_eq!(
+            range(&modules),
+            Some(["20241014T071500".to_owned(), "20241014T081500".to_owned()]),
+            "the start and end should be swapped"
+        );
+        assert!(
+            matches!(pipeline.exit_code(), Ok(ExitCode::Success)),
+            "a swapped event shouldn't fail the run"
+        );
+
+        let (pipeline, modules) = run(true);
+        assert_eq!(
+            range(&modules),
+            Some(["20241014T081500".to_owned(), "20241014T071500".to_owned()]),
+            "the event should be left alone with --strict-input"
+        );
+        assert!(
+            matches!(pipeline.exit_code(), Err(Error::InputAnomalies(1))),
+            "the event should fail the run with --strict-input"
+        );
+    }
+
+    #[test]
+    fn all_day_events_ending_on_their_start_day_are_written_with_the_next_day_as_end() {
+        let all_day = "BEGIN:VEVENT\r\nUID:Mathe-all-day\r\nSUMMARY:Mathe\r\nDTSTART;VALUE=DATE:20241231\r\nDTEND;VALUE=DATE:20241231\r\nEND:VEVENT\r\n";
+        let calendar = testing::calendar_of(&[all_day.to_owned()]);
+
+        testing::in_working_dir("all_day_events_ending_on_their_start_day", |_| {
+            let (pipeline, manifest_entries) = emit_run(
+                config(),
+                vec![download("/files/iue/WiSe_2425/semester_1/a.ics", &calendar)],
+            );
+
+            let ends: Vec<String> = written_events(&manifest_entries, "Mathe")
+                .iter()
+                .filter_map(|event| props::get_value(event, calendar::PROPERTY_NAME_DTEND))
+                .map(str::to_owned)
+                .collect();
+            assert_eq!(
+                ends,
+                ["20250101"],
+                "the event should end on the next day, across the year"
+            );
+            assert!(
+                pipeline.diagnostics.event_anomalies.is_empty(),
+                "a zero-length all-day event isn't an anomaly"
+            );
+        });
+    }
+
+    #[test]
+    fn the_lecture_week_is_added_to_the_summaries() {
+        let all_day = "BEGIN:VEVENT\r\nUID:Mathe-all-day\r\nSUMMARY:Mathe\r\nDTSTART;VALUE=DATE:20241010\r\nEND:VEVENT\r\n";
+        let calendar = testing::calendar_of(&[
+            testing::event("Mathe", "20241007T081500"),
+            all_day.to_owned(),
+            testing::event("Mathe", "20241014T081500"),
+        ]);
+        let config = Config {
+            academic_week: Some(WeekLabelPosition::Suffix),
+            semester_starts: BTreeMap::from([(
+                "WiSe_2425".to_owned(),
+                NaiveDate::from_ymd_opt(2024, 10, 9).unwrap(),
+            )]),
+            ..config()
+        };
+
+        testing::in_working_dir("the_lecture_week_is_added", |_| {
+            let (_, manifest_entries) = emit_run(
+                config,
+                vec![download("/files/iue/WiSe_2425/semester_1/a.ics", &calendar)],
+            );
+            let mut summaries: Vec<String> = written_events(&manifest_entries, "Mathe")
+                .iter()
+                .filter_map(|event| props::get_value(event, "SUMMARY").map(str::to_owned))
+                .collect();
+            summaries.sort();
+
+            assert_eq!(
+                summaries,
+                ["Mathe", "Mathe (VW 1)", "Mathe (VW 2)"],
+                "events before the start should have no week, all-day events the week of their day"
+            );
+        });
+    }
+
+    #[test]
+    fn departments_with_a_contact_get_it_as_organizer() {
+        let downloads = vec![
+            download(
+                "/files/iue/WiSe_2425/semester_1/a.ics",
+                &testing::calendar(&[("Mathe", "20241014T081500")]),
+            ),
+            download(
+                "/files/mbau/WiSe_2425/semester_1/a.ics",
+                &testing::calendar(&[("Physik", "20241015T100000")]),
+            ),
+        ];
+        let config = Config {
+            department_contacts: BTreeMap::from([(
+                "iue".to_owned(),
+                contact::Contact::new("Sekretariat Informatik, Elektrotechnik", "iue@fh-kiel.de")
+                    .unwrap(),
+            )]),
+            ..config()
+        };
+
+        testing::in_working_dir("departments_with_a_contact", |_| {
+            let (_, manifest_entries) = emit_run(config, downloads);
+
+            let organizers = |module: &str| {
+                written_events(&manifest_entries, module)
+                    .iter()
+                    .filter_map(|event| props::get_value(event, "ORGANIZER").map(str::to_owned))
+                    .collect::<Vec<_>>()
+            };
+            assert_eq!(
+                organizers("Mathe"),
+                ["mailto:iue@fh-kiel.de"],
+                "the events of the department should get its contact"
+            );
+            assert!(
+                organizers("Physik").is_empty(),
+                "departments without a contact shouldn't get an organizer"
+            );
+            let content = manifest_entries
+                .iter()
+                .find(|entry| entry.path.ends_with("Mathe.ics"))
+                .map(|entry| std::fs::read_to_string(&entry.path).unwrap())
+                .unwrap_or_default();
+            assert!(
+                content.contains(
+                    "ORGANIZER;CN=\"Sekretariat Informatik, Elektrotechnik\":mailto:iue@fh-kiel.de"
+                ),
+                "the name should be quoted in the calendar, got {content}"
+            );
+        });
+    }
+
+    #[test]
+    fn room_changes_bump_the_sequence_of_the_event() {
+        let path = "/files/iue/WiSe_2425/semester_1/a.ics";
+        // Without a UID from the source the generated one must survive the room change
+        let event = |start: &str, location: &str| {
+            testing::event("Mathe", start)
+                .replace(&format!("UID:Mathe-{start}\r\n"), "")
+                .replace("LOCATION:C-12.01", &format!("LOCATION:{location}"))
+        };
+        let run = |location: &str| {
+            let calendar = testing::calendar_of(&[
+                event("20241014T081500", location),
+                event("20241021T081500", "C-12.01"),
+            ]);
+            let (_, manifest_entries) = emit_run(config(), vec![download(path, &calendar)]);
+
+            written_events(&manifest_entries, "Mathe")
+                .iter()
+                .map(|event| {
+                    [
+                        calendar::PROPERTY_NAME_UID,
+                        "LOCATION",
+                        calendar::PROPERTY_NAME_SEQUENCE,
+                    ]
+                    .map(|name| props::get_value(event, name).unwrap_or_default().to_owned())
+                })
+                .collect::<Vec<_>>()
+        };
+
+        testing::in_working_dir("room_changes_bump_the_sequence", |_| {
+            let first = run("C-12.01");
+            let second = run("C-13.02");
+
+            let [moved_uid, _, moved_sequence] = first.first().cloned().unwrap_or_default();
+            let [unchanged_uid, _, unchanged_sequence] = first.last().cloned().unwrap_or_default();
+            assert_eq!(
+                (moved_sequence.as_str(), unchanged_sequence.as_str()),
+                ("0", "0"),
+                "new events should start at SEQUENCE 0"
+            );
+            assert_eq!(
+                second,
+                [
+                    [moved_uid, "C-13.02".to_owned(), "1".to_owned()],
+                    [unchanged_uid, "C-12.01".to_owned(), "0".to_owned()],
+                ],
+                "only the moved event should keep its UID with an incremented SEQUENCE"
+            );
+        });
+    }
+
+    #[test]
+    fn sequences_survive_a_partial_run() {
+        let mathe = "/files/iue/WiSe_2425/semester_1/a.ics";
+        let physik = "/files/iue/WiSe_2425/semester_1/b.ics";
+        let downloads = |physik_location: Option<&str>| {
+            let mut downloads = vec![download(
+                mathe,
+                &testing::calendar(&[("Mathe", "20241014T081500")]),
+            )];
+            if let Some(location) = physik_location {
+                let calendar = testing::calendar(&[("Physik", "20241015T100000")])
+                    .replace("LOCATION:C-12.01", &format!("LOCATION:{location}"));
+                downloads.push(download(physik, &calendar));
+            }
+            downloads
+        };
+        let sequence = |manifest_entries: &[ManifestEntry]| {
+            written_events(manifest_entries, "Physik")
+                .first()
+                .and_then(|event| props::get_value(event, calendar::PROPERTY_NAME_SEQUENCE))
+                .unwrap_or_default()
+                .to_owned()
+        };
+
+        testing::in_working_dir("sequences_survive_a_partial_run", |_| {
+            emit_run(config(), downloads(Some("C-12.01")));
+            let (_, moved) = emit_run(config(), downloads(Some("C-13.02")));
+            assert_eq!(sequence(&moved), "1", "the moved event should be bumped");
+
+            // The calendar of Physik fails to download
+            let clock = FakeClock::new();
+            let mut pipeline = Pipeline::with_dependencies(
+                config(),
+                Box::new(FakeFetcher::new(&clock)),
+                Cache::disabled(),
+                State::load(Path::new(STATE_FILE)).unwrap(),
+                Box::new(clock.clone()),
+                Box::new(testing::FakeSleeper::new(&clock)),
+            )
+            .unwrap();
+            pipeline.diagnostics.failed_urls =
+                links(&[physik]).into_iter().map(|link| link.url).collect();
+            let modules = pipeline.aggregate(downloads(None)).unwrap();
+            pipeline.emit(modules).unwrap();
+
+            let (_, back) = emit_run(config(), downloads(Some("C-13.02")));
+
+            assert_eq!(
+                sequence(&back),
+                "1",
+                "the event should keep its SEQUENCE after the partial run"
+            );
+            assert_eq!(
+                State::load(Path::new(STATE_FILE)).unwrap().feed.len(),
+                1,
+                "only the move should be in the feed, the event isn't new when it is back"
+            );
+        });
+    }
+
+    #[test]
+    fn the_feed_lists_the_changed_modules_of_each_run() {
+        let run = |mathe_start: &str| {
+            let calendar = testing::calendar_of(&[
+                testing::event("Mathe", mathe_start)
+                    .replace(&format!("UID:Mathe-{mathe_start}"), "UID:mathe-1"),
+                testing::event("Physik", "20241015T100000"),
+            ]);
+            emit_run(
+                config(),
+                vec![download("/files/iue/WiSe_2425/semester_1/a.ics", &calendar)],
+            );
+
+            testing::feed_entries(&std::fs::read_to_string(FEED_FILE).unwrap())
+        };
+
+        testing::in_working_dir("the_feed_lists_the_changed_modules", |_| {
+            assert_eq!(
+                run("20241014T081500"),
+                [],
+                "the first run shouldn't report every event as new"
+            );
+
+            let entries = run("20241021T081500");
+            let fields: Vec<[Option<&str>; 2]> = entries
+                .iter()
+                .map(|entry| ["title", "content"].map(|name| entry.get(name).map(String::as_str)))
+                .collect();
+            assert_eq!(
+                fields,
+                [[
+                    Some("Geändert: Mathe"),
+                    Some("Verschoben: 14.10.2024 08:15 → 21.10.2024 08:15")
+                ]],
+                "only the module with the moved event should get an entry"
+            );
+
+            assert_eq!(
+                run("20241021T081500"),
+                entries,
+                "a run without changes shouldn't add entries"
+            );
+        });
+    }
+
+    #[test]
+    fn failed_calendar_writes_are_reported_and_the_others_still_written() {
+        let downloads = || {
+            vec![
+                download(
+                    "/files/iue/WiSe_2425/semester_1/a.ics",
+                    &testing::calendar(&[("Mathe", "20241014T081500")]),
+                ),
+                download(
+                    "/files/iue/WiSe_2425/semester_2/b.ics",
+                    &testing::calendar(&[("Physik", "20241015T100000")]),
+                ),
+            ]
+        };
+
+        testing::in_working_dir("failed_calendar_writes_are_reported", |output| {
+            let (_, manifest_entries) = emit_run(config(), downloads());
+            // A file in place of its folder makes writing the calendar of Physik fail
+            let physik_folder = manifest_entries
+                .iter()
+                .find(|entry| entry.path.ends_with("Physik.ics"))
+                .and_then(|entry| Path::new(&entry.path).parent())
+                .unwrap()
+                .to_owned();
+            std::fs::remove_dir_all(&physik_folder).unwrap();
+            std::fs::write(&physik_folder, "").unwrap();
+            let config = Config {
+                strict: true,
+                ..config()
+            };
+            let previous_state = State::load(Path::new(STATE_FILE)).unwrap();
+
+            let (pipeline, manifest_entries) = emit_run(config, downloads());
+
+            assert_eq!(
+                pipeline
+                    .diagnostics
+                    .write_failures
+                    .iter()
+                    .map(|failure| failure.module.as_str())
+                    .collect::<Vec<_>>(),
+                ["Physik"],
+                "the failed calendar should be reported"
+            );
+            assert!(
+                manifest_entries
+                    .iter()
+                    .any(|entry| entry.path.ends_with("Mathe.ics")
+                        && output.join(&entry.path).exists()),
+                "the other calendar should still be written"
+            );
+            assert!(
+                output.join(INDEX_FILE).exists(),
+                "the index should still be written"
+            );
+            assert!(
+                matches!(pipeline.exit_code(), Err(Error::OutputWriteFailures(1))),
+                "the run should fail at the end in strict mode"
+            );
+            let state = State::load(Path::new(STATE_FILE)).unwrap();
+            assert_eq!(
+                (state.modules, state.events),
+                (previous_state.modules, previous_state.events),
+                "the failed module should keep its state for the next run"
+            );
+        });
+    }
+
+    #[test]
+    fn outlook_compatible_calendars_are_written() {
+        let summary = format!("Mathe {}", "Übung ".repeat(20));
+        let calendar = testing::calendar_of(&[testing::event(&summary, "20241014T081500")
+            .replace(
+                "DTEND;TZID=Europe/Berlin:20241014T094500",
+                "DTEND;TZID=Europe/Berlin:20241014T081500",
+            )]);
+        let config = Config {
+            compat: Some(Compat::Outlook),
+            ..config()
+        };
+
+        testing::in_working_dir("outlook_compatible_calendars", |_| {
+            let (_, manifest_entries) = emit_run(
+                config,
+                vec![download("/files/iue/WiSe_2425/semester_1/a.ics", &calendar)],
+            );
+            let content = std::fs::read_to_string(&manifest_entries.first().unwrap().path).unwrap();
+
+            assert!(
+                content.contains("\r\nMETHOD:PUBLISH\r\n"),
+                "the calendar should be published"
+            );
+            assert!(
+                content.contains("DTEND;TZID=Europe/Berlin:20241014T081600\r\n"),
+                "the event should last a minute"
+            );
+            assert!(
+                content.split("\r\n").all(|line| line.len() <= 75),
+                "no line should be longer than 75 octets"
+            );
+        });
+    }
+
+    #[test]
+    fn the_index_leaves_out_calendars_which_failed_to_write() {
+        let downloads = || {
+            vec![download(
+                "/files/iue/WiSe_2425/semester_1/a.ics",
+                &testing::calendar(&[
+                    ("Mathe", "20241014T081500"),
+                    ("Physik", "20241015T100000"),
+                    ("Chemie", "20241016T100000"),
+                ]),
+            )]
+        };
+
+        testing::in_working_dir("the_index_leaves_out_failed_calendars", |output| {
+            let (_, manifest_entries) = emit_run(config(), downloads());
+            // A folder in place of the calendar of Physik makes writing it fail
+            let physik = manifest_entries
+                .iter()
+                .find(|entry| entry.path.ends_with("Physik.ics"))
+                .map(|entry| entry.path.clone())
+                .unwrap();
+            std::fs::remove_file(&physik).unwrap();
+            std::fs::create_dir(&physik).unwrap();
+
+            let (pipeline, manifest_entries) = emit_run(config(), downloads());
+
+            assert_eq!(
+                pipeline.diagnostics.write_failures.len(),
+                1,
+                "only the calendar of Physik should fail"
+            );
+            let index = std::fs::read_to_string(output.join(INDEX_FILE)).unwrap();
+            let manifest = std::fs::read_to_string(output.join(manifest::MANIFEST_FILE)).unwrap();
+            for module in ["Mathe", "Chemie"] {
+                assert!(
+                    index.contains(&format!("{module}.ics")),
+                    "the index should link to {module}"
+                );
+                assert!(
+                    manifest.contains(&format!("{module}.ics")),
+                    "the manifest should list {module}"
+                );
+            }
+            assert!(
+                !index.contains("Physik.ics"),
+                "the index shouldn't link to the failed calendar"
+            );
+            assert!(
+                !manifest.contains("Physik.ics")
+                    && !manifest_entries
+                        .iter()
+                        .any(|entry| entry.path.ends_with("Physik.ics")),
+                "the manifest shouldn't list the failed calendar"
+            );
+        });
+    }
+
+    #[test]
+    fn calendars_moved_by_the_output_template_are_removed() {
+        let downloads = || {
+            vec![download(
+                "/files/iue/WiSe_2425/semester_1/a.ics",
+                &testing::calendar(&[("Mathe", "20241014T081500")]),
+            )]
+        };
+        let config = |template: &str| Config {
+            output_template: crate::template::OutputTemplate::parse(template).unwrap(),
+            tombstone_days: 0,
+            ..config()
+        };
+
+        testing::in_working_dir("calendars_moved_by_the_output_template", |output| {
+            let (_, first) = emit_run(config("files/{group}/{module_slug}.ics"), downloads());
+            let (_, second) = emit_run(config("calendars/{module_slug}.ics"), downloads());
+
+            let old_path = output.join(&first.first().unwrap().path);
+            let new_path = output.join(&second.first().unwrap().path);
+            assert_ne!(old_path, new_path, "the calendar should have moved");
+            assert!(new_path.exists(), "the calendar should be at its new path");
+            assert!(!old_path.exists(), "the old calendar should be removed");
+            assert!(
+                !output.join("files").exists(),
+                "the folders left empty should be removed"
+            );
+        });
+    }
+
+    #[test]
+    fn calendars_of_ignored_modules_are_removed_without_a_tombstone() {
+        let downloads = || {
+            vec![download(
+                "/files/iue/WiSe_2425/semester_1/a.ics",
+                &testing::calendar(&[("Mathe", "20241014T081500"), ("Physik", "20241015T100000")]),
+            )]
+        };
+
+        testing::in_working_dir("calendars_of_ignored_modules", |output| {
+            let (_, first) = emit_run(config(), downloads());
+            let physik = first
+                .iter()
+                .find(|entry| entry.path.ends_with("Physik.ics"))
+                .map(|entry| output.join(&entry.path))
+                .unwrap();
+            assert!(physik.exists(), "the calendar should be written first");
+
+            let config = Config {
+                ignored_modules: vec![(crate::ignore::MatchMode::Exact, "Physik".to_owned())],
+                ..config()
+            };
+            let (_, second) = emit_run(config, downloads());
+
+            assert!(!physik.exists(), "the calendar should be removed");
+            assert!(
+                !second
+                    .iter()
+                    .any(|entry| entry.path.ends_with("Physik.ics")),
+                "the manifest shouldn't list the calendar"
+            );
+            assert!(
+                State::load(Path::new(STATE_FILE))
+                    .unwrap()
+                    .tombstones
+                    .is_empty(),
+                "the calendar shouldn't get a tombstone"
+            );
+            assert!(
+                second.iter().any(|entry| entry.path.ends_with("Mathe.ics")),
+                "the other calendars should still be written"
+            );
+        });
+    }
+
+    #[test]
+    fn modules_with_colliding_paths_are_written_to_distinct_files() {
+        let downloads = vec![download(
+            "/files/iue/WiSe_2425/semester_1/a.ics",
+            &testing::calendar(&[
+                ("Physik 1/2", "20241014T081500"),
+                ("Physik 1 2", "20241015T100000"),
+            ]),
+        )];
+
+        testing::in_working_dir("modules_with_colliding_paths", |output| {
+            let (_, manifest_entries) = emit_run(config(), downloads);
+
+            let contents: BTreeSet<String> = manifest_entries
+                .iter()
+                .map(|entry| std::fs::read_to_string(output.join(&entry.path)).unwrap())
+                .collect();
+            assert_eq!(
+                manifest_entries
+                    .iter()
+                    .map(|entry| entry.path.as_str())
+                    .collect::<BTreeSet<_>>()
+                    .len(),
+                2,
+                "the modules should be written to different files"
+            );
+            assert_eq!(contents.len(), 2, "the files should differ");
+        });
+    }
+
+    #[test]
+    fn run_generates_the_calendars_of_the_scraped_site() {
+        let clock = FakeClock::new();
+        let fetcher = site(&clock, &["/files/iue/WiSe_2425/semester_1/a.ics"]);
+
+        testing::in_working_dir("run_generates_the_calendars", |output| {
+            let exit_code = testing::pipeline(config(), &fetcher, &clock).run().unwrap();
+
+            assert_eq!(exit_code, ExitCode::Success, "the run should succeed");
+            let index = std::fs::read_to_string(output.join(INDEX_FILE)).unwrap();
+            assert!(
+                index.contains("Modul 0"),
+                "the index should list the module"
+            );
+            assert!(
+                output.join(STATISTICS_JSON_FILE).exists(),
+                "the statistics should be written"
+            );
+        });
+    }
+
+    #[test]
+    fn new_links_on_cached_listing_pages_show_up_in_the_next_run() {
+        let first = "/files/iue/WiSe_2425/semester_1/a.ics";
+        let added = "/files/iue/WiSe_2425/semester_1/b.ics";
+        testing::in_working_dir("new_links_on_cached_listing_pages", |output| {
+            let run = |fetcher: &FakeFetcher, clock: &FakeClock| {
+                let config = Config {
+                    no_cache: false,
+                    ..config()
+                };
+                Pipeline::with_dependencies(
+                    config,
+                    Box::new(fetcher.clone()),
+                    Cache::new(&output.join(CACHE_FOLDER)).unwrap(),
+                    State::load(Path::new(STATE_FILE)).unwrap(),
+                    Box::new(clock.clone()),
+                    Box::new(testing::FakeSleeper::new(clock)),
+                )
+                .unwrap()
+                .run()
+                .unwrap()
+            };
+
+            let clock = FakeClock::new();
+            run(&site(&clock, &[first]), &clock);
+
+            // The department adds a calendar to its semester page
+            let fetcher = site(&clock, &[first, added]);
+            let exit_code = run(&fetcher, &clock);
+
+            assert_eq!(exit_code, ExitCode::Success, "the run should succeed");
+            let index = std::fs::read_to_string(output.join(INDEX_FILE)).unwrap();
+            assert!(
+                index.contains("Modul 1"),
+                "the module of the new link should be listed: {index}"
+            );
+            let requests = fetcher.requests();
+            assert!(
+                requests
+                    .iter()
+                    .any(|url| url.ends_with("informatik-elektrotechnik/wise-2425")),
+                "the listing page should be fetched again: {requests:?}"
+            );
+            assert!(
+                !requests.iter().any(|url| url.ends_with(first)),
+                "the known calendar should come from the cache: {requests:?}"
+            );
+        });
+    }
+
+    /// Path of the calendar served for the HEAD probe tests.
+    const PROBED_CALENDAR: &str = "/files/iue/WiSe_2425/semester_1/a.ics";
+
+    /// Serves the calendar at `PROBED_CALENDAR` with the `ETag` of its `version` and answers HEAD
+    /// requests with `head_status`.
+    fn probed_calendar(method: &str, version: &str, head_status: u16) -> testing::MockResponse {
+        let response = testing::MockResponse {
+            status_code: 200,
+            headers: vec![
+                ("ETag".to_owned(), format!("\"{version}\"")),
+                (
+                    "Last-Modified".to_owned(),
+                    "Wed, 12 Mar 2025 10:00:00 GMT".to_owned(),
+                ),
+            ],
+            body: testing::calendar(&[(version, "20241014T081500")]).into_bytes(),
+        };
+
+        if method == "HEAD" && head_status != 200 {
+            testing::MockResponse {
+                status_code: head_status,
+                headers: Vec::new(),
+                body: Vec::new(),
+            }
+        } else {
+            response
+        }
+    }
+
+    /// Downloads `PROBED_CALENDAR` from `server` into an empty cache, calls `between`, then
+    /// downloads it again with the HEAD probe once the cached copy is outdated. Returns the
+    /// requests and content of the second download.
+    fn download_with_head_probe(
+        name: &str,
+        server: &testing::MockServer,
+        between: impl FnOnce(),
+    ) -> (Vec<String>, String) {
+        testing::in_working_dir(name, |_| {
+            let download = || {
+                let config = Config {
+                    base_urls: vec![server.base_url.clone()],
+                    no_cache: false,
+                    head_probe: true,
+                    ics_cache_max_age: Some(Duration::ZERO),
+                    ..config()
+                };
+                let clock = FakeClock::new();
+                let cache = open_cache(&config);
+                let mut pipeline = Pipeline::with_dependencies(
+                    config,
+                    Box::new(mock_server_fetcher(server)),
+                    cache,
+                    State::default(),
+                    Box::new(clock.clone()),
+                    Box::new(testing::FakeSleeper::new(&clock)),
+                )
+                .unwrap();
+                let link = SourceLink::new(server.base_url.trim_end_matches('/'), PROBED_CALENDAR)
+                    .unwrap();
+
+                pipeline.download(vec![link]).pop().unwrap().content
+            };
+
+            download();
+            between();
+            let previous_requests = server.requests_with_methods().len();
+            let content = download();
+
+            (
+                server.requests_with_methods().split_off(previous_requests),
+                content,
+            )
+        })
+    }
+
+    #[test]
+    fn unchanged_calendars_are_not_downloaded_after_a_matching_head_request() {
+        let server =
+            testing::MockServer::start_with_methods(|method, _| probed_calendar(method, "v1", 200));
+
+        let (requests, content) = download_with_head_probe("head_probe_match", &server, || {});
+
+        assert_eq!(
+            requests,
+            [format!("HEAD {PROBED_CALENDAR}")],
+            "only the HEAD request should be sent"
+        );
+        assert!(
+            content.contains("SUMMARY:v1"),
+            "the cached calendar should be used: {content}"
+        );
+    }
+
+    #[test]
+    fn changed_calendars_are_downloaded_after_the_head_request() {
+        let changed = Arc::new(AtomicBool::new(false));
+        let server_changed = Arc::clone(&changed);
+        let server = testing::MockServer::start_with_methods(move |method, _| {
+            let version = if server_changed.load(Ordering::SeqCst) {
+                "v2"
+            } else {
+                "v1"
+            };
+            probed_calendar(method, version, 200)
+        });
+
+        let (requests, content) = download_with_head_probe("head_probe_mismatch", &server, || {
+            changed.store(true, Ordering::SeqCst);
+        });
+
+        assert_eq!(
+            requests,
+            [
+                format!("HEAD {PROBED_CALENDAR}"),
+                format!("GET {PROBED_CALENDAR}")
+            ],
+            "the calendar should be downloaded after the HEAD request"
+        );
+        assert!(
+            content.contains("SUMMARY:v2"),
+            "the changed calendar should be used: {content}"
+        );
+    }
+
+    #[test]
+    fn servers_without_head_support_get_a_conditional_request() {
+        let server =
+            testing::MockServer::start_with_methods(|method, _| probed_calendar(method, "v1", 405));
+
+        let (requests, _) = download_with_head_probe("head_probe_not_allowed", &server, || {});
+
+        assert_eq!(
+            requests,
+            [
+                format!("HEAD {PROBED_CALENDAR}"),
+                format!("GET {PROBED_CALENDAR}")
+            ],
+            "the calendar should be requested after the HEAD request was refused"
+        );
+        assert_eq!(
+            server
+                .request_headers("If-None-Match")
+                .last()
+                .cloned()
+                .flatten()
+                .as_deref(),
+            Some("\"v1\""),
+            "the request should be conditional"
+        );
+    }
+
+    #[test]
+    fn compressed_calendars_are_decoded_cached_and_measured() {
+        use std::io::Write as _;
+
+        let calendar = testing::calendar(&[("Mathe", "20241014T081500"); 20]);
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::best());
+        encoder.write_all(calendar.as_bytes()).unwrap();
+        let compressed = encoder.finish().unwrap();
+        let server = testing::MockServer::start({
+            let compressed = compressed.clone();
+            move |_| testing::MockResponse {
+                status_code: 200,
+                headers: vec![("Content-Encoding".to_owned(), "gzip".to_owned())],
+                body: compressed.clone(),
+            }
+        });
+        let config = || Config {
+            base_urls: vec![server.base_url.clone()],
+            no_cache: false,
+            ..config()
+        };
+
+        testing::in_working_dir("compressed_download", |_| {
+            let clock = FakeClock::new();
+            let mut pipeline = Pipeline::with_dependencies(
+                config(),
+                Box::new(mock_server_fetcher(&server)),
+                open_cache(&config()),
+                State::default(),
+                Box::new(clock.clone()),
+                Box::new(testing::FakeSleeper::new(&clock)),
+            )
+            .unwrap();
+            let link =
+                SourceLink::new(server.base_url.trim_end_matches('/'), PROBED_CALENDAR).unwrap();
+            let url = link.url.clone();
+
+            let downloads = pipeline.download(vec![link]);
+
+            assert_eq!(
+                downloads
+                    .iter()
+                    .map(|download| download.content.as_str())
+                    .collect::<Vec<_>>(),
+                [calendar.as_str()],
+                "the calendar should be decoded"
+            );
+            assert_eq!(
+                open_cache(&config()).load(&url, None).unwrap(),
+                Some(calendar.clone()),
+                "the decoded calendar should be cached"
+            );
+            assert_eq!(
+                pipeline
+                    .diagnostics
+                    .fetch_records()
+                    .iter()
+                    .map(|record| (
+                        record.content_encoding.clone(),
+                        record.encoded_size,
+                        record.decoded_size
+                    ))
+                    .collect::<Vec<_>>(),
+                [(
+                    Some("gzip".to_owned()),
+                    Some(compressed.len()),
+                    Some(calendar.len())
+                )],
+                "the transferred and the decoded size should be recorded"
+            );
+            assert!(
+                compressed.len() < calendar.len(),
+                "the calendar should have been transferred compressed"
+            );
+        });
+        assert_eq!(
+            server.request_headers("Accept-Encoding"),
+            [Some("gzip, deflate, br".to_owned())],
+            "compressed responses should be accepted"
+        );
+    }
+
+    #[test]
+    fn fetches_are_recorded_with_their_response_metadata() {
+        let requests = Arc::new(AtomicUsize::new(0));
+        let server = testing::MockServer::start({
+            let requests = Arc::clone(&requests);
+            move |_| {
+                // Every request after the first one is answered as a conditional request
+                let mut response = probed_calendar("GET", "v1", 200);
+                if requests.fetch_add(1, Ordering::SeqCst) > 0 {
+                    response.status_code = 304;
+                    response.body = Vec::new();
+                }
+                response
+            }
+        });
+        let calendar_size = probed_calendar("GET", "v1", 200).body.len().to_string();
+
+        let records = testing::in_working_dir("fetch_records", |output| {
+            let download = |max_age: Duration| {
+                let config = Config {
+                    base_urls: vec![server.base_url.clone()],
+                    no_cache: false,
+                    ics_cache_max_age: Some(max_age),
+                    ..config()
+                };
+                let clock = FakeClock::new();
+                let cache = open_cache(&config);
+                let mut pipeline = Pipeline::with_dependencies(
+                    config,
+                    Box::new(mock_server_fetcher(&server)),
+                    cache,
+                    State::default(),
+                    Box::new(clock.clone()),
+                    Box::new(testing::FakeSleeper::new(&clock)),
+                )
+                .unwrap();
+                let link = SourceLink::new(server.base_url.trim_end_matches('/'), PROBED_CALENDAR)
+                    .unwrap();
+                pipeline.download(vec![link]);
+
+                let records = pipeline.diagnostics.fetch_records().clone();
+                records
+            };
+
+            let records = [
+                download(Duration::ZERO),
+                download(Duration::ZERO),
+                download(Duration::from_secs(3600)),
+            ]
+            .concat();
+
+            let fetch_log = output.join("fetch_log.csv");
+            report::write_fetch_log(&records, &fetch_log).unwrap();
+            let fetch_log = std::fs::read_to_string(fetch_log).unwrap();
+            assert_eq!(
+                fetch_log.lines().count(),
+                4,
+                "the fetch log should have a line per fetch:\n{fetch_log}"
+            );
+
+            records
+        });
+
+        let url = format!("{}{PROBED_CALENDAR}", server.base_url.trim_end_matches('/'));
+        assert_eq!(
+            records
+                .iter()
+                .map(|record| (
+                    record.url.as_str(),
+                    record.status,
+                    record.origin,
+                    record.content_length.as_deref(),
+                    record.last_modified.as_deref(),
+                    record.etag.as_deref(),
+                ))
+                .collect::<Vec<_>>(),
+            [
+                (Some(200), FetchOrigin::Network),
+                (Some(304), FetchOrigin::NotModified),
+                (None, FetchOrigin::Cache),
+            ]
+            .map(|(status, origin)| (
+                url.as_str(),
+                status,
+                origin,
+                Some(calendar_size.as_str()),
+                Some("Wed, 12 Mar 2025 10:00:00 GMT"),
+                Some("\"v1\""),
+            )),
+            "the fresh fetch, the 304 and the cache hit should be recorded with the metadata of \
+             the downloaded version"
+        );
+    }
+
+    /// Runs twice against a site whose calendar grows from one event to `events` events in
+    /// between. Returns the result of the second run and what it logged.
+    fn run_with_growing_calendar(
+        name: &str,
+        events: usize,
+        strict: bool,
+    ) -> (Result<ExitCode>, Vec<String>) {
+        let path = "/files/iue/WiSe_2425/semester_1/a.ics";
+        testing::in_working_dir(name, |_| {
+            let run = |events: usize| {
+                let starts: Vec<String> = (0..events)
+                    .map(|day| format!("202410{:02}T081500", day + 1))
+                    .collect();
+                let calendar = testing::calendar(
+                    &starts
+                        .iter()
+                        .map(|start| ("Mathe", start.as_str()))
+                        .collect::<Vec<_>>(),
+                );
+                let clock = FakeClock::new();
+                let fetcher = site_at(
+                    FakeFetcher::new(&clock),
+                    testing::BASE_URL,
+                    &[(path, calendar)],
+                );
+                let config = Config { strict, ..config() };
+
+                Pipeline::with_dependencies(
+                    config,
+                    Box::new(fetcher),
+                    Cache::disabled(),
+                    State::load(Path::new(STATE_FILE)).unwrap(),
+                    Box::new(clock.clone()),
+                    Box::new(testing::FakeSleeper::new(&clock)),
+                )
+                .unwrap()
+                .run()
+            };
+
+            run(1).unwrap();
+            let logs = testing::CapturedLogs::default();
+            let result = tracing::subscriber::with_default(logs.subscriber(), || run(events));
+
+            (result, logs.lines())
+        })
+    }
+
+    /// Whether `logs` warn about the growth of the output.
+    fn warns_about_growth(logs: &[String]) -> bool {
+        logs.iter().any(|line| {
+            line.contains("WARN")
+                && line.contains("Total size of the calendars grew from")
+                && line.contains("more than 50% since the last run")
+        })
+    }
+
+    #[test]
+    fn tripled_output_size_is_warned_about() {
+        let (result, logs) = run_with_growing_calendar("size_growth_warning", 7, false);
+
+        assert!(
+            matches!(result, Ok(ExitCode::Success | ExitCode::Partial)),
+            "the run should still succeed: {result:?}"
+        );
+        assert!(
+            warns_about_growth(&logs),
+            "the growth should be warned about:\n{}",
+            logs.join("\n")
+        );
+    }
+
+    #[test]
+    fn tripled_output_size_fails_a_strict_run() {
+        let (result, logs) = run_with_growing_calendar("size_growth_strict", 7, true);
+
+        let growth = match result {
+            Err(Error::OutputSizeGrowth { previous, current }) => Some((previous, current)),
+            _ => None,
+        };
+        assert!(
+            growth.is_some_and(|(previous, current)| current >= previous * 3),
+            "the strict run should fail because the size tripled: {growth:?}"
+        );
+        assert!(
+            warns_about_growth(&logs),
+            "the growth should be warned about:\n{}",
+            logs.join("\n")
+        );
+    }
+
+    #[test]
+    fn small_growth_is_accepted() {
+        let (result, logs) = run_with_growing_calendar("size_growth_small", 1, true);
+
+        assert!(result.is_ok(), "the strict run should succeed: {result:?}");
+        assert!(
+            !warns_about_growth(&logs),
+            "an unchanged size shouldn't be warned about:\n{}",
+            logs.join("\n")
+        );
+    }
+
+    #[test]
+    fn failed_downloads_are_reported_at_the_end_of_the_run() {
+        let broken = "/files/iue/WiSe_2425/semester_1/b.ics";
+        let clock = FakeClock::new();
+        // Served before the calendar, so the only attempt fails
+        let fetcher = FakeFetcher::new(&clock).respond(
+            &format!("{}{broken}", testing::BASE_URL),
+            testing::response(broken, 503, ""),
+        );
+        let fetcher = site_at(
+            fetcher,
+            testing::BASE_URL,
+            &[
+                (
+                    "/files/iue/WiSe_2425/semester_1/a.ics",
+                    testing::calendar(&[("Mathe", "20241014T081500")]),
+                ),
+                (broken, testing::calendar(&[("Physik", "20241015T100000")])),
+            ],
+        );
+
+        testing::in_working_dir("failed_downloads_are_reported", |output| {
+            let config = Config {
+                retry_budget: 0,
+                report: Some(output.join("report.json")),
+                ..config()
+            };
+
+            let exit_code = testing::pipeline(config, &fetcher, &clock).run().unwrap();
+
+            assert_eq!(
+                exit_code,
+                ExitCode::Partial,
+                "the run should finish with partial output"
+            );
+            let report: serde_json::Value =
+                serde_json::from_str(&std::fs::read_to_string(output.join("report.json")).unwrap())
+                    .unwrap();
+            assert_eq!(
+                report.pointer("/run_stats/downloads"),
+                Some(&serde_json::json!({"cache_hit": 0, "network": 1, "failed": 1})),
+                "the report should count the failed download"
+            );
+        });
+    }
+
+    #[test]
+    fn files_besides_the_calendars_which_cant_be_written_make_the_output_partial() {
+        let clock = FakeClock::new();
+        let fetcher = site(&clock, &["/files/iue/WiSe_2425/semester_1/a.ics"]);
+        let blocked = [
+            STATISTICS_HTML_FILE,
+            STATISTICS_JSON_FILE,
+            CHANGES_FILE,
+            CHANGES_HTML_FILE,
+            FEED_FILE,
+        ];
+
+        testing::in_working_dir("files_besides_the_calendars", |output| {
+            // A folder in place of a file makes writing it fail
+            for file in blocked {
+                std::fs::create_dir_all(output.join(file)).unwrap();
+            }
+
+            let mut pipeline = testing::pipeline(config(), &fetcher, &clock);
+            let links = pipeline.scrape().unwrap();
+            let downloads = pipeline.download(links);
+            let modules = pipeline.aggregate(downloads).unwrap();
+            pipeline.write_statistics(&modules);
+            let manifest_entries = pipeline.emit(modules).unwrap();
+
+            assert!(
+                manifest_entries
+                    .iter()
+                    .all(|entry| output.join(&entry.path).is_file()),
+                "the calendars should still be written"
+            );
+            assert!(
+                output.join(INDEX_FILE).is_file(),
+                "the index should still be written"
+            );
+            let failed: BTreeSet<&str> = pipeline
+                .diagnostics
+                .index_write_failures
+                .iter()
+                .map(|failure| failure.file.as_str())
+                .collect();
+            assert_eq!(
+                failed,
+                BTreeSet::from(blocked),
+                "every file which can't be written should be reported"
+            );
+            assert!(
+                matches!(pipeline.exit_code(), Ok(ExitCode::Partial)),
+                "the output should be partial"
+            );
+        });
+    }
+
+    /// An event of `summary` without a DTSTART.
+    fn undated(summary: &str) -> String {
+        format!("BEGIN:VEVENT\r\nUID:{summary}-undated\r\nSUMMARY:{summary}\r\nLOCATION:C-12.01\r\nEND:VEVENT\r\n")
+    }
+
+    #[test]
+    fn undated_events_are_reported_with_their_properties_and_dropped() {
+        let path = "/files/iue/WiSe_2425/semester_1/a.ics";
+        let downloads = vec![download(
+            path,
+            &testing::calendar_of(&[testing::event("Mathe", "20241014T081500"), undated("Mathe")]),
+        )];
+
+        let (pipeline, modules) = aggregate(config(), downloads);
+
+        let url = format!("{}{path}", testing::BASE_URL.trim_end_matches('/'));
+        let undated_events = &pipeline.diagnostics.undated_events;
+        assert_eq!(
+            undated_events.len(),
+            1,
+            "the undated event should be reported"
+        );
+        assert!(
+            undated_events.iter().all(|event| event.source == url
+                && event.properties.contains(&"SUMMARY:Mathe".to_owned())
+                && event.properties.contains(&"LOCATION:C-12.01".to_owned())),
+            "the report should name the source and every property: {undated_events:?}"
+        );
+        assert_eq!(
+            pipeline
+                .diagnostics
+                .source_stats
+                .get(&url)
+                .map(|stats| stats.undated),
+            Some(1),
+            "the undated event should be counted for its source"
+        );
+        assert_eq!(
+            only_module_events(&modules).len(),
+            1,
+            "only the dated event should be kept"
+        );
+    }
+
+    #[test]
+    fn undated_events_are_kept_on_the_placeholder_date_with_keep_undated() {
+        let downloads = vec![download(
+            "/files/iue/WiSe_2425/semester_1/a.ics",
+            &testing::calendar_of(&[testing::event("Mathe", "20241014T081500"), undated("Mathe")]),
+        )];
+        let config = Config {
+            keep_undated: true,
+            undated_placeholder_date: NaiveDate::from_ymd_opt(2024, 10, 1).unwrap(),
+            ..config()
+        };
+
+        testing::in_working_dir("undated_events_are_kept", |_| {
+            let (pipeline, manifest_entries) = emit_run(config, downloads);
+
+            let events = written_events(&manifest_entries, "Mathe");
+            // The undated event is written on the placeholder date
+            let placeholder = events
+                .iter()
+                .find(|event| props::get_value(event, PROPERTY_NAME_DTSTART) == Some("20241001"))
+                .unwrap();
+            assert_eq!(
+                props::get_param(placeholder, PROPERTY_NAME_DTSTART, "VALUE"),
+                Some("DATE"),
+                "the placeholder should be an all-day event"
+            );
+            assert_eq!(
+                props::get_value(placeholder, PROPERTY_NAME_SUMMARY),
+                Some(format!("{UNDATED_SUMMARY_PREFIX} Mathe").as_str()),
+                "the summary should mark the date as unknown"
+            );
+            assert_eq!(events.len(), 2, "the dated event should be written as well");
+            assert_eq!(
+                pipeline.diagnostics.undated_events.len(),
+                1,
+                "the kept event should still be reported"
+            );
+        });
+    }
+
+    #[test]
+    fn calendars_with_a_bom_junk_or_line_feeds_only_are_aggregated() {
+        let downloads = vec![
+            download(
+                "/files/iue/WiSe_2425/semester_1/a.ics",
+                &format!(
+                    "\u{feff}{}",
+                    testing::calendar(&[("Mathe", "20241014T081500")])
+                ),
+            ),
+            download(
+                "/files/iue/WiSe_2425/semester_1/b.ics",
+                &format!(
+                    "\r\nExport\r\n{}",
+                    testing::calendar(&[("Physik", "20241015T100000")])
+                ),
+            ),
+            download(
+                "/files/iue/WiSe_2425/semester_1/c.ics",
+                &testing::calendar(&[("Chemie", "20241016T100000")]).replace("\r\n", "\n"),
+            ),
+        ];
+
+        let (_, modules) = aggregate(config(), downloads);
+
+        assert_eq!(
+            modules
+                .values()
+                .map(|entry| entry.display_name.as_str())
+                .collect::<BTreeSet<_>>(),
+            BTreeSet::from(["Chemie", "Mathe", "Physik"]),
+            "every calendar should be parsed"
+        );
+    }
+
+    /// Returns `TRANSP` of every event keyed by its summary.
+    fn transparencies(modules: &Modules) -> BTreeMap<String, Option<String>> {
+        modules
+            .values()
+            .flat_map(|entry| &entry.events)
+            .map(|event| {
+                (
+                    props::get_value(event, PROPERTY_NAME_SUMMARY)
+                        .unwrap_or_default()
+                        .to_owned(),
+                    props::get_value(event, PROPERTY_NAME_TRANSPARENCY).map(str::to_owned),
+                )
+            })
+            .collect()
+    }
+
+    fn transparency_calendar() -> String {
+        let marked = testing::event("Physik", "20241015T100000")
+            .replace("END:VEVENT", "TRANSP:TRANSPARENT\r\nEND:VEVENT");
+
+        testing::calendar_of(&[
+            testing::event("Mathe", "20241014T081500"),
+            testing::event("Anmeldezeitraum Prüfungen", "20241016T080000"),
+            testing::event("Tutorium", "20241017T080000"),
+            marked,
+        ])
+    }
+
+    #[test]
+    fn informational_events_are_transparent_unless_the_upstream_decided() {
+        let downloads = vec![download(
+            "/files/iue/WiSe_2425/semester_1/a.ics",
+            &transparency_calendar(),
+        )];
+
+        let (_, modules) = aggregate(config(), downloads);
+
+        let transparent = Some(TRANSPARENCY_TRANSPARENT.to_owned());
+        let opaque = Some(TRANSPARENCY_OPAQUE.to_owned());
+        assert_eq!(
+            transparencies(&modules),
+            BTreeMap::from([
+                ("Anmeldezeitraum Prüfungen".to_owned(), transparent.clone()),
+                ("Mathe".to_owned(), opaque.clone()),
+                ("Physik".to_owned(), transparent),
+                ("Tutorium".to_owned(), opaque),
+            ]),
+            "only the informational events and the upstream choice should be transparent"
+        );
+    }
+
+    #[test]
+    fn transparent_patterns_can_be_replaced() {
+        let downloads = vec![download(
+            "/files/iue/WiSe_2425/semester_1/a.ics",
+            &transparency_calendar(),
+        )];
+        let config = Config {
+            transparent_event_names: Config::parse(
+                [
+                    "--no-default-transparent",
+                    "--transparent",
+                    "exact:tutorium",
+                ]
+                .map(str::to_owned),
+            )
+            .unwrap()
+            .transparent_event_names,
+            ..config()
+        };
+
+        let (_, modules) = aggregate(config, downloads);
+
+        let transparent = Some(TRANSPARENCY_TRANSPARENT.to_owned());
+        let opaque = Some(TRANSPARENCY_OPAQUE.to_owned());
+        assert_eq!(
+            transparencies(&modules),
+            BTreeMap::from([
+                ("Anmeldezeitraum Prüfungen".to_owned(), opaque.clone()),
+                ("Mathe".to_owned(), opaque),
+                ("Physik".to_owned(), transparent.clone()),
+                ("Tutorium".to_owned(), transparent),
+            ]),
+            "the configured patterns should replace the default ones"
+        );
+    }
+
+    #[test]
+    fn events_are_counted_per_source_calendar() {
+        let first = "/files/iue/WiSe_2425/semester_1/a.ics";
+        let second = "/files/iue/WiSe_2425/semester_2/b.ics";
+        let downloads = vec![
+            download(
+                first,
+                &testing::calendar(&[
+                    ("Mathe", "20241014T081500"),
+                    ("Mathe", "20241021T081500"),
+                    ("Feiertag", "20241031T000000"),
+                ]),
+            ),
+            download(
+                second,
+                &testing::calendar(&[("Mathe", "20241014T081500"), ("Physik", "20241015T100000")]),
+            ),
+        ];
+
+        let (pipeline, modules) = aggregate(config(), downloads);
+
+        let stats = |path: &str| {
+            let url = format!("{}{path}", testing::BASE_URL.trim_end_matches('/'));
+            pipeline
+                .diagnostics
+                .source_stats
+                .get(&url)
+                .copied()
+                .unwrap_or_default()
+        };
+        let first = stats(first);
+        assert_eq!(
+            (
+                first.parsed,
+                first.ignored,
+                first.deduplicated,
+                first.accepted
+            ),
+            (3, 1, 0, 2),
+            "the holiday of the first calendar should be ignored"
+        );
+        let second = stats(second);
+        assert_eq!(
+            (
+                second.parsed,
+                second.ignored,
+                second.deduplicated,
+                second.accepted
+            ),
+            (2, 0, 1, 1),
+            "the lecture of the second calendar overlapping the first should be deduplicated"
+        );
+        assert_eq!(
+            modules.len(),
+            2,
+            "there should be a module for Mathe and Physik"
+        );
+        assert_eq!(
+            pipeline.diagnostics.run_stats.events_seen, 5,
+            "every event should be seen"
+        );
+    }
+
+    #[test]
+    fn events_with_empty_names_are_skipped_or_kept_as_unnamed() {
+        let calendar = testing::calendar(&[
+            ("", "20241014T081500"),
+            ("   ", "20241015T081500"),
+            ("Mathe", "20241016T081500"),
+        ]);
+        let downloads = || vec![download("/files/iue/WiSe_2425/semester_1/a.ics", &calendar)];
+
+        let (pipeline, modules) = aggregate(config(), downloads());
+
+        assert_eq!(
+            modules
+                .values()
+                .map(|module| module.display_name.as_str())
+                .collect::<Vec<_>>(),
+            ["Mathe"],
+            "events without a name should be skipped"
+        );
+        assert_eq!(
+            pipeline
+                .diagnostics
+                .source_stats
+                .values()
+                .map(|stats| stats.unnamed)
+                .sum::<u32>(),
+            2,
+            "the skipped events should be counted"
+        );
+
+        let config = Config {
+            keep_unnamed: true,
+            ..config()
+        };
+        let (_, modules) = aggregate(config, downloads());
+
+        assert_eq!(
+            modules
+                .values()
+                .find(|module| module.display_name == UNNAMED_MODULE)
+                .map(|module| module.events.len()),
+            Some(2),
+            "events without a name should be kept with --keep-unnamed"
+        );
+    }
+
+    #[test]
+    fn long_module_names_are_kept_in_full() {
+        let name = "Sehr langer Modulname ".repeat(14);
+        let name = name.trim();
+        let downloads = vec![download(
+            "/files/iue/WiSe_2425/semester_1/a.ics",
+            &testing::calendar(&[(name, "20241014T081500")]),
+        )];
+
+        testing::in_working_dir("long_module_names_are_kept_in_full", |_| {
+            let (_, manifest_entries) = emit_run(config(), downloads);
+
+            let path = Path::new(&manifest_entries.first().unwrap().path);
+            assert!(
+                path.file_name().unwrap().len()
+                    <= crate::config::DEFAULT_MAX_SLUG_LENGTH + ".ics".len(),
+                "the file name should be truncated"
+            );
+            let content = std::fs::read_to_string(path).unwrap();
+            assert!(
+                content.replace("\r\n ", "").contains(name),
+                "the calendar should use the full name"
+            );
+        });
+    }
+
+    /// Marks an event built by `testing::event` as cancelled.
+    fn cancelled(event: &str) -> String {
+        event.replace("END:VEVENT", "STATUS:CANCELLED\r\nEND:VEVENT")
+    }
+
+    /// Returns the events of the only module.
+    fn only_module_events(modules: &Modules) -> &[IcalEvent] {
+        assert_eq!(modules.len(), 1, "there should be a single module");
+        &modules.values().next().unwrap().events
+    }
+
+    /// Returns `(DTSTART, X-FH-SOURCE)` of every event.
+    fn sources(events: &[IcalEvent]) -> BTreeMap<&str, Option<&str>> {
+        events
+            .iter()
+            .map(|event| {
+                (
+                    props::get_value(event, PROPERTY_NAME_DTSTART).unwrap_or_default(),
+                    props::get_value(event, PROPERTY_NAME_SOURCE),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn events_record_the_first_source_calendar_they_were_found_in() {
+        let first = "/files/iue/WiSe_2425/semester_1/a.ics";
+        let second = "/files/iue/WiSe_2425/semester_1/b.ics";
+        let downloads = || {
+            vec![
+                download(
+                    first,
+                    &testing::calendar(&[
+                        ("Mathe", "20241014T081500"),
+                        ("Mathe", "20241014T100000"),
+                    ]),
+                ),
+                download(
+                    second,
+                    &testing::calendar(&[
+                        ("Mathe", "20241014T081500"),
+                        ("Mathe", "20241014T120000"),
+                    ]),
+                ),
+            ]
+        };
+
+        let (_, modules) = aggregate(config(), downloads());
+        assert_eq!(
+            sources(only_module_events(&modules)),
+            BTreeMap::from([
+                ("20241014T081500", Some(first)),
+                ("20241014T100000", Some(first)),
+                ("20241014T120000", Some(second)),
+            ]),
+            "every event should name its source, duplicates the first one"
+        );
+
+        let config = Config {
+            no_provenance: true,
+            ..config()
+        };
+        let (_, modules) = aggregate(config, downloads());
+        assert!(
+            sources(only_module_events(&modules))
+                .values()
+                .all(Option::is_none),
+            "--no-provenance should leave the source out"
+        );
+    }
+
+    #[test]
+    fn cancelled_status_is_kept() {
+        let downloads = vec![download(
+            "/files/iue/WiSe_2425/semester_1/a.ics",
+            &testing::calendar_of(&[cancelled(&testing::event("Mathe", "20241014T081500"))]),
+        )];
+
+        let (_, modules) = aggregate(config(), downloads);
+
+        let events = only_module_events(&modules);
+        assert_eq!(events.len(), 1, "the cancelled event should be kept");
+        assert!(
+            events.iter().all(calendar::is_cancelled),
+            "the event should stay cancelled"
+        );
+    }
+
+    #[test]
+    fn cancellation_prefix_in_the_summary_sets_the_status() {
+        let downloads = vec![download(
+            "/files/iue/WiSe_2425/semester_1/a.ics",
+            &testing::calendar(&[
+                ("Entfällt: Mathe", "20241014T081500"),
+                ("Mathe", "20241021T081500"),
+            ]),
+        )];
+
+        let (_, modules) = aggregate(config(), downloads);
+
+        let events = only_module_events(&modules);
+        assert_eq!(
+            modules.values().next().unwrap().display_name,
+            "Mathe",
+            "the prefix should not end up in the module name"
+        );
+        assert_eq!(
+            events
+                .iter()
+                .map(|event| (
+                    props::get_value(event, PROPERTY_NAME_SUMMARY).unwrap(),
+                    calendar::is_cancelled(event)
+                ))
+                .collect::<Vec<_>>(),
+            vec![("Mathe", true), ("Mathe", false)],
+            "only the event with the prefix should be cancelled"
+        );
+    }
+
+    #[test]
+    fn cancelled_events_are_dropped_with_drop_cancelled() {
+        let path = "/files/iue/WiSe_2425/semester_1/a.ics";
+        let downloads = vec![download(
+            path,
+            &testing::calendar_of(&[
+                cancelled(&testing::event("Mathe", "20241014T081500")),
+                testing::event("Entfällt: Mathe", "20241021T081500"),
+                testing::event("Mathe", "20241028T081500"),
+            ]),
+        )];
+        let config = Config {
+            drop_cancelled: true,
+            ..config()
+        };
+
+        let (pipeline, modules) = aggregate(config, downloads);
+
+        assert_eq!(
+            only_module_events(&modules).len(),
+            1,
+            "only the lecture taking place should be kept"
+        );
+        let url = format!("{}{path}", testing::BASE_URL.trim_end_matches('/'));
+        assert_eq!(
+            pipeline
+                .diagnostics
+                .source_stats
+                .get(&url)
+                .copied()
+                .unwrap_or_default()
+                .cancelled,
+            2,
+            "both cancelled events should be counted"
+        );
+    }
+
+    #[test]
+    fn cancelled_events_do_not_suppress_lectures_at_the_same_time() {
+        let first = "/files/iue/WiSe_2425/semester_1/a.ics";
+        let second = "/files/iue/WiSe_2425/semester_2/b.ics";
+        let lecture = testing::event("Mathe", "20241014T081500");
+        let downloads = vec![
+            download(first, &testing::calendar_of(&[cancelled(&lecture)])),
+            download(
+                second,
+                &testing::calendar_of(&[cancelled(&lecture), lecture.clone()]),
+            ),
+        ];
+
+        let (pipeline, modules) = aggregate(config(), downloads);
+
+        let events = only_module_events(&modules);
+        assert_eq!(
+            events
+                .iter()
+                .map(calendar::is_cancelled)
+                .collect::<Vec<_>>(),
+            vec![true, false],
+            "the lecture should be kept next to the cancelled event"
+        );
+        assert_eq!(
+            pipeline.diagnostics.run_stats.events_deduplicated, 1,
+            "the second cancelled event should be a duplicate of the first"
+        );
+    }
+
+    #[test]
+    fn downloads_stop_once_the_deadline_expires() {
+        let paths = [
+            "/files/iue/WiSe_2425/semester_1/a.ics",
+            "/files/iue/WiSe_2425/semester_1/b.ics",
+            "/files/iue/WiSe_2425/semester_1/c.ics",
+        ];
+        let clock = FakeClock::new();
+        let mut fetcher = FakeFetcher::new(&clock).latency("files/", Duration::from_secs(60));
+        for path in paths {
+            fetcher = fetcher.page(path, &testing::calendar(&[("Mathe", "20241014T081500")]));
+        }
+        let config = Config {
+            max_runtime: Some(Duration::from_secs(100)),
+            ..config()
+        };
+        let mut pipeline = testing::pipeline(config, &fetcher, &clock);
+
+        let downloads = pipeline.download(links(&paths));
+
+        // The second download starts before the deadline, the third one after it
+        assert_eq!(downloads.len(), 2, "two files should be downloaded");
+        assert_eq!(
+            fetcher.requests().len(),
+            2,
+            "the third file should not be requested"
+        );
+        assert_eq!(
+            pipeline.diagnostics.skipped_urls,
+            vec![format!(
+                "{}{}",
+                testing::BASE_URL.trim_end_matches('/'),
+                paths[2]
+            )],
+            "the third file should be reported as skipped"
+        );
+    }
+
+    /// Lets the requests to URLs starting with `prefix` take a moment of real time.
+    struct SlowPrefix {
+        fetcher: FakeFetcher,
+        prefix: String,
+    }
+
+    impl Fetcher for SlowPrefix {
+        fn fetch(&self, url: &str, conditional: Option<&CacheMetadata>) -> Result<FetchResponse> {
+            if url.starts_with(&self.prefix) {
+                std::thread::sleep(Duration::from_millis(100));
+            }
+
+            self.fetcher.fetch(url, conditional)
+        }
+    }
+
+    #[test]
+    fn a_slow_department_doesnt_hold_up_the_others() {
+        let slow = [
+            "/files/iue/WiSe_2425/semester_1/a.ics",
+            "/files/iue/WiSe_2425/semester_1/b.ics",
+            "/files/iue/WiSe_2425/semester_1/c.ics",
+        ];
+        let fast = [
+            "/files/mbau/WiSe_2425/semester_1/a.ics",
+            "/files/mbau/WiSe_2425/semester_1/b.ics",
+            "/files/medien/WiSe_2425/semester_1/a.ics",
+            "/files/medien/WiSe_2425/semester_1/b.ics",
+        ];
+        let clock = FakeClock::new();
+        let mut fetcher = FakeFetcher::new(&clock);
+        for path in slow.iter().chain(&fast) {
+            fetcher = fetcher.page(path, &testing::calendar(&[("Mathe", "20241014T081500")]));
+        }
+        let mut pipeline = Pipeline::with_dependencies(
+            config(),
+            Box::new(SlowPrefix {
+                fetcher: fetcher.clone(),
+                prefix: format!("{}files/iue/", testing::BASE_URL),
+            }),
+            Cache::disabled(),
+            State::default(),
+            Box::new(clock.clone()),
+            Box::new(testing::FakeSleeper::new(&clock)),
+        )
+        .unwrap();
+        let paths: Vec<&str> = slow.iter().chain(&fast).copied().collect();
+
+        let downloads = pipeline.download(links(&paths));
+
+        let url = |path: &str| format!("{}{path}", testing::BASE_URL.trim_end_matches('/'));
+        let requests = fetcher.requests();
+        let (first_requests, last_requests) = requests.split_at(fast.len());
+        assert_eq!(
+            last_requests,
+            slow.map(url),
+            "the slow department should finish last"
+        );
+        assert_eq!(
+            first_requests.iter().cloned().collect::<BTreeSet<_>>(),
+            fast.iter().copied().map(url).collect::<BTreeSet<_>>(),
+            "the other departments should be done while the slow one is still downloading"
+        );
+        assert_eq!(
+            downloads
+                .iter()
+                .map(|download| download.link.url.clone())
+                .collect::<Vec<_>>(),
+            paths.iter().copied().map(url).collect::<Vec<_>>(),
+            "the downloads should be in the order of the links"
+        );
+        let stats = &pipeline.diagnostics.department_stats;
+        assert_eq!(
+            ["iue", "mbau", "medien"]
+                .map(|department| stats.get(department).map(|stats| stats.downloads.network)),
+            [Some(3), Some(2), Some(2)],
+            "the downloads should be counted per department"
+        );
+        assert_eq!(
+            pipeline.diagnostics.run_stats.downloads.network, 7,
+            "the totals should count the downloads of every department"
+        );
+    }
+
+    #[test]
+    fn prefixes_with_a_longer_delay_are_requested_more_slowly() {
+        let paths = [
+            "/files/iue/WiSe_2425/semester_1/a.ics",
+            "/files/mbau/WiSe_2425/semester_1/a.ics",
+        ];
+        // Missing files are requested more slowly just the same
+        let missing = "/files/mbau/WiSe_2425/semester_1/missing.ics";
+        let clock = FakeClock::new();
+        let mut fetcher = FakeFetcher::new(&clock);
+        for path in paths {
+            fetcher = fetcher.page(path, &testing::calendar(&[("Mathe", "20241014T081500")]));
+        }
+        let config = Config {
+            min_delay: Duration::from_secs(1),
+            initial_burst: 0,
+            prefix_delays: vec![(
+                format!("{}files/mbau/", testing::BASE_URL),
+                Duration::from_secs(10),
+            )],
+            ..config()
+        };
+        let mut pipeline = testing::pipeline(config, &fetcher, &clock);
+        let start = clock.now();
+
+        pipeline.download(links(&[paths[0], paths[1], missing]));
+
+        // The departments are downloaded by different workers
+        let delays: BTreeSet<(String, Option<u128>)> = pipeline
+            .diagnostics
+            .fetch_records()
+            .iter()
+            .map(|record| (record.url.clone(), record.delay_ms))
+            .collect();
+        let url = |path: &str| format!("{}{path}", testing::BASE_URL.trim_end_matches('/'));
+        assert_eq!(
+            delays,
+            BTreeSet::from([
+                (url(paths[0]), Some(1_000)),
+                (url(paths[1]), Some(10_000)),
+                (url(missing), Some(10_000)),
+            ]),
+            "the delay after each request should be recorded"
+        );
+        assert_eq!(
+            clock.now() - start,
+            Duration::from_secs(21),
+            "the run should have waited the delay of each prefix"
+        );
+    }
+
+    #[test]
+    fn retries_stop_before_waiting_past_the_deadline() {
+        let path = "/files/iue/WiSe_2425/semester_1/a.ics";
+        let clock = FakeClock::new();
+        let fetcher = FakeFetcher::new(&clock).respond(path, testing::response(path, 503, ""));
+        let config = Config {
+            max_runtime: Some(DOWNLOAD_RETRY_DELAY * 3),
+            ..config()
+        };
+        let mut pipeline = testing::pipeline(config, &fetcher, &clock);
+
+        let downloads = pipeline.download(links(&[path]));
+
+        assert!(downloads.is_empty(), "nothing should be downloaded");
+        assert_eq!(
+            fetcher.requests().len(),
+            3,
+            "only the retries fitting before the deadline should be sent"
+        );
+    }
+}
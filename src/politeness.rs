@@ -0,0 +1,302 @@
+//! Delays between requests which adapt to how fast the server responds
+
+use crate::prelude::*;
+use serde::Serialize;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, PoisonError};
+use std::time::Duration;
+
+/// Share of the wall time our requests may occupy unless overridden, in percent.
+pub const DEFAULT_DUTY_CYCLE: u32 = 20;
+
+/// Shortest delay between two requests unless overridden.
+pub const DEFAULT_MIN_DELAY: Duration = Duration::from_secs(1);
+
+/// Longest delay between two requests unless overridden.
+pub const DEFAULT_MAX_DELAY: Duration = Duration::from_secs(60);
+
+/// Delay between the requests of the initial burst unless overridden.
+pub const DEFAULT_BURST_DELAY: Duration = Duration::from_millis(500);
+
+/// Returns how long to wait after a request which took `response_time`, so requests occupy at
+/// most `duty_cycle` percent of the wall time, bounded by `min_delay` and `max_delay`.
+pub fn next_delay(
+    response_time: Duration,
+    duty_cycle: u32,
+    min_delay: Duration,
+    max_delay: Duration,
+) -> Duration {
+    // A request taking r followed by a delay d occupies r / (r + d) of the time
+    let duty_cycle = duty_cycle.clamp(1, 100);
+    let delay = response_time
+        .checked_mul(100 - duty_cycle)
+        .map_or(max_delay, |busy| busy / duty_cycle);
+
+    delay.max(min_delay).min(max_delay.max(min_delay))
+}
+
+/// Summary of the delays chosen during a run.
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct DelaySummary {
+    pub count: usize,
+    pub total_ms: u128,
+    pub min_ms: u128,
+    pub max_ms: u128,
+}
+
+/// Chooses the delays between requests and remembers them for the report.
+#[derive(Debug)]
+pub struct AdaptiveDelay {
+    duty_cycle: u32,
+    min_delay: Duration,
+    max_delay: Duration,
+    /// Number of requests at the start which wait `burst_delay` only.
+    burst_requests: usize,
+    burst_delay: Duration,
+    /// Shortest delays after requests to URLs starting with the prefix, replacing `min_delay`.
+    prefix_overrides: Vec<(String, Duration)>,
+    /// Number of requests seen so far.
+    requests: AtomicUsize,
+    chosen: Mutex<Vec<Duration>>,
+}
+
+impl AdaptiveDelay {
+    pub const fn new(duty_cycle: u32, min_delay: Duration, max_delay: Duration) -> Self {
+        Self {
+            duty_cycle,
+            min_delay,
+            max_delay,
+            burst_requests: 0,
+            burst_delay: DEFAULT_BURST_DELAY,
+            prefix_overrides: Vec::new(),
+            requests: AtomicUsize::new(0),
+            chosen: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Waits only `burst_delay` after each of the first `burst_requests` requests.
+    pub const fn with_initial_burst(
+        mut self,
+        burst_requests: usize,
+        burst_delay: Duration,
+    ) -> Self {
+        self.burst_requests = burst_requests;
+        self.burst_delay = burst_delay;
+        self
+    }
+
+    /// Waits at least the given delay after requests to URLs starting with its prefix instead of
+    /// `min_delay`, the longest matching prefix wins.
+    pub fn with_prefix_overrides(mut self, prefix_overrides: Vec<(String, Duration)>) -> Self {
+        self.prefix_overrides = prefix_overrides;
+        self
+    }
+
+    /// Returns the shortest delay after a request to `url`, from the override with the longest
+    /// matching prefix or the global one.
+    pub fn min_delay_for(&self, url: &str) -> Duration {
+        self.override_for(url).unwrap_or(self.min_delay)
+    }
+
+    fn override_for(&self, url: &str) -> Option<Duration> {
+        self.prefix_overrides
+            .iter()
+            .filter(|(prefix, _)| url.starts_with(prefix.as_str()))
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map(|(_, min_delay)| *min_delay)
+    }
+
+    /// Returns the delay to wait after a request to `url` which took `response_time`.
+    pub fn after_response(&self, url: &str, response_time: Duration) -> Duration {
+        let requests = self.requests.fetch_add(1, Ordering::Relaxed) + 1;
+
+        // The initial burst doesn't apply to prefixes which asked for slower access
+        let prefix_override = self.override_for(url);
+        let delay = if requests <= self.burst_requests && prefix_override.is_none() {
+            self.burst_delay
+        } else {
+            if requests == self.burst_requests + 1 && self.burst_requests > 0 {
+                info!(
+                    "Initial burst of {} requests done, continuing with the normal delays",
+                    self.burst_requests
+                );
+            }
+
+            next_delay(
+                response_time,
+                self.duty_cycle,
+                prefix_override.unwrap_or(self.min_delay),
+                self.max_delay,
+            )
+        };
+        self.chosen
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(delay);
+
+        delay
+    }
+
+    pub fn summarize(&self) -> DelaySummary {
+        let chosen = self.chosen.lock().unwrap_or_else(PoisonError::into_inner);
+
+        DelaySummary {
+            count: chosen.len(),
+            total_ms: chosen.iter().map(Duration::as_millis).sum(),
+            min_ms: chosen.iter().min().map_or(0, Duration::as_millis),
+            max_ms: chosen.iter().max().map_or(0, Duration::as_millis),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const fn millis(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    #[test]
+    fn delays_follow_the_response_times() {
+        // (response time, duty cycle, expected delay) with a floor of 1s and a ceiling of 60s
+        let table = [
+            (millis(50), 20, millis(1_000)),
+            (millis(500), 20, millis(2_000)),
+            (millis(2_000), 20, millis(8_000)),
+            (millis(5_000), 20, millis(20_000)),
+            (millis(20_000), 20, millis(60_000)),
+            (millis(2_000), 50, millis(2_000)),
+            (millis(2_000), 100, millis(1_000)),
+            (millis(2_000), 0, millis(60_000)),
+            (Duration::MAX, 20, millis(60_000)),
+        ];
+
+        for (response_time, duty_cycle, expected) in table {
+            assert_eq!(
+                next_delay(response_time, duty_cycle, millis(1_000), millis(60_000)),
+                expected,
+                "a response after {response_time:?} at {duty_cycle}%"
+            );
+        }
+    }
+
+    #[test]
+    fn the_floor_wins_over_a_lower_ceiling() {
+        assert_eq!(
+            next_delay(millis(5_000), 20, millis(10_000), millis(5_000)),
+            millis(10_000),
+            "the floor should win if the bounds contradict each other"
+        );
+    }
+
+    #[test]
+    fn chosen_delays_are_summarized() {
+        let delay = AdaptiveDelay::new(20, millis(1_000), millis(60_000))
+            .with_initial_burst(1, millis(100));
+
+        let chosen = [
+            delay.after_response("https://fh-kalender.test/", millis(5_000)),
+            delay.after_response("https://fh-kalender.test/", millis(500)),
+            delay.after_response("https://fh-kalender.test/", millis(50)),
+        ];
+        let summary = delay.summarize();
+
+        assert_eq!(
+            chosen,
+            [millis(100), millis(2_000), millis(1_000)],
+            "the first request should only wait the burst delay"
+        );
+        assert_eq!(
+            (
+                summary.count,
+                summary.total_ms,
+                summary.min_ms,
+                summary.max_ms
+            ),
+            (3, 3_100, 100, 2_000),
+            "the summary should cover every chosen delay"
+        );
+    }
+
+    #[test]
+    fn the_longest_matching_prefix_wins() {
+        let delay =
+            AdaptiveDelay::new(20, millis(1_000), millis(60_000)).with_prefix_overrides(vec![
+                (
+                    "https://fh-kalender.test/files/mbau/".to_owned(),
+                    millis(10_000),
+                ),
+                ("https://fh-kalender.test/files/".to_owned(), millis(3_000)),
+                (
+                    "https://fh-kalender.test/files/mbau/WiSe_2425/".to_owned(),
+                    millis(5_000),
+                ),
+            ]);
+
+        let table = [
+            ("https://fh-kalender.test/", millis(1_000)),
+            ("https://fh-kalender.test/files/iue/a.ics", millis(3_000)),
+            ("https://fh-kalender.test/files/mbau/a.ics", millis(10_000)),
+            (
+                "https://fh-kalender.test/files/mbau/WiSe_2425/a.ics",
+                millis(5_000),
+            ),
+            ("https://mirror.fh-kalender.test/files/", millis(1_000)),
+        ];
+        for (url, expected) in table {
+            assert_eq!(
+                delay.min_delay_for(url),
+                expected,
+                "the delay after a request to '{url}'"
+            );
+        }
+    }
+
+    #[test]
+    fn overridden_prefixes_skip_the_initial_burst() {
+        let delay = AdaptiveDelay::new(20, millis(1_000), millis(60_000))
+            .with_initial_burst(2, millis(100))
+            .with_prefix_overrides(vec![(
+                "https://fh-kalender.test/files/mbau/".to_owned(),
+                millis(10_000),
+            )]);
+
+        assert_eq!(
+            [
+                delay.after_response("https://fh-kalender.test/files/mbau/a.ics", millis(50)),
+                delay.after_response("https://fh-kalender.test/files/iue/a.ics", millis(50)),
+                delay.after_response("https://fh-kalender.test/files/iue/b.ics", millis(50)),
+            ],
+            [millis(10_000), millis(100), millis(1_000)],
+            "the override should apply even during the initial burst"
+        );
+    }
+
+    #[test]
+    fn the_end_of_the_initial_burst_is_logged() {
+        let delay = AdaptiveDelay::new(20, millis(1_000), millis(60_000))
+            .with_initial_burst(2, millis(500));
+        let logs = crate::testing::CapturedLogs::default();
+
+        let chosen = tracing::subscriber::with_default(logs.subscriber(), || {
+            [millis(50), millis(50), millis(50)].map(|response_time| {
+                delay.after_response("https://fh-kalender.test/", response_time)
+            })
+        });
+
+        assert_eq!(
+            chosen,
+            [millis(500), millis(500), millis(1_000)],
+            "the third request should wait the normal delay"
+        );
+        let switches = logs
+            .lines()
+            .iter()
+            .filter(|line| {
+                line.contains("Initial burst of 2 requests done, continuing with the normal delays")
+            })
+            .count();
+        assert_eq!(switches, 1, "the switch should be logged once");
+    }
+}
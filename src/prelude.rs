@@ -7,4 +7,4 @@
 pub type Result<T> = color_eyre::Result<T, Error>;
 
 // Re-export tracing macros for convenience
-pub use tracing::{error, info, warn};
+pub use tracing::{debug, error, info, warn};
@@ -0,0 +1,195 @@
+//! Reading and rewriting event properties without losing their parameters
+//!
+//! Properties like `DTSTART;TZID=Europe/Berlin:...` or `DTSTART;VALUE=DATE:...` change their
+//! meaning when the parameters get lost, so every rewrite keeps them.
+
+use ical::parser::ical::component::IcalEvent;
+use ical::property::Property;
+
+/// Returns the value of the first property called `name`.
+pub fn get_value<'a>(event: &'a IcalEvent, name: &str) -> Option<&'a str> {
+    event
+        .properties
+        .iter()
+        .find(|p| p.name == name)
+        .and_then(|p| p.value.as_deref())
+}
+
+/// Returns the first value of the parameter `param` of the first property called `name`.
+/// Parameter names are compared case-insensitively.
+pub fn get_param<'a>(event: &'a IcalEvent, name: &str, param: &str) -> Option<&'a str> {
+    event
+        .properties
+        .iter()
+        .find(|p| p.name == name)
+        .and_then(|p| p.params.as_ref())
+        .and_then(|params| {
+            params
+                .iter()
+                .find(|(param_name, _)| param_name.eq_ignore_ascii_case(param))
+        })
+        .and_then(|(_, values)| values.first())
+        .map(String::as_str)
+}
+
+/// Replaces the value of the first property called `name`, keeping its parameters. Returns
+/// `false` if there is no such property.
+pub fn set_value_preserving_params(event: &mut IcalEvent, name: &str, value: String) -> bool {
+    let Some(property) = event.properties.iter_mut().find(|p| p.name == name) else {
+        return false;
+    };
+
+    property.value = Some(value);
+    true
+}
+
+/// Sets the value of the property called `name`, adding it without parameters if it is missing.
+/// Duplicates of the property are removed, the parameters of the first one are kept.
+pub fn upsert_property(event: &mut IcalEvent, name: &str, value: String) {
+    let mut found = false;
+    event.properties.retain_mut(|p| {
+        if p.name != name {
+            return true;
+        }
+        if found {
+            return false;
+        }
+
+        found = true;
+        p.value = Some(value.clone());
+        true
+    });
+
+    if !found {
+        event.properties.push(Property {
+            name: name.to_owned(),
+            params: None,
+            value: Some(value),
+        });
+    }
+}
+
+/// Removes every property called `name`. Returns `true` if anything was removed.
+pub fn remove_property(event: &mut IcalEvent, name: &str) -> bool {
+    let previous_len = event.properties.len();
+    event.properties.retain(|p| p.name != name);
+
+    event.properties.len() != previous_len
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn property(name: &str, params: &[(&str, &str)], value: &str) -> Property {
+        Property {
+            name: name.to_owned(),
+            params: Some(
+                params
+                    .iter()
+                    .map(|(param, value)| ((*param).to_owned(), vec![(*value).to_owned()]))
+                    .collect(),
+            ),
+            value: Some(value.to_owned()),
+        }
+    }
+
+    /// Event starting with a time zone and ending on a date, both properties with parameters.
+    fn event() -> IcalEvent {
+        let mut event = IcalEvent::new();
+        event.properties = vec![
+            property("DTSTART", &[("TZID", "Europe/Berlin")], "20241014T081500"),
+            property("DTEND", &[("VALUE", "DATE")], "20241015"),
+            property("SUMMARY", &[], "Mathe"),
+        ];
+
+        event
+    }
+
+    /// Returns the `TZID` of the start and the `VALUE` of the end.
+    fn params(event: &IcalEvent) -> (Option<&str>, Option<&str>) {
+        (
+            get_param(event, "DTSTART", "tzid"),
+            get_param(event, "DTEND", "VALUE"),
+        )
+    }
+
+    #[test]
+    fn values_and_params_are_read_from_the_first_property() {
+        let mut event = event();
+        event.properties.push(property("SUMMARY", &[], "Physik"));
+
+        assert_eq!(get_value(&event, "SUMMARY"), Some("Mathe"), "first value");
+        assert_eq!(get_value(&event, "LOCATION"), None, "missing property");
+        assert_eq!(
+            params(&event),
+            (Some("Europe/Berlin"), Some("DATE")),
+            "parameter names should be case-insensitive"
+        );
+    }
+
+    #[test]
+    fn setting_values_keeps_the_params() {
+        let mut event = event();
+
+        assert!(
+            set_value_preserving_params(&mut event, "DTSTART", "20241021T081500".to_owned()),
+            "the start should be found"
+        );
+        assert!(
+            !set_value_preserving_params(&mut event, "LOCATION", "C-12.01".to_owned()),
+            "a missing property should not be added"
+        );
+        upsert_property(&mut event, "DTEND", "20241016".to_owned());
+
+        assert_eq!(
+            (get_value(&event, "DTSTART"), get_value(&event, "DTEND")),
+            (Some("20241021T081500"), Some("20241016")),
+            "the values should be replaced"
+        );
+        assert_eq!(
+            params(&event),
+            (Some("Europe/Berlin"), Some("DATE")),
+            "the parameters should survive"
+        );
+    }
+
+    #[test]
+    fn upsert_adds_missing_properties_and_removes_duplicates() {
+        let mut event = event();
+        event.properties.push(property("SUMMARY", &[], "Physik"));
+
+        upsert_property(&mut event, "SUMMARY", "Chemie".to_owned());
+        upsert_property(&mut event, "UID", "mathe-1".to_owned());
+
+        assert_eq!(
+            event
+                .properties
+                .iter()
+                .filter(|p| p.name == "SUMMARY")
+                .count(),
+            1,
+            "duplicates should be removed"
+        );
+        assert_eq!(get_value(&event, "SUMMARY"), Some("Chemie"), "new summary");
+        assert_eq!(get_value(&event, "UID"), Some("mathe-1"), "added UID");
+        assert_eq!(
+            params(&event),
+            (Some("Europe/Berlin"), Some("DATE")),
+            "other properties should be untouched"
+        );
+    }
+
+    #[test]
+    fn removing_a_property_keeps_the_others() {
+        let mut event = event();
+
+        assert!(remove_property(&mut event, "SUMMARY"), "summary removed");
+        assert!(!remove_property(&mut event, "SUMMARY"), "nothing left");
+        assert_eq!(
+            params(&event),
+            (Some("Europe/Berlin"), Some("DATE")),
+            "the other parameters should survive"
+        );
+    }
+}
@@ -0,0 +1,98 @@
+//! Machine-readable run report
+
+use crate::diagnostics::{
+    CrossValidationFailure, FetchRecord, IgnoredModule, IndexWriteFailure, SkippedLink,
+    UndatedEvent, WriteFailure,
+};
+use crate::politeness::DelaySummary;
+use crate::prelude::*;
+use crate::stats::{RunStats, SourceStats};
+use crate::validate::EventAnomaly;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// Everything worth knowing about a run, written as JSON for later inspection.
+#[derive(Debug, Default, Serialize)]
+pub struct Report {
+    /// Links to calendar files which were filtered out while scraping, with the reason.
+    pub skipped_links: Vec<SkippedLink>,
+    /// Event statistics keyed by the URL of the source calendar.
+    pub sources: BTreeMap<String, SourceStats>,
+    /// Modules dropped by the module ignore list, with their number of events.
+    pub ignored_modules: Vec<IgnoredModule>,
+    /// Modules whose calendar could not be written.
+    pub write_failures: Vec<WriteFailure>,
+    /// Files listing the calendars, like the index, which could not be written.
+    pub index_write_failures: Vec<IndexWriteFailure>,
+    /// Events without a DTSTART, together with their source.
+    pub undated_events: Vec<UndatedEvent>,
+    /// Anomalies in the source events, like duplicate properties or odd dates.
+    pub event_anomalies: Vec<EventAnomaly>,
+    /// Properties of the written calendars the `icalendar` crate reads differently.
+    pub cross_validation_failures: Vec<CrossValidationFailure>,
+    /// Response metadata of every fetched calendar file.
+    pub fetches: Vec<FetchRecord>,
+    /// Delays chosen between requests.
+    pub delays: DelaySummary,
+    /// How often each deduplicated warning occurred.
+    pub repeated_warnings: BTreeMap<String, usize>,
+    /// Counters of the whole run, matching the final log lines.
+    pub run_stats: RunStats,
+}
+
+impl Report {
+    /// Writes the report as pretty-printed JSON to `path`.
+    pub fn write(&self, path: &Path) -> Result<()> {
+        let json = serde_json::to_string_pretty(self)?;
+        std::fs::write(path, json)?;
+
+        Ok(())
+    }
+}
+
+/// Writes the fetch records as CSV to `path`, one line per calendar file.
+pub fn write_fetch_log(records: &[FetchRecord], path: &Path) -> Result<()> {
+    let mut csv = String::from(
+        "url,status,origin,content_length,last_modified,etag,content_encoding,encoded_size,decoded_size,delay_ms\n",
+    );
+
+    for record in records {
+        let status = record.status.map(|status| status.to_string());
+        let encoded_size = record.encoded_size.map(|size| size.to_string());
+        let decoded_size = record.decoded_size.map(|size| size.to_string());
+        let delay_ms = record.delay_ms.map(|delay| delay.to_string());
+        let fields = [
+            Some(record.url.as_str()),
+            status.as_deref(),
+            Some(record.origin.description()),
+            record.content_length.as_deref(),
+            record.last_modified.as_deref(),
+            record.etag.as_deref(),
+            record.content_encoding.as_deref(),
+            encoded_size.as_deref(),
+            decoded_size.as_deref(),
+            delay_ms.as_deref(),
+        ];
+        let line = fields
+            .iter()
+            .map(|field| csv_field(field.unwrap_or_default()))
+            .collect::<Vec<_>>()
+            .join(",");
+        csv.push_str(&line);
+        csv.push('\n');
+    }
+
+    std::fs::write(path, csv)?;
+
+    Ok(())
+}
+
+/// Quotes a CSV field if it contains a separator, quote or line break.
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_owned()
+    }
+}
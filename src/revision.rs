@@ -0,0 +1,320 @@
+//! Revision markers in the file names of calendars, like `Gruppe_1_Stand_12_03.ics`
+//!
+//! Some departments upload a revised plan as a new file next to the old one. Both would be
+//! aggregated, so only the newest revision of every file is kept.
+
+use crate::prelude::*;
+use crate::scrape::{self, SourceLink};
+use chrono::{Datelike, NaiveDate};
+use regex::Regex;
+use std::collections::BTreeMap;
+
+/// Regex matching the revision marker at the end of a file name without its extension unless
+/// overridden, e.g. `_Stand_12_03` or `-Stand-12.03.2025`.
+pub const DEFAULT_REVISION_PATTERN: &str =
+    r"(?i)[_-]Stand[_-](?P<day>\d{1,2})[_.-](?P<month>\d{1,2})(?:[_.-](?P<year>\d{4}|\d{2}))?$";
+
+/// A calendar file replaced by a newer revision.
+#[derive(Debug, Clone)]
+pub struct Superseded {
+    pub link: SourceLink,
+    /// URL of the newest revision.
+    pub newest_url: String,
+}
+
+/// Finds the revision markers in the file names of calendars.
+#[derive(Debug)]
+pub struct RevisionTracker {
+    pattern: Regex,
+}
+
+impl RevisionTracker {
+    /// Creates a tracker for markers matching `pattern`, which needs the named groups `day` and
+    /// `month` and may have a `year` group.
+    pub fn new(pattern: &str) -> Result<Self> {
+        let regex = Regex::new(pattern)
+            .map_err(|err| Error::InvalidRevisionPattern(pattern.to_owned(), err.to_string()))?;
+
+        for group in ["day", "month"] {
+            if !regex.capture_names().any(|name| name == Some(group)) {
+                return Err(Error::InvalidRevisionPattern(
+                    pattern.to_owned(),
+                    format!("missing the named group '{group}'"),
+                ));
+            }
+        }
+
+        Ok(Self { pattern: regex })
+    }
+
+    /// Keeps only the newest revision of every calendar file and returns the superseded ones.
+    /// A file without a marker is the oldest revision of its name, so it is only kept if there
+    /// is no marked revision. Pinned files are always kept, the order of `links` is kept as well.
+    ///
+    /// Markers without a year are taken as the most recent such date up to `today`, since a plan
+    /// can't be revised in the future.
+    pub fn keep_newest(
+        &self,
+        links: Vec<SourceLink>,
+        today: NaiveDate,
+    ) -> (Vec<SourceLink>, Vec<Superseded>) {
+        let revisions: Vec<Option<(String, Option<NaiveDate>)>> = links
+            .iter()
+            .map(|link| {
+                if link.pinned {
+                    return None;
+                }
+
+                self.revision(&link.url, today)
+            })
+            .collect();
+
+        // Newest revision of every base name, the first one wins a tie. `None` sorts before every
+        // date, so files without a marker lose against every marked revision.
+        let mut newest: BTreeMap<&str, (Option<NaiveDate>, usize)> = BTreeMap::new();
+        for (position, revision) in revisions.iter().enumerate() {
+            let Some((base, date)) = revision else {
+                continue;
+            };
+
+            let current = newest.entry(base).or_insert((*date, position));
+            if *date > current.0 {
+                *current = (*date, position);
+            }
+        }
+
+        // URL of the newest revision for every link which isn't the newest itself
+        let newer_urls: Vec<Option<String>> = revisions
+            .iter()
+            .enumerate()
+            .map(|(position, revision)| {
+                let (base, _) = revision.as_ref()?;
+                let (_, newest_position) = newest.get(base.as_str())?;
+                if *newest_position == position {
+                    return None;
+                }
+
+                links.get(*newest_position).map(|link| link.url.clone())
+            })
+            .collect();
+
+        let mut kept = Vec::with_capacity(links.len());
+        let mut superseded = Vec::new();
+        for (link, newer_url) in links.into_iter().zip(newer_urls) {
+            match newer_url {
+                Some(newest_url) => superseded.push(Superseded { link, newest_url }),
+                None => kept.push(link),
+            }
+        }
+
+        (kept, superseded)
+    }
+
+    /// Returns the URL without the revision marker, as key shared by all revisions, and the date
+    /// of the revision. The date is `None` if the file name has no valid marker, which makes it
+    /// older than every dated revision. `None` if the URL isn't a calendar file.
+    fn revision(&self, url: &str, today: NaiveDate) -> Option<(String, Option<NaiveDate>)> {
+        let url = scrape::normalize_link(url, false);
+        if !scrape::is_ics_url(url) {
+            return None;
+        }
+
+        Some(self.marked_revision(url, today).map_or_else(
+            || (url.to_lowercase(), None),
+            |(base, date)| (base, Some(date)),
+        ))
+    }
+
+    /// Like `revision`, but `None` if the file name of the calendar file `url` has no valid
+    /// marker.
+    fn marked_revision(&self, url: &str, today: NaiveDate) -> Option<(String, NaiveDate)> {
+        let stem_end = url.len().checked_sub(".ics".len())?;
+        let stem = url.get(..stem_end)?;
+        let file_name_start = stem.rfind('/').map_or(0, |slash| slash + 1);
+
+        let captures = self.pattern.captures(stem.get(file_name_start..)?)?;
+        let marker = captures.get(0)?;
+        let number = |group: &str| -> Option<u32> { captures.name(group)?.as_str().parse().ok() };
+        let day = number("day")?;
+        let month = number("month")?;
+
+        let date = match number("year") {
+            // Two digit years like `12_03_25`
+            Some(year) => {
+                let year = if year < 100 { year + 2000 } else { year };
+                NaiveDate::from_ymd_opt(i32::try_from(year).ok()?, month, day)?
+            }
+            None => NaiveDate::from_ymd_opt(today.year(), month, day)
+                .filter(|date| *date <= today)
+                .or_else(|| NaiveDate::from_ymd_opt(today.year() - 1, month, day))?,
+        };
+
+        let marker_start = file_name_start + marker.start();
+        let marker_end = file_name_start + marker.end();
+        let base = format!(
+            "{}{}{}",
+            stem.get(..marker_start)?,
+            stem.get(marker_end..)?,
+            url.get(stem_end..)?
+        )
+        .to_lowercase();
+
+        Some((base, date))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FOLDER: &str = "/files/iue/WiSe_2425/semester_1/";
+
+    fn links(file_names: &[&str]) -> Vec<SourceLink> {
+        file_names
+            .iter()
+            .map(|file_name| {
+                SourceLink::new("https://fh-kalender.test", &format!("{FOLDER}{file_name}"))
+                    .unwrap()
+            })
+            .collect()
+    }
+
+    fn file_names(links: &[SourceLink]) -> Vec<&str> {
+        links
+            .iter()
+            .map(|link| link.path.trim_start_matches(FOLDER))
+            .collect()
+    }
+
+    fn keep_newest(links: Vec<SourceLink>, today: &str) -> (Vec<SourceLink>, Vec<Superseded>) {
+        RevisionTracker::new(DEFAULT_REVISION_PATTERN)
+            .unwrap()
+            .keep_newest(links, today.parse().unwrap())
+    }
+
+    #[test]
+    fn only_the_newest_revision_of_a_file_is_kept() {
+        let (kept, superseded) = keep_newest(
+            links(&[
+                "Gruppe_1_Stand_12_03.ics",
+                "Gruppe_2.ics",
+                "Gruppe_1_Stand_19_03.ics",
+            ]),
+            "2025-03-20",
+        );
+
+        assert_eq!(
+            file_names(&kept),
+            ["Gruppe_2.ics", "Gruppe_1_Stand_19_03.ics"],
+            "the newest revision and the unrelated file should be kept in order"
+        );
+        assert_eq!(
+            superseded
+                .iter()
+                .map(|superseded| (
+                    superseded.link.path.trim_start_matches(FOLDER),
+                    superseded.newest_url.as_str()
+                ))
+                .collect::<Vec<_>>(),
+            [(
+                "Gruppe_1_Stand_12_03.ics",
+                "https://fh-kalender.test/files/iue/WiSe_2425/semester_1/Gruppe_1_Stand_19_03.ics"
+            )],
+            "the older revision should be superseded by the newest one"
+        );
+    }
+
+    #[test]
+    fn revisions_are_grouped_by_the_name_without_the_marker() {
+        for (file_name, base) in [
+            ("Gruppe_1_Stand_12_03.ics", "gruppe_1.ics"),
+            ("Gruppe_1-Stand-12.03.2025.ics", "gruppe_1.ics"),
+            ("gruppe_1_stand_1_3_25.ics", "gruppe_1.ics"),
+            ("Gruppe_1.ics", "gruppe_1.ics"),
+            ("Gruppe_1_Stand_32_03.ics", "gruppe_1_stand_32_03.ics"),
+        ] {
+            let link = links(&[file_name]).remove(0);
+            let (key, _) = RevisionTracker::new(DEFAULT_REVISION_PATTERN)
+                .unwrap()
+                .revision(&link.url, "2025-03-20".parse().unwrap())
+                .unwrap();
+
+            assert_eq!(
+                key.rsplit('/').next(),
+                Some(base),
+                "'{file_name}' should be grouped under '{base}'"
+            );
+        }
+    }
+
+    #[test]
+    fn a_file_without_a_marker_is_the_oldest_revision() {
+        let (kept, superseded) = keep_newest(
+            links(&["Gruppe_1.ics", "Gruppe_1_Stand_12_03.ics", "Gruppe_2.ics"]),
+            "2025-03-20",
+        );
+
+        assert_eq!(
+            file_names(&kept),
+            ["Gruppe_1_Stand_12_03.ics", "Gruppe_2.ics"],
+            "the marked revision and the file without revisions should be kept"
+        );
+        assert_eq!(
+            superseded
+                .iter()
+                .map(|superseded| superseded.link.path.trim_start_matches(FOLDER))
+                .collect::<Vec<_>>(),
+            ["Gruppe_1.ics"],
+            "the file without a marker should be superseded"
+        );
+    }
+
+    #[test]
+    fn markers_without_a_year_are_not_in_the_future() {
+        let file_names_of_kept = |today: &str| {
+            let (kept, _) = keep_newest(
+                links(&["Gruppe_1_Stand_20_12.ics", "Gruppe_1_Stand_10_03.ics"]),
+                today,
+            );
+            file_names(&kept)
+                .into_iter()
+                .map(str::to_owned)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            file_names_of_kept("2025-03-15"),
+            ["Gruppe_1_Stand_10_03.ics"],
+            "March after December should be newer"
+        );
+        assert_eq!(
+            file_names_of_kept("2025-03-05"),
+            ["Gruppe_1_Stand_20_12.ics"],
+            "a March date still ahead should be taken from the year before"
+        );
+    }
+
+    #[test]
+    fn pinned_files_are_always_kept() {
+        let mut links = links(&["Gruppe_1_Stand_12_03.ics", "Gruppe_1_Stand_19_03.ics"]);
+        for link in &mut links {
+            link.pinned = true;
+        }
+
+        let (kept, superseded) = keep_newest(links, "2025-03-20");
+
+        assert_eq!(kept.len(), 2, "both pinned revisions should be kept");
+        assert!(superseded.is_empty(), "nothing should be superseded");
+    }
+
+    #[test]
+    fn patterns_without_the_date_groups_are_rejected() {
+        assert!(
+            matches!(
+                RevisionTracker::new(r"_Stand_(?P<day>\d+)$"),
+                Err(Error::InvalidRevisionPattern(..))
+            ),
+            "a pattern without a month should be rejected"
+        );
+    }
+}
@@ -0,0 +1,192 @@
+//! Minimal robots.txt parser
+
+/// Allow and disallow rules of the robots.txt group that applies to us.
+#[derive(Debug, Default)]
+pub struct Robots {
+    rules: Vec<Rule>,
+}
+
+#[derive(Debug)]
+struct Rule {
+    allow: bool,
+    pattern: String,
+}
+
+impl Robots {
+    /// Rules which allow everything, used when no robots.txt could be fetched.
+    pub fn allow_all() -> Self {
+        Self::default()
+    }
+
+    /// Parses `content` and keeps the rules for `user_agent`, falling back to the `*` group.
+    pub fn parse(content: &str, user_agent: &str) -> Self {
+        let user_agent = user_agent.to_lowercase();
+
+        let mut specific_rules: Option<Vec<Rule>> = None;
+        let mut wildcard_rules: Option<Vec<Rule>> = None;
+
+        let mut group_agents: Vec<String> = Vec::new();
+        let mut group_rules: Vec<Rule> = Vec::new();
+        let mut in_rules = false;
+
+        let mut finish_group = |agents: &[String], rules: Vec<Rule>| {
+            if agents.iter().any(|agent| {
+                !agent.is_empty() && agent != "*" && user_agent.contains(agent.as_str())
+            }) {
+                specific_rules.get_or_insert_with(Vec::new).extend(rules);
+            } else if agents.iter().any(|agent| agent == "*") {
+                wildcard_rules.get_or_insert_with(Vec::new).extend(rules);
+            }
+        };
+
+        for line in content.lines() {
+            // Strip comments
+            let line = line.split('#').next().unwrap_or_default().trim();
+            let Some((field, value)) = line.split_once(':') else {
+                continue;
+            };
+            let field = field.trim().to_lowercase();
+            let value = value.trim();
+
+            match field.as_str() {
+                "user-agent" => {
+                    // A user-agent line after rules starts a new group
+                    if in_rules {
+                        finish_group(&group_agents, std::mem::take(&mut group_rules));
+                        group_agents.clear();
+                        in_rules = false;
+                    }
+                    group_agents.push(value.to_lowercase());
+                }
+                "allow" | "disallow" => {
+                    in_rules = true;
+
+                    // An empty disallow means everything is allowed
+                    if !value.is_empty() {
+                        group_rules.push(Rule {
+                            allow: field == "allow",
+                            pattern: value.to_owned(),
+                        });
+                    }
+                }
+                _ => {}
+            }
+        }
+        finish_group(&group_agents, group_rules);
+
+        Self {
+            rules: specific_rules.or(wildcard_rules).unwrap_or_default(),
+        }
+    }
+
+    /// Checks whether `path` may be fetched. The longest matching rule wins, on a tie allow wins.
+    pub fn is_allowed(&self, path: &str) -> bool {
+        self.rules
+            .iter()
+            .filter(|rule| pattern_matches(&rule.pattern, path))
+            .max_by_key(|rule| (rule.pattern.len(), rule.allow))
+            .map_or(true, |rule| rule.allow)
+    }
+}
+
+/// Matches a robots.txt path pattern supporting `*` wildcards and a trailing `$` anchor.
+fn pattern_matches(pattern: &str, path: &str) -> bool {
+    let (pattern, anchored) = pattern
+        .strip_suffix('$')
+        .map_or((pattern, false), |pattern| (pattern, true));
+
+    let mut parts = pattern.split('*');
+    let Some(first) = parts.next() else {
+        return true;
+    };
+    let Some(mut rest) = path.strip_prefix(first) else {
+        return false;
+    };
+
+    let parts: Vec<&str> = parts.collect();
+    for (index, part) in parts.iter().enumerate() {
+        let is_last = index + 1 == parts.len();
+
+        // The last part of an anchored pattern has to match the end of the path
+        if is_last && anchored {
+            return rest.ends_with(part);
+        }
+
+        match rest.find(part) {
+            Some(position) => rest = rest.get(position + part.len()..).unwrap_or_default(),
+            None => return false,
+        }
+    }
+
+    !anchored || rest.is_empty()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ROBOTS_TXT: &str = "\
+# Comments are ignored
+User-agent: *
+Disallow: /files/
+Allow: /files/public/
+
+User-agent: fh_kiel_ical_splitter
+Disallow: /files/*.ics$
+Allow: /files/iue/
+";
+
+    #[test]
+    fn the_group_of_our_user_agent_is_preferred() {
+        let robots = Robots::parse(ROBOTS_TXT, "fh_kiel_ical_splitter/0.1.0");
+
+        assert!(
+            robots.is_allowed("/files/other/a.html"),
+            "the rules of the wildcard group should not apply"
+        );
+        assert!(
+            !robots.is_allowed("/files/other/a.ics"),
+            "the anchored wildcard pattern should match"
+        );
+        assert!(
+            robots.is_allowed("/files/other/a.ics?week=1"),
+            "the anchored pattern should only match at the end"
+        );
+    }
+
+    #[test]
+    fn the_longest_matching_rule_wins() {
+        let robots = Robots::parse(ROBOTS_TXT, "other-bot");
+
+        assert!(
+            !robots.is_allowed("/files/iue/a.ics"),
+            "the disallow of the wildcard group should apply"
+        );
+        assert!(
+            robots.is_allowed("/files/public/a.ics"),
+            "the longer allow rule should win"
+        );
+        assert!(robots.is_allowed("/"), "unmatched paths should be allowed");
+    }
+
+    #[test]
+    fn allow_wins_a_tie() {
+        let robots = Robots::parse("User-agent: *\nDisallow: /a\nAllow: /a\n", "bot");
+
+        assert!(robots.is_allowed("/a"), "allow should win a tie");
+    }
+
+    #[test]
+    fn empty_disallow_and_missing_file_allow_everything() {
+        let robots = Robots::parse("User-agent: *\nDisallow:\n", "bot");
+
+        assert!(
+            robots.is_allowed("/files/a.ics"),
+            "an empty disallow should allow everything"
+        );
+        assert!(
+            Robots::allow_all().is_allowed("/files/a.ics"),
+            "everything should be allowed without a robots.txt"
+        );
+    }
+}
@@ -0,0 +1,646 @@
+//! Extraction of links from the scraped websites
+
+use crate::prelude::*;
+use once_cell::sync::Lazy;
+use quick_xml::events::Event;
+use quick_xml::Reader;
+use regex::Regex;
+use regex::RegexBuilder;
+use serde::Deserialize;
+use std::fmt::Write as _;
+use std::path::Path;
+
+/// A calendar file found while scraping, together with the components of its URL.
+#[derive(Debug, Clone)]
+pub struct SourceLink {
+    /// Base URL of the site the link was found on.
+    pub base_url: String,
+    /// Absolute URL of the calendar file.
+    pub url: String,
+    /// Path of the calendar file relative to the base URL, e.g. `/files/iue/WiSe_2425/...`.
+    pub path: String,
+    pub source_path: SourcePath,
+    /// Text of the link on the listing page, e.g. `1. Semester Elektrotechnik – Stand 12.03.`.
+    pub link_text: Option<String>,
+    /// Whether the link was pinned on the command line, so failing to download it isn't fatal.
+    pub pinned: bool,
+}
+
+impl SourceLink {
+    /// Creates the link to the calendar at `path` below `base_url`.
+    pub fn new(base_url: &str, path: &str) -> Result<Self> {
+        Ok(Self {
+            base_url: base_url.to_owned(),
+            url: base_url.to_owned() + path,
+            path: path.to_owned(),
+            source_path: extract_components_from_url(normalize_link(path, false))?,
+            link_text: None,
+            pinned: false,
+        })
+    }
+}
+
+/// Whether `url` points to a calendar file, ignoring any query or fragment.
+pub fn is_ics_url(url: &str) -> bool {
+    normalize_link(url, false).to_lowercase().ends_with(".ics")
+}
+
+/// Removes the fragment of a link, and its query unless `keep_query` is set. Different queries
+/// can select different versions of a file, so they are kept for downloading by default.
+pub fn normalize_link(link: &str, keep_query: bool) -> &str {
+    let end = if keep_query {
+        link.find('#')
+    } else {
+        link.find(['?', '#'])
+    };
+
+    end.and_then(|end| link.get(..end)).unwrap_or(link)
+}
+
+/// Returns the path of a link relative to `base_url`, or `None` if it points to another host.
+pub fn site_path(base_url: &str, link: &str) -> Option<String> {
+    if !link.contains("://") {
+        return Some(link.to_owned());
+    }
+
+    link.strip_prefix(base_url).map(|path| format!("/{path}"))
+}
+
+/// Components of the path of a source calendar, `/files/{department}/{semester}/{group_dir}/...`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourcePath {
+    /// Department abbreviation, e.g. `iue`.
+    pub department: String,
+    /// Semester the calendar belongs to, e.g. `WiSe_2425`.
+    pub semester: String,
+    /// Directory of the group within the semester, e.g. `semester_1`.
+    pub group_dir: String,
+}
+
+pub fn extract_components_from_url(url: &str) -> Result<SourcePath> {
+    // Sample link: /files/iue/WiSe_2425/semester_1/1_Sem_Elektrotechnik_Gruppe_1.ics
+    static URL_COMPONENTS_EXTRACT_REGEX: Lazy<Regex> = Lazy::new(|| {
+        RegexBuilder::new(r"/files/(.*?)/(.*?)/(.*?)/.*?\.ics")
+            .case_insensitive(true)
+            .build()
+            .unwrap()
+    });
+
+    let captures = URL_COMPONENTS_EXTRACT_REGEX
+        .captures(url)
+        .ok_or(Error::InvalidUrl(url.to_owned()))?;
+
+    let source_path = SourcePath {
+        department: captures.get(1).unwrap().as_str().to_owned(),
+        semester: captures.get(2).unwrap().as_str().to_owned(),
+        group_dir: captures.get(3).unwrap().as_str().to_owned(),
+    };
+
+    // The components end up in the output paths, so a crafted link must not leave the output
+    // folder
+    for component in [
+        &source_path.department,
+        &source_path.semester,
+        &source_path.group_dir,
+    ] {
+        if let Some(reason) = unsafe_component_reason(component) {
+            return Err(Error::UnsafePathComponent {
+                url: url.to_owned(),
+                component: component.clone(),
+                reason,
+            });
+        }
+    }
+
+    Ok(source_path)
+}
+
+/// Returns why a path component can't be used as a directory name, or `None` if it can. The
+/// component is percent-decoded first, since `%2e%2e` is `..` to anyone decoding the URL.
+fn unsafe_component_reason(component: &str) -> Option<&'static str> {
+    let decoded = percent_decode(component);
+
+    if decoded.trim().is_empty() {
+        return Some("it is empty");
+    }
+    if decoded.contains(['/', '\\']) {
+        return Some("it contains a path separator");
+    }
+    // Windows ignores trailing dots, so `...` is as bad as `..`
+    if decoded.chars().all(|character| character == '.') {
+        return Some("it refers to the current or a parent directory");
+    }
+    // Drive letters like `C:` and alternate data streams
+    if decoded.contains(':') {
+        return Some("it contains a drive or stream separator");
+    }
+    if decoded.chars().any(char::is_control) {
+        return Some("it contains a control character");
+    }
+
+    None
+}
+
+/// Decodes the `%XX` escapes of a URL component, leaving invalid escapes as they are.
+pub fn percent_decode(text: &str) -> String {
+    let mut decoded = Vec::with_capacity(text.len());
+    let mut rest = text.as_bytes();
+
+    while let Some((&byte, tail)) = rest.split_first() {
+        let escaped = tail
+            .get(..2)
+            .filter(|_| byte == b'%')
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+
+        if let Some(value) = escaped {
+            decoded.push(value);
+            rest = tail.get(2..).unwrap_or_default();
+        } else {
+            decoded.push(byte);
+            rest = tail;
+        }
+    }
+
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// Escapes everything but unreserved characters in a URL path segment.
+fn percent_encode(segment: &str) -> String {
+    let mut encoded = String::with_capacity(segment.len());
+    for byte in segment.bytes() {
+        if byte.is_ascii_alphanumeric() || matches!(byte, b'-' | b'.' | b'_' | b'~') {
+            encoded.push(char::from(byte));
+        } else {
+            // Writing to a string can't fail
+            write!(encoded, "%{byte:02X}").unwrap();
+        }
+    }
+
+    encoded
+}
+
+/// Lists the calendar files below the `files` folder of `folder`, which is laid out like the
+/// site. Returns their paths as they would appear on the site, e.g.
+/// `/files/iue/WiSe_2425/semester_1/1_Sem_Elektrotechnik_Gruppe_1.ics`, in sorted order.
+pub fn find_ics_files(folder: &Path) -> Result<Vec<String>> {
+    let mut paths = Vec::new();
+    let mut pending = vec![(folder.join("files"), String::from("/files"))];
+
+    while let Some((directory, site_path)) = pending.pop() {
+        for entry in std::fs::read_dir(&directory)? {
+            let entry = entry?;
+            let file_name = entry.file_name();
+            let Some(file_name) = file_name.to_str() else {
+                warn!(
+                    "Skipping '{}' because its name is not valid UTF-8",
+                    entry.path().display()
+                );
+                continue;
+            };
+            let path = format!("{site_path}/{}", percent_encode(file_name));
+
+            let file_type = entry.file_type()?;
+            if file_type.is_dir() {
+                pending.push((entry.path(), path));
+            } else if file_type.is_file() && is_ics_url(&path) {
+                paths.push(path);
+            }
+        }
+    }
+
+    paths.sort_unstable();
+    Ok(paths)
+}
+
+/// Id of the script element holding the department list as JSON unless overridden.
+pub const DEFAULT_DEPARTMENTS_JSON_ID: &str = "departments";
+
+/// A department in the list embedded as JSON.
+#[derive(Debug, Deserialize)]
+struct DepartmentEntry {
+    name: String,
+    slug: String,
+}
+
+/// Returns the slugs of the departments listed as JSON in the script element with the id
+/// `element_id`, or `None` if the page has no such element.
+pub fn extract_departments_from_json(
+    website_source: &str,
+    element_id: &str,
+) -> Result<Option<Vec<String>>> {
+    // Sample: <script id="departments" type="application/json">[{"name": "Informatik und Elektrotechnik", "slug": "informatik-elektrotechnik"}]</script>
+    let script_regex = RegexBuilder::new(&format!(
+        "<script[^>]*\\sid=\"{}\"[^>]*>(.*?)</script>",
+        regex::escape(element_id)
+    ))
+    .case_insensitive(true)
+    .dot_matches_new_line(true)
+    .build()?;
+
+    let Some(json) = script_regex
+        .captures(website_source)
+        .and_then(|captures| captures.get(1))
+    else {
+        return Ok(None);
+    };
+
+    let departments: Vec<DepartmentEntry> = serde_json::from_str(json.as_str())?;
+
+    Ok(Some(
+        departments
+            .into_iter()
+            .filter_map(|department| {
+                let slug = department.slug.trim_matches('/');
+                if slug.is_empty() {
+                    debug!("Ignoring department '{}' without a slug", department.name);
+                    return None;
+                }
+                // The slug ends up in the URLs of the department pages, so it must stay a single
+                // path component
+                if let Some(reason) = unsafe_component_reason(slug) {
+                    warn!(
+                        "Ignoring department '{}' with the slug '{slug}' because {reason}",
+                        department.name
+                    );
+                    return None;
+                }
+
+                Some(slug.to_owned())
+            })
+            .collect(),
+    ))
+}
+
+pub fn extract_department_links_from_website(website_source: &str) -> Vec<String> {
+    // Sample: <a href="/informatik-elektrotechnik" role="button" class="contrast" style="display: grid; place-items: center; margin-bottom: 1rem;"> Informatik und Elektrotechnik </a>
+    static DEPARTMENT_LINK_REGEX: Lazy<Regex> = Lazy::new(|| {
+        RegexBuilder::new("<a href=\"/([a-zA-Z-]+?)\" role=\"button\"")
+            .case_insensitive(true)
+            .build()
+            .unwrap()
+    });
+
+    let mut links = vec![];
+
+    DEPARTMENT_LINK_REGEX
+        .captures_iter(website_source)
+        .map(|c| c.extract())
+        .for_each(|(_, [link])| {
+            links.push(link.to_owned());
+        });
+
+    links
+}
+
+/// A link to a calendar file on a listing page.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IcsLink<'a> {
+    pub href: &'a str,
+    /// Text of the link without markup, `None` if it is empty.
+    pub text: Option<String>,
+}
+
+pub fn extract_ics_links_from_website(website_source: &str) -> Vec<IcsLink<'_>> {
+    // Sample: <a href="/files/iue/WiSe_2425/semester_1/1_Sem_Elektrotechnik_Gruppe_1.ics">1. Semester Elektrotechnik Gruppe 1 – Stand 12.03.</a>
+    // Links may carry a query or fragment: <a href="/files/iue/WiSe_2425/semester_1/Plan.ics?v=3">
+    static ICS_LINK_REGEX: Lazy<Regex> = Lazy::new(|| {
+        RegexBuilder::new("a href=\"([^\"]*?\\.ics(?:[?#][^\"]*)?)\"(?:[^>]*>(.*?)</a>)?")
+            .case_insensitive(true)
+            .dot_matches_new_line(true)
+            .build()
+            .unwrap()
+    });
+
+    ICS_LINK_REGEX
+        .captures_iter(website_source)
+        .filter_map(|captures| {
+            Some(IcsLink {
+                href: captures.get(1)?.as_str(),
+                text: captures
+                    .get(2)
+                    .map(|text| clean_link_text(text.as_str()))
+                    .filter(|text| !text.is_empty()),
+            })
+        })
+        .collect()
+}
+
+/// Removes markup from the text of a link, decodes the common entities and collapses whitespace.
+fn clean_link_text(text: &str) -> String {
+    static TAG_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new("<[^>]*>").unwrap());
+
+    TAG_REGEX
+        .replace_all(text, " ")
+        .replace("&nbsp;", " ")
+        .replace("&ndash;", "–")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&amp;", "&")
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Escapes text for use in HTML content and attribute values.
+pub fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+pub fn extract_internal_links_from_website(website_source: &str) -> Vec<&str> {
+    // Sample: <a href="/informatik-elektrotechnik/wise-2425">
+    static INTERNAL_LINK_REGEX: Lazy<Regex> = Lazy::new(|| {
+        RegexBuilder::new("a href=\"(/[^\"#?]*?)\"")
+            .case_insensitive(true)
+            .build()
+            .unwrap()
+    });
+
+    INTERNAL_LINK_REGEX
+        .captures_iter(website_source)
+        .map(|c| c.extract())
+        .map(|(_, [link])| link)
+        .filter(|link| !link.to_lowercase().ends_with(".ics"))
+        .collect()
+}
+
+/// Returns the paths of all calendar files listed in a sitemap which belong to `base_url`.
+pub fn extract_ics_paths_from_sitemap(sitemap_source: &str, base_url: &str) -> Result<Vec<String>> {
+    // Sample: <url><loc>https://fh-kalender.de/files/iue/WiSe_2425/semester_1/1_Sem_Elektrotechnik_Gruppe_1.ics</loc></url>
+    let mut reader = Reader::from_str(sitemap_source);
+    let mut paths = Vec::new();
+    let mut in_location = false;
+
+    loop {
+        let event = reader.read_event()?;
+
+        if let Event::Start(start) = &event {
+            in_location = start.local_name().as_ref() == b"loc";
+        } else if let Event::Text(text) = &event {
+            if !in_location {
+                continue;
+            }
+
+            let location = text.unescape()?;
+            let location = location.trim();
+            if let Some(path) = location.strip_prefix(base_url) {
+                if is_ics_url(path) {
+                    paths.push(format!("/{path}"));
+                }
+            }
+        } else if let Event::End(_) = &event {
+            in_location = false;
+        } else if matches!(event, Event::Eof) {
+            break;
+        }
+    }
+
+    Ok(paths)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn departments_page(json: &str) -> String {
+        format!(
+            "<html><body><script id=\"departments\" type=\"application/json\">{json}</script></body></html>"
+        )
+    }
+
+    #[test]
+    fn departments_are_read_from_the_json() {
+        let page = departments_page(
+            r#"[{"name": "Informatik und Elektrotechnik", "slug": "/informatik-elektrotechnik/"}, {"name": "Ohne", "slug": ""}]"#,
+        );
+
+        assert_eq!(
+            extract_departments_from_json(&page, DEFAULT_DEPARTMENTS_JSON_ID).unwrap(),
+            Some(vec!["informatik-elektrotechnik".to_owned()]),
+            "the slugs should be read without slashes and empty ones skipped"
+        );
+    }
+
+    #[test]
+    fn pages_without_or_with_malformed_json_fall_back() {
+        assert_eq!(
+            extract_departments_from_json("<html></html>", DEFAULT_DEPARTMENTS_JSON_ID).unwrap(),
+            None,
+            "a page without the element should have no JSON departments"
+        );
+        assert!(
+            extract_departments_from_json(
+                &departments_page(r#"[{"name": "Informatik""#),
+                DEFAULT_DEPARTMENTS_JSON_ID
+            )
+            .is_err(),
+            "malformed JSON should be an error the caller falls back on"
+        );
+    }
+
+    #[test]
+    fn department_slugs_leaving_the_site_are_skipped() {
+        let page = departments_page(
+            r#"[
+                {"name": "Informatik", "slug": "informatik-elektrotechnik"},
+                {"name": "Parent", "slug": ".."},
+                {"name": "Encoded", "slug": "%2e%2e"},
+                {"name": "Nested", "slug": "../etc"},
+                {"name": "Backslash", "slug": "..\\etc"}
+            ]"#,
+        );
+
+        assert_eq!(
+            extract_departments_from_json(&page, DEFAULT_DEPARTMENTS_JSON_ID).unwrap(),
+            Some(vec!["informatik-elektrotechnik".to_owned()]),
+            "only the safe slug should be kept"
+        );
+    }
+
+    /// Returns the component rejected in `path`, or `None` if the path was accepted.
+    fn rejected_component(path: &str) -> Option<String> {
+        match extract_components_from_url(path) {
+            Ok(_) => None,
+            Err(Error::UnsafePathComponent { component, .. }) => Some(component),
+            Err(err) => Some(format!("unexpected error: {err}")),
+        }
+    }
+
+    #[test]
+    fn the_components_of_a_link_are_extracted() {
+        let source_path =
+            extract_components_from_url("/files/iue/WiSe_2425/semester_1/Elektrotechnik.ics")
+                .unwrap();
+
+        assert_eq!(
+            [
+                source_path.department,
+                source_path.semester,
+                source_path.group_dir
+            ],
+            ["iue", "WiSe_2425", "semester_1"],
+            "department, semester and group directory should be extracted"
+        );
+    }
+
+    #[test]
+    fn parent_directory_components_are_rejected() {
+        for (path, component) in [
+            ("/files/../WiSe_2425/semester_1/a.ics", ".."),
+            ("/files/iue/../semester_1/a.ics", ".."),
+            ("/files/iue/WiSe_2425/../a.ics", ".."),
+            ("/files/iue/WiSe_2425/./a.ics", "."),
+            ("/files/iue/WiSe_2425/.../a.ics", "..."),
+        ] {
+            assert_eq!(
+                rejected_component(path).as_deref(),
+                Some(component),
+                "'{path}' should be rejected"
+            );
+        }
+    }
+
+    #[test]
+    fn percent_encoded_parent_directory_components_are_rejected() {
+        for (path, component) in [
+            ("/files/%2e%2e/WiSe_2425/semester_1/a.ics", "%2e%2e"),
+            ("/files/iue/%2E%2E/semester_1/a.ics", "%2E%2E"),
+            ("/files/iue/WiSe_2425/.%2e/a.ics", ".%2e"),
+            ("/files/iue/WiSe_2425/%2e%2e%5cetc/a.ics", "%2e%2e%5cetc"),
+        ] {
+            assert_eq!(
+                rejected_component(path).as_deref(),
+                Some(component),
+                "'{path}' should be rejected"
+            );
+        }
+    }
+
+    #[test]
+    fn backslash_components_are_rejected() {
+        for (path, component) in [
+            ("/files/..\\etc/WiSe_2425/semester_1/a.ics", "..\\etc"),
+            ("/files/iue/WiSe\\2425/semester_1/a.ics", "WiSe\\2425"),
+            ("/files/iue/WiSe_2425/semester%5C1/a.ics", "semester%5C1"),
+        ] {
+            assert_eq!(
+                rejected_component(path).as_deref(),
+                Some(component),
+                "'{path}' should be rejected"
+            );
+        }
+    }
+
+    #[test]
+    fn empty_drive_and_control_components_are_rejected() {
+        for (path, component) in [
+            ("/files/ /WiSe_2425/semester_1/a.ics", " "),
+            ("/files/C:/WiSe_2425/semester_1/a.ics", "C:"),
+            ("/files/iue/WiSe_2425/semester%0A1/a.ics", "semester%0A1"),
+        ] {
+            assert_eq!(
+                rejected_component(path).as_deref(),
+                Some(component),
+                "'{path}' should be rejected"
+            );
+        }
+    }
+
+    #[test]
+    fn components_with_dots_inside_are_accepted() {
+        assert_eq!(
+            rejected_component("/files/iue/WiSe_24.25/semester_1..2/a.ics"),
+            None,
+            "dots next to other characters are harmless"
+        );
+    }
+
+    #[test]
+    fn links_with_a_query_fragment_or_uppercase_extension_are_found() {
+        let page = r#"<a href="/files/iue/WiSe_2425/semester_1/Plan.ics?v=3">
+            <a href="/files/iue/WiSe_2425/semester_1/Plan.ics#section">
+            <a href="/files/iue/WiSe_2425/semester_1/Plan.ICS">
+            <a href="/files/iue/WiSe_2425/semester_1/Plan.pdf?v=3">"#;
+
+        assert_eq!(
+            extract_ics_links_from_website(page)
+                .iter()
+                .map(|link| link.href)
+                .collect::<Vec<_>>(),
+            [
+                "/files/iue/WiSe_2425/semester_1/Plan.ics?v=3",
+                "/files/iue/WiSe_2425/semester_1/Plan.ics#section",
+                "/files/iue/WiSe_2425/semester_1/Plan.ICS",
+            ],
+            "every calendar link should be found"
+        );
+    }
+
+    #[test]
+    fn fragments_are_removed_and_queries_kept_on_request() {
+        for (link, keep_query, expected) in [
+            ("/a.ics?v=3", true, "/a.ics?v=3"),
+            ("/a.ics?v=3", false, "/a.ics"),
+            ("/a.ics#section", true, "/a.ics"),
+            ("/a.ics?v=3#section", true, "/a.ics?v=3"),
+            ("/a.ics?v=3#section", false, "/a.ics"),
+        ] {
+            assert_eq!(
+                normalize_link(link, keep_query),
+                expected,
+                "normalizing '{link}'"
+            );
+        }
+        assert!(
+            is_ics_url("https://www.fh-kiel.de/Plan.ICS?v=3#section"),
+            "the query, fragment and case of the extension should be ignored"
+        );
+    }
+
+    #[test]
+    fn query_versions_are_different_urls_of_the_same_calendar() {
+        let base_url = "https://www.fh-kiel.de";
+        let third =
+            SourceLink::new(base_url, "/files/iue/WiSe_2425/semester_1/Plan.ics?v=3").unwrap();
+        let fourth =
+            SourceLink::new(base_url, "/files/iue/WiSe_2425/semester_1/Plan.ics?v=4").unwrap();
+
+        assert_ne!(third.url, fourth.url, "the versions should be cached apart");
+        assert_eq!(
+            third.source_path, fourth.source_path,
+            "the versions should be placed in the same group"
+        );
+    }
+
+    #[test]
+    fn link_texts_are_captured_without_markup() {
+        let page = r#"<a href="/files/iue/WiSe_2425/semester_1/a.ics?v=2"><b>1. Semester</b>
+            Elektrotechnik&nbsp;&amp; Informatik &ndash; Stand 12.03.</a>
+            <a href="/files/iue/WiSe_2425/semester_1/b.ics"> <img src="ics.png"> </a>
+            <a href="/files/iue/WiSe_2425/semester_1/c.ics" class="download">Gruppe 2</a>"#;
+
+        assert_eq!(
+            extract_ics_links_from_website(page),
+            [
+                IcsLink {
+                    href: "/files/iue/WiSe_2425/semester_1/a.ics?v=2",
+                    text: Some("1. Semester Elektrotechnik & Informatik – Stand 12.03.".to_owned()),
+                },
+                IcsLink {
+                    href: "/files/iue/WiSe_2425/semester_1/b.ics",
+                    text: None,
+                },
+                IcsLink {
+                    href: "/files/iue/WiSe_2425/semester_1/c.ics",
+                    text: Some("Gruppe 2".to_owned()),
+                },
+            ],
+            "the texts should be cleaned up and empty ones dropped"
+        );
+    }
+}
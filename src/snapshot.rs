@@ -0,0 +1,323 @@
+//! Self-contained HTML snapshot of the whole output, with the events of every module as a table
+//!
+//! Meant for sharing or archiving a semester, so it needs neither the calendar files nor any
+//! other external resource.
+
+use crate::build_info::GENERATOR;
+use crate::datetime;
+use crate::index::{IndexEntry, IndexModel};
+use crate::label;
+use crate::prelude::*;
+use crate::scrape::escape_html;
+use crate::text_export::{self, ScheduleEvent};
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+
+/// Renders the snapshot. `schedules` holds the events of every module keyed by the link to its
+/// calendar in the index, modules without an entry get an empty table.
+pub fn render(
+    model: &IndexModel,
+    schedules: &BTreeMap<String, Vec<ScheduleEvent>>,
+) -> Result<String> {
+    let mut html = String::new();
+
+    writeln!(
+        &mut html,
+        "<!DOCTYPE html><html lang=\"de\">
+<head>
+<meta charset=\"UTF-8\">
+<meta name=\"generator\" content=\"{GENERATOR}\">
+<title>Vorlesungspläne der FH-Kiel</title>
+
+<style>
+table {{ border-collapse: collapse; }}
+th, td {{ border: 1px solid #ccc; padding: 0.2em 0.5em; text-align: left; }}
+</style>
+
+</head>
+
+<body>
+<h1>Vorlesungspläne der FH-Kiel</h1>
+<h4>Absolut kein Gewähr auf <b>Richtigkeit</b> oder <b>Vollständigkeit</b>. Überprüft vor der Nutzung bitte die offiziellen FH-Kiel-Pläne.</h4>
+<p>Stand: {}</p>",
+        datetime::format_in_zone(
+            model.generated_at,
+            model.display_timezone,
+            "%d.%m.%Y %H:%M:%S"
+        ),
+    )?;
+
+    // Table of contents, linking to the modules further down
+    let mut anchors: Vec<(String, &IndexEntry)> = Vec::new();
+    for section in &model.sections {
+        writeln!(
+            &mut html,
+            "<details open>
+<summary>{} – {} – {} ({} Module)</summary>
+<ul>",
+            label::semester_label(&section.semester),
+            section.department,
+            section.group_label,
+            section.entries.len(),
+        )?;
+
+        for entry in &section.entries {
+            let anchor = format!("modul-{}", anchors.len() + 1);
+            writeln!(
+                &mut html,
+                "<li><a href=\"#{anchor}\">{}</a> ({})</li>",
+                escape_html(&entry.module),
+                entry.group_label,
+            )?;
+            anchors.push((anchor, entry));
+        }
+
+        writeln!(&mut html, "</ul>\n</details>")?;
+    }
+
+    for (anchor, entry) in anchors {
+        writeln!(
+            &mut html,
+            "<h2 id=\"{anchor}\">{} ({})</h2>",
+            escape_html(&entry.module),
+            entry.group_label,
+        )?;
+        write_schedule_table(&mut html, schedules.get(&entry.href).map(Vec::as_slice))?;
+
+        // Earlier semesters of the module are kept as well, it's an archive after all
+        for past in &entry.history {
+            writeln!(
+                &mut html,
+                "<details><summary>{}</summary>",
+                label::semester_label(&past.semester)
+            )?;
+            write_schedule_table(&mut html, schedules.get(&past.href).map(Vec::as_slice))?;
+            writeln!(&mut html, "</details>")?;
+        }
+    }
+
+    writeln!(
+        &mut html,
+        "<footer>
+<p>Quelle: {}</p>
+<p>Erstellt von {GENERATOR}</p>
+</footer>
+</body>
+</html>",
+        model.base_urls.join(", "),
+    )?;
+
+    Ok(html)
+}
+
+/// Writes the events of a module as a table ordered by their start.
+fn write_schedule_table(html: &mut String, schedule: Option<&[ScheduleEvent]>) -> Result<()> {
+    let mut events: Vec<&ScheduleEvent> = schedule.into_iter().flatten().collect();
+    if events.is_empty() {
+        writeln!(html, "<p>Keine Termine</p>")?;
+        return Ok(());
+    }
+    events.sort_by_key(|event| event.start);
+
+    writeln!(
+        html,
+        "<table>\n<tr><th>Datum</th><th>Zeit</th><th>Raum</th></tr>"
+    )?;
+    for event in events {
+        writeln!(
+            html,
+            "<tr><td>{}</td><td>{}</td><td>{}</td></tr>",
+            text_export::format_day(event.start.date()),
+            format_time(event),
+            event
+                .location
+                .as_deref()
+                .map_or_else(String::new, escape_html),
+        )?;
+    }
+    writeln!(html, "</table>")?;
+
+    Ok(())
+}
+
+/// Formats the time of an event like `09:15–10:45`, with the end date if it ends on another day.
+fn format_time(event: &ScheduleEvent) -> String {
+    let end = event.end.filter(|end| *end != event.start);
+    let other_day = end.filter(|end| end.date() != event.start.date());
+
+    match (event.all_day, other_day, end) {
+        (true, None, _) => "ganztägig".to_owned(),
+        (true, Some(end), _) => {
+            format!("ganztägig bis {}", text_export::format_day(end.date()))
+        }
+        (false, Some(end), _) => format!(
+            "{}–{} {}",
+            event.start.format("%H:%M"),
+            text_export::format_day(end.date()),
+            end.format("%H:%M")
+        ),
+        (false, None, Some(end)) => {
+            format!("{}–{}", event.start.format("%H:%M"), end.format("%H:%M"))
+        }
+        (false, None, None) => event.start.format("%H:%M").to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::{IndexSection, PastSemester};
+    use chrono::{NaiveDate, NaiveDateTime, Utc};
+
+    fn at(day: u32, hour: u32, minute: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2024, 10, day)
+            .and_then(|date| date.and_hms_opt(hour, minute, 0))
+            .unwrap()
+    }
+
+    fn lecture(day: u32, location: Option<&str>) -> ScheduleEvent {
+        ScheduleEvent {
+            start: at(day, 9, 15),
+            end: Some(at(day, 10, 45)),
+            all_day: false,
+            location: location.map(str::to_owned),
+        }
+    }
+
+    fn entry(module: &str, href: &str, history: Vec<PastSemester>) -> IndexEntry {
+        IndexEntry {
+            href: href.to_owned(),
+            title: None,
+            module: module.to_owned(),
+            group_label: "1. Semester".to_owned(),
+            also_listed_in: Vec::new(),
+            last_changed: NaiveDate::from_ymd_opt(2024, 10, 14).unwrap(),
+            months: Vec::new(),
+            history,
+        }
+    }
+
+    /// Replaces the parts of the snapshot that change with every build and run.
+    fn normalize(html: &str) -> String {
+        html.replace(GENERATOR, "<generator>")
+            .lines()
+            .map(|line| {
+                if line.starts_with("<p>Stand: ") {
+                    "<p>Stand: <time></p>"
+                } else {
+                    line
+                }
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    #[test]
+    fn snapshot_of_a_small_semester() {
+        let sections = vec![IndexSection {
+            semester: "WiSe_2425".to_owned(),
+            department: "iue".to_owned(),
+            group_label: "1. Semester".to_owned(),
+            entries: vec![
+                entry(
+                    "Mathe & Statistik",
+                    "/files/mathe.ics",
+                    vec![PastSemester {
+                        semester: "SoSe_24".to_owned(),
+                        href: "/files/mathe-sose24.ics".to_owned(),
+                    }],
+                ),
+                entry("Physik", "/files/physik.ics", Vec::new()),
+            ],
+        }];
+        let schedules = BTreeMap::from([
+            (
+                "/files/mathe.ics".to_owned(),
+                vec![
+                    lecture(16, None),
+                    lecture(14, Some("C12-<1.13>")),
+                    ScheduleEvent {
+                        start: at(18, 0, 0),
+                        end: Some(at(18, 0, 0)),
+                        all_day: true,
+                        location: Some("Exkursion".to_owned()),
+                    },
+                ],
+            ),
+            (
+                "/files/mathe-sose24.ics".to_owned(),
+                vec![ScheduleEvent {
+                    start: at(21, 22, 0),
+                    end: Some(at(22, 2, 0)),
+                    all_day: false,
+                    location: None,
+                }],
+            ),
+        ]);
+        let model = IndexModel {
+            sections,
+            department_stats: &BTreeMap::new(),
+            base_urls: &["https://www.fh-kiel.de/stundenplan".to_owned()],
+            statistics_href: "stats.html",
+            changes_href: "changes.html",
+            generated_at: Utc::now(),
+            display_timezone: chrono_tz::Europe::Berlin,
+        };
+
+        let html = normalize(&render(&model, &schedules).unwrap());
+
+        assert_eq!(
+            html,
+            r##"<!DOCTYPE html><html lang="de">
+<head>
+<meta charset="UTF-8">
+<meta name="generator" content="<generator>">
+<title>Vorlesungspläne der FH-Kiel</title>
+
+<style>
+table { border-collapse: collapse; }
+th, td { border: 1px solid #ccc; padding: 0.2em 0.5em; text-align: left; }
+</style>
+
+</head>
+
+<body>
+<h1>Vorlesungspläne der FH-Kiel</h1>
+<h4>Absolut kein Gewähr auf <b>Richtigkeit</b> oder <b>Vollständigkeit</b>. Überprüft vor der Nutzung bitte die offiziellen FH-Kiel-Pläne.</h4>
+<p>Stand: <time></p>
+<details open>
+<summary>Wintersemester 2024/25 – iue – 1. Semester (2 Module)</summary>
+<ul>
+<li><a href="#modul-1">Mathe &amp; Statistik</a> (1. Semester)</li>
+<li><a href="#modul-2">Physik</a> (1. Semester)</li>
+</ul>
+</details>
+<h2 id="modul-1">Mathe &amp; Statistik (1. Semester)</h2>
+<table>
+<tr><th>Datum</th><th>Zeit</th><th>Raum</th></tr>
+<tr><td>Mo 14.10.</td><td>09:15–10:45</td><td>C12-&lt;1.13&gt;</td></tr>
+<tr><td>Mi 16.10.</td><td>09:15–10:45</td><td></td></tr>
+<tr><td>Fr 18.10.</td><td>ganztägig</td><td>Exkursion</td></tr>
+</table>
+<details><summary>Sommersemester 2024</summary>
+<table>
+<tr><th>Datum</th><th>Zeit</th><th>Raum</th></tr>
+<tr><td>Mo 21.10.</td><td>22:00–Di 22.10. 02:00</td><td></td></tr>
+</table>
+</details>
+<h2 id="modul-2">Physik (1. Semester)</h2>
+<p>Keine Termine</p>
+<footer>
+<p>Quelle: https://www.fh-kiel.de/stundenplan</p>
+<p>Erstellt von <generator></p>
+</footer>
+</body>
+</html>"##,
+            "the snapshot should match"
+        );
+        assert!(
+            !html.contains("href=\"/") && !html.contains("src="),
+            "the snapshot shouldn't link to other files"
+        );
+    }
+}
@@ -0,0 +1,232 @@
+//! Spill files keeping the events of each module on disk during aggregation, for runs too large
+//! to hold every event in memory
+
+use crate::calendar::{self, PROPERTY_NAME_DTEND, PROPERTY_NAME_DTSTART};
+use crate::manifest::sha256_hex;
+use crate::prelude::*;
+use crate::props;
+use ical::parser::ical::component::IcalEvent;
+use ical::property::Property;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::OpenOptions;
+use std::io::Write as _;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// A property as written to a spill file: name, parameters and value.
+type SpilledProperty = (String, Option<Vec<(String, Vec<String>)>>, Option<String>);
+
+/// What makes two events of a module duplicates, like in `calendar::is_event_already_present`:
+/// start, end and whether they are cancelled.
+type DuplicateKey = (Option<String>, Option<String>, bool);
+
+/// The spill files of a run, one per module, removed again when dropped.
+#[derive(Debug)]
+pub struct SpillStore {
+    folder: PathBuf,
+    /// Keys of the events already spilled for each module, to drop duplicates without reading
+    /// the files back.
+    seen: BTreeMap<String, BTreeSet<DuplicateKey>>,
+}
+
+impl SpillStore {
+    /// Creates an empty store in the temporary folder of the system.
+    pub fn new() -> Result<Self> {
+        // Numbered, so several pipelines of one process don't share their files
+        static STORES: AtomicUsize = AtomicUsize::new(0);
+        let folder = std::env::temp_dir().join(format!(
+            "fh_kiel_ical_splitter-spill-{}-{}",
+            std::process::id(),
+            STORES.fetch_add(1, Ordering::Relaxed)
+        ));
+
+        // Leftovers of a crashed run with the same process ID would be read back otherwise
+        if folder.exists() {
+            std::fs::remove_dir_all(&folder)?;
+        }
+        std::fs::create_dir_all(&folder)?;
+
+        Ok(Self {
+            folder,
+            seen: BTreeMap::new(),
+        })
+    }
+
+    /// Appends `event` to the spill file of the module `key`. Returns `false` without writing
+    /// anything if the module already contains a duplicate of the event.
+    pub fn push(&mut self, key: &str, event: &IcalEvent) -> Result<bool> {
+        let duplicate_key = (
+            props::get_value(event, PROPERTY_NAME_DTSTART).map(str::to_owned),
+            props::get_value(event, PROPERTY_NAME_DTEND).map(str::to_owned),
+            calendar::is_cancelled(event),
+        );
+        if !self
+            .seen
+            .entry(key.to_owned())
+            .or_default()
+            .insert(duplicate_key)
+        {
+            return Ok(false);
+        }
+
+        let properties: Vec<SpilledProperty> = event
+            .properties
+            .iter()
+            .map(|property| {
+                (
+                    property.name.clone(),
+                    property.params.clone(),
+                    property.value.clone(),
+                )
+            })
+            .collect();
+        let mut line = serde_json::to_string(&properties)?;
+        line.push('\n');
+
+        // One write per event, so a line is never split between two writes
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.path(key))?
+            .write_all(line.as_bytes())?;
+
+        Ok(true)
+    }
+
+    /// Reads the events of the module `key` back, in the order they were spilled.
+    pub fn load(&self, key: &str) -> Result<Vec<IcalEvent>> {
+        if !self.seen.contains_key(key) {
+            return Ok(Vec::new());
+        }
+
+        std::fs::read_to_string(self.path(key))?
+            .lines()
+            .map(|line| {
+                let properties: Vec<SpilledProperty> = serde_json::from_str(line)?;
+
+                let mut event = IcalEvent::new();
+                event.properties = properties
+                    .into_iter()
+                    .map(|(name, params, value)| Property {
+                        name,
+                        params,
+                        value,
+                    })
+                    .collect();
+
+                Ok(event)
+            })
+            .collect()
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        self.folder
+            .join(format!("{}.jsonl", sha256_hex(key.as_bytes())))
+    }
+}
+
+impl Drop for SpillStore {
+    fn drop(&mut self) {
+        if let Err(err) = std::fs::remove_dir_all(&self.folder) {
+            warn!(
+                "Failed to remove the spill files in '{}': {err}",
+                self.folder.display()
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::calendar::PROPERTY_NAME_SUMMARY;
+    use crate::testing;
+
+    fn events(events: &[String]) -> Vec<IcalEvent> {
+        ical::IcalParser::new(testing::calendar_of(events).as_bytes())
+            .flat_map(|calendar| calendar.unwrap().events)
+            .collect()
+    }
+
+    /// Every property of `events` with its parameters, to compare them.
+    fn properties(events: &[IcalEvent]) -> Vec<String> {
+        events
+            .iter()
+            .flat_map(|event| &event.properties)
+            .map(|property| {
+                format!(
+                    "{} {:?} {:?}",
+                    property.name, property.params, property.value
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn spilled_events_are_read_back_in_order() {
+        let events = events(&[
+            testing::event("Mathe", "20241021T081500"),
+            testing::event("Mathe", "20241014T081500"),
+        ]);
+        let mut store = SpillStore::new().unwrap();
+
+        for event in &events {
+            assert!(
+                store.push("mathe", event).unwrap(),
+                "the event should be new"
+            );
+        }
+
+        assert_eq!(
+            properties(&store.load("mathe").unwrap()),
+            properties(&events),
+            "the events should be read back unchanged and in order"
+        );
+        assert!(
+            store.load("physik").unwrap().is_empty(),
+            "a module without events should have none"
+        );
+    }
+
+    #[test]
+    fn duplicates_are_only_spilled_once_per_module() {
+        let events = events(&[
+            testing::event("Mathe", "20241014T081500"),
+            testing::event("Mathe (Kopie)", "20241014T081500"),
+        ]);
+        let mut store = SpillStore::new().unwrap();
+
+        let pushed = [("mathe", 0), ("mathe", 1), ("physik", 0)].map(|(key, index)| {
+            events
+                .get(index)
+                .is_some_and(|event| store.push(key, event).unwrap())
+        });
+
+        assert_eq!(
+            pushed,
+            [true, false, true],
+            "an event with the same start and end should be a duplicate within its module only"
+        );
+        assert_eq!(
+            store
+                .load("mathe")
+                .unwrap()
+                .iter()
+                .filter_map(|event| props::get_value(event, PROPERTY_NAME_SUMMARY))
+                .collect::<Vec<_>>(),
+            ["Mathe"],
+            "the first event should be kept"
+        );
+    }
+
+    #[test]
+    fn spill_files_are_removed_with_the_store() {
+        let mut store = SpillStore::new().unwrap();
+        store.push("mathe", &IcalEvent::new()).unwrap();
+        let folder = store.folder.clone();
+
+        drop(store);
+
+        assert!(!folder.exists(), "the spill files should be removed");
+    }
+}
@@ -0,0 +1,287 @@
+//! State persisted between runs
+
+use crate::change_history::ChangeRun;
+use crate::changes::ChangeRecord;
+use crate::feed::FeedEntry;
+use crate::prelude::*;
+use crate::tombstone::Tombstone;
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+
+/// What we remember about a single emitted event.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EventState {
+    /// Hash over the content of the event, see `calendar::event_content_hash`.
+    pub content_hash: String,
+    /// SEQUENCE the event was emitted with.
+    pub sequence: u32,
+    /// Canonical name of the module the event belongs to.
+    #[serde(default)]
+    pub module: String,
+    /// DTSTART the event was emitted with.
+    #[serde(default)]
+    pub start: String,
+}
+
+/// What we remember about a single emitted module.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ModuleState {
+    /// Hash over the content hashes of all events of the module.
+    pub content_hash: String,
+    /// Date the module was first emitted.
+    pub first_seen: NaiveDate,
+    /// Date the content of the module last changed.
+    pub last_changed: NaiveDate,
+}
+
+/// Everything carried over from one run to the next.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct State {
+    /// Emitted events keyed by their UID.
+    #[serde(default)]
+    pub events: BTreeMap<String, EventState>,
+
+    /// Emitted modules keyed by their canonical name.
+    #[serde(default)]
+    pub modules: BTreeMap<String, ModuleState>,
+
+    /// Output paths of modules which collided with another module, keyed by their canonical name.
+    #[serde(default)]
+    pub output_paths: BTreeMap<String, String>,
+
+    /// Total size of all calendar files in bytes.
+    #[serde(default)]
+    pub total_size: Option<usize>,
+
+    /// Recent changes of the module calendars, newest first.
+    #[serde(default)]
+    pub changes: Vec<ChangeRecord>,
+
+    /// Entries of the Atom feed, newest first.
+    #[serde(default)]
+    pub feed: Vec<FeedEntry>,
+
+    /// Changes of the last runs shown on the changes page, newest first.
+    #[serde(default)]
+    pub change_history: Vec<ChangeRun>,
+
+    /// Keys of the events of every module calendar keyed by its path, see `tombstone::event_key`.
+    #[serde(default)]
+    pub calendar_events: BTreeMap<String, Vec<String>>,
+
+    /// Calendars which disappeared and are replaced by a tombstone, keyed by their path.
+    #[serde(default)]
+    pub tombstones: BTreeMap<String, Tombstone>,
+
+    /// Calendar files written by the run including month files and tombstones, the next run
+    /// deletes the ones it doesn't write anymore.
+    #[serde(default)]
+    pub output_files: BTreeSet<String>,
+}
+
+impl State {
+    /// Loads the state of the previous run. A missing file results in an empty state.
+    pub fn load(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let content = std::fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&content)?)
+    }
+
+    /// Writes the state for the next run.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let json = serde_json::to_string_pretty(self)?;
+        std::fs::write(path, json)?;
+
+        Ok(())
+    }
+
+    /// Returns the SEQUENCE to emit for an event of the module `module` starting at `start` and
+    /// records it in `self`.
+    ///
+    /// New events start at 0, unchanged events keep their previous value and changed events get
+    /// the previous value incremented.
+    pub fn assign_sequence(
+        &mut self,
+        previous: &Self,
+        uid: &str,
+        module: &str,
+        start: &str,
+        content_hash: String,
+    ) -> u32 {
+        let sequence = match previous.events.get(uid) {
+            Some(event) if event.content_hash == content_hash => event.sequence,
+            Some(event) => event.sequence + 1,
+            None => 0,
+        };
+
+        self.events.insert(
+            uid.to_owned(),
+            EventState {
+                content_hash,
+                sequence,
+                module: module.to_owned(),
+                start: start.to_owned(),
+            },
+        );
+
+        sequence
+    }
+
+    /// Returns the dates of a module and records them in `self`.
+    ///
+    /// New modules are first seen and last changed `today`, for known modules the last changed
+    /// date only moves when the content hash differs from the previous run.
+    pub fn assign_module_dates(
+        &mut self,
+        previous: &Self,
+        key: &str,
+        content_hash: String,
+        today: NaiveDate,
+    ) -> ModuleState {
+        let module_state = match previous.modules.get(key) {
+            Some(module) if module.content_hash == content_hash => module.clone(),
+            Some(module) => ModuleState {
+                content_hash,
+                first_seen: module.first_seen,
+                last_changed: today,
+            },
+            None => ModuleState {
+                content_hash,
+                first_seen: today,
+                last_changed: today,
+            },
+        };
+
+        self.modules.insert(key.to_owned(), module_state.clone());
+
+        module_state
+    }
+
+    /// Copies the module `key` and its events from `previous` unless the module is recorded
+    /// already, for modules missing from a run which might be back next time.
+    pub fn carry_over_module(&mut self, previous: &Self, key: &str) {
+        let Some(module_state) = previous.modules.get(key) else {
+            return;
+        };
+        if self.modules.contains_key(key) {
+            return;
+        }
+
+        self.modules.insert(key.to_owned(), module_state.clone());
+        for (uid, event) in &previous.events {
+            if event.module == key {
+                self.events
+                    .entry(uid.clone())
+                    .or_insert_with(|| event.clone());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 10, day).unwrap()
+    }
+
+    /// Records the modules given as `(key, content_hash)` on top of `previous`, like a run on
+    /// `today`.
+    fn run(previous: &State, modules: &[(&str, &str)], today: NaiveDate) -> State {
+        let mut state = State::default();
+        for (key, content_hash) in modules {
+            state.assign_module_dates(previous, key, (*content_hash).to_owned(), today);
+        }
+
+        state
+    }
+
+    /// Returns the `(first_seen, last_changed)` dates of `key`.
+    fn dates(state: &State, key: &str) -> Option<(NaiveDate, NaiveDate)> {
+        state
+            .modules
+            .get(key)
+            .map(|module| (module.first_seen, module.last_changed))
+    }
+
+    #[test]
+    fn module_dates_only_move_when_the_content_changes() {
+        let first = run(&State::default(), &[("mathe", "a")], date(1));
+        let unchanged = run(&first, &[("mathe", "a")], date(2));
+        let changed = run(&unchanged, &[("mathe", "b"), ("physik", "c")], date(3));
+
+        assert_eq!(
+            dates(&first, "mathe"),
+            Some((date(1), date(1))),
+            "a new module should be first seen and changed today"
+        );
+        assert_eq!(
+            dates(&unchanged, "mathe"),
+            Some((date(1), date(1))),
+            "an unchanged module should keep its dates"
+        );
+        assert_eq!(
+            dates(&changed, "mathe"),
+            Some((date(1), date(3))),
+            "a changed module should keep its first seen date"
+        );
+        assert_eq!(
+            dates(&changed, "physik"),
+            Some((date(3), date(3))),
+            "a module added later should show the date it was first seen"
+        );
+    }
+
+    #[test]
+    fn missing_modules_are_carried_over_with_their_events() {
+        let mut previous = run(
+            &State::default(),
+            &[("mathe", "a"), ("physik", "b")],
+            date(1),
+        );
+        previous.assign_sequence(
+            &State::default(),
+            "m1",
+            "mathe",
+            "20241014T081500",
+            "x".into(),
+        );
+        previous.assign_sequence(
+            &State::default(),
+            "p1",
+            "physik",
+            "20241015T100000",
+            "y".into(),
+        );
+        let mut state = run(&previous, &[("mathe", "c")], date(2));
+        state.assign_sequence(&previous, "m1", "mathe", "20241014T081500", "z".into());
+
+        state.carry_over_module(&previous, "physik");
+        state.carry_over_module(&previous, "mathe");
+
+        assert_eq!(
+            dates(&state, "physik"),
+            Some((date(1), date(1))),
+            "the missing module should keep its dates"
+        );
+        assert_eq!(
+            state.events.get("p1"),
+            previous.events.get("p1"),
+            "the events of the missing module should be kept"
+        );
+        assert_eq!(
+            (
+                dates(&state, "mathe"),
+                state.events.get("m1").map(|event| event.sequence)
+            ),
+            (Some((date(1), date(2))), Some(1)),
+            "a module of this run should keep what it recorded"
+        );
+    }
+}
@@ -0,0 +1,393 @@
+//! Aggregate statistics over all generated calendars
+
+use crate::calendar::{Modules, PROPERTY_NAME_DTSTART, PROPERTY_NAME_LOCATION};
+use crate::datetime;
+use crate::prelude::*;
+use crate::spill::SpillStore;
+use chrono::{Datelike, Timelike};
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write as _;
+
+/// Number of modules listed in the top list.
+const TOP_MODULES: usize = 10;
+
+const WEEKDAYS: [&str; 7] = [
+    "Montag",
+    "Dienstag",
+    "Mittwoch",
+    "Donnerstag",
+    "Freitag",
+    "Samstag",
+    "Sonntag",
+];
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize)]
+pub struct Statistics {
+    pub total_events: usize,
+    pub events_per_department: BTreeMap<String, usize>,
+    pub events_per_semester: BTreeMap<String, usize>,
+    /// Events starting on each weekday, Monday first.
+    pub events_per_weekday: [usize; 7],
+    /// Events starting in each hour of the day, all-day events are not counted.
+    pub events_per_hour: [usize; 24],
+    pub distinct_rooms: usize,
+    /// Modules with the most events, most events first.
+    pub top_modules: Vec<(String, usize)>,
+}
+
+/// Computes the statistics over the aggregated modules, reading their events back from `spill`
+/// one module at a time if they were spilled.
+pub fn compute(modules: &Modules, spill: Option<&SpillStore>) -> Result<Statistics> {
+    let mut statistics = Statistics::default();
+    let mut rooms: BTreeSet<String> = BTreeSet::new();
+    let mut module_sizes: Vec<(String, usize)> = Vec::with_capacity(modules.len());
+
+    for (key, entry) in modules {
+        let spilled_events = spill.map(|spill| spill.load(key)).transpose()?;
+        let events = spilled_events.as_ref().unwrap_or(&entry.events);
+        let event_count = events.len();
+
+        statistics.total_events += event_count;
+        *statistics
+            .events_per_department
+            .entry(entry.department.clone())
+            .or_default() += event_count;
+        *statistics
+            .events_per_semester
+            .entry(entry.semester.clone())
+            .or_default() += event_count;
+        module_sizes.push((entry.display_name.clone(), event_count));
+
+        for event in events {
+            for property in &event.properties {
+                let Some(value) = property.value.as_deref() else {
+                    continue;
+                };
+
+                if property.name == PROPERTY_NAME_LOCATION && !value.trim().is_empty() {
+                    rooms.insert(value.trim().to_owned());
+                } else if property.name == PROPERTY_NAME_DTSTART {
+                    let Some(start) = datetime::parse_date_time(value) else {
+                        continue;
+                    };
+
+                    let weekday = start.weekday().num_days_from_monday() as usize;
+                    if let Some(count) = statistics.events_per_weekday.get_mut(weekday) {
+                        *count += 1;
+                    }
+
+                    if !datetime::is_date_only(value) {
+                        if let Some(count) =
+                            statistics.events_per_hour.get_mut(start.hour() as usize)
+                        {
+                            *count += 1;
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    statistics.distinct_rooms = rooms.len();
+
+    // Most events first, ties sorted by name to keep the output stable
+    module_sizes.sort_by(|(a_name, a_count), (b_name, b_count)| {
+        b_count.cmp(a_count).then_with(|| a_name.cmp(b_name))
+    });
+    module_sizes.truncate(TOP_MODULES);
+    statistics.top_modules = module_sizes;
+
+    Ok(statistics)
+}
+
+/// Renders the statistics as a standalone HTML page.
+pub fn render_html(statistics: &Statistics) -> Result<String> {
+    let mut html = String::new();
+
+    write!(
+        &mut html,
+        "<!DOCTYPE html><html lang=\"de\">
+<head>
+<meta charset=\"UTF-8\">
+<meta name=\"description\" content=\"Statistiken der Vorlesungspläne der FH-Kiel\">
+<title>Statistiken der Vorlesungspläne der FH-Kiel</title>
+</head>
+
+<body>
+<h1>Statistiken der Vorlesungspläne der FH-Kiel</h1>
+<p>Termine insgesamt: {}</p>
+<p>Verschiedene Räume: {}</p>
+",
+        statistics.total_events, statistics.distinct_rooms
+    )?;
+
+    write_table(
+        &mut html,
+        "Termine pro Fachbereich",
+        statistics
+            .events_per_department
+            .iter()
+            .map(|(name, count)| (name.as_str(), *count)),
+    )?;
+    write_table(
+        &mut html,
+        "Termine pro Semester",
+        statistics
+            .events_per_semester
+            .iter()
+            .map(|(name, count)| (name.as_str(), *count)),
+    )?;
+    write_table(
+        &mut html,
+        "Termine pro Wochentag",
+        WEEKDAYS
+            .iter()
+            .copied()
+            .zip(statistics.events_per_weekday.iter().copied()),
+    )?;
+
+    let hours: Vec<(String, usize)> = statistics
+        .events_per_hour
+        .iter()
+        .enumerate()
+        .filter(|(_, count)| **count > 0)
+        .map(|(hour, count)| (format!("{hour:02}:00"), *count))
+        .collect();
+    write_table(
+        &mut html,
+        "Termine pro Uhrzeit",
+        hours.iter().map(|(hour, count)| (hour.as_str(), *count)),
+    )?;
+    write_table(
+        &mut html,
+        "Module mit den meisten Terminen",
+        statistics
+            .top_modules
+            .iter()
+            .map(|(name, count)| (name.as_str(), *count)),
+    )?;
+
+    html.push_str("<p><a href=\"index.html\">Zurück zur Übersicht</a></p>\n</body>\n</html>\n");
+
+    Ok(html)
+}
+
+fn write_table<'a, I>(html: &mut String, title: &str, rows: I) -> Result<()>
+where
+    I: Iterator<Item = (&'a str, usize)>,
+{
+    writeln!(html, "<h2>{title}</h2>\n<table>")?;
+    for (name, count) in rows {
+        writeln!(html, "<tr><td>{name}</td><td>{count}</td></tr>")?;
+    }
+    html.push_str("</table>\n");
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::calendar::CalendarEntry;
+    use crate::testing;
+    use ical::parser::ical::component::IcalEvent;
+
+    fn events(events: &[String]) -> Vec<IcalEvent> {
+        ical::IcalParser::new(testing::calendar_of(events).as_bytes())
+            .flat_map(|calendar| calendar.unwrap().events)
+            .collect()
+    }
+
+    /// An event lasting 90 minutes from `start` in `room`.
+    fn lecture(start: &str, room: &str) -> String {
+        testing::event("Vorlesung", start).replace("LOCATION:C-12.01", &format!("LOCATION:{room}"))
+    }
+
+    fn module(
+        department: &str,
+        semester: &str,
+        name: &str,
+        events: Vec<IcalEvent>,
+    ) -> CalendarEntry {
+        CalendarEntry {
+            events,
+            display_name: name.to_owned(),
+            department: department.to_owned(),
+            semester: semester.to_owned(),
+            group_dir: "semester_1".to_owned(),
+            base_url: String::new(),
+            source_last_modified: None,
+            group_dir_events: BTreeMap::new(),
+            link_texts: Vec::new(),
+            name_stripped: false,
+        }
+    }
+
+    /// Two modules with three events each on Monday to Wednesday, one with a single event on a
+    /// Friday without a room and ten with a single event on a Saturday.
+    fn dataset() -> Modules {
+        let mut modules = Modules::new();
+        modules.insert(
+            "mathe".to_owned(),
+            module(
+                "iue",
+                "WiSe_2425",
+                "Mathe",
+                events(&[
+                    lecture("20241014T081500", "C-12.01"),
+                    lecture("20241021T081500", "C-12.01"),
+                    lecture("20241028T081500", " C-12.01 "),
+                ]),
+            ),
+        );
+        modules.insert(
+            "physik".to_owned(),
+            module(
+                "iue",
+                "WiSe_2425",
+                "Physik",
+                events(&[
+                    lecture("20241015T100000", "C-12.02"),
+                    lecture("20241022T100000", "C-12.02"),
+                    // All-day events count for their weekday but not for an hour
+                    "BEGIN:VEVENT\r\nSUMMARY:Exkursion\r\nDTSTART;VALUE=DATE:20241016\r\nEND:VEVENT\r\n"
+                        .to_owned(),
+                ]),
+            ),
+        );
+        modules.insert(
+            "chemie".to_owned(),
+            module(
+                "mbau",
+                "SoSe_25",
+                "Chemie",
+                events(&[lecture("20250411T140000", " ")]),
+            ),
+        );
+        for number in 1..=10 {
+            let name = format!("Modul {number:02}");
+            modules.insert(
+                name.to_lowercase(),
+                module(
+                    "mbau",
+                    "SoSe_25",
+                    &name,
+                    events(&[lecture("20250412T120000", "")]),
+                ),
+            );
+        }
+
+        modules
+    }
+
+    #[test]
+    fn events_are_counted_per_department_semester_weekday_and_hour() {
+        let statistics = compute(&dataset(), None).unwrap();
+
+        assert_eq!(statistics.total_events, 17, "every event should be counted");
+        assert_eq!(
+            statistics.events_per_department,
+            BTreeMap::from([("iue".to_owned(), 6), ("mbau".to_owned(), 11)]),
+            "the events should be counted per department"
+        );
+        assert_eq!(
+            statistics.events_per_semester,
+            BTreeMap::from([("SoSe_25".to_owned(), 11), ("WiSe_2425".to_owned(), 6)]),
+            "the events should be counted per semester"
+        );
+        assert_eq!(
+            statistics.events_per_weekday,
+            [3, 2, 1, 0, 1, 10, 0],
+            "the events should be counted by the weekday they start on"
+        );
+
+        let mut hours = [0; 24];
+        for (hour, count) in [(8, 3), (10, 2), (12, 10), (14, 1)] {
+            if let Some(slot) = hours.get_mut(hour) {
+                *slot = count;
+            }
+        }
+        assert_eq!(
+            statistics.events_per_hour, hours,
+            "timed events should be counted by the hour they start in"
+        );
+        assert_eq!(
+            statistics.distinct_rooms, 2,
+            "rooms should be trimmed and blank rooms ignored"
+        );
+    }
+
+    #[test]
+    fn only_the_ten_modules_with_the_most_events_are_listed() {
+        let statistics = compute(&dataset(), None).unwrap();
+
+        let mut expected = vec![
+            ("Mathe".to_owned(), 3),
+            ("Physik".to_owned(), 3),
+            ("Chemie".to_owned(), 1),
+        ];
+        expected.extend((1..=7).map(|number| (format!("Modul {number:02}"), 1)));
+        assert_eq!(
+            statistics.top_modules, expected,
+            "the modules should be ordered by their events, then by name"
+        );
+    }
+
+    #[test]
+    fn spilled_events_are_counted_like_kept_ones() {
+        let kept = dataset();
+        let mut spilled = dataset();
+        let mut spill = SpillStore::new().unwrap();
+        for (key, entry) in &mut spilled {
+            for event in std::mem::take(&mut entry.events) {
+                spill.push(key, &event).unwrap();
+            }
+        }
+
+        assert_eq!(
+            compute(&spilled, Some(&spill)).unwrap(),
+            compute(&kept, None).unwrap(),
+            "the statistics shouldn't depend on where the events are kept"
+        );
+    }
+
+    #[test]
+    fn page_and_json_show_every_statistic() {
+        let statistics = compute(&dataset(), None).unwrap();
+
+        let html = render_html(&statistics).unwrap();
+        for row in [
+            "<p>Termine insgesamt: 17</p>",
+            "<p>Verschiedene Räume: 2</p>",
+            "<tr><td>mbau</td><td>11</td></tr>",
+            "<tr><td>WiSe_2425</td><td>6</td></tr>",
+            "<tr><td>Donnerstag</td><td>0</td></tr>",
+            "<tr><td>Samstag</td><td>10</td></tr>",
+            "<tr><td>08:00</td><td>3</td></tr>",
+            "<tr><td>Modul 07</td><td>1</td></tr>",
+            "<a href=\"index.html\">",
+        ] {
+            assert!(html.contains(row), "the page should contain '{row}'");
+        }
+        assert!(
+            !html.contains("<td>09:00</td>") && !html.contains("Modul 08"),
+            "hours without events and modules beyond the top list should be left out"
+        );
+
+        let json = serde_json::to_value(&statistics).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "total_events": 17,
+                "events_per_department": { "iue": 6, "mbau": 11 },
+                "events_per_semester": { "SoSe_25": 11, "WiSe_2425": 6 },
+                "events_per_weekday": [3, 2, 1, 0, 1, 10, 0],
+                "events_per_hour": statistics.events_per_hour,
+                "distinct_rooms": 2,
+                "top_modules": statistics.top_modules,
+            }),
+            "the JSON should have a field per statistic"
+        );
+    }
+}
@@ -0,0 +1,162 @@
+//! Per-source event statistics
+
+use crate::diagnostics::SkipReason;
+use crate::prelude::*;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::time::Duration;
+
+/// Counts what happened to the events of a single source calendar during aggregation.
+#[derive(Debug, Default, Clone, Copy, Serialize)]
+pub struct SourceStats {
+    /// Events parsed from the source file.
+    pub parsed: u32,
+    /// Events dropped because they matched the ignore list.
+    pub ignored: u32,
+    /// Events dropped because they matched an event filter rule.
+    pub filtered: u32,
+    /// Events dropped because the module already contained an identical event.
+    pub deduplicated: u32,
+    /// Events dropped because they were cancelled.
+    pub cancelled: u32,
+    /// Events dropped because their name was empty.
+    pub unnamed: u32,
+    /// Events without a DTSTART, dropped unless kept with a placeholder date.
+    pub undated: u32,
+    /// Events which made it into a module calendar.
+    pub accepted: u32,
+}
+
+/// Counts what was found on a single base URL.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct BaseStats {
+    pub departments: usize,
+    pub calendars: u32,
+    pub events: u32,
+}
+
+/// Counts what was found and generated for a single department.
+#[derive(Debug, Default, Clone, Copy, Serialize)]
+pub struct DepartmentStats {
+    /// VCALENDAR blocks parsed from the calendar files of the department.
+    pub calendars: u32,
+    /// Module calendars written for the department.
+    pub modules: u32,
+    /// Events in the calendar files of the department.
+    pub events: u32,
+    /// Calendars which failed to parse plus module calendars which failed to write.
+    pub failures: u32,
+    /// How the calendar files of the department were obtained.
+    pub downloads: DownloadStats,
+}
+
+/// Counters of a whole run, shared by the final log lines and the report so they agree.
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct RunStats {
+    /// Downloaded calendar files which were parsed.
+    pub calendar_files: usize,
+    /// VCALENDAR blocks parsed successfully, a file can contain several.
+    pub calendars_parsed: usize,
+    /// VCALENDAR blocks which failed to parse.
+    pub calendars_broken: usize,
+    /// Events found in the parsed calendars.
+    pub events_seen: usize,
+    /// Events dropped before reaching a module, because they were unnamed, undated, cancelled,
+    /// ignored or filtered.
+    pub events_ignored: usize,
+    /// Events dropped because the module already contained an identical event.
+    pub events_deduplicated: usize,
+    /// Events written to the module calendars, after merging adjacent events.
+    pub events_emitted: usize,
+    /// Links to calendar files which were not downloaded, by reason.
+    pub urls_skipped: BTreeMap<SkipReason, usize>,
+    pub downloads: DownloadStats,
+}
+
+/// How the calendar files were obtained.
+#[derive(Debug, Default, Clone, Copy, Serialize)]
+pub struct DownloadStats {
+    /// Taken from the cache, with or without asking the server.
+    pub cache_hit: usize,
+    /// Downloaded from the server.
+    pub network: usize,
+    /// Could not be downloaded.
+    pub failed: usize,
+}
+
+/// Logs the statistics of every source calendar as a table at debug level.
+pub fn log_source_stats(source_stats: &BTreeMap<String, SourceStats>) {
+    debug!(
+        "{:>8} {:>8} {:>8} {:>8} {:>9} {:>8} {:>8}  source",
+        "parsed", "ignored", "filtered", "dedup", "cancelled", "undated", "accepted"
+    );
+
+    for (url, stats) in source_stats {
+        debug!(
+            "{:>8} {:>8} {:>8} {:>8} {:>9} {:>8} {:>8}  {url}",
+            stats.parsed,
+            stats.ignored,
+            stats.filtered,
+            stats.deduplicated,
+            stats.cancelled,
+            stats.undated,
+            stats.accepted
+        );
+    }
+}
+
+/// Logs that every calendar file of `department` was handled, `elapsed` after the downloads
+/// started.
+pub fn log_department_downloads(
+    department: &str,
+    downloads: &DownloadStats,
+    skipped: usize,
+    elapsed: Duration,
+) {
+    info!(
+        "Department '{department}' done after {} seconds: {} downloaded, {} from the cache, {} failed, {} skipped",
+        elapsed.as_secs(),
+        downloads.network,
+        downloads.cache_hit,
+        downloads.failed,
+        skipped
+    );
+}
+
+/// Logs the statistics of every department as a table.
+pub fn log_department_stats(department_stats: &BTreeMap<String, DepartmentStats>) {
+    info!(
+        "{:>9} {:>8} {:>8} {:>8}  department",
+        "calendars", "modules", "events", "failures"
+    );
+
+    for (department, stats) in department_stats {
+        info!(
+            "{:>9} {:>8} {:>8} {:>8}  {department}",
+            stats.calendars, stats.modules, stats.events, stats.failures
+        );
+    }
+}
+
+/// Renders the statistics of every department as an HTML table for the index footer.
+pub fn render_department_table(
+    department_stats: &BTreeMap<String, DepartmentStats>,
+    headers: [&str; 5],
+) -> Result<String> {
+    let [department, calendars, modules, events, failures] = headers;
+    let mut html = format!(
+        "<table>\n<tr><th>{department}</th><th>{calendars}</th><th>{modules}</th><th>{events}</th><th>{failures}</th></tr>\n",
+    );
+
+    for (department, stats) in department_stats {
+        writeln!(
+            &mut html,
+            "<tr><td>{department}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+            stats.calendars, stats.modules, stats.events, stats.failures
+        )?;
+    }
+    html.push_str("</table>");
+
+    Ok(html)
+}
@@ -0,0 +1,271 @@
+//! Templates for the paths of the generated calendars and the event summaries
+
+use crate::prelude::*;
+
+/// Layout of the generated calendars unless overridden.
+pub const DEFAULT_OUTPUT_TEMPLATE: &str = "files/{semester}/{department}/{group}/{module_slug}.ics";
+
+const PLACEHOLDERS: [&str; 5] = ["semester", "department", "group", "module", "module_slug"];
+
+/// Values substituted for the placeholders of an [`OutputTemplate`] or a [`SummaryTemplate`].
+pub struct PathValues<'a> {
+    pub semester: &'a str,
+    pub department: &'a str,
+    pub group: &'a str,
+    pub module: &'a str,
+    pub module_slug: &'a str,
+}
+
+/// Relative path of a calendar file with placeholders like `{module_slug}`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OutputTemplate {
+    template: String,
+}
+
+impl Default for OutputTemplate {
+    fn default() -> Self {
+        Self {
+            template: DEFAULT_OUTPUT_TEMPLATE.to_owned(),
+        }
+    }
+}
+
+impl OutputTemplate {
+    /// Parses a template, rejecting unknown placeholders and paths leaving the output folder.
+    pub fn parse(template: &str) -> Result<Self> {
+        let invalid =
+            |reason: &str| Error::InvalidOutputTemplate(template.to_owned(), reason.to_owned());
+
+        if template.trim().is_empty() {
+            return Err(invalid("template is empty"));
+        }
+
+        // Absolute paths, including Windows drive letters
+        if template.starts_with('/')
+            || template.starts_with('\\')
+            || template
+                .split(['/', '\\'])
+                .next()
+                .is_some_and(|first| first.contains(':'))
+        {
+            return Err(invalid("path must be relative"));
+        }
+
+        if template
+            .split(['/', '\\'])
+            .any(|segment| segment == ".." || segment == "." || segment.is_empty())
+        {
+            return Err(invalid("path must not contain empty, '.' or '..' segments"));
+        }
+
+        check_placeholders(template).map_err(|reason| invalid(&reason))?;
+
+        // Every module needs a file of its own
+        if !template.contains("{module}") && !template.contains("{module_slug}") {
+            return Err(invalid("must contain '{module}' or '{module_slug}'"));
+        }
+
+        Ok(Self {
+            template: template.to_owned(),
+        })
+    }
+
+    /// Renders the path of a calendar. Path separators in the values are replaced so every value
+    /// stays within its own path segment.
+    #[must_use]
+    pub fn render(&self, values: &PathValues) -> String {
+        substitute(&self.template, values, sanitize_segment)
+    }
+}
+
+/// Text appended to the summary of every event, e.g. `({department}, {group})`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SummaryTemplate {
+    template: String,
+}
+
+impl SummaryTemplate {
+    /// Parses a template, rejecting unknown placeholders.
+    pub fn parse(template: &str) -> Result<Self> {
+        check_placeholders(template)
+            .map_err(|reason| Error::InvalidSummaryTemplate(template.to_owned(), reason))?;
+
+        Ok(Self {
+            template: template.to_owned(),
+        })
+    }
+
+    /// Renders the text appended to a summary.
+    #[must_use]
+    pub fn render(&self, values: &PathValues) -> String {
+        substitute(&self.template, values, str::to_owned)
+    }
+}
+
+/// Checks that a template only uses known placeholders and has no unmatched braces.
+fn check_placeholders(template: &str) -> std::result::Result<(), String> {
+    for segment in segments(template) {
+        match segment {
+            Segment::Literal(_) => {}
+            Segment::Placeholder(name) if PLACEHOLDERS.contains(&name) => {}
+            Segment::Placeholder(name) => {
+                return Err(format!("unknown placeholder '{{{name}}}'"));
+            }
+            Segment::Unmatched(brace) => return Err(format!("unmatched '{brace}'")),
+        }
+    }
+
+    Ok(())
+}
+
+/// Replaces the placeholders of a checked template with the values passed through `convert`.
+fn substitute(template: &str, values: &PathValues, convert: fn(&str) -> String) -> String {
+    let mut text = String::with_capacity(template.len());
+
+    for segment in segments(template) {
+        match segment {
+            Segment::Literal(literal) => text.push_str(literal),
+            Segment::Placeholder(name) => {
+                let value = match name {
+                    "semester" => values.semester,
+                    "department" => values.department,
+                    "group" => values.group,
+                    "module" => values.module,
+                    _ => values.module_slug,
+                };
+                text.push_str(&convert(value));
+            }
+            // Rejected when parsing
+            Segment::Unmatched(_) => {}
+        }
+    }
+
+    text
+}
+
+/// Part of a template.
+enum Segment<'a> {
+    Literal(&'a str),
+    Placeholder(&'a str),
+    Unmatched(char),
+}
+
+/// Splits a template into literal text and placeholders.
+fn segments(template: &str) -> Vec<Segment<'_>> {
+    let mut segments = Vec::new();
+    let mut rest = template;
+
+    while let Some(start) = rest.find(['{', '}']) {
+        let (literal, tail) = rest.split_at(start);
+        if !literal.is_empty() {
+            segments.push(Segment::Literal(literal));
+        }
+
+        if let Some(after_brace) = tail.strip_prefix('{') {
+            if let Some((name, after_placeholder)) = after_brace.split_once('}') {
+                segments.push(Segment::Placeholder(name));
+                rest = after_placeholder;
+            } else {
+                segments.push(Segment::Unmatched('{'));
+                return segments;
+            }
+        } else {
+            segments.push(Segment::Unmatched('}'));
+            rest = tail.get(1..).unwrap_or_default();
+        }
+    }
+
+    if !rest.is_empty() {
+        segments.push(Segment::Literal(rest));
+    }
+
+    segments
+}
+
+/// Keeps a value from adding path segments or leaving the output folder.
+fn sanitize_segment(value: &str) -> String {
+    match value {
+        "" | "." | ".." => "_".to_owned(),
+        _ => value.replace(['/', '\\'], "_"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const VALUES: PathValues = PathValues {
+        semester: "WiSe 24/25",
+        department: "iue",
+        group: "Semester 1",
+        module: "Mathe 1",
+        module_slug: "Mathe_1",
+    };
+
+    #[test]
+    fn templates_render_the_values_into_their_segments() {
+        let cases = [
+            (
+                DEFAULT_OUTPUT_TEMPLATE,
+                "files/WiSe 24_25/iue/Semester 1/Mathe_1.ics",
+            ),
+            (
+                "calendars/{department}/{semester}/{module}.ics",
+                "calendars/iue/WiSe 24_25/Mathe 1.ics",
+            ),
+            ("{module_slug}.ics", "Mathe_1.ics"),
+        ];
+
+        for (template, expected) in cases {
+            assert_eq!(
+                OutputTemplate::parse(template).unwrap().render(&VALUES),
+                expected,
+                "rendering '{template}'"
+            );
+        }
+    }
+
+    #[test]
+    fn summary_templates_render_the_values_unchanged() {
+        let template = SummaryTemplate::parse("({department}, {group}, {semester})").unwrap();
+
+        assert_eq!(
+            template.render(&VALUES),
+            "(iue, Semester 1, WiSe 24/25)",
+            "summary suffixes shouldn't be sanitized like paths"
+        );
+    }
+
+    #[test]
+    fn summary_templates_with_unknown_placeholders_are_rejected() {
+        for template in ["{lecturer}", "({department", "{group}}"] {
+            assert!(
+                SummaryTemplate::parse(template).is_err(),
+                "'{template}' should be rejected"
+            );
+        }
+    }
+
+    #[test]
+    fn malicious_templates_are_rejected() {
+        let templates = [
+            "",
+            "/etc/{module}.ics",
+            "\\\\server\\{module}.ics",
+            "C:/{module}.ics",
+            "../{module}.ics",
+            "files/../../{module}.ics",
+            "files//{module}.ics",
+            "files/{unknown}/{module}.ics",
+            "files/{module.ics",
+            "files/{department}.ics",
+        ];
+
+        for template in templates {
+            assert!(
+                OutputTemplate::parse(template).is_err(),
+                "'{template}' should be rejected"
+            );
+        }
+    }
+}
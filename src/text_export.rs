@@ -0,0 +1,211 @@
+//! Plain-text weekly overview of a module, meant for pasting into announcements
+
+use crate::calendar::{PROPERTY_NAME_DTEND, PROPERTY_NAME_DTSTART, PROPERTY_NAME_LOCATION};
+use crate::{datetime, props};
+use chrono::{Datelike, Duration, NaiveDate, NaiveDateTime};
+use chrono_tz::Tz;
+use ical::parser::ical::component::IcalEvent;
+use std::collections::BTreeMap;
+
+const WEEKDAY_ABBREVIATIONS: [&str; 7] = ["Mo", "Di", "Mi", "Do", "Fr", "Sa", "So"];
+
+/// The parts of an event shown in the text export.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScheduleEvent {
+    pub start: NaiveDateTime,
+    /// Inclusive end, for all-day events the last day at midnight.
+    pub end: Option<NaiveDateTime>,
+    pub all_day: bool,
+    pub location: Option<String>,
+}
+
+impl ScheduleEvent {
+    /// Extracts the schedule of an event with its times in `display_zone`, `None` if it has no
+    /// valid start.
+    pub fn from_ical(event: &IcalEvent, display_zone: Tz) -> Option<Self> {
+        let start_value = props::get_value(event, PROPERTY_NAME_DTSTART)?;
+        let start = datetime::parse_display_time(
+            start_value,
+            props::get_param(event, PROPERTY_NAME_DTSTART, "TZID"),
+            display_zone,
+        )?;
+        let all_day = datetime::is_date_only(start_value)
+            || props::get_param(event, PROPERTY_NAME_DTSTART, "VALUE") == Some("DATE");
+
+        // DTEND of all-day events is exclusive
+        let end = props::get_value(event, PROPERTY_NAME_DTEND)
+            .and_then(|end| {
+                datetime::parse_display_time(
+                    end,
+                    props::get_param(event, PROPERTY_NAME_DTEND, "TZID"),
+                    display_zone,
+                )
+            })
+            .map(|end| {
+                if all_day {
+                    end - Duration::days(1)
+                } else {
+                    end
+                }
+            })
+            .filter(|end| *end >= start);
+
+        let location = props::get_value(event, PROPERTY_NAME_LOCATION)
+            .map(str::trim)
+            .filter(|location| !location.is_empty())
+            .map(str::to_owned);
+
+        Some(Self {
+            start,
+            end,
+            all_day,
+            location,
+        })
+    }
+}
+
+/// Formats the events of a module grouped by ISO week, one line per event, e.g.
+/// `Mo 07.04. 09:15–10:45 C12-1.13`.
+pub fn format_schedule(module: &str, events: &[ScheduleEvent]) -> String {
+    let mut weeks: BTreeMap<(i32, u32), Vec<&ScheduleEvent>> = BTreeMap::new();
+    for event in events {
+        let week = event.start.iso_week();
+        weeks
+            .entry((week.year(), week.week()))
+            .or_default()
+            .push(event);
+    }
+
+    let mut lines = vec![module.to_owned()];
+    for ((year, week), mut week_events) in weeks {
+        week_events.sort_by_key(|event| event.start);
+
+        lines.push(String::new());
+        lines.push(format!("KW {week} ({year})"));
+        lines.extend(week_events.into_iter().map(format_event));
+    }
+
+    lines.push(String::new());
+    lines.join("\n")
+}
+
+fn format_event(event: &ScheduleEvent) -> String {
+    let start_day = format_day(event.start.date());
+    let end = event.end.filter(|end| end.date() != event.start.date());
+
+    let mut line = match (event.all_day, end) {
+        (true, None) => format!("{start_day} ganztägig"),
+        (true, Some(end)) => format!("{start_day}–{} ganztägig", format_day(end.date())),
+        (false, None) => format!("{start_day} {}", event.start.format("%H:%M")),
+        (false, Some(end)) => format!(
+            "{start_day} {}–{} {}",
+            event.start.format("%H:%M"),
+            format_day(end.date()),
+            end.format("%H:%M")
+        ),
+    };
+
+    // Events ending on the same day only show the end time
+    if !event.all_day && end.is_none() {
+        if let Some(end) = event.end.filter(|end| *end != event.start) {
+            line = format!("{line}–{}", end.format("%H:%M"));
+        }
+    }
+
+    if let Some(location) = &event.location {
+        line = format!("{line} {location}");
+    }
+
+    line
+}
+
+/// Formats a date with its German weekday, e.g. `Mo 07.04.`.
+pub fn format_day(date: NaiveDate) -> String {
+    let weekday = WEEKDAY_ABBREVIATIONS
+        .get(date.weekday().num_days_from_monday() as usize)
+        .copied()
+        .unwrap_or_default();
+
+    format!("{weekday} {}", date.format("%d.%m."))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono_tz::Europe::Berlin;
+
+    fn event(properties: &str) -> IcalEvent {
+        let calendar = format!(
+            "BEGIN:VCALENDAR\r\nVERSION:2.0\r\nBEGIN:VEVENT\r\n{properties}END:VEVENT\r\nEND:VCALENDAR\r\n"
+        );
+
+        ical::IcalParser::new(calendar.as_bytes())
+            .flat_map(|calendar| calendar.unwrap().events)
+            .next()
+            .unwrap()
+    }
+
+    fn schedule(properties: &str) -> ScheduleEvent {
+        ScheduleEvent::from_ical(&event(properties), Berlin).unwrap()
+    }
+
+    #[test]
+    fn week_is_listed_by_day_with_local_times_and_rooms() {
+        let events = [
+            schedule(
+                "DTSTART;TZID=Europe/Berlin:20250409T091500\r\nDTEND;TZID=Europe/Berlin:20250409T104500\r\nLOCATION:C12-1.13\r\n",
+            ),
+            // UTC times are shown in the local time zone
+            schedule("DTSTART:20250407T071500Z\r\nDTEND:20250407T084500Z\r\nLOCATION:C12-1.13\r\n"),
+            schedule("DTSTART;VALUE=DATE:20250410\r\nDTEND;VALUE=DATE:20250411\r\n"),
+            schedule("DTSTART;VALUE=DATE:20250411\r\nDTEND;VALUE=DATE:20250414\r\nLOCATION:Exkursion\r\n"),
+            schedule(
+                "DTSTART;TZID=Europe/Berlin:20250415T220000\r\nDTEND;TZID=Europe/Berlin:20250416T020000\r\n",
+            ),
+        ];
+
+        assert_eq!(
+            format_schedule("Mathematik 1", &events),
+            "Mathematik 1
+
+KW 15 (2025)
+Mo 07.04. 09:15–10:45 C12-1.13
+Mi 09.04. 09:15–10:45 C12-1.13
+Do 10.04. ganztägig
+Fr 11.04.–So 13.04. ganztägig Exkursion
+
+KW 16 (2025)
+Di 15.04. 22:00–Mi 16.04. 02:00
+",
+            "events should be grouped by week and sorted by start"
+        );
+    }
+
+    #[test]
+    fn end_before_the_start_is_ignored() {
+        let event = schedule(
+            "DTSTART;TZID=Europe/Berlin:20250409T091500\r\nDTEND;TZID=Europe/Berlin:20250409T081500\r\n",
+        );
+
+        assert_eq!(event.end, None, "an end before the start should be dropped");
+        assert_eq!(
+            format_event(&event),
+            "Mi 09.04. 09:15",
+            "only the start should be shown"
+        );
+    }
+
+    #[test]
+    fn times_are_exported_in_the_display_zone() {
+        let event = event(
+            "DTSTART;TZID=Europe/Berlin:20250409T091500\r\nDTEND;TZID=Europe/Berlin:20250409T104500\r\n",
+        );
+
+        assert_eq!(
+            [Berlin, chrono_tz::UTC]
+                .map(|zone| { format_event(&ScheduleEvent::from_ical(&event, zone).unwrap()) }),
+            ["Mi 09.04. 09:15–10:45", "Mi 09.04. 07:15–08:45"],
+            "the times should be shown in each zone"
+        );
+    }
+}
@@ -0,0 +1,355 @@
+//! Placeholder calendars at the paths of calendars which disappeared since the last run
+//!
+//! Students subscribe to the calendar files directly, so a module renamed upstream would silently
+//! break their subscriptions. A tombstone tells them where the calendar went.
+
+use crate::calendar::{
+    PROPERTY_NAME_DESCRIPTION, PROPERTY_NAME_DTEND, PROPERTY_NAME_DTSTAMP, PROPERTY_NAME_DTSTART,
+    PROPERTY_NAME_LOCATION, PROPERTY_NAME_SUMMARY, PROPERTY_NAME_UID,
+};
+use crate::manifest::sha256_hex;
+use crate::props;
+use chrono::{Duration, NaiveDate};
+use ical::generator::{Emitter, IcalCalendarBuilder};
+use ical::parser::ical::component::IcalEvent;
+use ical::property::Property;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Days a tombstone is published unless overridden.
+pub const DEFAULT_TOMBSTONE_DAYS: u32 = 60;
+
+/// Share of common events in percent above which a new calendar is taken as the renamed old one.
+const MIN_RENAME_SIMILARITY: usize = 50;
+
+/// Length of the event keys, shortened to keep the state file small.
+const EVENT_KEY_LENGTH: usize = 16;
+
+/// A calendar which disappeared, published as a tombstone during the grace period.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Tombstone {
+    /// Day the calendar disappeared.
+    pub since: NaiveDate,
+    /// Path of the calendar the events moved to, if a rename could be inferred.
+    pub new_path: Option<String>,
+}
+
+/// Returns a key identifying an event regardless of the name of its module, built from its
+/// times and location.
+pub fn event_key(event: &IcalEvent) -> String {
+    let value = |name: &str| props::get_value(event, name).unwrap_or_default();
+    let key = format!(
+        "{}\n{}\n{}",
+        value(PROPERTY_NAME_DTSTART),
+        value(PROPERTY_NAME_DTEND),
+        value(PROPERTY_NAME_LOCATION)
+    );
+
+    let mut hash = sha256_hex(key.as_bytes());
+    hash.truncate(EVENT_KEY_LENGTH);
+    hash
+}
+
+/// Returns the size of the intersection and of the union of two key sets, their Jaccard
+/// similarity being the quotient.
+pub fn jaccard(a: &BTreeSet<&str>, b: &BTreeSet<&str>) -> (usize, usize) {
+    let intersection = a.intersection(b).count();
+
+    (intersection, a.len() + b.len() - intersection)
+}
+
+/// Returns the calendar among `candidates` whose events are most similar to `old_keys`, if it
+/// shares enough of them to be the renamed calendar.
+pub fn infer_rename<'a>(
+    old_keys: &[String],
+    candidates: &'a BTreeMap<String, Vec<String>>,
+) -> Option<&'a str> {
+    let old_keys: BTreeSet<&str> = old_keys.iter().map(String::as_str).collect();
+
+    candidates
+        .iter()
+        .map(|(path, keys)| {
+            let keys: BTreeSet<&str> = keys.iter().map(String::as_str).collect();
+            (path.as_str(), jaccard(&old_keys, &keys))
+        })
+        .filter(|(_, (intersection, union))| {
+            *union > 0 && intersection * 100 >= union * MIN_RENAME_SIMILARITY
+        })
+        // Compare the fractions without floating point by multiplying crosswise
+        .max_by(
+            |(_, (a_intersection, a_union)), (_, (b_intersection, b_union))| {
+                (a_intersection * b_union).cmp(&(b_intersection * a_union))
+            },
+        )
+        .map(|(path, _)| path)
+}
+
+/// Carries over the tombstones which are still within `grace_days` and adds ones for the
+/// calendars of the previous run which disappeared, inferring where they moved to.
+pub fn update_tombstones(
+    previous_tombstones: &BTreeMap<String, Tombstone>,
+    previous_calendars: &BTreeMap<String, Vec<String>>,
+    current_calendars: &BTreeMap<String, Vec<String>>,
+    today: NaiveDate,
+    grace_days: u32,
+) -> BTreeMap<String, Tombstone> {
+    let oldest = today - Duration::days(i64::from(grace_days));
+
+    // Only calendars which didn't exist before can be the new home of a renamed one
+    let new_calendars: BTreeMap<String, Vec<String>> = current_calendars
+        .iter()
+        .filter(|(path, _)| !previous_calendars.contains_key(*path))
+        .map(|(path, keys)| (path.clone(), keys.clone()))
+        .collect();
+
+    let mut tombstones: BTreeMap<String, Tombstone> = previous_tombstones
+        .iter()
+        .filter(|(path, tombstone)| {
+            tombstone.since > oldest && !current_calendars.contains_key(*path)
+        })
+        .map(|(path, tombstone)| (path.clone(), tombstone.clone()))
+        .collect();
+
+    for (path, keys) in previous_calendars {
+        if current_calendars.contains_key(path) || tombstones.contains_key(path) {
+            continue;
+        }
+
+        tombstones.insert(
+            path.clone(),
+            Tombstone {
+                since: today,
+                new_path: infer_rename(keys, &new_calendars).map(str::to_owned),
+            },
+        );
+    }
+
+    tombstones
+}
+
+/// Renders the tombstone for the calendar at `path`, linking the new calendar below
+/// `link_prefix` if known.
+pub fn render_tombstone(path: &str, tombstone: &Tombstone, link_prefix: &str) -> String {
+    let mut calendar = IcalCalendarBuilder::version("2.0")
+        .gregorian()
+        .prodid("-//Veranstaltungsplan der FH Kiel//Umgezogen//".to_owned())
+        .build();
+
+    let date = tombstone.since.format("%Y%m%d").to_string();
+    let next_date = tombstone
+        .since
+        .succ_opt()
+        .unwrap_or(tombstone.since)
+        .format("%Y%m%d");
+    let date_param = || Some(vec![("VALUE".to_owned(), vec!["DATE".to_owned()])]);
+    let description = tombstone.new_path.as_ref().map_or_else(
+        || "Dieser Kalender wird nicht mehr veröffentlicht.".to_owned(),
+        |new_path| format!("Der Kalender ist jetzt unter {link_prefix}{new_path} zu finden."),
+    );
+
+    let mut event = IcalEvent::new();
+    let mut push = |name: &str, params, value: String| {
+        event.properties.push(Property {
+            name: name.to_owned(),
+            params,
+            value: Some(value),
+        });
+    };
+
+    push(
+        PROPERTY_NAME_UID,
+        None,
+        format!(
+            "tombstone-{}@fh_kiel_ical_splitter",
+            sha256_hex(path.as_bytes())
+        ),
+    );
+    push(PROPERTY_NAME_DTSTAMP, None, format!("{date}T000000Z"));
+    push(PROPERTY_NAME_DTSTART, date_param(), date);
+    push(PROPERTY_NAME_DTEND, date_param(), next_date.to_string());
+    push(
+        PROPERTY_NAME_SUMMARY,
+        None,
+        "Dieser Kalender ist umgezogen".to_owned(),
+    );
+    push(PROPERTY_NAME_DESCRIPTION, None, description);
+
+    calendar.events.push(event);
+
+    calendar.generate()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys(keys: &[&str]) -> Vec<String> {
+        keys.iter().map(|key| (*key).to_owned()).collect()
+    }
+
+    fn candidates(calendars: &[(&str, &[&str])]) -> BTreeMap<String, Vec<String>> {
+        calendars
+            .iter()
+            .map(|(path, calendar_keys)| ((*path).to_owned(), keys(calendar_keys)))
+            .collect()
+    }
+
+    fn date(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 11, day).unwrap()
+    }
+
+    #[test]
+    fn jaccard_counts_the_intersection_and_the_union() {
+        let set = |keys: &[&'static str]| keys.iter().copied().collect::<BTreeSet<&str>>();
+
+        assert_eq!(
+            jaccard(&set(&["a", "b", "c"]), &set(&["b", "c", "d"])),
+            (2, 4),
+            "two of four keys should be shared"
+        );
+        assert_eq!(
+            jaccard(&set(&["a"]), &set(&["a"])),
+            (1, 1),
+            "equal sets should be fully similar"
+        );
+        assert_eq!(
+            jaccard(&set(&["a"]), &set(&[])),
+            (0, 1),
+            "nothing should be shared with an empty set"
+        );
+        assert_eq!(
+            jaccard(&set(&[]), &set(&[])),
+            (0, 0),
+            "two empty sets should have an empty union"
+        );
+    }
+
+    #[test]
+    fn a_rename_needs_half_of_the_events_in_common() {
+        let old = keys(&["a", "b", "c"]);
+
+        assert_eq!(
+            infer_rename(&old, &candidates(&[("new.ics", &["a", "b", "c", "d"])])),
+            Some("new.ics"),
+            "three of four shared events should be a rename"
+        );
+        assert_eq!(
+            infer_rename(&old, &candidates(&[("new.ics", &["a", "b", "d"])])),
+            Some("new.ics"),
+            "exactly half of the events shared should still be a rename"
+        );
+        assert_eq!(
+            infer_rename(&old, &candidates(&[("new.ics", &["a", "b", "d", "e"])])),
+            None,
+            "two of five shared events shouldn't be a rename"
+        );
+    }
+
+    #[test]
+    fn the_most_similar_calendar_is_taken_as_the_rename() {
+        let old = keys(&["a", "b", "c", "d"]);
+        let calendars = candidates(&[
+            ("similar.ics", &["a", "b", "c", "x"]),
+            ("same.ics", &["a", "b", "c", "d"]),
+            ("other.ics", &["x", "y"]),
+        ]);
+
+        assert_eq!(
+            infer_rename(&old, &calendars),
+            Some("same.ics"),
+            "the calendar with the same events should win"
+        );
+    }
+
+    #[test]
+    fn empty_calendars_are_never_a_rename() {
+        assert_eq!(
+            infer_rename(&keys(&[]), &candidates(&[("new.ics", &[])])),
+            None,
+            "two empty calendars shouldn't count as similar"
+        );
+        assert_eq!(
+            infer_rename(&keys(&[]), &candidates(&[("new.ics", &["a"])])),
+            None,
+            "an empty old calendar shouldn't match any calendar"
+        );
+        assert_eq!(
+            infer_rename(&keys(&["a"]), &BTreeMap::new()),
+            None,
+            "there should be no rename without new calendars"
+        );
+    }
+
+    #[test]
+    fn tombstones_point_to_the_renamed_calendar() {
+        let previous = candidates(&[("old.ics", &["a", "b"]), ("kept.ics", &["c"])]);
+        let current = candidates(&[("new.ics", &["a", "b"]), ("kept.ics", &["a", "b"])]);
+
+        let tombstones = update_tombstones(&BTreeMap::new(), &previous, &current, date(1), 60);
+
+        assert_eq!(
+            tombstones.get("old.ics").cloned(),
+            Some(Tombstone {
+                since: date(1),
+                new_path: Some("new.ics".to_owned())
+            }),
+            "only a calendar which didn't exist before should be the new home"
+        );
+        assert_eq!(
+            tombstones.len(),
+            1,
+            "kept calendars shouldn't get a tombstone"
+        );
+    }
+
+    #[test]
+    fn tombstones_expire_after_the_grace_period_or_when_the_calendar_returns() {
+        let tombstone = |day| Tombstone {
+            since: date(day),
+            new_path: None,
+        };
+        let previous_tombstones = BTreeMap::from([
+            ("recent.ics".to_owned(), tombstone(20)),
+            ("expired.ics".to_owned(), tombstone(10)),
+            ("returned.ics".to_owned(), tombstone(20)),
+        ]);
+        let current = candidates(&[("returned.ics", &["a"])]);
+
+        let tombstones = update_tombstones(
+            &previous_tombstones,
+            &BTreeMap::new(),
+            &current,
+            date(30),
+            20,
+        );
+
+        assert_eq!(
+            tombstones.keys().collect::<Vec<_>>(),
+            ["recent.ics"],
+            "only the tombstone within the grace period should be kept"
+        );
+    }
+
+    #[test]
+    fn the_tombstone_names_the_new_path() {
+        let tombstone = Tombstone {
+            since: date(1),
+            new_path: Some("iue/Analysis.ics".to_owned()),
+        };
+
+        let rendered = render_tombstone("old.ics", &tombstone, "/");
+
+        assert!(
+            rendered.contains("SUMMARY:Dieser Kalender ist umgezogen"),
+            "the notice should be the summary:\n{rendered}"
+        );
+        assert!(
+            rendered.contains("unter /iue/Analysis.ics"),
+            "the description should link the new calendar:\n{rendered}"
+        );
+        assert!(
+            rendered.contains("DTSTART;VALUE=DATE:20241101"),
+            "the notice should be an all-day event on the day of the move:\n{rendered}"
+        );
+    }
+}
@@ -0,0 +1,404 @@
+//! Checks of parsed events for anomalies the parser accepts without complaint
+
+use crate::calendar::{
+    PROPERTY_NAME_DTEND, PROPERTY_NAME_DTSTAMP, PROPERTY_NAME_DTSTART, PROPERTY_NAME_LOCATION,
+    PROPERTY_NAME_SEQUENCE, PROPERTY_NAME_STATUS, PROPERTY_NAME_SUMMARY, PROPERTY_NAME_UID,
+};
+use crate::datetime::{self, DEFAULT_DISPLAY_TIME_ZONE};
+use crate::props;
+use chrono::{Datelike, Days, NaiveDate, NaiveDateTime};
+use ical::parser::ical::component::IcalEvent;
+use ical::property::Property;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// Properties an event may have at most once.
+const SINGLETON_PROPERTIES: [&str; 8] = [
+    PROPERTY_NAME_DTSTART,
+    PROPERTY_NAME_DTEND,
+    PROPERTY_NAME_DTSTAMP,
+    PROPERTY_NAME_SUMMARY,
+    PROPERTY_NAME_LOCATION,
+    PROPERTY_NAME_STATUS,
+    PROPERTY_NAME_UID,
+    PROPERTY_NAME_SEQUENCE,
+];
+
+/// Properties which are useless without a value.
+const REQUIRED_VALUE_PROPERTIES: [&str; 3] = [
+    PROPERTY_NAME_DTSTART,
+    PROPERTY_NAME_SUMMARY,
+    PROPERTY_NAME_UID,
+];
+
+/// Properties holding a date or date-time.
+const DATE_PROPERTIES: [&str; 3] = [
+    PROPERTY_NAME_DTSTART,
+    PROPERTY_NAME_DTEND,
+    PROPERTY_NAME_DTSTAMP,
+];
+
+/// Years outside of this range are most likely typos in the source.
+const PLAUSIBLE_YEARS: std::ops::RangeInclusive<i32> = 2000..=2100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AnomalyKind {
+    /// A property which may occur only once occurs several times.
+    DuplicateProperty,
+    /// A property without which the event makes no sense has an empty value.
+    EmptyValue,
+    /// A date which can't be parsed, is in an implausible year or mixes UTC with a time zone.
+    SuspiciousDate,
+    /// DTEND is before DTSTART.
+    EndBeforeStart,
+}
+
+/// How `normalize_range` changed an event.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RangeFix {
+    /// The event ended before it started, so DTSTART and DTEND were swapped. Holds the new start.
+    Swapped(NaiveDateTime),
+    /// The all-day event ended on the day it started, DTEND now is the next day.
+    AllDayExtended(NaiveDate),
+}
+
+/// Something odd about a single property of an event.
+#[derive(Debug, Clone, Serialize)]
+pub struct Anomaly {
+    pub kind: AnomalyKind,
+    pub property: String,
+    pub detail: String,
+}
+
+/// An anomaly together with the event it was found in.
+#[derive(Debug, Clone, Serialize)]
+pub struct EventAnomaly {
+    /// URL of the source calendar.
+    pub source: String,
+    /// Summary of the event as found in the source.
+    pub summary: String,
+    #[serde(flatten)]
+    pub anomaly: Anomaly,
+}
+
+/// Returns every anomaly found in `event`.
+pub fn validate_event(event: &IcalEvent) -> Vec<Anomaly> {
+    let mut anomalies = Vec::new();
+
+    let mut counts: BTreeMap<&str, usize> = BTreeMap::new();
+    for property in &event.properties {
+        *counts.entry(property.name.as_str()).or_default() += 1;
+    }
+    for name in SINGLETON_PROPERTIES {
+        let count = counts.get(name).copied().unwrap_or_default();
+        if count > 1 {
+            anomalies.push(Anomaly {
+                kind: AnomalyKind::DuplicateProperty,
+                property: name.to_owned(),
+                detail: format!("occurs {count} times"),
+            });
+        }
+    }
+
+    for property in &event.properties {
+        let value = property.value.as_deref().map_or("", str::trim);
+
+        if REQUIRED_VALUE_PROPERTIES.contains(&property.name.as_str()) && value.is_empty() {
+            anomalies.push(Anomaly {
+                kind: AnomalyKind::EmptyValue,
+                property: property.name.clone(),
+                detail: "has no value".to_owned(),
+            });
+            continue;
+        }
+
+        if DATE_PROPERTIES.contains(&property.name.as_str()) {
+            if let Some(detail) = check_date(property, value) {
+                anomalies.push(Anomaly {
+                    kind: AnomalyKind::SuspiciousDate,
+                    property: property.name.clone(),
+                    detail,
+                });
+            }
+        }
+    }
+
+    if let Some((start, end)) = range(event).filter(|(start, end)| end < start) {
+        anomalies.push(Anomaly {
+            kind: AnomalyKind::EndBeforeStart,
+            property: PROPERTY_NAME_DTEND.to_owned(),
+            detail: format!("{end} is before DTSTART {start}"),
+        });
+    }
+
+    anomalies
+}
+
+/// Fixes the range of `event` for calendar clients. All-day events ending on the day they start
+/// last that day, since DTEND is exclusive. With `swap`, events ending before they start get
+/// their DTSTART and DTEND swapped, parameters included.
+pub fn normalize_range(event: &mut IcalEvent, swap: bool) -> Option<RangeFix> {
+    let (start, end) = range(event)?;
+
+    if swap && end < start {
+        for property in &mut event.properties {
+            if property.name == PROPERTY_NAME_DTSTART {
+                PROPERTY_NAME_DTEND.clone_into(&mut property.name);
+            } else if property.name == PROPERTY_NAME_DTEND {
+                PROPERTY_NAME_DTSTART.clone_into(&mut property.name);
+            }
+        }
+
+        return Some(RangeFix::Swapped(end));
+    }
+
+    let all_day = [PROPERTY_NAME_DTSTART, PROPERTY_NAME_DTEND]
+        .into_iter()
+        .all(|name| props::get_value(event, name).is_some_and(datetime::is_date_only));
+    if all_day && end == start {
+        let next_day = start.date().checked_add_days(Days::new(1))?;
+        props::set_value_preserving_params(
+            event,
+            PROPERTY_NAME_DTEND,
+            next_day.format("%Y%m%d").to_string(),
+        );
+
+        return Some(RangeFix::AllDayExtended(start.date()));
+    }
+
+    None
+}
+
+/// Returns DTSTART and DTEND of `event` as wall times in a common time zone, so they can be
+/// compared. `None` unless both can be parsed.
+fn range(event: &IcalEvent) -> Option<(NaiveDateTime, NaiveDateTime)> {
+    let parse = |name: &str| {
+        datetime::parse_display_time(
+            props::get_value(event, name)?,
+            props::get_param(event, name, "TZID"),
+            DEFAULT_DISPLAY_TIME_ZONE,
+        )
+    };
+
+    Some((parse(PROPERTY_NAME_DTSTART)?, parse(PROPERTY_NAME_DTEND)?))
+}
+
+/// Describes what is suspicious about the date `value` of `property`, if anything.
+fn check_date(property: &Property, value: &str) -> Option<String> {
+    if value.is_empty() {
+        return None;
+    }
+
+    let Some(date_time) = datetime::parse_date_time(value) else {
+        return Some(format!("'{}' is not a valid date", value.escape_debug()));
+    };
+
+    if !PLAUSIBLE_YEARS.contains(&date_time.year()) {
+        return Some(format!("'{value}' is in an implausible year"));
+    }
+
+    let has_time_zone = property.params.as_ref().is_some_and(|params| {
+        params
+            .iter()
+            .any(|(name, _)| name.eq_ignore_ascii_case("TZID"))
+    });
+    if has_time_zone && value.ends_with('Z') {
+        return Some(format!("'{value}' is in UTC but also has a TZID"));
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    /// Parses an event from its content lines, which are wrapped in a VEVENT and a VCALENDAR.
+    fn event(lines: &[&str]) -> IcalEvent {
+        let event = format!("BEGIN:VEVENT\r\n{}\r\nEND:VEVENT\r\n", lines.join("\r\n"));
+        let calendar = testing::calendar_of(&[event]);
+
+        ical::IcalParser::new(calendar.as_bytes())
+            .flat_map(|calendar| calendar.unwrap().events)
+            .next()
+            .unwrap()
+    }
+
+    fn anomalies(lines: &[&str]) -> Vec<(AnomalyKind, String)> {
+        validate_event(&event(lines))
+            .into_iter()
+            .map(|anomaly| (anomaly.kind, anomaly.property))
+            .collect()
+    }
+
+    #[test]
+    fn a_regular_event_has_no_anomalies() {
+        assert_eq!(
+            anomalies(&[
+                "UID:a",
+                "SUMMARY:Mathe",
+                "DTSTART;TZID=Europe/Berlin:20241014T081500",
+                "DTEND;TZID=Europe/Berlin:20241014T094500",
+            ]),
+            [],
+            "nothing should be reported"
+        );
+    }
+
+    #[test]
+    fn duplicate_singleton_properties_are_reported() {
+        assert_eq!(
+            anomalies(&[
+                "UID:a",
+                "SUMMARY:Mathe",
+                "DTSTART:20241014T081500",
+                "DTSTART:20241015T081500",
+                "CATEGORIES:A",
+                "CATEGORIES:B",
+            ]),
+            [(AnomalyKind::DuplicateProperty, "DTSTART".to_owned())],
+            "only the duplicate DTSTART should be reported"
+        );
+    }
+
+    #[test]
+    fn empty_required_values_are_reported() {
+        assert_eq!(
+            anomalies(&["UID:a", "SUMMARY:", "DTSTART:20241014T081500", "LOCATION:"]),
+            [(AnomalyKind::EmptyValue, "SUMMARY".to_owned())],
+            "only the empty SUMMARY should be reported"
+        );
+    }
+
+    #[test]
+    fn suspicious_dates_are_reported() {
+        for date in [
+            "DTSTART:2024-10-14",
+            "DTSTART:20241314T081500",
+            "DTSTART:19241014T081500",
+            "DTSTART;TZID=Europe/Berlin:20241014T081500Z",
+        ] {
+            assert_eq!(
+                anomalies(&["UID:a", "SUMMARY:Mathe", date]),
+                [(AnomalyKind::SuspiciousDate, "DTSTART".to_owned())],
+                "'{date}' should be reported"
+            );
+        }
+    }
+
+    #[test]
+    fn ends_before_the_start_are_reported_and_swapped() {
+        let lines = [
+            "UID:a",
+            "SUMMARY:Mathe",
+            "DTSTART:20241014T094500",
+            "DTEND:20241014T081500",
+        ];
+        let mut swapped = event(&lines);
+
+        assert_eq!(
+            anomalies(&lines),
+            [(AnomalyKind::EndBeforeStart, "DTEND".to_owned())],
+            "the end before the start should be reported"
+        );
+        assert_eq!(
+            normalize_range(&mut swapped, true),
+            NaiveDate::from_ymd_opt(2024, 10, 14)
+                .and_then(|date| date.and_hms_opt(8, 15, 0))
+                .map(RangeFix::Swapped),
+            "the start and end should be swapped"
+        );
+        assert_eq!(
+            props::get_value(&swapped, PROPERTY_NAME_DTEND),
+            Some("20241014T094500"),
+            "the end should now be the later time"
+        );
+        assert_eq!(
+            normalize_range(&mut event(&lines), false),
+            None,
+            "nothing should be swapped in strict mode"
+        );
+    }
+
+    #[test]
+    fn swapping_keeps_the_parameters_of_the_times() {
+        // 06:15 UTC is 08:15 in Berlin, before the start
+        let mut swapped = event(&[
+            "UID:a",
+            "SUMMARY:Mathe",
+            "DTSTART;TZID=Europe/Berlin:20241014T094500",
+            "DTEND:20241014T061500Z",
+        ]);
+
+        assert!(
+            matches!(
+                normalize_range(&mut swapped, true),
+                Some(RangeFix::Swapped(_))
+            ),
+            "the times should be compared in a common time zone"
+        );
+        assert_eq!(
+            (
+                props::get_value(&swapped, PROPERTY_NAME_DTSTART),
+                props::get_param(&swapped, PROPERTY_NAME_DTSTART, "TZID"),
+                props::get_param(&swapped, PROPERTY_NAME_DTEND, "TZID"),
+            ),
+            (Some("20241014T061500Z"), None, Some("Europe/Berlin")),
+            "the parameters should move with their values"
+        );
+    }
+
+    #[test]
+    fn ranges_in_order_are_left_alone() {
+        for lines in [
+            [
+                "DTSTART;TZID=Europe/Berlin:20241014T081500",
+                "DTEND;TZID=Europe/Berlin:20241014T094500",
+            ],
+            ["DTSTART;VALUE=DATE:20241003", "DTEND;VALUE=DATE:20241004"],
+            [
+                "DTSTART;TZID=Europe/Berlin:20241014T081500",
+                "DTEND;TZID=Europe/Berlin:20241014T081500",
+            ],
+        ] {
+            let mut unchanged = event(&lines);
+
+            assert_eq!(
+                normalize_range(&mut unchanged, true),
+                None,
+                "{lines:?} shouldn't be changed"
+            );
+            assert_eq!(
+                props::get_value(&unchanged, PROPERTY_NAME_DTEND),
+                lines
+                    .last()
+                    .and_then(|line| line.rsplit_once(':'))
+                    .map(|(_, value)| value),
+                "the end of {lines:?} should be kept"
+            );
+        }
+    }
+
+    #[test]
+    fn all_day_events_ending_on_their_start_last_the_day() {
+        let mut all_day = event(&[
+            "UID:a",
+            "SUMMARY:Feiertag",
+            "DTSTART;VALUE=DATE:20241003",
+            "DTEND;VALUE=DATE:20241003",
+        ]);
+
+        assert_eq!(
+            normalize_range(&mut all_day, true),
+            NaiveDate::from_ymd_opt(2024, 10, 3).map(RangeFix::AllDayExtended),
+            "the all-day event should be extended"
+        );
+        assert_eq!(
+            props::get_value(&all_day, PROPERTY_NAME_DTEND),
+            Some("20241004"),
+            "the event should end on the next day"
+        );
+    }
+}
@@ -0,0 +1,393 @@
+//! Checks of the published calendars against the local output
+
+use crate::clock::{Sleeper, ThreadSleeper};
+use crate::config::Config;
+use crate::exit_code::ExitCode;
+use crate::manifest::{self, MANIFEST_FILE};
+use crate::pipeline::{self, DOWNLOAD_DELAY, INDEX_FILE};
+use crate::prelude::*;
+use once_cell::sync::Lazy;
+use regex::{Regex, RegexBuilder};
+use reqwest::Url;
+use std::collections::BTreeSet;
+use std::path::Path;
+
+/// Number of calendars checked unless all of them are.
+const SAMPLE_SIZE: usize = 10;
+
+/// Checks the site published at `public_base_url` against the local output.
+///
+/// Compares the calendars linked in the published index with the local manifest and checks that
+/// a sample of them, or all with `full`, are served as parseable calendars. Returns a failing exit
+/// code if anything is wrong.
+pub fn verify_deployment(config: &Config, public_base_url: &str, full: bool) -> Result<ExitCode> {
+    let client = pipeline::build_client(config)?;
+    let verification = check_site(&client, &ThreadSleeper, public_base_url, full)?;
+
+    if verification.failures.is_empty() {
+        info!(
+            "Verification passed: checked {} of {} published calendars, the index matches the local output",
+            verification.checked,
+            verification.published
+        );
+        return Ok(ExitCode::Success);
+    }
+
+    for failure in &verification.failures {
+        error!("{failure}");
+    }
+    error!(
+        "Verification failed with {} problems",
+        verification.failures.len()
+    );
+
+    Ok(ExitCode::VerificationFailed)
+}
+
+/// Outcome of checking a published site.
+#[derive(Debug)]
+struct Verification {
+    /// Number of calendars linked in the published index.
+    published: usize,
+    /// Number of calendars which were downloaded.
+    checked: usize,
+    /// Description of every problem found.
+    failures: Vec<String>,
+}
+
+/// Checks the site published at `public_base_url` with `client`, sleeping with `sleeper` before
+/// every request for a calendar.
+fn check_site(
+    client: &reqwest::blocking::Client,
+    sleeper: &dyn Sleeper,
+    public_base_url: &str,
+    full: bool,
+) -> Result<Verification> {
+    let parse_url = |url: &str| Url::parse(url).map_err(|_| Error::InvalidUrl(url.to_owned()));
+    let base_url = parse_url(public_base_url)?;
+    let index_url = parse_url(&format!("{public_base_url}{INDEX_FILE}"))?;
+
+    let mut failures: Vec<String> = Vec::new();
+
+    // Links on the published index, without cache-busting query parameters
+    let index = client
+        .get(index_url.clone())
+        .send()?
+        .error_for_status()?
+        .text()?;
+    let published: BTreeSet<Url> = extract_calendar_links(&index)
+        .filter_map(|link| index_url.join(link).ok())
+        .collect();
+
+    // Files of the local output
+    let expected: BTreeSet<Url> = manifest::read_manifest_paths(Path::new(MANIFEST_FILE))?
+        .iter()
+        .filter_map(|path| base_url.join(path).ok())
+        .collect();
+
+    for url in published.difference(&expected) {
+        failures.push(format!(
+            "'{url}' is linked in the index but not part of the local output"
+        ));
+    }
+    for url in expected.difference(&published) {
+        failures.push(format!("'{url}' is missing from the published index"));
+    }
+
+    // Spread the sample over the whole index
+    let step = (published.len() / SAMPLE_SIZE).max(1);
+    let checked: Vec<&Url> = if full {
+        published.iter().collect()
+    } else {
+        published.iter().step_by(step).take(SAMPLE_SIZE).collect()
+    };
+
+    for url in &checked {
+        if let Err(problem) = check_calendar(client, sleeper, url) {
+            failures.push(format!("'{url}': {problem}"));
+        }
+    }
+
+    Ok(Verification {
+        published: published.len(),
+        checked: checked.len(),
+        failures,
+    })
+}
+
+/// Returns the links to calendar files in the index, without any query.
+fn extract_calendar_links(index: &str) -> impl Iterator<Item = &str> {
+    // Sample: <a href="/files/iue/WiSe_2425/semester_1/Mathematik_1.ics?v=0123456789abcdef">
+    static CALENDAR_LINK_REGEX: Lazy<Regex> = Lazy::new(|| {
+        RegexBuilder::new("href=\"([^\"?#]*?\\.ics)(?:[?#][^\"]*)?\"")
+            .case_insensitive(true)
+            .build()
+            .unwrap()
+    });
+
+    CALENDAR_LINK_REGEX
+        .captures_iter(index)
+        .map(|c| c.extract())
+        .map(|(_, [link])| link)
+}
+
+/// Checks that `url` is served as `text/calendar` and parses.
+fn check_calendar(
+    client: &reqwest::blocking::Client,
+    sleeper: &dyn Sleeper,
+    url: &Url,
+) -> std::result::Result<(), String> {
+    sleeper.sleep(DOWNLOAD_DELAY);
+    let head = client
+        .head(url.clone())
+        .send()
+        .map_err(|err| err.to_string())?;
+    if head.status() != reqwest::StatusCode::OK {
+        return Err(format!("status {}", head.status()));
+    }
+
+    let content_type = head
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+    if !content_type.starts_with("text/calendar") {
+        return Err(format!(
+            "served as '{content_type}' instead of text/calendar"
+        ));
+    }
+
+    sleeper.sleep(DOWNLOAD_DELAY);
+    let content = client
+        .get(url.clone())
+        .send()
+        .and_then(reqwest::blocking::Response::error_for_status)
+        .and_then(reqwest::blocking::Response::text)
+        .map_err(|err| err.to_string())?;
+
+    let mut number_of_calendars = 0;
+    for calendar in ical::IcalParser::new(content.as_bytes()) {
+        calendar.map_err(|err| format!("failed to parse: {err}"))?;
+        number_of_calendars += 1;
+    }
+    if number_of_calendars == 0 {
+        return Err("contains no calendar".to_owned());
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{self, FakeClock, FakeFetcher, FakeSleeper, MockResponse, MockServer};
+    use std::path::PathBuf;
+
+    /// Generates the output of a run over a site with two module calendars.
+    fn generate_output() {
+        let clock = FakeClock::new();
+        let fetcher = FakeFetcher::new(&clock)
+            .page(
+                testing::BASE_URL,
+                r#"<a href="/informatik-elektrotechnik" role="button"> Informatik </a>"#,
+            )
+            .page(
+                "informatik-elektrotechnik",
+                r#"<a href="/informatik-elektrotechnik/wise-2425">Wintersemester</a>"#,
+            )
+            .page(
+                "informatik-elektrotechnik/wise-2425",
+                r#"<h1>Wintersemester</h1>
+                <a href="/files/iue/WiSe_2425/semester_1/a.ics">a.ics</a>"#,
+            )
+            // Scraped links are the base URL followed by the absolute path of the link
+            .page(
+                &format!("{}/files/iue/WiSe_2425/semester_1/a.ics", testing::BASE_URL),
+                &testing::calendar(&[("Mathe", "20241014T081500"), ("Physik", "20241015T100000")]),
+            );
+
+        testing::pipeline(testing::config(), &fetcher, &clock)
+            .run()
+            .unwrap();
+    }
+
+    /// Serves the files in `output` like a static web host, passing every response through
+    /// `tamper` together with the path of the file.
+    fn publish(
+        output: &Path,
+        tamper: impl Fn(&str, MockResponse) -> MockResponse + Send + 'static,
+    ) -> MockServer {
+        let output = PathBuf::from(output);
+
+        MockServer::start(move |path| {
+            let file = path
+                .split(['?', '#'])
+                .next()
+                .unwrap_or_default()
+                .trim_start_matches('/');
+            let response = std::fs::read_to_string(output.join(file)).map_or_else(
+                |_| MockResponse {
+                    status_code: 404,
+                    headers: Vec::new(),
+                    body: Vec::new(),
+                },
+                |body| MockResponse {
+                    headers: vec![(
+                        "Content-Type".to_owned(),
+                        if Path::new(file)
+                            .extension()
+                            .is_some_and(|extension| extension == "ics")
+                        {
+                            "text/calendar; charset=utf-8"
+                        } else {
+                            "text/html; charset=utf-8"
+                        }
+                        .to_owned(),
+                    )],
+                    ..MockResponse::ok(&body)
+                },
+            );
+
+            tamper(file, response)
+        })
+    }
+
+    /// Checks every calendar of the site published by `server`.
+    fn check(server: &MockServer) -> Verification {
+        let clock = FakeClock::new();
+
+        check_site(
+            &reqwest::blocking::Client::new(),
+            &FakeSleeper::new(&clock),
+            &server.base_url,
+            true,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn correctly_published_output_passes() {
+        testing::in_working_dir("verify_published_output", |output| {
+            generate_output();
+            let server = publish(output, |_, response| response);
+
+            let verification = check(&server);
+
+            assert_eq!(
+                verification.failures,
+                Vec::<String>::new(),
+                "nothing should be wrong"
+            );
+            assert_eq!(
+                (verification.published, verification.checked),
+                (2, 2),
+                "both calendars should be checked"
+            );
+        });
+    }
+
+    #[test]
+    fn missing_and_wrongly_served_calendars_fail() {
+        testing::in_working_dir("verify_broken_output", |output| {
+            generate_output();
+            let server = publish(output, |file, response| {
+                if file.ends_with("Mathe.ics") {
+                    MockResponse {
+                        status_code: 404,
+                        ..response
+                    }
+                } else if file.ends_with("Physik.ics") {
+                    MockResponse {
+                        headers: vec![("Content-Type".to_owned(), "text/plain".to_owned())],
+                        ..response
+                    }
+                } else {
+                    response
+                }
+            });
+
+            let verification = check(&server);
+
+            assert_eq!(
+                verification.failures.len(),
+                2,
+                "both calendars should fail: {:?}",
+                verification.failures
+            );
+            assert!(
+                verification
+                    .failures
+                    .iter()
+                    .any(|failure| failure.contains("Mathe.ics': status 404")),
+                "the missing calendar should fail: {:?}",
+                verification.failures
+            );
+            assert!(
+                verification.failures.iter().any(|failure| failure
+                    .contains("Physik.ics': served as 'text/plain' instead of text/calendar")),
+                "the calendar with the wrong type should fail: {:?}",
+                verification.failures
+            );
+        });
+    }
+
+    #[test]
+    fn index_published_under_the_wrong_prefix_fails() {
+        testing::in_working_dir("verify_wrong_prefix", |output| {
+            generate_output();
+            let server = publish(output, |file, response| {
+                if file == INDEX_FILE {
+                    MockResponse {
+                        body: String::from_utf8_lossy(&response.body)
+                            .replace("href=\"/files/", "href=\"/site/files/")
+                            .into_bytes(),
+                        ..response
+                    }
+                } else {
+                    response
+                }
+            });
+
+            let verification = check(&server);
+
+            assert_eq!(
+                verification
+                    .failures
+                    .iter()
+                    .filter(|failure| failure.contains("is missing from the published index"))
+                    .count(),
+                2,
+                "the local calendars should be missing from the index: {:?}",
+                verification.failures
+            );
+            assert_eq!(
+                verification
+                    .failures
+                    .iter()
+                    .filter(|failure| failure.contains("/site/files/")
+                        && failure
+                            .contains("is linked in the index but not part of the local output"))
+                    .count(),
+                2,
+                "the published links should be unknown: {:?}",
+                verification.failures
+            );
+        });
+    }
+
+    #[test]
+    fn calendar_links_are_extracted_without_the_query() {
+        let index = r#"<li> <a href="/files/iue/WiSe_2425/semester_1/Mathe.ics?v=0123">Mathe</a>
+<a href="/changes.html">Änderungen</a>
+<a href="https://example.test/Physik.ICS#top">Physik</a>"#;
+
+        assert_eq!(
+            extract_calendar_links(index).collect::<Vec<_>>(),
+            [
+                "/files/iue/WiSe_2425/semester_1/Mathe.ics",
+                "https://example.test/Physik.ICS"
+            ],
+            "only the calendars should be extracted"
+        );
+    }
+}